@@ -173,3 +173,85 @@ pub enum RenderingIntent {
     RelativeColorimetric,
     Perceptual
 }
+
+/// Coding independent code points (CICP)
+///
+/// Describes an image's color primaries, transfer function and
+/// matrix coefficients using the code points of ITU-T H.273,
+/// which is how HDR content (e.g PQ or HLG) is signalled.
+///
+/// Values are kept as raw code points so that they round trip unchanged
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CodingIndependentCodePoints {
+    /// Color primaries, e.g 1 for BT.709, 9 for BT.2020
+    pub color_primaries:          u8,
+    /// Transfer characteristics, e.g 13 for sRGB, 16 for PQ, 18 for HLG
+    pub transfer_characteristics: u8,
+    /// Matrix coefficients, 0 for RGB
+    pub matrix_coefficients:      u8,
+    /// Whether the image uses the full range of values, otherwise
+    /// it's in narrow (video) range
+    pub full_range:               bool
+}
+
+impl CodingIndependentCodePoints {
+    /// Transfer characteristics code point of SMPTE ST 2084 (PQ)
+    pub const TRANSFER_PQ: u8 = 16;
+    /// Transfer characteristics code point of ARIB STD-B67 (HLG)
+    pub const TRANSFER_HLG: u8 = 18;
+
+    /// Return true if the transfer characteristics are one
+    /// of the HDR transfer functions, PQ or HLG
+    pub const fn is_hdr(&self) -> bool {
+        matches!(
+            self.transfer_characteristics,
+            Self::TRANSFER_PQ | Self::TRANSFER_HLG
+        )
+    }
+}
+
+/// Mastering display color volume
+///
+/// Describes the display the content was mastered on,
+/// as defined by SMPTE ST 2086.
+///
+/// Chromaticities are in units of 0.00002 and luminance
+/// in units of 0.0001 candelas per square meter
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MasteringDisplayColorVolume {
+    /// CIE 1931 x and y chromaticity of the red primary
+    pub red:           (u16, u16),
+    /// CIE 1931 x and y chromaticity of the green primary
+    pub green:         (u16, u16),
+    /// CIE 1931 x and y chromaticity of the blue primary
+    pub blue:          (u16, u16),
+    /// CIE 1931 x and y chromaticity of the white point
+    pub white_point:   (u16, u16),
+    /// Maximum luminance of the display
+    pub max_luminance: u32,
+    /// Minimum luminance of the display
+    pub min_luminance: u32
+}
+
+impl MasteringDisplayColorVolume {
+    /// Maximum luminance of the display in candelas per square meter
+    pub fn max_luminance_nits(&self) -> f64 {
+        f64::from(self.max_luminance) / 10000.0
+    }
+    /// Minimum luminance of the display in candelas per square meter
+    pub fn min_luminance_nits(&self) -> f64 {
+        f64::from(self.min_luminance) / 10000.0
+    }
+}
+
+/// Content light level information
+///
+/// Light levels are in units of 0.0001 candelas per square meter
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    /// Maximum content light level (MaxCLL), the light
+    /// level of the brightest pixel
+    pub max_content_light_level:       u32,
+    /// Maximum frame-average light level (MaxFALL)
+    pub max_frame_average_light_level: u32
+}
@@ -7,7 +7,7 @@
 //! options e.g the same  `DecoderOption` can be reused for all other decoders
 //!
 pub use decoder::DecoderOptions;
-pub use encoder::EncoderOptions;
+pub use encoder::{EncoderOptions, JpegSubsampling};
 
 mod decoder;
 mod encoder;
@@ -39,6 +39,7 @@ fn fast_options() -> DecoderFlags {
         png_add_alpha_channel:     false,
         png_strip_16_bit_to_8_bit: false,
         png_decode_animated:       true,
+        png_keep_palette:          false,
         jxl_decode_animated:       true
     }
 }
@@ -67,6 +68,7 @@ fn cmd_options() -> DecoderFlags {
         png_strip_16_bit_to_8_bit: false,
 
         png_decode_animated: true,
+        png_keep_palette:    false,
         jxl_decode_animated: true
     }
 }
@@ -108,6 +110,9 @@ pub struct DecoderFlags {
     png_strip_16_bit_to_8_bit:    bool,
     /// Decode all frames for an animated images
     png_decode_animated:          bool,
+    /// Whether the png decoder should return palette indexes
+    /// instead of expanding them to colors
+    png_keep_palette:             bool,
     jxl_decode_animated:          bool
 }
 
@@ -150,6 +155,17 @@ pub struct DecoderOptions {
     /// - Default value:100
     /// - Respected by: `jpeg`
     max_scans:     usize,
+    /// Denominator of the scale to reduce images by,
+    /// one of 1, 2, 4 or 8
+    ///
+    /// - Default value: 1
+    /// - Respected by: `jpeg`
+    scale_denom:   usize,
+    /// Region of the image to decode, as x, y, width and height
+    ///
+    /// - Default value: None
+    /// - Respected by: `jpeg`
+    crop:          Option<(usize, usize, usize, usize)>,
     /// Maximum size for deflate.
     /// Respected by all decoders that use inflate/deflate
     deflate_limit: usize,
@@ -210,10 +226,10 @@ impl DecoderOptions {
 
     /// Return true whether the decoder should be in strict mode
     /// And reject most errors
+    ///
+    /// Confirming png crc checksums on its own doesn't enable strict mode
     pub fn strict_mode(&self) -> bool {
-        self.flags.jpg_error_on_non_conformance
-            | self.flags.png_confirm_crc
-            | self.flags.inflate_confirm_adler
+        self.flags.jpg_error_on_non_conformance | self.flags.inflate_confirm_adler
     }
     /// Return true if the decoder should use unsafe
     /// routines where possible
@@ -346,6 +362,9 @@ impl DecoderOptions {
     }
     /// Set whether the png decoder should confirm
     /// CRC 32 checksums
+    ///
+    /// Outside of [strict mode](Self::set_strict_mode), image data
+    /// with a wrong checksum is still decoded and the error reported
     #[must_use]
     pub fn png_set_confirm_crc(mut self, yes: bool) -> Self {
         self.flags.png_confirm_crc = yes;
@@ -393,6 +412,21 @@ impl DecoderOptions {
         self.flags.png_decode_animated = yes;
         self
     }
+
+    /// Set whether the png decoder should return palette indexes
+    /// for indexed images instead of expanding them to RGB or RGBA
+    ///
+    /// Indexes are returned one byte per pixel as a `Luma` image, the palette
+    /// and its transparency can be retrieved from the decoder
+    pub const fn png_set_keep_palette(mut self, yes: bool) -> Self {
+        self.flags.png_keep_palette = yes;
+        self
+    }
+    /// Return true if the png decoder should return palette indexes
+    /// for indexed images instead of expanding them
+    pub const fn png_get_keep_palette(&self) -> bool {
+        self.flags.png_keep_palette
+    }
 }
 
 /// JPEG specific options
@@ -425,6 +459,40 @@ impl DecoderOptions {
         self.out_colorspace = colorspace;
         self
     }
+    /// Get the denominator of the scale the jpeg decoder reduces images by
+    pub const fn jpeg_get_scale_denom(&self) -> usize {
+        self.scale_denom
+    }
+    /// Set the denominator of the scale the jpeg decoder reduces images by
+    ///
+    /// The decoder runs smaller IDCTs, producing an image whose width and height
+    /// are divided by `scale_denom` and rounded up, which is faster than decoding
+    /// the whole image and resizing it.
+    ///
+    /// Supported values are 1, 2, 4 and 8, the decoder returns an error for others
+    #[must_use]
+    pub fn jpeg_set_scale_denom(mut self, scale_denom: usize) -> Self {
+        self.scale_denom = scale_denom;
+        self
+    }
+    /// Get the region of the image the jpeg decoder decodes, as x, y, width and height
+    pub const fn jpeg_get_crop(&self) -> Option<(usize, usize, usize, usize)> {
+        self.crop
+    }
+    /// Decode only a region of the image with the jpeg decoder
+    ///
+    /// The output holds only the pixels of the `width`x`height` rectangle whose top
+    /// left corner is at `x`,`y`. The region is in the coordinates of the image after
+    /// scaling it down, see [`jpeg_set_scale_denom`](Self::jpeg_set_scale_denom), and
+    /// must be inside the image, the decoder returns an error otherwise.
+    ///
+    /// Decoding of baseline images stops after the last rows of the region, and only
+    /// the blocks around the region are transformed and color converted.
+    #[must_use]
+    pub fn jpeg_set_crop(mut self, x: usize, y: usize, width: usize, height: usize) -> Self {
+        self.crop = Some((x, y, width, height));
+        self
+    }
 }
 
 /// Intrinsics support
@@ -639,6 +707,8 @@ impl Default for DecoderOptions {
     ///
     /// - JPEG
     ///     - max_scans: 100 (progressive images only, artificial cap to prevent a specific DOS)
+    ///     - scale_denom: 1 (images are decoded at their full size)
+    ///     - crop: None (the whole image is decoded)
     ///     - error_on_non_conformance: False (slightly corrupt images will be allowed)
     /// - DEFLATE
     ///     - deflate_limit: 1GB (will not continue decoding deflate archives larger than this)
@@ -658,6 +728,8 @@ impl Default for DecoderOptions {
             max_width:      1 << 14,
             max_height:     1 << 14,
             max_scans:      100,
+            scale_denom:    1,
+            crop:           None,
             deflate_limit:  1 << 30,
             flags:          decoder_error_tolerance_mode(),
             endianness:     ByteEndian::BE
@@ -20,18 +20,46 @@ struct EncoderFlags {
     image_strip_metadata:    bool
 }
 
+/// Chroma subsampling used by the JPEG encoder
+///
+/// The names follow the usual `J:a:b` notation, subsampling
+/// only affects the chroma components of YCbCr and YCCK images
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum JpegSubsampling {
+    /// Chroma has the same resolution as luma
+    S444,
+    /// Chroma has half the horizontal resolution of luma
+    S422,
+    /// Chroma has half the horizontal and half the vertical resolution of luma
+    S420
+}
+
+impl JpegSubsampling {
+    /// Horizontal and vertical sampling factors of the luma component,
+    /// chroma components are always sampled at `1x1`
+    pub const fn luma_sampling_factors(self) -> (usize, usize) {
+        match self {
+            JpegSubsampling::S444 => (1, 1),
+            JpegSubsampling::S422 => (2, 1),
+            JpegSubsampling::S420 => (2, 2)
+        }
+    }
+}
+
 /// Options shared by some of the encoders in
 /// the `zune-` family of image crates
 #[derive(Debug, Copy, Clone)]
 pub struct EncoderOptions {
-    width:       usize,
-    height:      usize,
-    colorspace:  ColorSpace,
-    quality:     u8,
-    depth:       BitDepth,
-    num_threads: u8,
-    effort:      u8,
-    flags:       EncoderFlags
+    width:                 usize,
+    height:                usize,
+    colorspace:            ColorSpace,
+    quality:               u8,
+    depth:                 BitDepth,
+    num_threads:           u8,
+    effort:                u8,
+    jpeg_subsampling:      JpegSubsampling,
+    jpeg_restart_interval: u16,
+    flags:                 EncoderFlags
 }
 
 impl Default for EncoderOptions {
@@ -44,7 +72,11 @@ impl Default for EncoderOptions {
             depth:       BitDepth::Eight,
             num_threads: 4,
             effort:      4,
-            flags:       EncoderFlags::default()
+
+            jpeg_subsampling:      JpegSubsampling::S420,
+            jpeg_restart_interval: 0,
+
+            flags: EncoderFlags::default()
         }
     }
 }
@@ -183,7 +215,7 @@ impl EncoderOptions {
     /// The default value is false, and encoders that respect this try to preserve as much
     /// data as possible from one image to another
     pub const fn strip_metadata(&self) -> bool {
-        !self.flags.image_strip_metadata
+        self.flags.image_strip_metadata
     }
 }
 
@@ -211,7 +243,52 @@ impl EncoderOptions {
     ///
     /// Default is `false`
     pub fn set_jpeg_encode_progressive(mut self, yes: bool) -> Self {
+        self.flags.jpeg_encode_progressive = yes;
+        self
+    }
+
+    /// Set whether the jpeg encoder should optimize huffman tables to create smaller files
+    /// at the cost of processing time
+    ///
+    /// Progressive images always use optimized tables
+    ///
+    /// Default is `false`
+    pub fn set_jpeg_optimized_huffman_tables(mut self, yes: bool) -> Self {
         self.flags.jpeg_optimize_huffman = yes;
         self
     }
+
+    /// Get the chroma subsampling the jpeg encoder uses for YCbCr and YCCK images
+    ///
+    /// Default is [`JpegSubsampling::S420`]
+    pub const fn jpeg_subsampling(&self) -> JpegSubsampling {
+        self.jpeg_subsampling
+    }
+
+    /// Set the chroma subsampling the jpeg encoder uses for YCbCr and YCCK images
+    ///
+    /// Default is [`JpegSubsampling::S420`]
+    pub fn set_jpeg_subsampling(mut self, subsampling: JpegSubsampling) -> Self {
+        self.jpeg_subsampling = subsampling;
+        self
+    }
+
+    /// Get the number of MCUs between restart markers the jpeg encoder writes,
+    /// zero means no restart markers are written
+    ///
+    /// Default is `0`
+    pub const fn jpeg_restart_interval(&self) -> u16 {
+        self.jpeg_restart_interval
+    }
+
+    /// Set the number of MCUs between restart markers the jpeg encoder writes
+    ///
+    /// Restart markers allow decoders to resynchronize after corrupt data at the cost
+    /// of slightly bigger files, zero means no restart markers are written
+    ///
+    /// Default is `0`
+    pub fn set_jpeg_restart_interval(mut self, interval: u16) -> Self {
+        self.jpeg_restart_interval = interval;
+        self
+    }
 }
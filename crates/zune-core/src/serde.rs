@@ -14,12 +14,19 @@
 //!  - ColorSpace
 //!  - BitDepth
 //!  - ColorCharacteristics
+//!  - RenderingIntent
+//!  - CodingIndependentCodePoints
+//!  - MasteringDisplayColorVolume
+//!  - ContentLightLevel
 use alloc::format;
 
 use serde::ser::*;
 
 use crate::bit_depth::BitDepth;
-use crate::colorspace::{ColorCharacteristics, ColorSpace, RenderingIntent};
+use crate::colorspace::{
+    CodingIndependentCodePoints, ColorCharacteristics, ColorSpace, ContentLightLevel,
+    MasteringDisplayColorVolume, RenderingIntent
+};
 
 impl Serialize for ColorSpace {
     #[allow(clippy::uninlined_format_args)]
@@ -61,3 +68,48 @@ impl Serialize for RenderingIntent {
         serializer.serialize_str(&format!("{:?}", self))
     }
 }
+
+impl Serialize for CodingIndependentCodePoints {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        let mut state = serializer.serialize_struct("CodingIndependentCodePoints", 4)?;
+        state.serialize_field("color_primaries", &self.color_primaries)?;
+        state.serialize_field("transfer_characteristics", &self.transfer_characteristics)?;
+        state.serialize_field("matrix_coefficients", &self.matrix_coefficients)?;
+        state.serialize_field("full_range", &self.full_range)?;
+        state.end()
+    }
+}
+
+impl Serialize for MasteringDisplayColorVolume {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        let mut state = serializer.serialize_struct("MasteringDisplayColorVolume", 6)?;
+        state.serialize_field("red", &self.red)?;
+        state.serialize_field("green", &self.green)?;
+        state.serialize_field("blue", &self.blue)?;
+        state.serialize_field("white_point", &self.white_point)?;
+        state.serialize_field("max_luminance", &self.max_luminance)?;
+        state.serialize_field("min_luminance", &self.min_luminance)?;
+        state.end()
+    }
+}
+
+impl Serialize for ContentLightLevel {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        let mut state = serializer.serialize_struct("ContentLightLevel", 2)?;
+        state.serialize_field("max_content_light_level", &self.max_content_light_level)?;
+        state.serialize_field(
+            "max_frame_average_light_level",
+            &self.max_frame_average_light_level
+        )?;
+        state.end()
+    }
+}
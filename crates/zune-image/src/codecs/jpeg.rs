@@ -9,18 +9,17 @@
 #![cfg(feature = "jpeg")]
 //! Jpeg decoding and encoding support
 //!
-//! The decoder uses a delegate library [`zune-jpeg`](zune_jpeg)
-//! for decoding and [`jpeg-encoder`](jpeg_encoder) for encoding
+//! The decoder and encoder use a delegate library [`zune-jpeg`](zune_jpeg)
+//! for decoding and encoding
 //!
 //! The decoder and encoder both support metadata extraction and saving.
 //!
-use jpeg_encoder::{ColorType, EncodingError, JfifWrite};
 use zune_core::bit_depth::BitDepth;
-use zune_core::bytestream::{ZByteIoError, ZByteReaderTrait, ZByteWriterTrait, ZWriter};
+use zune_core::bytestream::{ZByteReaderTrait, ZByteWriterTrait};
 use zune_core::colorspace::ColorSpace;
 use zune_core::log::warn;
 use zune_core::options::EncoderOptions;
-use zune_jpeg::errors::DecodeErrors;
+use zune_jpeg::errors::{DecodeErrors, EncodeErrors};
 pub use zune_jpeg::{ImageInfo, JpegDecoder};
 
 use crate::codecs::{create_options_for_encoder, ImageFormat};
@@ -29,29 +28,32 @@ use crate::image::Image;
 use crate::metadata::ImageMetadata;
 use crate::traits::{DecodeInto, DecoderTrait, EncoderTrait};
 
-struct TempVt<'a, T: ZByteWriterTrait> {
-    inner: &'a mut ZWriter<T>
-}
-impl<'a, T: ZByteWriterTrait> JfifWrite for TempVt<'a, T> {
-    fn write_all(&mut self, buf: &[u8]) -> Result<(), EncodingError> {
-        self.inner.write_all(buf).map_err(|r| match r {
-            ZByteIoError::StdIoError(e) => EncodingError::IoError(e),
-            r => EncodingError::Write(format!("{:?}", r))
-        })
-    }
-}
 impl<T: ZByteReaderTrait> DecoderTrait for zune_jpeg::JpegDecoder<T> {
     fn decode(&mut self) -> Result<Image, crate::errors::ImageErrors> {
         let metadata = self.read_headers()?.unwrap();
 
-        let pixels = self
-            .decode()
-            .map_err(<DecodeErrors as Into<ImageErrors>>::into)?;
-
         let colorspace = self.output_colorspace().unwrap();
         let (width, height) = self.dimensions().unwrap();
 
-        let mut image = Image::from_u8(&pixels, width, height, colorspace);
+        let mut image = if self.depth() == Some(BitDepth::Sixteen) {
+            // 12 bit and lossless images with more than 8 bits
+            let mut pixels = self
+                .decode_u16()
+                .map_err(<DecodeErrors as Into<ImageErrors>>::into)?;
+            // scale samples to the full 16 bit range
+            let max_value = (1_u32 << self.precision().unwrap()) - 1;
+
+            for pixel in &mut pixels {
+                *pixel = (u32::from(*pixel) * 65535 / max_value) as u16;
+            }
+            Image::from_u16(&pixels, width, height, colorspace)
+        } else {
+            let pixels = self
+                .decode()
+                .map_err(<DecodeErrors as Into<ImageErrors>>::into)?;
+
+            Image::from_u8(&pixels, width, height, colorspace)
+        };
         image.metadata = metadata;
         image.metadata.colorspace = self.output_colorspace().unwrap();
         Ok(image)
@@ -78,7 +80,7 @@ impl<T: ZByteReaderTrait> DecoderTrait for zune_jpeg::JpegDecoder<T> {
         let mut metadata = ImageMetadata {
             format: Some(ImageFormat::JPEG),
             colorspace: self.input_colorspace().unwrap(),
-            depth: BitDepth::Eight,
+            depth: self.depth().unwrap(),
             width: width,
             height: height,
             ..Default::default()
@@ -105,10 +107,6 @@ impl From<zune_jpeg::errors::DecodeErrors> for ImageErrors {
         ImageErrors::ImageDecodeErrors(err)
     }
 }
-// Okay I just need to really appreciate jpeg-encoder crate
-// it's well written and covers quite a lot of things really
-// well, so thank you Volker Ströbel
-
 /// A simple JPEG encoder
 #[derive(Copy, Clone, Default)]
 pub struct JpegEncoder {
@@ -130,7 +128,7 @@ impl JpegEncoder {
 
 impl EncoderTrait for JpegEncoder {
     fn name(&self) -> &'static str {
-        "jpeg-encoder(vstroebel)"
+        "zune-jpeg"
     }
 
     fn encode_inner<T: ZByteWriterTrait>(
@@ -143,75 +141,56 @@ impl EncoderTrait for JpegEncoder {
             image.depth()
         );
         let pixels = &image.flatten_frames::<u8>()[0];
+        let options = create_options_for_encoder(self.options, image);
 
-        if let Some(colorspace) = match_colorspace_to_colortype(image.colorspace()) {
-            let max_dims = usize::from(u16::MAX);
-
-            let (width, height) = image.dimensions();
-
-            // check dimensions
-            if (width > max_dims) || (height > max_dims) {
-                let msg = format!(
-                    "Too large image dimensions {} x {}, maximum is {} x {}",
-                    width, height, max_dims, max_dims
-                );
-                return Err(ImgEncodeErrors::ImageEncodeErrors(msg).into());
-            }
-            let mut writer = ZWriter::new(sink);
-            let temp_c = TempVt { inner: &mut writer };
-            // create space for our encoder
-
-            let options = create_options_for_encoder(self.options, image);
-
-            // create encoder finally
-            // vec<u8> supports write so we use that as our encoder
-            let mut encoder = jpeg_encoder::Encoder::new(temp_c, options.quality());
-
-            // add options
-            encoder.set_progressive(options.jpeg_encode_progressive());
-            encoder.set_optimized_huffman_tables(options.jpeg_optimized_huffman_tables());
+        #[allow(unused_mut)]
+        let mut exif = vec![];
 
-            #[cfg(feature = "metadata")]
-            {
-                use exif::experimental::Writer;
+        #[cfg(feature = "metadata")]
+        {
+            use exif::experimental::Writer;
 
-                if options.strip_metadata() {
-                    // explicit :)
-                } else if let Some(metadata) = &image.metadata.exif {
+            if !options.strip_metadata() {
+                if let Some(fields) = &image.metadata.exif {
                     let mut writer = Writer::new();
-                    // write first tags for exif
-                    let mut buf = std::io::Cursor::new(b"Exif\x00\x00".to_vec());
-                    // set buffer position to be bytes written, to ensure we don't overwrite anything
-                    buf.set_position(6);
+                    let mut buf = std::io::Cursor::new(vec![]);
 
-                    for metadatum in metadata {
+                    for metadatum in fields {
                         writer.push_field(metadatum);
                     }
                     let result = writer.write(&mut buf, false);
                     if result.is_ok() {
-                        // add the exif tag to APP1 segment
-                        encoder.add_app_segment(1, buf.get_ref())?;
+                        exif = buf.into_inner();
                     } else {
                         warn!("Writing exif failed {:?}", result);
                     }
                 }
             }
+        }
 
-            encoder.encode(pixels, width as u16, height as u16, colorspace)?;
+        let mut encoder = zune_jpeg::JpegEncoder::new(pixels, options);
 
-            Ok(writer.bytes_written())
-        } else {
-            Err(ImgEncodeErrors::UnsupportedColorspace(
-                image.colorspace(),
-                self.supported_colorspaces()
-            )
-            .into())
+        // color information is kept even when stripping metadata,
+        // as the image would display differently without it
+        if let Some(icc) = image.metadata().icc_chunk() {
+            encoder.add_icc_profile(icc);
+        }
+        if !exif.is_empty() {
+            encoder.add_exif(&exif);
         }
+        let written = encoder.encode(sink).map_err(|e| match e {
+            EncodeErrors::UnsupportedColorspace(colorspace) => ImageErrors::EncodeErrors(
+                ImgEncodeErrors::UnsupportedColorspace(colorspace, self.supported_colorspaces())
+            ),
+            e => e.into()
+        })?;
+
+        Ok(written)
     }
 
     fn supported_colorspaces(&self) -> &'static [ColorSpace] {
-        // should match with the
-        // jpeg-encoder crate
+        // should match with the colorspaces
+        // zune_jpeg::JpegEncoder accepts
         &[
             ColorSpace::Luma,
             ColorSpace::RGB,
@@ -239,22 +218,9 @@ impl EncoderTrait for JpegEncoder {
     }
 }
 
-/// Match the library colorspace to jpeg color type
-const fn match_colorspace_to_colortype(colorspace: ColorSpace) -> Option<ColorType> {
-    match colorspace {
-        ColorSpace::RGBA => Some(ColorType::Rgba),
-        ColorSpace::RGB => Some(ColorType::Rgb),
-        ColorSpace::YCbCr => Some(ColorType::Ycbcr),
-        ColorSpace::Luma => Some(ColorType::Luma),
-        ColorSpace::YCCK => Some(ColorType::Ycck),
-        ColorSpace::CMYK => Some(ColorType::Cmyk),
-        _ => None
-    }
-}
-
-impl From<EncodingError> for ImageErrors {
-    fn from(value: EncodingError) -> Self {
-        ImageErrors::EncodeErrors(ImgEncodeErrors::Generic(value.to_string()))
+impl From<EncodeErrors> for ImageErrors {
+    fn from(value: EncodeErrors) -> Self {
+        ImageErrors::EncodeErrors(ImgEncodeErrors::ImageEncodeErrors(format!("jpg: {value:?}")))
     }
 }
 
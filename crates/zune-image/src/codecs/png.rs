@@ -14,7 +14,7 @@ use std::io::Cursor;
 
 use zune_core::bit_depth::BitDepth;
 use zune_core::bytestream::{ZByteReaderTrait, ZByteWriterTrait};
-use zune_core::colorspace::ColorSpace;
+use zune_core::colorspace::{ColorCharacteristics, ColorSpace, RenderingIntent};
 use zune_core::log::warn;
 use zune_core::options::EncoderOptions;
 use zune_core::result::DecodingResult;
@@ -138,6 +138,14 @@ where
         if let Some(icc) = &self.info().unwrap().icc_profile {
             metadata.set_icc_chunk(icc.to_owned());
         }
+        if self.info().unwrap().srgb_intent.is_some() {
+            metadata.set_color_trc(ColorCharacteristics::sRGB);
+        }
+        // HDR information
+        let info = self.info().unwrap();
+        metadata.cicp = info.cicp;
+        metadata.mastering_display = info.mastering_display;
+        metadata.content_light = info.content_light_level;
 
         Ok(Some(metadata))
     }
@@ -153,7 +161,8 @@ impl From<zune_png::error::PngDecodeErrors> for ImageErrors {
 
 #[derive(Default)]
 pub struct PngEncoder {
-    options: Option<EncoderOptions>
+    options: Option<EncoderOptions>,
+    text:    Vec<(String, String)>
 }
 
 impl PngEncoder {
@@ -162,9 +171,17 @@ impl PngEncoder {
     }
     pub fn new_with_options(options: EncoderOptions) -> PngEncoder {
         PngEncoder {
-            options: Some(options)
+            options: Some(options),
+            ..Default::default()
         }
     }
+    /// Add a text key and value which will be written with the image
+    ///
+    /// ASCII values are written in tEXt chunks, others in UTF-8 iTXt chunks.
+    /// Text isn't written if the encoder options ask to strip metadata
+    pub fn add_text(&mut self, key: &str, value: &str) {
+        self.text.push((key.to_string(), value.to_string()));
+    }
 }
 
 impl EncoderTrait for PngEncoder {
@@ -177,9 +194,81 @@ impl EncoderTrait for PngEncoder {
     ) -> Result<usize, ImageErrors> {
         let options = create_options_for_encoder(self.options, image);
 
-        let frame = &image.to_u8_be()[0];
+        let frames = image.to_u8_be();
+
+        let mut encoder = zune_png::PngEncoder::new(&frames[0], options);
+
+        if image.is_animated() {
+            let (width, height) = image.dimensions();
+
+            let frame_info = |frame: &Frame| {
+                let (delay_num, delay_denom) = png_delay(frame.numerator, frame.denominator);
 
-        let mut encoder = zune_png::PngEncoder::new(frame, options);
+                FrameInfo {
+                    seq_number: 0,
+                    width,
+                    height,
+                    x_offset: 0,
+                    y_offset: 0,
+                    delay_num,
+                    delay_denom,
+                    dispose_op: DisposeOp::None,
+                    blend_op: BlendOp::Source,
+                    is_part_of_seq: true
+                }
+            };
+            encoder.set_frame_info(frame_info(&image.frames_ref()[0]));
+
+            for (pixels, frame) in frames.iter().zip(image.frames_ref()).skip(1) {
+                encoder.add_frame(pixels, frame_info(frame));
+            }
+            // frames are fully drawn, so only the area that changed needs to be stored
+            encoder.set_crop_frames(true);
+        }
+
+        // color information is kept even when stripping metadata,
+        // as the image would display differently without it
+        let metadata = image.metadata();
+
+        if let Some(icc) = metadata.icc_chunk() {
+            encoder.add_icc_profile(icc);
+        }
+        if let Some(gamma) = metadata.default_gamma {
+            encoder.set_gamma(gamma);
+        }
+        match metadata.color_trc() {
+            Some(ColorCharacteristics::sRGB) => {
+                encoder.set_srgb_intent(RenderingIntent::Perceptual);
+            }
+            Some(ColorCharacteristics::Linear) if metadata.default_gamma.is_none() => {
+                encoder.set_gamma(1.0);
+            }
+            _ => ()
+        }
+        if let Some(cicp) = metadata.cicp() {
+            encoder.set_cicp(cicp);
+        }
+        if let Some(mastering_display) = metadata.mastering_display() {
+            encoder.set_mastering_display(mastering_display);
+        }
+        if let Some(content_light) = metadata.content_light_level() {
+            encoder.set_content_light_level(content_light);
+        }
+        if !options.strip_metadata() {
+            for (key, value) in &self.text {
+                if value.is_ascii() {
+                    encoder.add_text_chunk(TextChunk {
+                        keyword: key.as_bytes().to_vec(),
+                        text:    value.as_bytes().to_vec()
+                    });
+                } else {
+                    encoder.add_itxt_chunk(ItxtChunk {
+                        keyword: key.as_bytes().to_vec(),
+                        text:    value.as_bytes().to_vec()
+                    });
+                }
+            }
+        }
 
         #[allow(unused_mut)]
         let mut buf: Cursor<Vec<u8>> = std::io::Cursor::new(vec![]);
@@ -235,4 +324,87 @@ impl EncoderTrait for PngEncoder {
     fn set_options(&mut self, opts: EncoderOptions) {
         self.options = Some(opts)
     }
+
+    fn supports_animated_images(&self) -> bool {
+        true
+    }
+}
+
+/// Convert a frame delay to the 16 bit fraction stored in fcTL chunks,
+/// losing precision if it doesn't fit
+fn png_delay(mut numerator: usize, mut denominator: usize) -> (u16, u16) {
+    let max = usize::from(u16::MAX);
+
+    while numerator > max || denominator > max {
+        numerator /= 2;
+        denominator /= 2;
+    }
+    (numerator as u16, denominator as u16)
+}
+
+#[test]
+fn test_png_keeps_color_metadata() {
+    use zune_core::bytestream::ZCursor;
+    use zune_core::options::DecoderOptions;
+
+    let mut image = Image::fill(100_u8, ColorSpace::RGB, 8, 4);
+    let profile: Vec<u8> = (0..200).map(|x| x as u8).collect();
+
+    image.metadata_mut().set_icc_chunk(profile.clone());
+    image.metadata_mut().set_default_gamma(0.45455);
+
+    let mut encoder = PngEncoder::new();
+    encoder.add_text("Title", "Colors");
+
+    let mut sink = vec![];
+    encoder.encode(&image, &mut sink).unwrap();
+
+    let mut decoder = zune_png::PngDecoder::new(ZCursor::new(&sink));
+    decoder.decode_headers().unwrap();
+
+    let info = decoder.info().unwrap();
+    assert_eq!(info.icc_profile.as_ref(), Some(&profile));
+    assert_eq!(info.text_chunk[0].text, b"Colors");
+
+    let decoded = Image::read(ZCursor::new(&sink), DecoderOptions::default()).unwrap();
+    assert_eq!(decoded.metadata().icc_chunk(), Some(&profile));
+    assert_eq!(decoded.metadata().default_gamma, Some(0.45455));
+}
+
+#[test]
+fn test_png_keeps_hdr_metadata() {
+    use zune_core::bytestream::ZCursor;
+    use zune_core::colorspace::{
+        CodingIndependentCodePoints, ContentLightLevel, MasteringDisplayColorVolume
+    };
+    use zune_core::options::DecoderOptions;
+
+    let mut image = Image::fill(1000_u16, ColorSpace::RGB, 8, 4);
+    // BT.2100 HLG
+    let cicp = CodingIndependentCodePoints {
+        color_primaries:          9,
+        transfer_characteristics: CodingIndependentCodePoints::TRANSFER_HLG,
+        matrix_coefficients:      0,
+        full_range:               true
+    };
+    let mdcv = MasteringDisplayColorVolume {
+        max_luminance: 1000 * 10000,
+        min_luminance: 50,
+        ..Default::default()
+    };
+    let clli = ContentLightLevel {
+        max_content_light_level:       1000,
+        max_frame_average_light_level: 200
+    };
+    image.metadata_mut().set_cicp(cicp);
+    image.metadata_mut().set_mastering_display(mdcv);
+    image.metadata_mut().set_content_light_level(clli);
+
+    let mut sink = vec![];
+    PngEncoder::new().encode(&image, &mut sink).unwrap();
+
+    let decoded = Image::read(ZCursor::new(&sink), DecoderOptions::default()).unwrap();
+    assert_eq!(decoded.metadata().cicp(), Some(cicp));
+    assert_eq!(decoded.metadata().mastering_display(), Some(mdcv));
+    assert_eq!(decoded.metadata().content_light_level(), Some(clli));
 }
@@ -21,7 +21,7 @@
 //!| Feature      | Decoder       | Encoder        |
 //!|--------------|---------------|----------------|
 //!| bmp          | zune-bmp      |     -          |
-//!| jpeg         | zune-jpeg     | [jpeg-encoder] |
+//!| jpeg         | zune-jpeg     | zune-jpeg      |
 //!| png          | zune-png      | zune-png       |
 //!| ppm          | zune-ppm      | zune-ppm       |
 //!| qoi          | zune-qoi      | zune-qoi       |
@@ -86,7 +86,6 @@
 //!
 //!
 //![image]:https://crates.io/crates/image
-//! [jpeg-encoder]: https://crates.io/crates/jpeg-encoder
 //! [jxl-oxide]: https://crates.io/crates/jxl-oxide
 #![allow(
     clippy::redundant_field_names,
@@ -12,7 +12,10 @@
 //! from one image to another
 
 use zune_core::bit_depth::BitDepth;
-use zune_core::colorspace::{ColorCharacteristics, ColorSpace};
+use zune_core::colorspace::{
+    CodingIndependentCodePoints, ColorCharacteristics, ColorSpace, ContentLightLevel,
+    MasteringDisplayColorVolume
+};
 
 use crate::codecs::ImageFormat;
 
@@ -36,17 +39,20 @@ pub enum AlphaState {
 pub struct ImageMetadata {
     // REMEMBER: If you add a field here add it's serialization
     // to mod file
-    pub(crate) color_trc:     Option<ColorCharacteristics>,
-    pub(crate) default_gamma: Option<f32>,
-    pub(crate) width:         usize,
-    pub(crate) height:        usize,
-    pub(crate) colorspace:    ColorSpace,
-    pub(crate) depth:         BitDepth,
-    pub(crate) format:        Option<ImageFormat>,
-    pub(crate) alpha:         AlphaState,
+    pub(crate) color_trc:         Option<ColorCharacteristics>,
+    pub(crate) default_gamma:     Option<f32>,
+    pub(crate) width:             usize,
+    pub(crate) height:            usize,
+    pub(crate) colorspace:        ColorSpace,
+    pub(crate) depth:             BitDepth,
+    pub(crate) format:            Option<ImageFormat>,
+    pub(crate) alpha:             AlphaState,
     #[cfg(feature = "metadata")]
-    pub(crate) exif:          Option<Vec<::exif::Field>>,
-    pub(crate) icc_chunk:     Option<Vec<u8>>
+    pub(crate) exif:              Option<Vec<::exif::Field>>,
+    pub(crate) icc_chunk:         Option<Vec<u8>>,
+    pub(crate) cicp:              Option<CodingIndependentCodePoints>,
+    pub(crate) mastering_display: Option<MasteringDisplayColorVolume>,
+    pub(crate) content_light:     Option<ContentLightLevel>
 }
 
 impl Default for ImageMetadata {
@@ -63,7 +69,10 @@ impl Default for ImageMetadata {
             #[cfg(feature = "metadata")]
             exif: None,
 
-            icc_chunk: None
+            icc_chunk:         None,
+            cicp:              None,
+            mastering_display: None,
+            content_light:     None
         }
     }
 }
@@ -208,4 +217,31 @@ impl ImageMetadata {
     pub fn icc_chunk(&self) -> Option<&Vec<u8>> {
         self.icc_chunk.as_ref()
     }
+    /// Get the coding independent code points of the image
+    ///
+    /// These describe the color primaries and transfer function
+    /// of e.g HDR images using PQ or HLG
+    pub const fn cicp(&self) -> Option<CodingIndependentCodePoints> {
+        self.cicp
+    }
+    /// Set the coding independent code points of the image
+    pub fn set_cicp(&mut self, cicp: CodingIndependentCodePoints) {
+        self.cicp = Some(cicp);
+    }
+    /// Get the mastering display color volume of the image
+    pub const fn mastering_display(&self) -> Option<MasteringDisplayColorVolume> {
+        self.mastering_display
+    }
+    /// Set the mastering display color volume of the image
+    pub fn set_mastering_display(&mut self, mastering_display: MasteringDisplayColorVolume) {
+        self.mastering_display = Some(mastering_display);
+    }
+    /// Get the content light level information of the image
+    pub const fn content_light_level(&self) -> Option<ContentLightLevel> {
+        self.content_light
+    }
+    /// Set the content light level information of the image
+    pub fn set_content_light_level(&mut self, content_light: ContentLightLevel) {
+        self.content_light = Some(content_light);
+    }
 }
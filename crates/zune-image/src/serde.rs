@@ -21,7 +21,7 @@ impl Serialize for ImageMetadata {
     where
         S: Serializer
     {
-        const STRUCT_FIELDS: usize = 7;
+        const STRUCT_FIELDS: usize = 10;
         let mut state = serializer.serialize_struct("Metadata", STRUCT_FIELDS)?;
 
         state.serialize_field("width", &self.width)?;
@@ -31,6 +31,9 @@ impl Serialize for ImageMetadata {
         state.serialize_field("format", &self.format)?;
         state.serialize_field("color_transfer_characteristics", &self.color_trc)?;
         state.serialize_field("gamma_value", &self.default_gamma)?;
+        state.serialize_field("cicp", &self.cicp)?;
+        state.serialize_field("mastering_display", &self.mastering_display)?;
+        state.serialize_field("content_light_level", &self.content_light)?;
 
         #[cfg(feature = "metadata")]
         {
@@ -46,3 +46,48 @@ fn test_fractal() {
         .unwrap();
     image.save_to("a.ppm", ImageFormat::PPM).unwrap()
 }
+
+#[test]
+#[cfg(all(feature = "metadata", feature = "png", feature = "jpeg"))]
+fn test_strip_metadata() {
+    use zune_core::bytestream::ZCursor;
+    use zune_core::colorspace::ColorSpace;
+    use zune_core::options::EncoderOptions;
+
+    use crate::codecs::jpeg::JpegEncoder;
+    use crate::codecs::png::PngEncoder;
+    use crate::image::Image;
+    use crate::traits::EncoderTrait;
+
+    // big endian TIFF header with a single orientation entry
+    let exif = b"MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0";
+
+    let mut image = Image::fill(100_u8, ColorSpace::RGB, 16, 16);
+    image.metadata_mut().parse_raw_exif(exif);
+
+    for strip in [false, true] {
+        let options = EncoderOptions::default().set_strip_metadata(strip);
+
+        let mut encoder = PngEncoder::new_with_options(options);
+        encoder.add_text("Title", "Stripped");
+
+        let mut png = vec![];
+        encoder.encode(&image, &mut png).unwrap();
+
+        let mut decoder = zune_png::PngDecoder::new(ZCursor::new(&png));
+        decoder.decode_headers().unwrap();
+
+        let info = decoder.info().unwrap();
+        assert_eq!(info.exif.is_some(), !strip);
+        assert_eq!(info.text_chunk.is_empty(), strip);
+
+        let mut jpeg = vec![];
+        JpegEncoder::new_with_options(options)
+            .encode(&image, &mut jpeg)
+            .unwrap();
+
+        let mut decoder = zune_jpeg::JpegDecoder::new(ZCursor::new(&jpeg));
+        decoder.decode_headers().unwrap();
+        assert_eq!(decoder.exif().is_some(), !strip);
+    }
+}
@@ -33,6 +33,9 @@ use crate::transpose::Transpose;
 ///
 /// If orientation is applied, it will also modify the exif tag to indicate
 /// the image was oriented
+///
+/// This works on decoded pixels, so JPEG images lose quality when they are
+/// encoded again, `zune_jpeg::JpegTransformer` orients them losslessly instead.
 pub struct AutoOrient;
 
 impl OperationsTrait for AutoOrient {
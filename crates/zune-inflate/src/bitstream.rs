@@ -11,6 +11,8 @@
 //! This module provides an interface to read and write bits (and bytes) for
 //! huffman
 
+use alloc::vec::Vec;
+
 pub struct BitStreamReader<'src> {
     // buffer from which we are pulling in bits from
     // used in decompression.
@@ -173,3 +175,69 @@ impl<'src> BitStreamReader<'src> {
         self.src.len().saturating_sub(self.position)
     }
 }
+
+/// A bit writer that packs bits from the least significant bit upwards
+/// as required by deflate.
+///
+/// Bits are accumulated in a 64 bit buffer and flushed to the output
+/// vector 32 bits at a time.
+pub struct BitStreamWriter {
+    // bytes written so far
+    pub output:     Vec<u8>,
+    buffer:         u64,
+    bits_in_buffer: u8
+}
+
+impl BitStreamWriter {
+    /// Create a new writer which appends to `output`
+    pub fn new(output: Vec<u8>) -> BitStreamWriter {
+        BitStreamWriter {
+            output,
+            buffer: 0,
+            bits_in_buffer: 0
+        }
+    }
+    /// Write the lower `num_bits` bits of `value`
+    ///
+    /// # Expectations
+    /// `num_bits` must not be greater than 32 and `value` must not have
+    /// bits set above `num_bits`
+    #[inline(always)]
+    pub fn put_bits(&mut self, value: u32, num_bits: u8) {
+        debug_assert!(num_bits <= 32);
+        debug_assert!(num_bits == 32 || value >> num_bits == 0);
+
+        self.buffer |= u64::from(value) << self.bits_in_buffer;
+        self.bits_in_buffer += num_bits;
+
+        if self.bits_in_buffer >= 32 {
+            self.output
+                .extend_from_slice(&(self.buffer as u32).to_le_bytes());
+            self.buffer >>= 32;
+            self.bits_in_buffer -= 32;
+        }
+    }
+    /// Flush all whole bytes in the bit buffer, padding the last partial
+    /// byte with zeroes, after this call the stream is byte aligned.
+    pub fn align_to_byte(&mut self) {
+        while self.bits_in_buffer > 0 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits_in_buffer = self.bits_in_buffer.saturating_sub(8);
+        }
+        self.buffer = 0;
+    }
+    /// Write bytes directly to the output
+    ///
+    /// # Expectations
+    /// The stream must be byte aligned, see [`align_to_byte`](Self::align_to_byte)
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        debug_assert_eq!(self.bits_in_buffer, 0);
+        self.output.extend_from_slice(bytes);
+    }
+    /// Align the stream and return the written bytes
+    pub fn finish(mut self) -> Vec<u8> {
+        self.align_to_byte();
+        self.output
+    }
+}
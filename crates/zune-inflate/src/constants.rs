@@ -160,15 +160,74 @@ pub const FASTCOPY_BYTES: usize = 16;
 /// fastloop.
 pub const FASTLOOP_MAX_BYTES_WRITTEN: usize = 6 + DEFLATE_MAX_MATCH_LEN + (2 * FASTCOPY_BYTES);
 
-/// Max Size of a deflate compressed block
-///
-/// offsets are limited to this length hence it is the maximum allowed
-pub const DEFLATE_MAX_BLOCK_SIZE: usize = 1 << 15;
 /// Minimum supported match length for deflate
 pub const DEFLATE_MIN_LENGTH: usize = 3;
-/// Size for which the match fidner should not try to find matches
-/// if we are this close to the end
-pub const DEFLATE_WINDOW_SIZE: usize = 10;
 
-/// Max sequnces expected to be found in a block
-pub const MAX_SEQ_PER_BLOCK: usize = DEFLATE_MAX_BLOCK_SIZE / 3;
+/// Symbol marking the end of a block in the litlen alphabet
+pub const DEFLATE_END_OF_BLOCK: usize = 256;
+
+/// Maximum distance a match can reference backwards
+pub const DEFLATE_MAX_MATCH_OFFSET: usize = 32768;
+
+/// Base match length for each length slot, i.e litlen symbols 257..285
+pub static DEFLATE_LENGTH_SLOT_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258
+];
+
+/// Number of extra bits following each length slot
+pub static DEFLATE_EXTRA_LENGTH_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0
+];
+
+/// Base match offset for each offset slot
+pub static DEFLATE_OFFSET_SLOT_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577
+];
+
+/// Number of extra bits following each offset slot
+pub static DEFLATE_EXTRA_OFFSET_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13
+];
+
+/// Map a match length (3..=258) to its length slot, the litlen symbol is
+/// the slot plus 257
+pub static DEFLATE_LENGTH_SLOT: [u8; DEFLATE_MAX_MATCH_LEN + 1] = make_length_slot_table();
+
+const fn make_length_slot_table() -> [u8; DEFLATE_MAX_MATCH_LEN + 1] {
+    let mut table = [0; DEFLATE_MAX_MATCH_LEN + 1];
+    let mut slot = 0;
+
+    while slot < 28 {
+        let base = DEFLATE_LENGTH_SLOT_BASE[slot] as usize;
+        let count = 1 << DEFLATE_EXTRA_LENGTH_BITS[slot];
+        let mut i = 0;
+
+        while i < count {
+            table[base + i] = slot as u8;
+            i += 1;
+        }
+        slot += 1;
+    }
+    // 258 has its own slot, which overrides slot 27's last entry
+    table[DEFLATE_MAX_MATCH_LEN] = 28;
+
+    table
+}
+
+/// Get the offset slot for a match offset (1..=32768)
+#[inline(always)]
+pub const fn deflate_offset_slot(offset: usize) -> usize {
+    let distance = (offset - 1) as u32;
+
+    if distance < 4 {
+        distance as usize
+    } else {
+        // position of the highest set bit and the bit immediately below it
+        // determine the slot
+        let log2 = 31 - distance.leading_zeros();
+        (2 * log2 + ((distance >> (log2 - 1)) & 1)) as usize
+    }
+}
@@ -15,13 +15,13 @@ use crate::bitstream::BitStreamReader;
 use crate::constants::{
     DEFLATE_BLOCKTYPE_DYNAMIC_HUFFMAN, DEFLATE_BLOCKTYPE_RESERVED, DEFLATE_BLOCKTYPE_STATIC,
     DEFLATE_BLOCKTYPE_UNCOMPRESSED, DEFLATE_MAX_CODEWORD_LENGTH,
-    DEFLATE_MAX_LITLEN_CODEWORD_LENGTH, DEFLATE_MAX_NUM_SYMS, DEFLATE_MAX_OFFSET_CODEWORD_LENGTH,
-    DEFLATE_MAX_PRE_CODEWORD_LEN, DEFLATE_NUM_LITLEN_SYMS, DEFLATE_NUM_OFFSET_SYMS,
-    DEFLATE_NUM_PRECODE_SYMS, DEFLATE_PRECODE_LENS_PERMUTATION, DELFATE_MAX_LENS_OVERRUN,
-    FASTCOPY_BYTES, FASTLOOP_MAX_BYTES_WRITTEN, HUFFDEC_END_OF_BLOCK, HUFFDEC_EXCEPTIONAL,
-    HUFFDEC_LITERAL, HUFFDEC_SUITABLE_POINTER, LITLEN_DECODE_BITS, LITLEN_DECODE_RESULTS,
-    LITLEN_ENOUGH, LITLEN_TABLE_BITS, OFFSET_DECODE_RESULTS, OFFSET_ENOUGH, OFFSET_TABLEBITS,
-    PRECODE_DECODE_RESULTS, PRECODE_ENOUGH, PRECODE_TABLE_BITS
+    DEFLATE_MAX_LITLEN_CODEWORD_LENGTH, DEFLATE_MAX_MATCH_OFFSET, DEFLATE_MAX_NUM_SYMS,
+    DEFLATE_MAX_OFFSET_CODEWORD_LENGTH, DEFLATE_MAX_PRE_CODEWORD_LEN, DEFLATE_NUM_LITLEN_SYMS,
+    DEFLATE_NUM_OFFSET_SYMS, DEFLATE_NUM_PRECODE_SYMS, DEFLATE_PRECODE_LENS_PERMUTATION,
+    DELFATE_MAX_LENS_OVERRUN, FASTCOPY_BYTES, FASTLOOP_MAX_BYTES_WRITTEN, HUFFDEC_END_OF_BLOCK,
+    HUFFDEC_EXCEPTIONAL, HUFFDEC_LITERAL, HUFFDEC_SUITABLE_POINTER, LITLEN_DECODE_BITS,
+    LITLEN_DECODE_RESULTS, LITLEN_ENOUGH, LITLEN_TABLE_BITS, OFFSET_DECODE_RESULTS, OFFSET_ENOUGH,
+    OFFSET_TABLEBITS, PRECODE_DECODE_RESULTS, PRECODE_ENOUGH, PRECODE_TABLE_BITS
 };
 use crate::errors::{DecodeErrorStatus, InflateDecodeErrors};
 #[cfg(feature = "gzip")]
@@ -250,6 +250,48 @@ impl<'a> DeflateDecoder<'a> {
     ///
     #[cfg(feature = "zlib")]
     pub fn decode_zlib(&mut self) -> Result<Vec<u8>, InflateDecodeErrors> {
+        self.decode_zlib_inner(None)
+    }
+
+    /// Decode zlib-encoded data that was compressed with a preset dictionary
+    ///
+    /// The dictionary's adler32 checksum is compared with the dictionary id stored in the
+    /// stream's header, and the dictionary is then used as history for the
+    /// first matches of the stream.
+    ///
+    /// If the stream does not use a preset dictionary, `dictionary` is ignored
+    /// and this behaves like [decode_zlib](Self::decode_zlib)
+    ///
+    /// # Arguments
+    /// - `dictionary`: The dictionary used when compressing the data
+    ///
+    /// # Returns
+    /// Result type containing the decoded data, see [decode_zlib](Self::decode_zlib)
+    ///
+    /// # Example
+    /// ```
+    /// use zune_inflate::{DeflateDecoder, DeflateEncoder};
+    /// let dictionary = b"{\"width\":, \"height\":, \"format\":}";
+    /// let data = b"{\"width\":200, \"height\":100, \"format\":\"png\"}";
+    ///
+    /// let compressed = DeflateEncoder::new(data).encode_zlib_with_dictionary(dictionary);
+    /// let decompressed = DeflateDecoder::new(&compressed)
+    ///     .decode_zlib_with_dictionary(dictionary)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(&decompressed, data);
+    /// ```
+    #[cfg(feature = "zlib")]
+    pub fn decode_zlib_with_dictionary(
+        &mut self, dictionary: &[u8]
+    ) -> Result<Vec<u8>, InflateDecodeErrors> {
+        self.decode_zlib_inner(Some(dictionary))
+    }
+
+    #[cfg(feature = "zlib")]
+    fn decode_zlib_inner(
+        &mut self, dictionary: Option<&[u8]>
+    ) -> Result<Vec<u8>, InflateDecodeErrors> {
         use crate::utils::calc_adler_hash;
 
         if self.data.len()
@@ -272,8 +314,8 @@ impl<'a> DeflateDecoder<'a> {
         let cinfo = cmf >> 4;
 
         // let fcheck = flg & 0xF;
-        // let fdict = (flg >> 4) & 1;
-        // let flevel = flg >> 5;
+        let fdict = (flg >> 5) & 1;
+        // let flevel = flg >> 6;
 
         // confirm we have the right deflate methods
         if cm != 8 {
@@ -303,7 +345,38 @@ impl<'a> DeflateDecoder<'a> {
 
         self.position = 2;
 
-        let data = self.decode_deflate()?;
+        let mut history: &[u8] = &[];
+
+        if fdict == 1 {
+            // the adler32 of the dictionary follows the header
+            let Some(id_bytes) = self.data.get(2..6) else {
+                return Err(InflateDecodeErrors::new_with_error(
+                    DecodeErrorStatus::InsufficientData
+                ));
+            };
+            let Some(dictionary) = dictionary else {
+                return Err(InflateDecodeErrors::new_with_error(
+                    DecodeErrorStatus::Generic(
+                        "Stream needs a preset dictionary, use decode_zlib_with_dictionary"
+                    )
+                ));
+            };
+            let id_expected = u32::from_be_bytes(id_bytes.try_into().unwrap());
+            let id_found = calc_adler_hash(dictionary);
+
+            if id_expected != id_found {
+                return Err(InflateDecodeErrors::new_with_error(
+                    DecodeErrorStatus::GenericStr(format!(
+                        "Preset dictionary id mismatch, expected {id_expected} but found {id_found}"
+                    ))
+                ));
+            }
+            // only the last 32 KiB can be referenced by matches
+            history = &dictionary[dictionary.len().saturating_sub(DEFLATE_MAX_MATCH_OFFSET)..];
+            self.position = 6;
+        }
+
+        let data = self.decode_deflate_with_history(history)?;
 
         if self.options.confirm_checksum {
             // Get number of consumed bytes from the input
@@ -351,6 +424,10 @@ impl<'a> DeflateDecoder<'a> {
     /// It's possible to recover bytes even after an error occurred, bytes up
     /// to when error was encountered are stored in [InflateDecodeErrors]
     ///
+    /// Like `gzip -d`, concatenated gzip members are decoded one after the other
+    /// and their output is joined, anything after the last member that
+    /// does not start with a gzip header is ignored.
+    ///
     /// # Note
     /// This needs the `gzip` feature enabled to be available, otherwise it's a
     /// compile time error
@@ -359,7 +436,32 @@ impl<'a> DeflateDecoder<'a> {
     ///
     #[cfg(feature = "gzip")]
     pub fn decode_gzip(&mut self) -> Result<Vec<u8>, InflateDecodeErrors> {
-        if self.data.len() < 18 {
+        let mut data = self.decode_gzip_member()?;
+
+        while self.data.get(self.position..self.position + 2) == Some(&[GZIP_ID1, GZIP_ID2]) {
+            match self.decode_gzip_member() {
+                Ok(member) => data.extend_from_slice(&member),
+                Err(mut error) => {
+                    data.extend_from_slice(&error.data);
+                    error.data = data;
+
+                    return Err(error);
+                }
+            }
+            if data.len() > self.options.limit {
+                let err = DecodeErrorStatus::OutputLimitExceeded(self.options.limit, data.len());
+
+                return Err(InflateDecodeErrors::new(err, data));
+            }
+        }
+        Ok(data)
+    }
+
+    /// Decode a single gzip member starting at `self.position`, on success
+    /// `self.position` points to the byte after the member's trailer
+    #[cfg(feature = "gzip")]
+    fn decode_gzip_member(&mut self) -> Result<Vec<u8>, InflateDecodeErrors> {
+        if self.data.len().saturating_sub(self.position) < 18 {
             return Err(InflateDecodeErrors::new_with_error(
                 DecodeErrorStatus::InsufficientData
             ));
@@ -504,6 +606,7 @@ impl<'a> DeflateDecoder<'a> {
 
             return Err(err);
         }
+        self.position = out_pos + 4;
 
         Ok(data)
     }
@@ -531,12 +634,32 @@ impl<'a> DeflateDecoder<'a> {
     ///
     ///  [InflateDecodeErrors]:crate::errors::InflateDecodeErrors
     pub fn decode_deflate(&mut self) -> Result<Vec<u8>, InflateDecodeErrors> {
-        self.start_deflate_block()
+        self.start_deflate_block(&[])
+    }
+    /// Decode a deflate stream whose matches may reference `history`,
+    /// the history is not part of the returned data
+    #[cfg(feature = "zlib")]
+    fn decode_deflate_with_history(
+        &mut self, history: &[u8]
+    ) -> Result<Vec<u8>, InflateDecodeErrors> {
+        match self.start_deflate_block(history) {
+            Ok(mut data) => {
+                data.drain(..history.len());
+                Ok(data)
+            }
+            Err(mut error) => {
+                let prefix = history.len().min(error.data.len());
+                error.data.drain(..prefix);
+                Err(error)
+            }
+        }
     }
     /// Main inner loop for decompressing deflate data
     #[allow(unused_assignments)]
     #[allow(clippy::never_loop)] // wrong submission
-    fn start_deflate_block(&mut self) -> Result<Vec<u8>, InflateDecodeErrors> {
+    ///
+    /// `history` is placed before the output, matches may reference it
+    fn start_deflate_block(&mut self, history: &[u8]) -> Result<Vec<u8>, InflateDecodeErrors> {
         // start deflate decode
         // re-read the stream so that we can remove code read by zlib
         self.stream = BitStreamReader::new(&self.data[self.position..]);
@@ -544,11 +667,12 @@ impl<'a> DeflateDecoder<'a> {
         self.stream.refill();
 
         // Output space for our decoded bytes.
-        let mut out_block = vec![0; self.options.size_hint];
+        let mut out_block = vec![0; self.options.size_hint + history.len()];
+        out_block[..history.len()].copy_from_slice(history);
         // bits used
 
         let mut src_offset = 0;
-        let mut dest_offset = 0;
+        let mut dest_offset = history.len();
 
         loop {
             self.stream.refill();
@@ -613,21 +737,24 @@ impl<'a> DeflateDecoder<'a> {
 
                     return Err(error);
                 }
-                if dest_offset > self.options.limit {
+
+                out_block[dest_offset..dest_offset + len]
+                    .copy_from_slice(&self.data[start..start + len]);
+
+                dest_offset += len;
+
+                if dest_offset - history.len() > self.options.limit {
                     out_block.truncate(dest_offset);
 
-                    let err_msg =
-                        DecodeErrorStatus::OutputLimitExceeded(self.options.limit, out_block.len());
+                    let err_msg = DecodeErrorStatus::OutputLimitExceeded(
+                        self.options.limit,
+                        dest_offset - history.len()
+                    );
                     let error = InflateDecodeErrors::new(err_msg, out_block);
 
                     return Err(error);
                 }
 
-                out_block[dest_offset..dest_offset + len]
-                    .copy_from_slice(&self.data[start..start + len]);
-
-                dest_offset += len;
-
                 // get the new position to write.
                 self.stream.position =
                     len + (self.stream.position - usize::from(self.stream.bits_left >> 3));
@@ -959,12 +1086,12 @@ impl<'a> DeflateDecoder<'a> {
                             }
                         }
 
-                        if dest_offset > self.options.limit {
+                        if dest_offset - history.len() > self.options.limit {
                             out_block.truncate(dest_offset);
 
                             let err_msg = DecodeErrorStatus::OutputLimitExceeded(
                                 self.options.limit,
-                                dest_offset
+                                dest_offset - history.len()
                             );
                             let error = InflateDecodeErrors::new(err_msg, out_block);
 
@@ -1080,11 +1207,13 @@ impl<'a> DeflateDecoder<'a> {
 
                     dest_offset += length;
 
-                    if dest_offset > self.options.limit {
+                    if dest_offset - history.len() > self.options.limit {
                         out_block.truncate(dest_offset);
 
-                        let err_msg =
-                            DecodeErrorStatus::OutputLimitExceeded(self.options.limit, dest_offset);
+                        let err_msg = DecodeErrorStatus::OutputLimitExceeded(
+                            self.options.limit,
+                            dest_offset - history.len()
+                        );
                         let error = InflateDecodeErrors::new(err_msg, out_block);
 
                         return Err(error);
@@ -1174,7 +1303,7 @@ impl<'a> DeflateDecoder<'a> {
                 precode_lens[usize::from(*i)] = bits;
             }
 
-            self.build_decode_table_inner(
+            build_decode_table_inner(
                 &precode_lens,
                 &PRECODE_DECODE_RESULTS,
                 &mut precode_decode_table,
@@ -1293,7 +1422,7 @@ impl<'a> DeflateDecoder<'a> {
             num_offset_syms = 32;
         }
         // build offset decode table
-        self.build_decode_table_inner(
+        build_decode_table_inner(
             &lens[num_litlen_syms..],
             &OFFSET_DECODE_RESULTS,
             &mut offset_decode_table,
@@ -1302,7 +1431,7 @@ impl<'a> DeflateDecoder<'a> {
             DEFLATE_MAX_OFFSET_CODEWORD_LENGTH
         )?;
 
-        self.build_decode_table_inner(
+        build_decode_table_inner(
             &lens,
             &LITLEN_DECODE_RESULTS,
             &mut litlen_decode_table,
@@ -1316,309 +1445,307 @@ impl<'a> DeflateDecoder<'a> {
 
         Ok(())
     }
-    /// Build the decode table for the precode
-    #[allow(clippy::needless_range_loop)]
-    fn build_decode_table_inner(
-        &mut self, lens: &[u8], decode_results: &[u32], decode_table: &mut [u32],
-        table_bits: usize, num_syms: usize, mut max_codeword_len: usize
-    ) -> Result<(), DecodeErrorStatus> {
-        const BITS: u32 = usize::BITS - 1;
-
-        let mut len_counts: [u32; DEFLATE_MAX_CODEWORD_LENGTH + 1] =
-            [0; DEFLATE_MAX_CODEWORD_LENGTH + 1];
-        let mut offsets: [u32; DEFLATE_MAX_CODEWORD_LENGTH + 1] =
-            [0; DEFLATE_MAX_CODEWORD_LENGTH + 1];
-        let mut sorted_syms: [u16; DEFLATE_MAX_NUM_SYMS] = [0; DEFLATE_MAX_NUM_SYMS];
-
-        let mut i;
-
-        // count how many codewords have each length, including 0.
-        for sym in 0..num_syms {
-            len_counts[usize::from(lens[sym])] += 1;
-        }
-
-        /*
-         * Determine the actual maximum codeword length that was used, and
-         * decrease table_bits to it if allowed.
-         */
-        while max_codeword_len > 1 && len_counts[max_codeword_len] == 0 {
-            max_codeword_len -= 1;
-        }
-        /*
-         * Sort the symbols primarily by increasing codeword length and
-         *	A temporary array of length @num_syms.
-         * secondarily by increasing symbol value; or equivalently by their
-         * codewords in lexicographic order, since a canonical code is assumed.
-         *
-         * For efficiency, also compute 'codespace_used' in the same pass over
-         * 'len_counts[]' used to build 'offsets[]' for sorting.
-         */
-        offsets[0] = 0;
-        offsets[1] = len_counts[0];
+}
 
-        let mut codespace_used = 0_u32;
+/// Build a decode table from codeword lengths, used for the precode,
+/// litlen and offset codes
+#[allow(clippy::needless_range_loop)]
+pub(crate) fn build_decode_table_inner(
+    lens: &[u8], decode_results: &[u32], decode_table: &mut [u32], table_bits: usize,
+    num_syms: usize, mut max_codeword_len: usize
+) -> Result<(), DecodeErrorStatus> {
+    const BITS: u32 = usize::BITS - 1;
+
+    let mut len_counts: [u32; DEFLATE_MAX_CODEWORD_LENGTH + 1] =
+        [0; DEFLATE_MAX_CODEWORD_LENGTH + 1];
+    let mut offsets: [u32; DEFLATE_MAX_CODEWORD_LENGTH + 1] = [0; DEFLATE_MAX_CODEWORD_LENGTH + 1];
+    let mut sorted_syms: [u16; DEFLATE_MAX_NUM_SYMS] = [0; DEFLATE_MAX_NUM_SYMS];
+
+    let mut i;
+
+    // count how many codewords have each length, including 0.
+    for sym in 0..num_syms {
+        len_counts[usize::from(lens[sym])] += 1;
+    }
 
-        for len in 1..max_codeword_len {
-            offsets[len + 1] = offsets[len] + len_counts[len];
-            codespace_used = (codespace_used << 1) + len_counts[len];
-        }
-        codespace_used = (codespace_used << 1) + len_counts[max_codeword_len];
+    /*
+     * Determine the actual maximum codeword length that was used, and
+     * decrease table_bits to it if allowed.
+     */
+    while max_codeword_len > 1 && len_counts[max_codeword_len] == 0 {
+        max_codeword_len -= 1;
+    }
+    /*
+     * Sort the symbols primarily by increasing codeword length and
+     *	A temporary array of length @num_syms.
+     * secondarily by increasing symbol value; or equivalently by their
+     * codewords in lexicographic order, since a canonical code is assumed.
+     *
+     * For efficiency, also compute 'codespace_used' in the same pass over
+     * 'len_counts[]' used to build 'offsets[]' for sorting.
+     */
+    offsets[0] = 0;
+    offsets[1] = len_counts[0];
+
+    let mut codespace_used = 0_u32;
+
+    for len in 1..max_codeword_len {
+        offsets[len + 1] = offsets[len] + len_counts[len];
+        codespace_used = (codespace_used << 1) + len_counts[len];
+    }
+    codespace_used = (codespace_used << 1) + len_counts[max_codeword_len];
 
-        for sym in 0..num_syms {
-            let pos = usize::from(lens[sym]);
-            sorted_syms[offsets[pos] as usize] = sym as u16;
-            offsets[pos] += 1;
-        }
-        i = (offsets[0]) as usize;
+    for sym in 0..num_syms {
+        let pos = usize::from(lens[sym]);
+        sorted_syms[offsets[pos] as usize] = sym as u16;
+        offsets[pos] += 1;
+    }
+    i = (offsets[0]) as usize;
+
+    /*
+     * Check whether the lengths form a complete code (exactly fills the
+     * codespace), an incomplete code (doesn't fill the codespace), or an
+     * overfull code (overflows the codespace).  A codeword of length 'n'
+     * uses proportion '1/(2^n)' of the codespace.  An overfull code is
+     * nonsensical, so is considered invalid.  An incomplete code is
+     * considered valid only in two specific cases; see below.
+     */
+
+    // Overfull code
+    if codespace_used > 1 << max_codeword_len {
+        return Err(DecodeErrorStatus::Generic("Overflown code"));
+    }
+    // incomplete code
+    if codespace_used < 1 << max_codeword_len {
+        let entry = if codespace_used == 0 {
+            /*
+             * An empty code is allowed.  This can happen for the
+             * offset code in DEFLATE, since a dynamic Huffman block
+             * need not contain any matches.
+             */
 
+            /* sym=0, len=1 (arbitrary) */
+            make_decode_table_entry(decode_results, 0, 1)
+        } else {
+            /*
+             * Allow codes with a single used symbol, with codeword
+             * length 1.  The DEFLATE RFC is unclear regarding this
+             * case.  What zlib's decompressor does is permit this
+             * for the litlen and offset codes and assume the
+             * codeword is '0' rather than '1'.  We do the same
+             * except we allow this for precodes too, since there's
+             * no convincing reason to treat the codes differently.
+             * We also assign both codewords '0' and '1' to the
+             * symbol to avoid having to handle '1' specially.
+             */
+            if codespace_used != 1 << (max_codeword_len - 1) || len_counts[1] != 1 {
+                return Err(DecodeErrorStatus::Generic(
+                    "Cannot work with empty pre-code table"
+                ));
+            }
+            make_decode_table_entry(decode_results, usize::from(sorted_syms[i]), 1)
+        };
         /*
-         * Check whether the lengths form a complete code (exactly fills the
-         * codespace), an incomplete code (doesn't fill the codespace), or an
-         * overfull code (overflows the codespace).  A codeword of length 'n'
-         * uses proportion '1/(2^n)' of the codespace.  An overfull code is
-         * nonsensical, so is considered invalid.  An incomplete code is
-         * considered valid only in two specific cases; see below.
+         * Note: the decode table still must be fully initialized, in
+         * case the stream is malformed and contains bits from the part
+         * of the codespace the incomplete code doesn't use.
          */
+        decode_table.fill(entry);
+        return Ok(());
+    }
 
-        // Overfull code
-        if codespace_used > 1 << max_codeword_len {
-            return Err(DecodeErrorStatus::Generic("Overflown code"));
+    /*
+     * The lengths form a complete code.  Now, enumerate the codewords in
+     * lexicographic order and fill the decode table entries for each one.
+     *
+     * First, process all codewords with len <= table_bits.  Each one gets
+     * '2^(table_bits-len)' direct entries in the table.
+     *
+     * Since DEFLATE uses bit-reversed codewords, these entries aren't
+     * consecutive but rather are spaced '2^len' entries apart.  This makes
+     * filling them naively somewhat awkward and inefficient, since strided
+     * stores are less cache-friendly and preclude the use of word or
+     * vector-at-a-time stores to fill multiple entries per instruction.
+     *
+     * To optimize this, we incrementally double the table size.  When
+     * processing codewords with length 'len', the table is treated as
+     * having only '2^len' entries, so each codeword uses just one entry.
+     * Then, each time 'len' is incremented, the table size is doubled and
+     * the first half is copied to the second half.  This significantly
+     * improves performance over naively doing strided stores.
+     *
+     * Note that some entries copied for each table doubling may not have
+     * been initialized yet, but it doesn't matter since they're guaranteed
+     * to be initialized later (because the Huffman code is complete).
+     */
+    let mut codeword = 0;
+    let mut len = 1;
+    let mut count = len_counts[1];
+
+    while count == 0 {
+        len += 1;
+
+        if len >= len_counts.len() {
+            break;
         }
-        // incomplete code
-        if codespace_used < 1 << max_codeword_len {
-            let entry = if codespace_used == 0 {
-                /*
-                 * An empty code is allowed.  This can happen for the
-                 * offset code in DEFLATE, since a dynamic Huffman block
-                 * need not contain any matches.
-                 */
+        count = len_counts[len];
+    }
 
-                /* sym=0, len=1 (arbitrary) */
-                make_decode_table_entry(decode_results, 0, 1)
-            } else {
-                /*
-                 * Allow codes with a single used symbol, with codeword
-                 * length 1.  The DEFLATE RFC is unclear regarding this
-                 * case.  What zlib's decompressor does is permit this
-                 * for the litlen and offset codes and assume the
-                 * codeword is '0' rather than '1'.  We do the same
-                 * except we allow this for precodes too, since there's
-                 * no convincing reason to treat the codes differently.
-                 * We also assign both codewords '0' and '1' to the
-                 * symbol to avoid having to handle '1' specially.
-                 */
-                if codespace_used != 1 << (max_codeword_len - 1) || len_counts[1] != 1 {
-                    return Err(DecodeErrorStatus::Generic(
-                        "Cannot work with empty pre-code table"
-                    ));
+    let mut curr_table_end = 1 << len;
+
+    while len <= table_bits {
+        // Process all count codewords with length len
+        loop {
+            let entry =
+                make_decode_table_entry(decode_results, usize::from(sorted_syms[i]), len as u32);
+            i += 1;
+            // fill first entry for current codeword
+            decode_table[codeword] = entry;
+
+            if codeword == curr_table_end - 1 {
+                // last codeword (all 1's)
+                for _ in len..table_bits {
+                    decode_table.copy_within(0..curr_table_end, curr_table_end);
+
+                    curr_table_end <<= 1;
                 }
-                make_decode_table_entry(decode_results, usize::from(sorted_syms[i]), 1)
-            };
+                return Ok(());
+            }
             /*
-             * Note: the decode table still must be fully initialized, in
-             * case the stream is malformed and contains bits from the part
-             * of the codespace the incomplete code doesn't use.
+             * To advance to the lexicographically next codeword in
+             * the canonical code, the codeword must be incremented,
+             * then 0's must be appended to the codeword as needed
+             * to match the next codeword's length.
+             *
+             * Since the codeword is bit-reversed, appending 0's is
+             * a no-op.  However, incrementing it is nontrivial.  To
+             * do so efficiently, use the 'bsr' instruction to find
+             * the last (highest order) 0 bit in the codeword, set
+             * it, and clear any later (higher order) 1 bits.  But
+             * 'bsr' actually finds the highest order 1 bit, so to
+             * use it first flip all bits in the codeword by XOR' ing
+             * it with (1U << len) - 1 == cur_table_end - 1.
              */
-            decode_table.fill(entry);
-            return Ok(());
-        }
 
-        /*
-         * The lengths form a complete code.  Now, enumerate the codewords in
-         * lexicographic order and fill the decode table entries for each one.
-         *
-         * First, process all codewords with len <= table_bits.  Each one gets
-         * '2^(table_bits-len)' direct entries in the table.
-         *
-         * Since DEFLATE uses bit-reversed codewords, these entries aren't
-         * consecutive but rather are spaced '2^len' entries apart.  This makes
-         * filling them naively somewhat awkward and inefficient, since strided
-         * stores are less cache-friendly and preclude the use of word or
-         * vector-at-a-time stores to fill multiple entries per instruction.
-         *
-         * To optimize this, we incrementally double the table size.  When
-         * processing codewords with length 'len', the table is treated as
-         * having only '2^len' entries, so each codeword uses just one entry.
-         * Then, each time 'len' is incremented, the table size is doubled and
-         * the first half is copied to the second half.  This significantly
-         * improves performance over naively doing strided stores.
-         *
-         * Note that some entries copied for each table doubling may not have
-         * been initialized yet, but it doesn't matter since they're guaranteed
-         * to be initialized later (because the Huffman code is complete).
-         */
-        let mut codeword = 0;
-        let mut len = 1;
-        let mut count = len_counts[1];
+            let adv = BITS - (codeword ^ (curr_table_end - 1)).leading_zeros();
+            let bit = 1 << adv;
 
-        while count == 0 {
-            len += 1;
+            codeword &= bit - 1;
+            codeword |= bit;
+            count -= 1;
 
-            if len >= len_counts.len() {
+            if count == 0 {
                 break;
             }
-            count = len_counts[len];
         }
+        // advance to the next codeword length
+        loop {
+            len += 1;
 
-        let mut curr_table_end = 1 << len;
-
-        while len <= table_bits {
-            // Process all count codewords with length len
-            loop {
-                let entry = make_decode_table_entry(
-                    decode_results,
-                    usize::from(sorted_syms[i]),
-                    len as u32
-                );
-                i += 1;
-                // fill first entry for current codeword
-                decode_table[codeword] = entry;
-
-                if codeword == curr_table_end - 1 {
-                    // last codeword (all 1's)
-                    for _ in len..table_bits {
-                        decode_table.copy_within(0..curr_table_end, curr_table_end);
-
-                        curr_table_end <<= 1;
-                    }
-                    return Ok(());
-                }
-                /*
-                 * To advance to the lexicographically next codeword in
-                 * the canonical code, the codeword must be incremented,
-                 * then 0's must be appended to the codeword as needed
-                 * to match the next codeword's length.
-                 *
-                 * Since the codeword is bit-reversed, appending 0's is
-                 * a no-op.  However, incrementing it is nontrivial.  To
-                 * do so efficiently, use the 'bsr' instruction to find
-                 * the last (highest order) 0 bit in the codeword, set
-                 * it, and clear any later (higher order) 1 bits.  But
-                 * 'bsr' actually finds the highest order 1 bit, so to
-                 * use it first flip all bits in the codeword by XOR' ing
-                 * it with (1U << len) - 1 == cur_table_end - 1.
-                 */
-
-                let adv = BITS - (codeword ^ (curr_table_end - 1)).leading_zeros();
-                let bit = 1 << adv;
+            if len <= table_bits {
+                // dest is decode_table[curr_table_end]
+                // source is decode_table(start of table);
+                // size is curr_table;
 
-                codeword &= bit - 1;
-                codeword |= bit;
-                count -= 1;
+                decode_table.copy_within(0..curr_table_end, curr_table_end);
 
-                if count == 0 {
-                    break;
-                }
+                //decode_table.copy_within(range, curr_table_end);
+                curr_table_end <<= 1;
             }
-            // advance to the next codeword length
-            loop {
-                len += 1;
-
-                if len <= table_bits {
-                    // dest is decode_table[curr_table_end]
-                    // source is decode_table(start of table);
-                    // size is curr_table;
-
-                    decode_table.copy_within(0..curr_table_end, curr_table_end);
-
-                    //decode_table.copy_within(range, curr_table_end);
-                    curr_table_end <<= 1;
-                }
-                count = len_counts[len];
+            count = len_counts[len];
 
-                if count != 0 {
-                    break;
-                }
+            if count != 0 {
+                break;
             }
         }
-        // process codewords with len > table_bits.
-        // Require sub-tables
-        curr_table_end = 1 << table_bits;
+    }
+    // process codewords with len > table_bits.
+    // Require sub-tables
+    curr_table_end = 1 << table_bits;
 
-        let mut subtable_prefix = usize::MAX;
-        let mut subtable_start = 0;
-        let mut subtable_bits;
+    let mut subtable_prefix = usize::MAX;
+    let mut subtable_start = 0;
+    let mut subtable_bits;
+
+    loop {
+        /*
+         * Start a new sub-table if the first 'table_bits' bits of the
+         * codeword don't match the prefix of the current subtable.
+         */
+        if codeword & ((1_usize << table_bits) - 1) != subtable_prefix {
+            subtable_prefix = codeword & ((1 << table_bits) - 1);
+            subtable_start = curr_table_end;
 
-        loop {
             /*
-             * Start a new sub-table if the first 'table_bits' bits of the
-             * codeword don't match the prefix of the current subtable.
+             * Calculate the subtable length.  If the codeword has
+             * length 'table_bits + n', then the subtable needs
+             * '2^n' entries.  But it may need more; if fewer than
+             * '2^n' codewords of length 'table_bits + n' remain,
+             * then the length will need to be incremented to bring
+             * in longer codewords until the subtable can be
+             * completely filled.  Note that because the Huffman
+             * code is complete, it will always be possible to fill
+             * the sub-table eventually.
              */
-            if codeword & ((1_usize << table_bits) - 1) != subtable_prefix {
-                subtable_prefix = codeword & ((1 << table_bits) - 1);
-                subtable_start = curr_table_end;
-
-                /*
-                 * Calculate the subtable length.  If the codeword has
-                 * length 'table_bits + n', then the subtable needs
-                 * '2^n' entries.  But it may need more; if fewer than
-                 * '2^n' codewords of length 'table_bits + n' remain,
-                 * then the length will need to be incremented to bring
-                 * in longer codewords until the subtable can be
-                 * completely filled.  Note that because the Huffman
-                 * code is complete, it will always be possible to fill
-                 * the sub-table eventually.
-                 */
-                subtable_bits = len - table_bits;
-                codespace_used = count;
+            subtable_bits = len - table_bits;
+            codespace_used = count;
 
-                while codespace_used < (1 << subtable_bits) {
-                    subtable_bits += 1;
+            while codespace_used < (1 << subtable_bits) {
+                subtable_bits += 1;
 
-                    if subtable_bits + table_bits > 15 {
-                        return Err(DecodeErrorStatus::CorruptData);
-                    }
-
-                    codespace_used = (codespace_used << 1) + len_counts[table_bits + subtable_bits];
+                if subtable_bits + table_bits > 15 {
+                    return Err(DecodeErrorStatus::CorruptData);
                 }
 
-                /*
-                 * Create the entry that points from the main table to
-                 * the subtable.
-                 */
-                decode_table[subtable_prefix] = (subtable_start as u32) << 16
-                    | HUFFDEC_EXCEPTIONAL
-                    | HUFFDEC_SUITABLE_POINTER
-                    | (subtable_bits as u32) << 8
-                    | table_bits as u32;
-
-                curr_table_end = subtable_start + (1 << subtable_bits);
+                codespace_used = (codespace_used << 1) + len_counts[table_bits + subtable_bits];
             }
 
-            /* Fill the sub-table entries for the current codeword. */
+            /*
+             * Create the entry that points from the main table to
+             * the subtable.
+             */
+            decode_table[subtable_prefix] = (subtable_start as u32) << 16
+                | HUFFDEC_EXCEPTIONAL
+                | HUFFDEC_SUITABLE_POINTER
+                | (subtable_bits as u32) << 8
+                | table_bits as u32;
 
-            let stride = 1 << (len - table_bits);
+            curr_table_end = subtable_start + (1 << subtable_bits);
+        }
 
-            let mut j = subtable_start + (codeword >> table_bits);
+        /* Fill the sub-table entries for the current codeword. */
 
-            let entry = make_decode_table_entry(
-                decode_results,
-                sorted_syms[i] as usize,
-                (len - table_bits) as u32
-            );
-            i += 1;
+        let stride = 1 << (len - table_bits);
 
-            while j < curr_table_end {
-                decode_table[j] = entry;
-                j += stride;
-            }
-            //advance to the next codeword
-            if codeword == (1 << len) - 1 {
-                // last codeword
-                return Ok(());
-            }
+        let mut j = subtable_start + (codeword >> table_bits);
 
-            let adv = BITS - (codeword ^ ((1 << len) - 1)).leading_zeros();
-            let bit = 1 << adv;
+        let entry = make_decode_table_entry(
+            decode_results,
+            sorted_syms[i] as usize,
+            (len - table_bits) as u32
+        );
+        i += 1;
 
-            codeword &= bit - 1;
-            codeword |= bit;
-            count -= 1;
+        while j < curr_table_end {
+            decode_table[j] = entry;
+            j += stride;
+        }
+        //advance to the next codeword
+        if codeword == (1 << len) - 1 {
+            // last codeword
+            return Ok(());
+        }
 
-            while count == 0 {
-                len += 1;
-                count = len_counts[len];
-            }
+        let adv = BITS - (codeword ^ ((1 << len) - 1)).leading_zeros();
+        let bit = 1 << adv;
+
+        codeword &= bit - 1;
+        codeword |= bit;
+        count -= 1;
+
+        while count == 0 {
+            len += 1;
+            count = len_counts[len];
         }
     }
 }
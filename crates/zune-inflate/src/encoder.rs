@@ -5,238 +5,619 @@
  */
 #![allow(dead_code)]
 
-use alloc::boxed::Box;
-use alloc::vec;
 use alloc::vec::Vec;
 
-use crate::constants::{DEFLATE_BLOCKTYPE_UNCOMPRESSED, MAX_SEQ_PER_BLOCK};
-
+use crate::bitstream::BitStreamWriter;
+#[cfg(feature = "zlib")]
+use crate::constants::DEFLATE_MAX_MATCH_OFFSET;
+use crate::encoder::block_writer::{write_block, write_stored_blocks};
+use crate::encoder::hc_matchfinder::{compress_block, HcMatchFinder};
+#[cfg(feature = "gzip")]
+use crate::gzip_constants::{
+    GZIP_CM_DEFLATE, GZIP_FCOMMENT, GZIP_FEXTRA, GZIP_FNAME, GZIP_ID1, GZIP_ID2, GZIP_OS_UNKNOWN,
+    GZIP_XFL_FASTEST, GZIP_XFL_SLOWEST
+};
+
+mod block_writer;
 mod hc_matchfinder;
+mod huffman;
+mod optimal;
+mod parallel;
 
-const _SEQ_LENGTH_SHIFT: u32 = 23;
-
-const _SEQ_LITRUNLEN_MASK: u32 = (1_u32 << _SEQ_LENGTH_SHIFT) - 1;
+/// Maximum number of input bytes covered by a single block
+///
+/// Smaller blocks adapt faster to changing data, larger
+/// blocks amortize the cost of the block header
+const MAX_BLOCK_LENGTH: usize = 1 << 16;
 
 #[derive(Default, Copy, Clone)]
 pub struct MatchSequence {
+    /// Start of the literal run
     pub start: usize,
+    /// Number of literals before the match
     pub ll:    usize,
+    /// Match length, zero if there is no match after the literals
     pub ml:    usize,
+    /// Match offset
     pub ol:    usize
 }
 
-pub(crate) struct _Sequence {
-    /*
-     * Bits 0..22: the number of literals in this run.  This may be 0 and
-     * can be at most MAX_BLOCK_LENGTH.  The literals are not stored
-     * explicitly in this structure; instead, they are read directly from
-     * the uncompressed data.
-     *
-     * Bits 23..31: the length of the match which follows the literals, or 0
-     * if this literal run was the last in the block, so there is no match
-     * which follows it.
-     */
-    litrunlen_and_length: u32
-}
-
 pub struct EncodedSequences {
-    pub literals: Box<[u32; MAX_SEQ_PER_BLOCK]>,
-    pub matches:  Box<[u32; MAX_SEQ_PER_BLOCK]>,
-    pub offsets:  Box<[u32; MAX_SEQ_PER_BLOCK]>,
-
-    pub current_pos: usize
+    pub sequences: Vec<MatchSequence>
 }
 
 impl EncodedSequences {
     /// Add a new encoded sequence
     pub fn add(&mut self, seq: MatchSequence) {
-        self.literals[self.current_pos % MAX_SEQ_PER_BLOCK] = seq.ll as u32;
-        self.matches[self.current_pos % MAX_SEQ_PER_BLOCK] = seq.ml as u32;
-        self.offsets[self.current_pos % MAX_SEQ_PER_BLOCK] = seq.ol as u32;
-
-        self.current_pos += 1;
+        self.sequences.push(seq);
     }
     /// Create a new encoder
     pub fn new() -> EncodedSequences {
-        let t1 = vec![0_u32; MAX_SEQ_PER_BLOCK].into_boxed_slice();
-        let t2 = vec![0_u32; MAX_SEQ_PER_BLOCK].into_boxed_slice();
-        let t3 = vec![0_u32; MAX_SEQ_PER_BLOCK].into_boxed_slice();
-
         EncodedSequences {
-            literals:    Box::try_from(t1).unwrap(),
-            matches:     Box::try_from(t2).unwrap(),
-            offsets:     Box::try_from(t3).unwrap(),
-            current_pos: 0
+            sequences: Vec::with_capacity(MAX_BLOCK_LENGTH / 4)
         }
     }
     /// Reset the sequences
     pub fn clear(&mut self) {
-        self.current_pos = 0;
+        self.sequences.clear();
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// How the encoder searches for matches
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum DeflateEncodingStrategy {
-    NoCompression
-}
-
-impl DeflateEncodingStrategy {
-    #[allow(dead_code)]
-    fn to_level(self) -> u8 {
-        match self {
-            Self::NoCompression => 0
-        }
-    }
+    /// Do not look for matches, write data in stored blocks
+    NoCompression,
+    /// Take the longest match found at each position
+    Greedy,
+    /// Before taking a match, check whether the next position
+    /// has a longer one, and if so, take that instead
+    Lazy,
+    /// Find the cheapest mix of literals and matches using
+    /// iterative optimal parsing, see
+    /// [`DeflateEncodingOptions::set_optimal_iterations`]
+    Optimal
 }
 
+/// Options that influence compression
+/// in Deflate/Zlib
+///
+/// To use them, pass a customized options to
+/// the deflate encoder.
+#[derive(Debug, Copy, Clone)]
 pub struct DeflateEncodingOptions {
-    strategy: DeflateEncodingStrategy
+    level:        u8,
+    strategy:     DeflateEncodingStrategy,
+    search_depth: usize,
+    nice_length:  usize,
+    num_threads:  usize,
+    iterations:   usize
 }
 
 impl Default for DeflateEncodingOptions {
     fn default() -> Self {
         DeflateEncodingOptions {
-            strategy: DeflateEncodingStrategy::NoCompression
+            level:        0,
+            strategy:     DeflateEncodingStrategy::NoCompression,
+            search_depth: 0,
+            nice_length:  0,
+            num_threads:  1,
+            iterations:   0
+        }
+        .set_level(6)
+    }
+}
+
+impl DeflateEncodingOptions {
+    /// Get the compression level
+    pub const fn get_level(&self) -> u8 {
+        self.level
+    }
+    /// Set the compression level
+    ///
+    /// Levels go from 0 to 9, 0 stores the data uncompressed,
+    /// 1 is the fastest and 9 produces the smallest output.
+    /// Levels above 9 are treated as 9
+    ///
+    /// The default level is 6
+    ///
+    /// # Arguments
+    /// - level: The new compression level
+    #[must_use]
+    pub fn set_level(mut self, level: u8) -> Self {
+        // (strategy, search depth, nice length)
+        let (strategy, search_depth, nice_length) = match level {
+            0 => (DeflateEncodingStrategy::NoCompression, 0, 0),
+            1 => (DeflateEncodingStrategy::Greedy, 2, 8),
+            2 => (DeflateEncodingStrategy::Greedy, 6, 10),
+            3 => (DeflateEncodingStrategy::Greedy, 12, 14),
+            4 => (DeflateEncodingStrategy::Greedy, 16, 30),
+            5 => (DeflateEncodingStrategy::Lazy, 16, 30),
+            6 => (DeflateEncodingStrategy::Lazy, 35, 65),
+            7 => (DeflateEncodingStrategy::Lazy, 100, 130),
+            8 => (DeflateEncodingStrategy::Lazy, 300, 258),
+            _ => (DeflateEncodingStrategy::Lazy, 600, 258)
+        };
+        self.level = level.min(9);
+        self.strategy = strategy;
+        self.search_depth = search_depth;
+        self.nice_length = nice_length;
+        self
+    }
+    /// Get the match finding strategy used by the current options
+    pub const fn get_strategy(&self) -> DeflateEncodingStrategy {
+        if self.iterations > 0 {
+            return DeflateEncodingStrategy::Optimal;
         }
+        self.strategy
+    }
+    /// Get the number of optimal parsing iterations, zero if
+    /// optimal parsing is disabled
+    pub const fn get_optimal_iterations(&self) -> usize {
+        self.iterations
+    }
+    /// Enable optimal parsing, which produces the smallest output this
+    /// encoder can make at a large cost in speed, this is meant for
+    /// data that is compressed once and decompressed many times.
+    ///
+    /// The data is split into blocks at points where its statistics change,
+    /// and each block is parsed by finding the cheapest mix of literals
+    /// and matches given the symbol costs of the previous parse, `iterations`
+    /// times or until the parse stops changing.
+    ///
+    /// When enabled this overrides the level's strategy, though the level is
+    /// still used for the gzip and zlib header hints.
+    ///
+    /// Zero disables optimal parsing, which is the default, around 15 iterations
+    /// gets most of the gains.
+    ///
+    /// # Arguments
+    /// - iterations: Maximum number of times each block is re-parsed
+    #[must_use]
+    pub const fn set_optimal_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+    /// Get the number of threads used for compression
+    pub const fn get_num_threads(&self) -> usize {
+        self.num_threads
+    }
+    /// Set the number of threads used for compression
+    ///
+    /// When greater than 1, the input is split into chunks which are
+    /// compressed on separate threads and joined into a single stream.
+    /// Each chunk can still reference the 32 KiB before it, so the
+    /// output is only slightly larger than single threaded compression.
+    ///
+    /// Small inputs are always compressed on the calling thread.
+    ///
+    /// This requires the `std` feature, without it compression
+    /// is always single threaded.
+    ///
+    /// The default is 1
+    ///
+    /// # Arguments
+    /// - threads: Number of threads, 0 is treated as 1
+    #[must_use]
+    pub const fn set_num_threads(mut self, threads: usize) -> Self {
+        self.num_threads = if threads == 0 { 1 } else { threads };
+        self
+    }
+    /// Return the compression level hint stored in the zlib header
+    ///
+    /// See RFC 1950, `FLEVEL`
+    fn zlib_level_hint(&self) -> u16 {
+        if self.iterations > 0 {
+            return 3;
+        }
+        match self.level {
+            0 | 1 => 0,
+            2..=5 => 1,
+            6 => 2,
+            _ => 3
+        }
+    }
+}
+
+/// Optional fields written to a gzip header
+///
+/// All fields are empty by default, resulting in the smallest
+/// possible header.
+///
+/// See [RFC 1952](https://www.rfc-editor.org/rfc/rfc1952#section-2.3) for
+/// what each field means
+#[cfg(feature = "gzip")]
+#[derive(Debug, Clone, Default)]
+pub struct GzipHeader {
+    filename: Option<Vec<u8>>,
+    comment:  Option<Vec<u8>>,
+    extra:    Option<Vec<u8>>,
+    mtime:    u32
+}
+
+#[cfg(feature = "gzip")]
+impl GzipHeader {
+    /// Get the original file name (FNAME)
+    pub fn get_filename(&self) -> Option<&[u8]> {
+        self.filename.as_deref()
+    }
+    /// Set the original file name (FNAME)
+    ///
+    /// The name should be ISO 8859-1 (LATIN-1) encoded, it is stored
+    /// zero terminated hence anything after a zero byte is dropped
+    #[must_use]
+    pub fn set_filename(mut self, filename: impl AsRef<[u8]>) -> Self {
+        self.filename = Some(until_zero(filename.as_ref()));
+        self
+    }
+    /// Get the file comment (FCOMMENT)
+    pub fn get_comment(&self) -> Option<&[u8]> {
+        self.comment.as_deref()
+    }
+    /// Set the file comment (FCOMMENT)
+    ///
+    /// Like the file name, the comment is stored zero terminated hence
+    /// anything after a zero byte is dropped
+    #[must_use]
+    pub fn set_comment(mut self, comment: impl AsRef<[u8]>) -> Self {
+        self.comment = Some(until_zero(comment.as_ref()));
+        self
+    }
+    /// Get the extra field (FEXTRA)
+    pub fn get_extra(&self) -> Option<&[u8]> {
+        self.extra.as_deref()
+    }
+    /// Set the extra field (FEXTRA)
+    ///
+    /// The field holds subfields, each made of a two byte id,
+    /// a two byte little endian length and the data, the bytes are
+    /// written as is.
+    ///
+    /// The field length is stored in two bytes, hence only the
+    /// first 65535 bytes are kept
+    #[must_use]
+    pub fn set_extra(mut self, extra: &[u8]) -> Self {
+        self.extra = Some(extra[..extra.len().min(usize::from(u16::MAX))].to_vec());
+        self
+    }
+    /// Get the modification time (MTIME)
+    pub const fn get_mtime(&self) -> u32 {
+        self.mtime
+    }
+    /// Set the modification time (MTIME) of the original file
+    /// in seconds since the unix epoch
+    ///
+    /// Zero means no time stamp is available, this is the default
+    #[must_use]
+    pub const fn set_mtime(mut self, mtime: u32) -> Self {
+        self.mtime = mtime;
+        self
     }
 }
 
-/// A simple Deflate Encoder.
+#[cfg(feature = "gzip")]
+fn until_zero(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().take_while(|x| **x != 0).copied().collect()
+}
+
+/// A deflate encoder.
+///
+/// The encoder compresses a whole buffer at once, finding matches
+/// with hash chains and encoding them in stored, static or dynamic huffman blocks,
+/// whichever is the smallest.
+///
+/// # Example
+/// ```
+/// use zune_inflate::{DeflateDecoder, DeflateEncoder, DeflateEncodingOptions};
+/// let data = b"Hello, hello, hello, hello world";
+///
+/// let options = DeflateEncodingOptions::default().set_level(9);
+/// let compressed = DeflateEncoder::new_with_options(data, options).encode_zlib();
 ///
-/// Not yet complete
+/// let decompressed = DeflateDecoder::new(&compressed).decode_zlib().unwrap();
+/// assert_eq!(&decompressed, data);
+/// ```
 pub struct DeflateEncoder<'a> {
-    data:            &'a [u8],
-    options:         DeflateEncodingOptions,
-    output_position: usize,
-    input_position:  usize,
-    output:          Vec<u8>
+    data:    &'a [u8],
+    options: DeflateEncodingOptions
 }
 
 impl<'a> DeflateEncoder<'a> {
-    /// Create a new deflate encoder.
+    /// Create a new deflate encoder with the default options
     ///
-    /// The
+    /// # Arguments
+    /// - `data`: The data to compress
     pub fn new(data: &'a [u8]) -> DeflateEncoder<'a> {
         DeflateEncoder::new_with_options(data, DeflateEncodingOptions::default())
     }
+    /// Create a new deflate encoder with the specified options
+    ///
+    /// # Arguments
+    /// - `data`: The data to compress
+    /// - `options`: Options that influence compression
     pub fn new_with_options(data: &'a [u8], options: DeflateEncodingOptions) -> DeflateEncoder<'a> {
-        let length = data.len() + 1024;
-        let out_array = vec![0; length];
-
-        DeflateEncoder {
-            data,
-            options,
-            output_position: 0,
-            input_position: 0,
-            output: out_array
-        }
+        DeflateEncoder { data, options }
     }
 
-    #[cfg(feature = "zlib")]
-    fn write_zlib_header(&mut self) {
-        const ZLIB_CM_DEFLATE: u16 = 8;
-        const ZLIB_CINFO_32K_WINDOW: u16 = 7;
-
-        let level_hint = self.options.strategy.to_level();
-
-        let mut hdr = (ZLIB_CM_DEFLATE << 8) | (ZLIB_CINFO_32K_WINDOW << 12);
-
-        hdr |= u16::from(level_hint) << 6;
-        hdr |= 31 - (hdr % 31);
-
-        self.output[self.output_position..self.output_position + 2]
-            .copy_from_slice(&hdr.to_be_bytes());
-    }
-    /// Encode a deflate data block with no compression
-    ///
-    /// # Argument
-    /// - `bytes`: number of bytes to compress from input as non-compressed
-    /// bytes
-    fn encode_no_compression(&mut self, bytes: usize) {
-        let final_position = self.input_position + bytes;
-
-        /*
-         * If the input is zero-length, we still must output a block in order
-         * for the output to be a valid DEFLATE stream.  Handle this case
-         * specially to avoid potentially passing NULL to memcpy() below.
-         */
-        if self.data.is_empty() {
-            /* BFINAL and BTYPE */
-            self.output[self.output_position] = (1 | (DEFLATE_BLOCKTYPE_UNCOMPRESSED << 1)) as u8;
-            self.output_position += 1;
-            /* LEN and NLEN */
-            let num: u32 = 0xFFFF0000;
-            self.output[self.output_position..self.output_position + 4]
-                .copy_from_slice(&num.to_le_bytes());
-            self.output_position += 4;
-            return;
-        }
-        loop {
-            let mut bfinal = 0;
-            let mut len = usize::from(u16::MAX);
+    /// Encode a raw deflate stream
+    ///
+    /// # Returns
+    /// The compressed data
+    pub fn encode_deflate(&mut self) -> Vec<u8> {
+        let mut writer = BitStreamWriter::new(Vec::with_capacity(self.data.len() / 2 + 64));
 
-            if final_position - self.input_position <= usize::from(u16::MAX) {
-                bfinal = 1;
-                len = final_position - self.input_position;
-            }
-            /*
-             * Output BFINAL and BTYPE.  The stream is already byte-aligned
-             * here, so this step always requires outputting exactly 1 byte.
-             */
-            self.output[self.output_position] =
-                (bfinal | (DEFLATE_BLOCKTYPE_UNCOMPRESSED << 1)) as u8;
-
-            self.output_position += 1;
-            // output len and nlen
-            let len_u16 = len as u16;
-
-            self.output[self.output_position..self.output_position + 2]
-                .copy_from_slice(&len_u16.to_le_bytes());
-            self.output_position += 2;
-
-            self.output[self.output_position..self.output_position + 2]
-                .copy_from_slice(&(!len_u16).to_le_bytes());
-            self.output_position += 2;
-
-            // copy from input to output
-            self.output[self.output_position..self.output_position + len]
-                .copy_from_slice(&self.data[self.input_position..self.input_position + len]);
-            self.output_position += len;
-            self.input_position += len;
-
-            if self.input_position == final_position {
-                break;
-            }
-        }
-    }
+        compress_stream(&self.options, self.data, 0, &mut writer);
 
-    /// Encode a deflate stream
-    pub fn encode_deflate(&mut self) {
-        match self.options.strategy {
-            DeflateEncodingStrategy::NoCompression => {
-                self.encode_no_compression(self.data.len());
-            }
-        }
+        writer.finish()
     }
 
+    /// Encode a zlib stream, i.e a deflate stream with a zlib header
+    /// and adler32 checksum
+    ///
+    /// # Returns
+    /// The compressed data
     #[cfg(feature = "zlib")]
     pub fn encode_zlib(&mut self) -> Vec<u8> {
-        let extra = 40 * ((self.data.len() + 41) / 40);
-        self.output = vec![0_u8; self.data.len() + extra];
-        self.write_zlib_header();
-        self.output_position = 2;
+        let mut output = Vec::with_capacity(self.data.len() / 2 + 64);
 
-        self.encode_deflate();
+        write_zlib_header(&self.options, &mut output, None);
 
+        let mut writer = BitStreamWriter::new(output);
+
+        compress_stream(&self.options, self.data, 0, &mut writer);
+
+        let mut output = writer.finish();
         // add adler hash
         let hash = crate::utils::calc_adler_hash(self.data);
-        self.output[self.output_position..self.output_position + 4]
-            .copy_from_slice(&hash.to_be_bytes());
-        self.output_position += 4;
+        output.extend_from_slice(&hash.to_be_bytes());
+
+        output
+    }
+
+    /// Encode a zlib stream using a preset dictionary
+    ///
+    /// The dictionary is used as history, so data resembling it
+    /// can be encoded as matches into the dictionary, this helps a lot
+    /// for small inputs with a lot in common.
+    /// Only the last 32 KiB of the dictionary can be referenced.
+    ///
+    /// The stream header stores the adler32 of the dictionary, and the
+    /// same dictionary has to be passed when decoding, see
+    /// [`DeflateDecoder::decode_zlib_with_dictionary`](crate::DeflateDecoder::decode_zlib_with_dictionary)
+    ///
+    /// # Arguments
+    /// - `dictionary`: Bytes likely to appear in the data
+    ///
+    /// # Returns
+    /// The compressed data
+    #[cfg(feature = "zlib")]
+    pub fn encode_zlib_with_dictionary(&mut self, dictionary: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(self.data.len() / 2 + 64);
+
+        let dictionary_id = crate::utils::calc_adler_hash(dictionary);
+
+        write_zlib_header(&self.options, &mut output, Some(dictionary_id));
+
+        // matches can't reach further back than the window
+        let history = &dictionary[dictionary.len().saturating_sub(DEFLATE_MAX_MATCH_OFFSET)..];
+
+        let mut input = Vec::with_capacity(history.len() + self.data.len());
+        input.extend_from_slice(history);
+        input.extend_from_slice(self.data);
 
-        self.output.truncate(self.output_position);
+        let mut writer = BitStreamWriter::new(output);
 
-        core::mem::take(&mut self.output)
+        compress_stream(&self.options, &input, history.len(), &mut writer);
+
+        let mut output = writer.finish();
+        // the checksum covers the data only
+        let hash = crate::utils::calc_adler_hash(self.data);
+        output.extend_from_slice(&hash.to_be_bytes());
+
+        output
+    }
+
+    /// Encode a gzip stream with an empty header
+    ///
+    /// See [`encode_gzip_with_header`](Self::encode_gzip_with_header)
+    /// to write a file name, comment, modification time or extra field
+    ///
+    /// # Returns
+    /// The compressed data
+    #[cfg(feature = "gzip")]
+    pub fn encode_gzip(&mut self) -> Vec<u8> {
+        self.encode_gzip_with_header(&GzipHeader::default())
+    }
+
+    /// Encode a gzip stream, i.e a deflate stream with a gzip header
+    /// and crc32 checksum
+    ///
+    /// # Arguments
+    /// - `header`: Optional header fields to write
+    ///
+    /// # Returns
+    /// The compressed data
+    ///
+    /// # Example
+    /// ```
+    /// use zune_inflate::{DeflateDecoder, DeflateEncoder, GzipHeader};
+    /// let data = b"Hello, hello, hello, hello world";
+    ///
+    /// let header = GzipHeader::default().set_filename("hello.txt").set_mtime(1_700_000_000);
+    /// let compressed = DeflateEncoder::new(data).encode_gzip_with_header(&header);
+    ///
+    /// let decompressed = DeflateDecoder::new(&compressed).decode_gzip().unwrap();
+    /// assert_eq!(&decompressed, data);
+    /// ```
+    #[cfg(feature = "gzip")]
+    pub fn encode_gzip_with_header(&mut self, header: &GzipHeader) -> Vec<u8> {
+        let mut output = Vec::with_capacity(self.data.len() / 2 + 64);
+
+        write_gzip_header(&self.options, header, &mut output);
+
+        let mut writer = BitStreamWriter::new(output);
+
+        compress_stream(&self.options, self.data, 0, &mut writer);
+
+        let mut output = writer.finish();
+        // crc and size of the input modulo 2^32
+        let crc = !crate::crc::crc32(self.data, !0);
+        output.extend_from_slice(&crc.to_le_bytes());
+        output.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+
+        output
+    }
+}
+
+/// Write a zlib header, `dictionary_id` is the adler32 of
+/// the preset dictionary if one is used
+#[cfg(feature = "zlib")]
+pub(crate) fn write_zlib_header(
+    options: &DeflateEncodingOptions, output: &mut Vec<u8>, dictionary_id: Option<u32>
+) {
+    const ZLIB_CM_DEFLATE: u16 = 8;
+    const ZLIB_CINFO_32K_WINDOW: u16 = 7;
+    const ZLIB_FDICT: u16 = 1 << 5;
+
+    let level_hint = options.zlib_level_hint();
+
+    let mut hdr = (ZLIB_CM_DEFLATE << 8) | (ZLIB_CINFO_32K_WINDOW << 12);
+
+    hdr |= level_hint << 6;
+
+    if dictionary_id.is_some() {
+        hdr |= ZLIB_FDICT;
+    }
+    hdr |= 31 - (hdr % 31);
+
+    output.extend_from_slice(&hdr.to_be_bytes());
+
+    if let Some(id) = dictionary_id {
+        output.extend_from_slice(&id.to_be_bytes());
+    }
+}
+
+/// Write a gzip header containing the fields set in `header`
+#[cfg(feature = "gzip")]
+pub(crate) fn write_gzip_header(
+    options: &DeflateEncodingOptions, header: &GzipHeader, output: &mut Vec<u8>
+) {
+    let mut flags = 0;
+
+    if header.extra.is_some() {
+        flags |= GZIP_FEXTRA;
+    }
+    if header.filename.is_some() {
+        flags |= GZIP_FNAME;
+    }
+    if header.comment.is_some() {
+        flags |= GZIP_FCOMMENT;
+    }
+    let extra_flags = match options.level {
+        _ if options.iterations > 0 => GZIP_XFL_SLOWEST,
+        1 => GZIP_XFL_FASTEST,
+        9 => GZIP_XFL_SLOWEST,
+        _ => 0
+    };
+    output.extend_from_slice(&[GZIP_ID1, GZIP_ID2, GZIP_CM_DEFLATE, flags]);
+    output.extend_from_slice(&header.mtime.to_le_bytes());
+    output.extend_from_slice(&[extra_flags, GZIP_OS_UNKNOWN]);
+
+    if let Some(extra) = &header.extra {
+        output.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        output.extend_from_slice(extra);
+    }
+    for field in [&header.filename, &header.comment].into_iter().flatten() {
+        output.extend_from_slice(field);
+        output.push(0);
+    }
+}
+
+/// Write an empty stored block, this byte aligns the stream so that
+/// everything written before it can be decoded, like zlib's `Z_SYNC_FLUSH`
+pub(crate) fn write_sync_flush(writer: &mut BitStreamWriter) {
+    write_stored_blocks(writer, &[], false);
+}
+
+/// Compress `data[start..]` into a complete deflate stream, using
+/// multiple threads if the options allow it
+///
+/// See [`compress_deflate`] for what the arguments mean
+fn compress_stream(
+    options: &DeflateEncodingOptions, data: &[u8], start: usize, writer: &mut BitStreamWriter
+) {
+    #[cfg(feature = "std")]
+    {
+        // with only a chunk or two there is not enough work to share
+        if options.num_threads > 1 && data.len() - start > 2 * parallel::PARALLEL_CHUNK_SIZE {
+            parallel::compress_deflate_parallel(options, data, start, writer);
+            return;
+        }
+    }
+    compress_deflate(options, data, start, true, writer);
+}
+
+/// Compress `data[start..]` into deflate blocks
+///
+/// Bytes before `start` are history, matches may reference them
+/// but they are not written out.
+///
+/// # Arguments
+/// - `options`: Options controlling compression
+/// - `data`: History followed by the data to compress
+/// - `start`: Where the data to compress starts
+/// - `is_final`: Whether the last block written should be marked as the final block
+/// - `writer`: Where to write the compressed blocks to
+pub(crate) fn compress_deflate(
+    options: &DeflateEncodingOptions, data: &[u8], start: usize, is_final: bool,
+    writer: &mut BitStreamWriter
+) {
+    if options.get_strategy() == DeflateEncodingStrategy::NoCompression {
+        write_stored_blocks(writer, &data[start..], is_final);
+        return;
+    }
+    if start == data.len() {
+        // nothing to compress, but the stream needs at least one block
+        write_block(writer, data, start..start, &[], is_final);
+        return;
+    }
+    if options.get_strategy() == DeflateEncodingStrategy::Optimal {
+        optimal::compress_optimal(options.iterations, data, start, is_final, writer);
+        return;
+    }
+    let mut table = HcMatchFinder::new(options.search_depth, options.nice_length);
+    let mut sequences = EncodedSequences::new();
+    let lazy = options.strategy == DeflateEncodingStrategy::Lazy;
+
+    // add the history to the hash chains
+    table.insert_up_to(data, start);
+
+    let mut block_start = start;
+
+    while block_start < data.len() {
+        let block_end = (block_start + MAX_BLOCK_LENGTH).min(data.len());
+
+        sequences.clear();
+
+        let parsed_end = compress_block(
+            data,
+            block_start,
+            block_end,
+            &mut table,
+            lazy,
+            &mut sequences
+        );
+
+        let last_block = parsed_end == data.len();
+
+        write_block(
+            writer,
+            data,
+            block_start..parsed_end,
+            &sequences.sequences,
+            is_final && last_block
+        );
+        block_start = parsed_end;
     }
 }
 
@@ -246,6 +627,11 @@ pub fn v_hash(bytes: &[u8], num_bits: usize, min_length: usize) -> usize {
     debug_assert!(min_length < 8);
 
     match min_length {
+        3 => {
+            const PRIME_BYTES: u32 = 506832829;
+            (u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]).wrapping_mul(PRIME_BYTES)
+                >> (32 - num_bits)) as usize
+        }
         4 => {
             const PRIME_BYTES: u32 = 2654435761;
             (u32::from_le_bytes(bytes[0..4].try_into().unwrap()).wrapping_mul(PRIME_BYTES)
@@ -270,3 +656,155 @@ pub fn v_hash(bytes: &[u8], num_bits: usize, min_length: usize) -> usize {
         }
     }
 }
+
+#[test]
+fn test_encode_roundtrip() {
+    use alloc::vec;
+
+    use crate::DeflateDecoder;
+
+    let mut inputs = vec![vec![], vec![1], vec![7; 1000]];
+    // something with both short and long range repetitions
+    inputs.push(
+        (0..200_000_u32)
+            .map(|x| ((x / 7) % 251) as u8 ^ (x.wrapping_mul(2654435761) >> 31) as u8)
+            .collect()
+    );
+    // and something incompressible
+    inputs.push(
+        (0..70_000_u32)
+            .map(|x| (x.wrapping_mul(2654435761) >> 24) as u8)
+            .collect()
+    );
+    for input in &inputs {
+        for level in 0..=9 {
+            let options = DeflateEncodingOptions::default().set_level(level);
+            let compressed = DeflateEncoder::new_with_options(input, options).encode_deflate();
+            let decompressed = DeflateDecoder::new(&compressed).decode_deflate().unwrap();
+
+            assert_eq!(input, &decompressed, "level {level}");
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_encode_gzip_members() {
+    use alloc::vec;
+
+    use crate::gzip_constants::GZIP_FHCRC;
+    use crate::{DeflateDecoder, DeflateFormat, DeflateStreamDecoder, StreamStatus};
+
+    let first: Vec<u8> = (0..50_000_u32).map(|x| (x % 13) as u8).collect();
+    let second = b"a second member".to_vec();
+
+    let header = GzipHeader::default()
+        .set_filename("first.bin")
+        .set_comment("a comment\0 not written")
+        .set_extra(&[b'Z', b'I', 2, 0, 1, 2])
+        .set_mtime(1234);
+
+    let mut stream = DeflateEncoder::new(&first).encode_gzip_with_header(&header);
+
+    assert_eq!(stream[3] & GZIP_FHCRC, 0);
+    assert_eq!(stream[4..8], 1234_u32.to_le_bytes());
+    assert_eq!(stream[10..12], [6, 0]);
+    assert_eq!(&stream[18..28], b"first.bin\0");
+    assert_eq!(&stream[28..38], b"a comment\0");
+
+    stream.extend_from_slice(&DeflateEncoder::new(&second).encode_gzip());
+    stream.extend_from_slice(&DeflateEncoder::new(&[]).encode_gzip());
+
+    let mut expected = first.clone();
+    expected.extend_from_slice(&second);
+
+    let decoded = DeflateDecoder::new(&stream).decode_gzip().unwrap();
+    assert!(decoded == expected);
+
+    // the streaming decoder should also continue with the next members
+    let mut decoder = DeflateStreamDecoder::new(DeflateFormat::Gzip);
+    let mut output = vec![0; expected.len() + 10];
+    let result = decoder.decode(&stream, &mut output).unwrap();
+
+    assert_eq!(result.status, StreamStatus::Done);
+    assert_eq!(result.consumed, stream.len());
+    assert!(output[..result.written] == expected);
+}
+
+#[cfg(feature = "zlib")]
+#[test]
+fn test_encode_zlib_dictionary() {
+    use alloc::vec;
+
+    use crate::{DeflateDecoder, DeflateFormat, DeflateStreamDecoder};
+
+    let dictionary: Vec<u8> = (0..40_000_u32).map(|x| (x % 7 + x % 11) as u8).collect();
+    let data: Vec<u8> = dictionary[35_000..39_000].to_vec();
+
+    for level in [0, 1, 6, 9] {
+        let options = DeflateEncodingOptions::default().set_level(level);
+        let with_dictionary = DeflateEncoder::new_with_options(&data, options)
+            .encode_zlib_with_dictionary(&dictionary);
+
+        if level != 0 {
+            let without = DeflateEncoder::new_with_options(&data, options).encode_zlib();
+            assert!(with_dictionary.len() < without.len());
+        }
+        let decoded = DeflateDecoder::new(&with_dictionary)
+            .decode_zlib_with_dictionary(&dictionary)
+            .unwrap();
+        assert!(decoded == data, "level {level}");
+
+        // missing or wrong dictionaries are errors
+        assert!(DeflateDecoder::new(&with_dictionary).decode_zlib().is_err());
+        assert!(DeflateDecoder::new(&with_dictionary)
+            .decode_zlib_with_dictionary(&dictionary[1..])
+            .is_err());
+
+        let mut decoder = DeflateStreamDecoder::new(DeflateFormat::Zlib);
+        decoder.set_dictionary(&dictionary);
+
+        let mut output = vec![0; data.len()];
+        let result = decoder.decode(&with_dictionary, &mut output).unwrap();
+
+        assert_eq!(result.written, data.len());
+        assert_eq!(decoder.total_out(), data.len());
+        assert!(output == data);
+    }
+}
+
+#[cfg(feature = "zlib")]
+#[test]
+fn test_zlib_dictionary_limit() {
+    use crate::errors::DecodeErrorStatus;
+    use crate::{DeflateDecoder, DeflateOptions};
+
+    // the dictionary doesn't count against the output limit
+    let dictionary: Vec<u8> = (0..30_000_u32).map(|x| (x % 13 + x % 5) as u8).collect();
+    let data: Vec<u8> = dictionary[10_000..12_000].to_vec();
+
+    for level in [0, 6] {
+        let options = DeflateEncodingOptions::default().set_level(level);
+        let compressed = DeflateEncoder::new_with_options(&data, options)
+            .encode_zlib_with_dictionary(&dictionary);
+
+        let options = DeflateOptions::default().set_limit(10_000);
+        let decoded = DeflateDecoder::new_with_options(&compressed, options)
+            .decode_zlib_with_dictionary(&dictionary)
+            .unwrap();
+        assert!(decoded == data, "level {level}");
+
+        let options = DeflateOptions::default().set_limit(1000);
+        let error = DeflateDecoder::new_with_options(&compressed, options)
+            .decode_zlib_with_dictionary(&dictionary)
+            .unwrap_err();
+
+        match error.error {
+            DecodeErrorStatus::OutputLimitExceeded(limit, size) => {
+                assert_eq!(limit, 1000);
+                assert!(size <= data.len(), "level {level}: {size}");
+            }
+            _ => panic!("level {level}: {error:?}")
+        }
+    }
+}
@@ -0,0 +1,374 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Writing of deflate blocks
+//!
+//! Given a parsed block, we compute the cost of encoding it as a stored,
+//! static huffman and dynamic huffman block and write whichever is the smallest.
+use alloc::vec::Vec;
+
+use crate::bitstream::BitStreamWriter;
+use crate::constants::{
+    deflate_offset_slot, DEFLATE_BLOCKTYPE_DYNAMIC_HUFFMAN, DEFLATE_BLOCKTYPE_STATIC,
+    DEFLATE_BLOCKTYPE_UNCOMPRESSED, DEFLATE_END_OF_BLOCK, DEFLATE_EXTRA_LENGTH_BITS,
+    DEFLATE_EXTRA_OFFSET_BITS, DEFLATE_LENGTH_SLOT, DEFLATE_LENGTH_SLOT_BASE,
+    DEFLATE_MAX_CODEWORD_LENGTH, DEFLATE_MAX_PRE_CODEWORD_LEN, DEFLATE_NUM_LITLEN_SYMS,
+    DEFLATE_NUM_OFFSET_SYMS, DEFLATE_NUM_PRECODE_SYMS, DEFLATE_OFFSET_SLOT_BASE,
+    DEFLATE_PRECODE_LENS_PERMUTATION
+};
+use crate::encoder::huffman::HuffmanCode;
+use crate::encoder::MatchSequence;
+
+/// Maximum number of bytes a single stored block can hold
+const MAX_STORED_BLOCK_LENGTH: usize = u16::MAX as usize;
+
+/// Extra bits for precode symbols 16, 17 and 18
+const PRECODE_EXTRA_BITS: [u8; 3] = [2, 3, 7];
+
+/// Symbol frequencies of a block
+#[derive(Clone)]
+pub struct BlockFrequencies {
+    pub litlen: [u32; DEFLATE_NUM_LITLEN_SYMS],
+    pub offset: [u32; DEFLATE_NUM_OFFSET_SYMS]
+}
+
+impl BlockFrequencies {
+    /// Gather frequencies for the sequences of a block, including the end
+    /// of block symbol
+    pub fn from_sequences(data: &[u8], sequences: &[MatchSequence]) -> BlockFrequencies {
+        let mut freqs = BlockFrequencies {
+            litlen: [0; DEFLATE_NUM_LITLEN_SYMS],
+            offset: [0; DEFLATE_NUM_OFFSET_SYMS]
+        };
+        for seq in sequences {
+            for byte in &data[seq.start..seq.start + seq.ll] {
+                freqs.litlen[usize::from(*byte)] += 1;
+            }
+            if seq.ml != 0 {
+                freqs.litlen[257 + usize::from(DEFLATE_LENGTH_SLOT[seq.ml])] += 1;
+                freqs.offset[deflate_offset_slot(seq.ol)] += 1;
+            }
+        }
+        freqs.litlen[DEFLATE_END_OF_BLOCK] += 1;
+
+        freqs
+    }
+    /// Number of extra bits needed by lengths and offsets
+    fn extra_bits(&self) -> usize {
+        let length_bits: usize = self.litlen[257..257 + DEFLATE_EXTRA_LENGTH_BITS.len()]
+            .iter()
+            .zip(DEFLATE_EXTRA_LENGTH_BITS.iter())
+            .map(|(freq, bits)| *freq as usize * usize::from(*bits))
+            .sum();
+
+        let offset_bits: usize = self
+            .offset
+            .iter()
+            .zip(DEFLATE_EXTRA_OFFSET_BITS.iter())
+            .map(|(freq, bits)| *freq as usize * usize::from(*bits))
+            .sum();
+
+        length_bits + offset_bits
+    }
+}
+
+/// The litlen and offset codes used to encode a huffman block
+#[derive(Clone)]
+pub struct BlockCodes {
+    pub litlen: HuffmanCode<DEFLATE_NUM_LITLEN_SYMS>,
+    pub offset: HuffmanCode<DEFLATE_NUM_OFFSET_SYMS>
+}
+
+impl BlockCodes {
+    /// The fixed codes defined by the deflate specification, RFC 1951 section 3.2.6
+    pub fn static_codes() -> BlockCodes {
+        let mut litlen_lens = [0; DEFLATE_NUM_LITLEN_SYMS];
+
+        litlen_lens[000..144].fill(8);
+        litlen_lens[144..256].fill(9);
+        litlen_lens[256..280].fill(7);
+        litlen_lens[280..288].fill(8);
+
+        BlockCodes {
+            litlen: HuffmanCode::from_lens(litlen_lens),
+            offset: HuffmanCode::from_lens([5; DEFLATE_NUM_OFFSET_SYMS])
+        }
+    }
+    /// Optimal codes for the block frequencies
+    pub fn dynamic_codes(freqs: &BlockFrequencies) -> BlockCodes {
+        BlockCodes {
+            litlen: HuffmanCode::from_freqs(&freqs.litlen, DEFLATE_MAX_CODEWORD_LENGTH),
+            offset: HuffmanCode::from_freqs(&freqs.offset, DEFLATE_MAX_CODEWORD_LENGTH)
+        }
+    }
+    /// Number of bits needed to encode the block data, excluding the block header
+    pub fn data_cost(&self, freqs: &BlockFrequencies) -> usize {
+        self.litlen.cost(&freqs.litlen) + self.offset.cost(&freqs.offset) + freqs.extra_bits()
+    }
+}
+
+/// A dynamic block header, i.e the run length encoded codeword lengths and
+/// the precode used to compress them
+struct DynamicHeader {
+    num_litlen_syms:  usize,
+    num_offset_syms:  usize,
+    num_precode_syms: usize,
+    /// run length encoded lengths, as a (precode symbol, extra bits) pair
+    items:            Vec<(u8, u8)>,
+    precode:          HuffmanCode<DEFLATE_NUM_PRECODE_SYMS>
+}
+
+impl DynamicHeader {
+    fn new(codes: &BlockCodes) -> DynamicHeader {
+        let num_litlen_syms = 257.max(
+            codes.litlen.lens[..286]
+                .iter()
+                .rposition(|x| *x != 0)
+                .map_or(0, |x| x + 1)
+        );
+        let num_offset_syms = 1.max(
+            codes.offset.lens[..30]
+                .iter()
+                .rposition(|x| *x != 0)
+                .map_or(0, |x| x + 1)
+        );
+        // litlen and offset lengths are compressed as one sequence, runs can cross
+        // from one to the other
+        let mut lens = Vec::with_capacity(num_litlen_syms + num_offset_syms);
+        lens.extend_from_slice(&codes.litlen.lens[..num_litlen_syms]);
+        lens.extend_from_slice(&codes.offset.lens[..num_offset_syms]);
+
+        let items = run_length_encode(&lens);
+
+        let mut precode_freqs = [0; DEFLATE_NUM_PRECODE_SYMS];
+
+        for (symbol, _) in &items {
+            precode_freqs[usize::from(*symbol)] += 1;
+        }
+        let precode =
+            HuffmanCode::from_freqs(&precode_freqs, usize::from(DEFLATE_MAX_PRE_CODEWORD_LEN));
+
+        let num_precode_syms = 4.max(
+            DEFLATE_PRECODE_LENS_PERMUTATION
+                .iter()
+                .rposition(|x| precode.lens[usize::from(*x)] != 0)
+                .map_or(0, |x| x + 1)
+        );
+
+        DynamicHeader {
+            num_litlen_syms,
+            num_offset_syms,
+            num_precode_syms,
+            items,
+            precode
+        }
+    }
+    /// Size of the header in bits, excluding the 3 bit block type
+    fn cost(&self) -> usize {
+        let items: usize = self
+            .items
+            .iter()
+            .map(|(symbol, _)| {
+                let extra =
+                    if *symbol >= 16 { PRECODE_EXTRA_BITS[usize::from(*symbol - 16)] } else { 0 };
+                usize::from(self.precode.lens[usize::from(*symbol)] + extra)
+            })
+            .sum();
+
+        5 + 5 + 4 + 3 * self.num_precode_syms + items
+    }
+
+    fn write(&self, writer: &mut BitStreamWriter) {
+        writer.put_bits((self.num_litlen_syms - 257) as u32, 5);
+        writer.put_bits((self.num_offset_syms - 1) as u32, 5);
+        writer.put_bits((self.num_precode_syms - 4) as u32, 4);
+
+        for symbol in &DEFLATE_PRECODE_LENS_PERMUTATION[..self.num_precode_syms] {
+            writer.put_bits(u32::from(self.precode.lens[usize::from(*symbol)]), 3);
+        }
+        for (symbol, extra) in &self.items {
+            let symbol = usize::from(*symbol);
+
+            writer.put_bits(self.precode.codes[symbol], self.precode.lens[symbol]);
+
+            if symbol >= 16 {
+                writer.put_bits(u32::from(*extra), PRECODE_EXTRA_BITS[symbol - 16]);
+            }
+        }
+    }
+}
+
+/// Run length encode codeword lengths using precode symbols 16 (repeat previous),
+/// 17 (short run of zeroes) and 18 (long run of zeroes)
+fn run_length_encode(lens: &[u8]) -> Vec<(u8, u8)> {
+    let mut items = Vec::with_capacity(lens.len());
+    let mut i = 0;
+
+    while i < lens.len() {
+        let len = lens[i];
+        let mut run = lens[i..].iter().take_while(|x| **x == len).count();
+
+        i += run;
+
+        if len == 0 {
+            while run >= 11 {
+                let take = run.min(138);
+                items.push((18, (take - 11) as u8));
+                run -= take;
+            }
+            if run >= 3 {
+                items.push((17, (run - 3) as u8));
+                run = 0;
+            }
+        } else if run >= 4 {
+            // emit the length once, then repeat it
+            items.push((len, 0));
+            run -= 1;
+
+            while run >= 3 {
+                let take = run.min(6);
+                items.push((16, (take - 3) as u8));
+                run -= take;
+            }
+        }
+        for _ in 0..run {
+            items.push((len, 0));
+        }
+    }
+    items
+}
+
+/// Cost in bits of writing `length` bytes as stored blocks, assuming the worst case
+/// alignment padding
+fn stored_cost(length: usize) -> usize {
+    let num_blocks = length.div_ceil(MAX_STORED_BLOCK_LENGTH).max(1);
+
+    // block type + padding + len and nlen per block
+    num_blocks * (3 + 7 + 32) + length * 8
+}
+
+/// Write `data` as one or more stored blocks
+pub fn write_stored_blocks(writer: &mut BitStreamWriter, data: &[u8], is_final: bool) {
+    let mut chunks = data.chunks(MAX_STORED_BLOCK_LENGTH).peekable();
+
+    if data.is_empty() {
+        // we still must output a block for the stream to be valid
+        write_stored_block(writer, &[], is_final);
+        return;
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+
+        write_stored_block(writer, chunk, is_final && last);
+    }
+}
+
+fn write_stored_block(writer: &mut BitStreamWriter, data: &[u8], is_final: bool) {
+    debug_assert!(data.len() <= MAX_STORED_BLOCK_LENGTH);
+
+    writer.put_bits(u32::from(is_final), 1);
+    writer.put_bits(DEFLATE_BLOCKTYPE_UNCOMPRESSED as u32, 2);
+    writer.align_to_byte();
+
+    let len = data.len() as u16;
+
+    writer.write_bytes(&len.to_le_bytes());
+    writer.write_bytes(&(!len).to_le_bytes());
+    writer.write_bytes(data);
+}
+
+/// Write the sequences of a block using huffman codes
+pub fn write_sequences(
+    writer: &mut BitStreamWriter, data: &[u8], sequences: &[MatchSequence], codes: &BlockCodes
+) {
+    let litlen = &codes.litlen;
+    let offset = &codes.offset;
+
+    for seq in sequences {
+        for byte in &data[seq.start..seq.start + seq.ll] {
+            let byte = usize::from(*byte);
+            writer.put_bits(litlen.codes[byte], litlen.lens[byte]);
+        }
+        if seq.ml == 0 {
+            continue;
+        }
+        // length symbol followed by its extra bits
+        let length_slot = usize::from(DEFLATE_LENGTH_SLOT[seq.ml]);
+        let symbol = 257 + length_slot;
+        let extra = (seq.ml - usize::from(DEFLATE_LENGTH_SLOT_BASE[length_slot])) as u32;
+
+        writer.put_bits(
+            litlen.codes[symbol] | (extra << litlen.lens[symbol]),
+            litlen.lens[symbol] + DEFLATE_EXTRA_LENGTH_BITS[length_slot]
+        );
+        // offset symbol followed by its extra bits
+        let offset_slot = deflate_offset_slot(seq.ol);
+        let extra = (seq.ol - usize::from(DEFLATE_OFFSET_SLOT_BASE[offset_slot])) as u32;
+
+        writer.put_bits(
+            offset.codes[offset_slot] | (extra << offset.lens[offset_slot]),
+            offset.lens[offset_slot] + DEFLATE_EXTRA_OFFSET_BITS[offset_slot]
+        );
+    }
+    writer.put_bits(
+        litlen.codes[DEFLATE_END_OF_BLOCK],
+        litlen.lens[DEFLATE_END_OF_BLOCK]
+    );
+}
+
+/// Size in bits of a parsed block when written by [`write_block`]
+///
+/// # Arguments
+/// - `data`: The buffer sequences point into
+/// - `block_length`: Number of bytes covered by the sequences
+/// - `sequences`: Literal runs and matches covering the block
+pub fn block_cost(data: &[u8], block_length: usize, sequences: &[MatchSequence]) -> usize {
+    let freqs = BlockFrequencies::from_sequences(data, sequences);
+
+    let dynamic_codes = BlockCodes::dynamic_codes(&freqs);
+    let dynamic_cost = DynamicHeader::new(&dynamic_codes).cost() + dynamic_codes.data_cost(&freqs);
+    let static_cost = BlockCodes::static_codes().data_cost(&freqs);
+
+    (3 + dynamic_cost.min(static_cost)).min(stored_cost(block_length))
+}
+
+/// Write a parsed block, choosing the cheapest of a stored, static or dynamic block
+///
+/// # Arguments
+/// - `data`: The buffer sequences point into
+/// - `block`: The range of `data` covered by the sequences
+/// - `sequences`: Literal runs and matches covering the block
+/// - `is_final`: Whether this is the last block of the stream
+pub fn write_block(
+    writer: &mut BitStreamWriter, data: &[u8], block: core::ops::Range<usize>,
+    sequences: &[MatchSequence], is_final: bool
+) {
+    let freqs = BlockFrequencies::from_sequences(data, sequences);
+
+    let dynamic_codes = BlockCodes::dynamic_codes(&freqs);
+    let dynamic_header = DynamicHeader::new(&dynamic_codes);
+    let dynamic_cost = dynamic_header.cost() + dynamic_codes.data_cost(&freqs);
+
+    let static_codes = BlockCodes::static_codes();
+    let static_cost = static_codes.data_cost(&freqs);
+
+    let stored_cost = stored_cost(block.len());
+
+    if stored_cost <= dynamic_cost.min(static_cost) {
+        write_stored_blocks(writer, &data[block], is_final);
+    } else if static_cost <= dynamic_cost {
+        writer.put_bits(u32::from(is_final), 1);
+        writer.put_bits(DEFLATE_BLOCKTYPE_STATIC as u32, 2);
+        write_sequences(writer, data, sequences, &static_codes);
+    } else {
+        writer.put_bits(u32::from(is_final), 1);
+        writer.put_bits(DEFLATE_BLOCKTYPE_DYNAMIC_HUFFMAN as u32, 2);
+        dynamic_header.write(writer);
+        write_sequences(writer, data, sequences, &dynamic_codes);
+    }
+}
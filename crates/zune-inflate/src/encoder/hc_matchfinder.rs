@@ -7,6 +7,103 @@
  */
 #![allow(dead_code)]
 
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::constants::{DEFLATE_MAX_MATCH_LEN, DEFLATE_MAX_MATCH_OFFSET, DEFLATE_MIN_LENGTH};
+use crate::encoder::{v_hash, EncodedSequences, MatchSequence};
+
+const HASH_LOG_SIZE: usize = 15;
+const FIRST_BYTE_OFFSET: u32 = 24;
+const POSITION_MASK: u32 = (1 << FIRST_BYTE_OFFSET) - 1;
+/// Amount positions are moved by when they would overflow the
+/// position bits of an entry
+const SLIDE_AMOUNT: usize = 1 << (FIRST_BYTE_OFFSET - 1);
+
+const HASH_SIZE: usize = 1 << HASH_LOG_SIZE;
+
+/// Matches of length 3 further than this are usually more expensive to encode
+/// than the literals they replace, so we don't take them
+const TOO_FAR: usize = 4096;
+
+/// Parse `data[start..end]` into a series of literal runs and matches.
+///
+/// Bytes before `start` are treated as history and can be referenced by matches,
+/// they must have already been added to the table.
+///
+/// Matches may extend past `end`, hence the function returns the position
+/// parsing actually stopped at, which is where the next block should begin
+///
+/// # Arguments
+/// - `lazy`: When true, before committing to a match, check if the next position
+///   has a longer match, if it does emit a literal and take that match instead.
+#[inline(never)]
+pub fn compress_block(
+    data: &[u8], start: usize, end: usize, table: &mut HcMatchFinder, lazy: bool,
+    sequences: &mut EncodedSequences
+) -> usize {
+    let mut window_start = start;
+    let mut literals_start = start;
+
+    while window_start < end {
+        let max_len = DEFLATE_MAX_MATCH_LEN.min(data.len() - window_start);
+
+        if max_len < DEFLATE_MIN_LENGTH {
+            window_start += 1;
+            continue;
+        }
+        let (mut length, mut offset) =
+            table.longest_match(data, window_start, max_len, DEFLATE_MIN_LENGTH - 1);
+
+        if length < DEFLATE_MIN_LENGTH {
+            window_start += 1;
+            continue;
+        }
+        if lazy {
+            // see if deferring the match by one byte gets us a longer match
+            while length < table.nice_length {
+                let next = window_start + 1;
+                let next_max_len = DEFLATE_MAX_MATCH_LEN.min(data.len() - next);
+
+                if next_max_len < DEFLATE_MIN_LENGTH {
+                    break;
+                }
+                let (next_length, next_offset) =
+                    table.longest_match(data, next, next_max_len, length);
+
+                if next_length <= length {
+                    break;
+                }
+                // emit current byte as a literal and take the longer match
+                window_start = next;
+                length = next_length;
+                offset = next_offset;
+            }
+        }
+        sequences.add(MatchSequence {
+            start: literals_start,
+            ll:    window_start - literals_start,
+            ml:    length,
+            ol:    offset
+        });
+        window_start += length;
+        literals_start = window_start;
+        // add the positions covered by the match
+        table.insert_up_to(data, window_start);
+    }
+    if window_start > literals_start {
+        // trailing literals
+        sequences.add(MatchSequence {
+            start: literals_start,
+            ll:    window_start - literals_start,
+            ml:    0,
+            ol:    0
+        });
+    }
+    window_start
+}
+
 /// An optimized hash chains match finder
 ///
 ///
@@ -54,251 +151,252 @@
 ///
 /// 1. Limit depth search to prevent O(n^2) behaviour
 /// 2. Store first match byte in `hc_tab` and `next_tab` entries, this helps us
-/// to ensure that we may have a potential match and not a hash collision when checking for a potential match
-/// useful because checking for matches incur cache costs
+///    to ensure that we may have a potential match and not a hash collision when checking for a potential match
+///    useful because checking for matches incur cache costs
 ///
 /// 3. In case a match is found, and we are still looking for a better match,  check if current match length will go past
-///  the previous match length by looking at the byte in current length plus 1
-///  if they match, then this has the potential to beat the previous ML, better than reading
-///  recalculating the new length if it will just be shorter.
-///                    
-use alloc::boxed::Box;
-use alloc::vec;
-
-use crate::constants::{
-    DEFLATE_MAX_BLOCK_SIZE, DEFLATE_MAX_MATCH_LEN, DEFLATE_MIN_LENGTH, DEFLATE_WINDOW_SIZE
-};
-use crate::encoder::{v_hash, EncodedSequences, MatchSequence};
+///    the previous match length by looking at the byte in current length
+///    if they match, then this has the potential to beat the previous ML, better than reading
+///    recalculating the new length if it will just be shorter.
+///
+/// Positions are stored relative to a base in the lower 24 bits of each entry,
+/// with the first byte in the upper 8 bits, when positions would no longer fit,
+/// the tables are slid forward, since deflate can only look back 32 KiB, nothing of
+/// value is lost.
+pub struct HcMatchFinder {
+    hc_tab:           Box<[u32; HASH_SIZE]>,
+    next_tab:         Box<[u32; DEFLATE_MAX_MATCH_OFFSET]>,
+    /// Position which stored position 0 refers to
+    base:             usize,
+    /// Next position to be inserted into the tables
+    cursor:           usize,
+    pub search_depth: usize,
+    pub nice_length:  usize
+}
 
-const HASH_FOUR_LOG_SIZE: usize = 17;
-const FIRST_BYTE_OFFSET: u32 = 24;
+impl HcMatchFinder {
+    /// create a new match finder
+    ///
+    /// # Arguments
+    /// - `search_depth`: Maximum number of chain entries to visit when looking for a match.
+    /// - `nice_length`: Stop searching once a match this long is found.
+    pub fn new(search_depth: usize, nice_length: usize) -> HcMatchFinder {
+        let hc_tab = vec![0; HASH_SIZE].into_boxed_slice();
+        let next_tab = vec![0; DEFLATE_MAX_MATCH_OFFSET].into_boxed_slice();
 
-const HASH_FOUR_SIZE: usize = 1 << HASH_FOUR_LOG_SIZE;
+        HcMatchFinder {
+            hc_tab:       hc_tab.try_into().unwrap(),
+            next_tab:     next_tab.try_into().unwrap(),
+            base:         0,
+            cursor:       0,
+            search_depth: search_depth.max(1),
+            nice_length:  nice_length.clamp(DEFLATE_MIN_LENGTH, DEFLATE_MAX_MATCH_LEN)
+        }
+    }
 
-#[inline(never)]
-#[allow(clippy::too_many_lines, unused_assignments)]
-pub fn compress_block(
-    src: &[u8], _dest: &mut [u8], table: &mut HcMatchFinder, sequences: &mut EncodedSequences
-) {
-    let mut window_start = 0;
-    let mut literals_before_match = 0;
-    let skip_literals = 1;
-    let mut compressed_bytes = 0;
-
-    let mut sequence = MatchSequence::default();
-
-    'match_loop: loop {
-        // main match finder loop
-        'inner_loop: loop {
-            if window_start + skip_literals + DEFLATE_WINDOW_SIZE > src.len() {
-                // close to input end
-                break 'match_loop;
-            }
+    pub fn reset(&mut self) {
+        self.hc_tab.fill(0);
+        self.next_tab.fill(0);
+        self.base = 0;
+        self.cursor = 0;
+    }
 
-            if table.longest_four_match(src, window_start, literals_before_match, &mut sequence) {
-                sequence.ll = literals_before_match;
-                break 'inner_loop;
-            }
+    /// Tell the match finder that the first `amount` bytes of the
+    /// buffer it is searching in have been removed, and all positions
+    /// moved back by `amount`
+    pub fn discard(&mut self, amount: usize) {
+        debug_assert!(amount <= self.cursor);
 
-            window_start += skip_literals;
-            literals_before_match += skip_literals;
+        if amount <= self.base {
+            self.base -= amount;
+        } else {
+            self.slide(amount - self.base);
+            self.base = 0;
         }
-        compressed_bytes += sequence.ll + sequence.ml;
-
-        sequences.add(sequence);
-        table.advance_four_match(src, window_start, sequence.ml);
-        literals_before_match = 0;
-
-        window_start += sequence.ml;
+        self.cursor -= amount;
+    }
 
-        sequence.ml = 0;
+    /// Move stored positions back by `amount`, dropping anything that
+    /// falls before the start
+    fn slide(&mut self, amount: usize) {
+        let amount = amount as u32;
 
-        if window_start + DEFLATE_WINDOW_SIZE + skip_literals > src.len() {
-            // close to input end
-            break 'match_loop;
+        for entry in self.hc_tab.iter_mut().chain(self.next_tab.iter_mut()) {
+            if (*entry & POSITION_MASK) <= amount {
+                *entry = 0;
+            } else {
+                *entry -= amount;
+            }
         }
     }
-    {
-        assert_eq!(sequence.ml, 0);
 
-        sequence.ll = src
-            .len()
-            .wrapping_sub(window_start)
-            .wrapping_add(literals_before_match);
+    #[inline(always)]
+    fn insert(&mut self, data: &[u8], position: usize) -> u32 {
+        if position + 1 - self.base > POSITION_MASK as usize {
+            self.base += SLIDE_AMOUNT;
+            self.slide(SLIDE_AMOUNT);
+        }
+        let hash = v_hash(&data[position..], HASH_LOG_SIZE, DEFLATE_MIN_LENGTH);
+        // store the current first byte in the hash, we use this to
+        // determine if a match is either a true mach or a hash collision
+        let entry =
+            (u32::from(data[position]) << FIRST_BYTE_OFFSET) | (position + 1 - self.base) as u32;
+
+        let previous = self.hc_tab[hash];
 
-        sequence.ol = 10;
-        sequence.start = src.len() - sequence.ll;
-        sequence.ml = DEFLATE_MIN_LENGTH;
-        sequences.add(sequence);
+        self.hc_tab[hash] = entry;
+        self.next_tab[position % DEFLATE_MAX_MATCH_OFFSET] = previous;
 
-        compressed_bytes += sequence.ll;
+        previous
     }
-    table.reset();
-    assert_eq!(compressed_bytes, src.len());
-}
 
-pub struct HcMatchFinder {
-    next_hash:    [usize; 2],
-    hc_tab:       [u32; 1 << HASH_FOUR_LOG_SIZE],
-    next_tab:     Box<[u32; DEFLATE_MAX_BLOCK_SIZE]>,
-    search_depth: i32,
-    min_length:   usize,
-    nice_length:  usize
-}
+    /// Add all positions up to (but not including) `end` into the hash tables
+    ///
+    /// Positions too close to the end of `data` to be hashed are left out,
+    /// and will be added on a later call if `data` grows
+    pub fn insert_up_to(&mut self, data: &[u8], end: usize) {
+        let end = end.min((data.len() + 1).saturating_sub(DEFLATE_MIN_LENGTH));
 
-impl HcMatchFinder {
-    /// create a new match finder
-    pub fn new(
-        buf_size: usize, search_depth: i32, min_length: usize, nice_length: usize
-    ) -> HcMatchFinder {
-        let n_tab = vec![0; buf_size].into_boxed_slice();
-        //debug_assert!(min_length == 4);
-        HcMatchFinder {
-            next_hash: [0, 0],
-            hc_tab: [0; 1 << HASH_FOUR_LOG_SIZE],
-            next_tab: n_tab.try_into().expect("Uh oh, fix values bro :)"),
-            search_depth,
-            nice_length,
-            min_length
+        while self.cursor < end {
+            self.insert(data, self.cursor);
+            self.cursor += 1;
         }
     }
 
-    pub fn reset(&mut self) {
-        self.hc_tab.fill(0);
-        self.next_hash.fill(0);
-    }
+    /// Find the longest match for `data[position..]`, inserting `position`
+    /// (and any position before it not yet seen) into the tables
+    ///
+    /// # Arguments
+    /// - `max_len`: Longest match allowed, must not go past the end of `data`
+    /// - `best_len`: Only matches longer than this are reported
+    ///
+    /// # Returns
+    /// A tuple of `(length, offset)`, length is zero if no match longer than `best_len` exists
     #[inline(always)]
-    pub fn longest_four_match(
-        &mut self, bytes: &[u8], start: usize, literal_length: usize, sequence: &mut MatchSequence
-    ) -> bool {
-        let curr_start = &bytes[start..];
-        // store the current first byte in the hash, we use this to
-        // determine if a match is either a true mach or a hash collision
-        // in the bottom
-        let curr_match_byte = usize::from(curr_start[0]);
-        let curr_byte = u32::from(curr_start[0]) << FIRST_BYTE_OFFSET;
-
-        let next_window = &bytes[start + 1..];
-        /* Get the precomputed hash codes */
-        let hash = self.next_hash[1];
-        /* From the hash buckets, get the first node of each linked list. */
-        let mut cur_offset = self.hc_tab[hash % HASH_FOUR_SIZE] as usize;
+    pub fn longest_match(
+        &mut self, data: &[u8], position: usize, max_len: usize, mut best_len: usize
+    ) -> (usize, usize) {
+        debug_assert!(max_len >= DEFLATE_MIN_LENGTH);
+        debug_assert!(position + max_len <= data.len());
+        debug_assert!(self.cursor <= position);
 
-        self.hc_tab[hash % HASH_FOUR_SIZE] = curr_byte | (start as u32);
-        self.next_tab[start % DEFLATE_MAX_BLOCK_SIZE] = cur_offset as u32;
+        self.insert_up_to(data, position);
 
-        //  compute the next hash codes
-        let n_hash4 = v_hash(next_window, HASH_FOUR_LOG_SIZE, self.min_length);
+        let mut cur_offset = self.insert(data, position) as usize;
+        self.cursor = position + 1;
 
-        self.next_hash[1] = n_hash4;
-        let mut match_found = false;
+        let curr_start = &data[position..];
+        let curr_match_byte = usize::from(curr_start[0]);
 
-        if cur_offset != 0 {
-            // top byte is usually first match offset, so remove it
-            let mut first_match_byte = cur_offset >> FIRST_BYTE_OFFSET;
+        let mut best_offset = 0;
+        let mut depth = self.search_depth;
+        let lowest_position = position.saturating_sub(DEFLATE_MAX_MATCH_OFFSET - 1);
 
-            cur_offset &= (1 << FIRST_BYTE_OFFSET) - 1;
+        if best_len >= max_len {
+            return (0, 0);
+        }
 
-            let mut depth = self.search_depth;
+        while cur_offset != 0 && depth > 0 {
+            depth -= 1;
 
-            'outer: loop {
-                if cur_offset == 0 || depth <= 0 {
-                    return match_found;
-                }
-                'inner: loop {
-                    depth -= 1;
-
-                    // compare first byte usually stored in hc_tab and next tab for
-                    // the offset
-                    if first_match_byte == curr_match_byte {
-                        // found a possible match, break to see how
-                        // long it is
-                        // this calls into extend
-                        break 'inner;
-                    }
+            // top byte is first match byte, so remove it
+            let first_match_byte = cur_offset >> FIRST_BYTE_OFFSET;
+            let match_position = (cur_offset & POSITION_MASK as usize) + self.base - 1;
 
-                    cur_offset = self.next_tab[cur_offset % DEFLATE_MAX_BLOCK_SIZE] as usize;
-                    first_match_byte = cur_offset >> FIRST_BYTE_OFFSET;
-                    cur_offset &= (1 << FIRST_BYTE_OFFSET) - 1;
+            if match_position < lowest_position {
+                break;
+            }
+            let match_start = &data[match_position..];
+
+            // compare first byte stored in the tables, and if we have a previous match,
+            // check if current match length will go past it by looking at the byte
+            // in current length, if either don't match, this is a hash collision or a
+            // shorter match
+            if first_match_byte == curr_match_byte && match_start[best_len] == curr_start[best_len]
+            {
+                let new_match_length = count(match_start, curr_start, max_len);
+                let distance = position - match_position;
+
+                if new_match_length > best_len
+                    && (new_match_length > DEFLATE_MIN_LENGTH || distance <= TOO_FAR)
+                {
+                    best_len = new_match_length;
+                    best_offset = distance;
 
-                    if depth <= 0 || cur_offset == 0 {
-                        // no match found
-                        // go and try the other tab
-                        break 'outer;
+                    if new_match_length >= self.nice_length || new_match_length == max_len {
+                        break;
                     }
                 }
-                if match_found {
-                    // we have a previous match, check if current match length will go past
-                    // the previous match length by looking at the byte in current length plus 1
-                    // if they match, then this has the potential to beat the previous ML
-                    let prev_match_end = bytes[cur_offset + sequence.ml];
-                    // N.B: This may read +1 byte past curr_start, but that is okay
-                    let curr_match_end = curr_start[sequence.ml];
-
-                    if prev_match_end != curr_match_end {
-                        // go to next node
-                        cur_offset = self.next_tab[cur_offset % DEFLATE_MAX_BLOCK_SIZE] as usize;
-                        first_match_byte = cur_offset >> FIRST_BYTE_OFFSET;
-                        cur_offset &= (1 << FIRST_BYTE_OFFSET) - 1;
-
-                        depth -= 1;
-                        continue;
-                    }
-                }
-                // extend
-                let new_match_length =
-                    count(&bytes[cur_offset..], curr_start, DEFLATE_MAX_MATCH_LEN);
+            }
+            // go to next node
+            cur_offset = self.next_tab[match_position % DEFLATE_MAX_MATCH_OFFSET] as usize;
+        }
+        if best_offset == 0 {
+            return (0, 0);
+        }
+        (best_len, best_offset)
+    }
 
-                let diff = start - cur_offset;
+    /// Find all matches for `data[position..]` that are longer than
+    /// every closer match, inserting `position` into the tables
+    ///
+    /// Matches are appended to `matches` as `(length, offset)` pairs
+    /// in order of increasing length and offset, so for any length,
+    /// the first pair at least that long has the closest offset
+    /// a match of that length can use.
+    ///
+    /// # Arguments
+    /// - `max_len`: Longest match allowed, must not go past the end of `data`
+    pub fn find_matches(
+        &mut self, data: &[u8], position: usize, max_len: usize, matches: &mut Vec<(u16, u16)>
+    ) {
+        debug_assert!(max_len >= DEFLATE_MIN_LENGTH);
+        debug_assert!(position + max_len <= data.len());
+        debug_assert!(self.cursor <= position);
+
+        self.insert_up_to(data, position);
+
+        let mut cur_offset = self.insert(data, position) as usize;
+        self.cursor = position + 1;
+
+        let curr_start = &data[position..];
+        let curr_match_byte = usize::from(curr_start[0]);
 
-                if new_match_length >= self.min_length && new_match_length > sequence.ml && diff > 3
-                {
-                    sequence.ml = new_match_length;
-                    sequence.ol = diff;
-                    sequence.start = start - literal_length;
+        let mut best_len = DEFLATE_MIN_LENGTH - 1;
+        let mut depth = self.search_depth;
+        let lowest_position = position.saturating_sub(DEFLATE_MAX_MATCH_OFFSET - 1);
 
-                    match_found = true;
+        while cur_offset != 0 && depth > 0 {
+            depth -= 1;
 
-                    if new_match_length > self.nice_length {
-                        return true;
-                    }
-                }
-                // go to next node
-                cur_offset = self.next_tab[cur_offset % DEFLATE_MAX_BLOCK_SIZE] as usize;
-                first_match_byte = cur_offset >> FIRST_BYTE_OFFSET;
-                cur_offset &= (1 << FIRST_BYTE_OFFSET) - 1;
+            let first_match_byte = cur_offset >> FIRST_BYTE_OFFSET;
+            let match_position = (cur_offset & POSITION_MASK as usize) + self.base - 1;
 
-                depth -= 1;
+            if match_position < lowest_position {
+                break;
             }
-        }
-        match_found
-    }
+            let match_start = &data[match_position..];
 
-    #[inline(always)]
-    pub fn advance_four_match(&mut self, window_start: &[u8], mut start: usize, mut length: usize) {
-        if (start + length + 100) < window_start.len() {
-            let mut hash4 = self.next_hash[1];
-            loop {
-                let next_window = &window_start[start + 1..];
-
-                let curr_byte = u32::from(window_start[start]) << FIRST_BYTE_OFFSET;
-
-                self.next_tab[start % DEFLATE_MAX_BLOCK_SIZE] = self.hc_tab[hash4 % HASH_FOUR_SIZE];
-                self.hc_tab[hash4 % HASH_FOUR_SIZE] = curr_byte | (start as u32);
-                start += 1;
-                //  compute the next hash codes
-                hash4 = v_hash(next_window, HASH_FOUR_LOG_SIZE, self.min_length);
-                length -= 1;
-
-                if length == 0 {
-                    break;
+            if first_match_byte == curr_match_byte && match_start[best_len] == curr_start[best_len]
+            {
+                let new_match_length = count(match_start, curr_start, max_len);
+
+                if new_match_length > best_len {
+                    best_len = new_match_length;
+                    matches.push((best_len as u16, (position - match_position) as u16));
+
+                    if new_match_length >= self.nice_length || new_match_length == max_len {
+                        break;
+                    }
                 }
             }
-            self.next_hash[1] = hash4;
+            cur_offset = self.next_tab[match_position % DEFLATE_MAX_MATCH_OFFSET] as usize;
         }
     }
 }
 
+/// Count how many bytes `window` and `match_window` have in common
+/// from the start, up to `max_match` bytes
 pub fn count(window: &[u8], match_window: &[u8], max_match: usize) -> usize {
     /*
      * This is pretty neat and worth an explanation
@@ -313,18 +411,17 @@ pub fn count(window: &[u8], match_window: &[u8], max_match: usize) -> usize {
 
     const SIZE: usize = usize::BITS as usize / 8;
 
-    let mut match_length = 0;
+    let max_match = max_match.min(window.len()).min(match_window.len());
 
-    let window_chunks = window.chunks_exact(SIZE);
-    let match_chunks = match_window.chunks_exact(SIZE);
-    // number of iterations the loop below can iterate
-    // let it be one less max match to ensure we never go past that
-    let num_iters = (max_match / SIZE).saturating_sub(1);
+    let mut match_length = 0;
 
-    for (sm_window, sm_match) in window_chunks.zip(match_chunks).take(num_iters) {
-        let sm_w: usize = usize::from_ne_bytes(sm_window.try_into().unwrap());
-        let sm_m: usize = usize::from_ne_bytes(sm_match.try_into().unwrap());
-        let diff = sm_w ^ sm_m; // it's associative.
+    for (sm_window, sm_match) in window[..max_match]
+        .chunks_exact(SIZE)
+        .zip(match_window[..max_match].chunks_exact(SIZE))
+    {
+        let sm_w: usize = usize::from_le_bytes(sm_window.try_into().unwrap());
+        let sm_m: usize = usize::from_le_bytes(sm_match.try_into().unwrap());
+        let diff = sm_w ^ sm_m;
 
         if diff == 0 {
             match_length += SIZE;
@@ -334,19 +431,10 @@ pub fn count(window: &[u8], match_window: &[u8], max_match: usize) -> usize {
             return match_length;
         }
     }
-
-    // PS: There is a bug with this, investigate
-    //
-    // // small chunks
-    // match_window[match_length..]
-    //     .iter()
-    //     .zip(&window[match_length..])
-    //     .for_each(|(a, b)| {
-    //         if a == b
-    //         {
-    //             match_length += 1;
-    //         }
-    //     });
+    // small chunks
+    while match_length < max_match && window[match_length] == match_window[match_length] {
+        match_length += 1;
+    }
 
     match_length
 }
@@ -0,0 +1,221 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Huffman code construction for the deflate encoder
+//!
+//! Codes are built in three steps
+//!
+//! 1. A plain huffman tree is built from symbol frequencies using the
+//!    two queue method over symbols sorted by frequency, this gives us the
+//!    codeword length for each symbol.
+//! 2. If any length exceeds the maximum allowed by deflate, the length counts are
+//!    rebalanced the same way zlib does it, moving leaves up the tree until the code is
+//!    valid again, lengths are then handed out to symbols in order of frequency.
+//! 3. Canonical codewords are assigned from the lengths, and bit-reversed since deflate
+//!    writes huffman codes starting from the most significant bit while our writer
+//!    packs from the least significant bit.
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::constants::{DEFLATE_MAX_CODEWORD_LENGTH, DEFLATE_MAX_NUM_SYMS};
+
+/// Build length limited huffman codeword lengths for `freqs`
+///
+/// # Arguments
+/// - `freqs`: Frequency of each symbol
+/// - `max_len`: Maximum codeword length allowed
+/// - `lens`: Output codeword lengths, unused symbols get a length of zero
+///
+/// The resulting code is always complete, if less than two symbols are used,
+/// extra symbols are given codewords so that decoders which reject incomplete
+/// codes can still decode the stream
+pub fn build_huffman_lengths(freqs: &[u32], max_len: usize, lens: &mut [u8]) {
+    debug_assert!(freqs.len() <= DEFLATE_MAX_NUM_SYMS);
+    debug_assert!(max_len <= DEFLATE_MAX_CODEWORD_LENGTH);
+
+    lens[..freqs.len()].fill(0);
+
+    // symbols sorted by increasing frequency, ties broken by symbol value
+    let mut symbols: Vec<usize> = (0..freqs.len()).filter(|x| freqs[*x] != 0).collect();
+    symbols.sort_unstable_by_key(|x| (freqs[*x], *x));
+
+    if symbols.len() < 2 {
+        // a single symbol (or none at all), give two symbols a one bit codeword
+        let first = symbols.first().copied().unwrap_or(0);
+        let second = if first == 0 { 1 } else { 0 };
+
+        lens[first] = 1;
+        lens[second] = 1;
+        return;
+    }
+
+    let num_leaves = symbols.len();
+    // weights of leaves followed by internal nodes
+    let mut weights = vec![0_u64; 2 * num_leaves - 1];
+    // parent of each node, the root has no parent
+    let mut parents = vec![0_usize; 2 * num_leaves - 1];
+
+    for (weight, symbol) in weights.iter_mut().zip(symbols.iter()) {
+        *weight = u64::from(freqs[*symbol]);
+    }
+    // two queue method, leaves are consumed in order from the first queue,
+    // internal nodes are created in increasing weight order hence also form a sorted
+    // queue
+    let mut next_leaf = 0;
+    let mut next_node = num_leaves;
+
+    for new_node in num_leaves..2 * num_leaves - 1 {
+        let mut children = [0; 2];
+
+        for child in &mut children {
+            let take_leaf = next_leaf < num_leaves
+                && (next_node >= new_node || weights[next_leaf] <= weights[next_node]);
+
+            if take_leaf {
+                *child = next_leaf;
+                next_leaf += 1;
+            } else {
+                *child = next_node;
+                next_node += 1;
+            }
+        }
+        weights[new_node] = weights[children[0]] + weights[children[1]];
+        parents[children[0]] = new_node;
+        parents[children[1]] = new_node;
+    }
+    // compute depths from the root downwards, parents always come after their children
+    let root = 2 * num_leaves - 2;
+    let mut depths = vec![0_usize; 2 * num_leaves - 1];
+
+    for node in (0..root).rev() {
+        depths[node] = depths[parents[node]] + 1;
+    }
+    // count leaves at each depth, anything deeper than max_len is clamped, and then
+    // the tree is rebalanced
+    let mut len_counts = [0_usize; 64];
+
+    for depth in &depths[..num_leaves] {
+        len_counts[(*depth).min(63)] += 1;
+    }
+    limit_lengths(&mut len_counts, max_len);
+
+    // Hand out lengths, the least frequent symbols get the longest codewords.
+    let mut pos = 0;
+
+    for len in (1..=max_len).rev() {
+        for _ in 0..len_counts[len] {
+            lens[symbols[pos]] = len as u8;
+            pos += 1;
+        }
+    }
+    debug_assert_eq!(pos, num_leaves);
+}
+
+/// Rebalance length counts so that no codeword is longer than `max_len`
+/// while keeping the code complete
+fn limit_lengths(len_counts: &mut [usize; 64], max_len: usize) {
+    let mut overflow = 0;
+
+    for len in max_len + 1..64 {
+        overflow += len_counts[len];
+        len_counts[max_len] += len_counts[len];
+        len_counts[len] = 0;
+    }
+    if overflow == 0 {
+        return;
+    }
+    // Each step moves a leaf from max_len up to a shorter length by splitting another
+    // leaf into two, see zlib's gen_bitlen
+    let kraft = |counts: &[usize; 64]| -> usize {
+        (1..=max_len)
+            .map(|len| counts[len] << (max_len - len))
+            .sum()
+    };
+
+    while kraft(len_counts) > 1 << max_len {
+        let mut bits = max_len - 1;
+
+        while len_counts[bits] == 0 {
+            bits -= 1;
+        }
+        len_counts[bits] -= 1;
+        len_counts[bits + 1] += 2;
+        len_counts[max_len] -= 1;
+    }
+}
+
+/// Assign canonical codewords from codeword lengths
+///
+/// The codewords are returned bit reversed, ready to be written by
+/// [`BitStreamWriter`](crate::bitstream::BitStreamWriter)
+pub fn build_canonical_codes(lens: &[u8], codes: &mut [u32]) {
+    let mut len_counts = [0_u32; DEFLATE_MAX_CODEWORD_LENGTH + 1];
+    let mut next_code = [0_u32; DEFLATE_MAX_CODEWORD_LENGTH + 1];
+
+    for len in lens {
+        len_counts[usize::from(*len)] += 1;
+    }
+    len_counts[0] = 0;
+
+    let mut code = 0;
+
+    for len in 1..=DEFLATE_MAX_CODEWORD_LENGTH {
+        code = (code + len_counts[len - 1]) << 1;
+        next_code[len] = code;
+    }
+    for (len, code) in lens.iter().zip(codes.iter_mut()) {
+        let len = usize::from(*len);
+
+        if len == 0 {
+            *code = 0;
+            continue;
+        }
+        *code = reverse_bits(next_code[len], len);
+        next_code[len] += 1;
+    }
+}
+
+/// Reverse the lower `len` bits of `code`
+#[inline(always)]
+fn reverse_bits(code: u32, len: usize) -> u32 {
+    code.reverse_bits() >> (32 - len)
+}
+
+/// A huffman code ready for encoding, holding both the codeword lengths and
+/// the bit reversed codewords
+#[derive(Clone)]
+pub struct HuffmanCode<const N: usize> {
+    pub lens:  [u8; N],
+    pub codes: [u32; N]
+}
+
+impl<const N: usize> HuffmanCode<N> {
+    /// Build a code from codeword lengths
+    pub fn from_lens(lens: [u8; N]) -> HuffmanCode<N> {
+        let mut codes = [0; N];
+        build_canonical_codes(&lens, &mut codes);
+
+        HuffmanCode { lens, codes }
+    }
+    /// Build an optimal length limited code from symbol frequencies
+    pub fn from_freqs(freqs: &[u32; N], max_len: usize) -> HuffmanCode<N> {
+        let mut lens = [0; N];
+        build_huffman_lengths(freqs, max_len, &mut lens);
+
+        HuffmanCode::from_lens(lens)
+    }
+    /// Number of bits needed to encode symbols with the frequencies `freqs`
+    /// excluding extra bits
+    pub fn cost(&self, freqs: &[u32; N]) -> usize {
+        freqs
+            .iter()
+            .zip(self.lens.iter())
+            .map(|(freq, len)| *freq as usize * usize::from(*len))
+            .sum()
+    }
+}
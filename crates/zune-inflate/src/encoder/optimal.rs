@@ -0,0 +1,505 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Optimal parsing, in the spirit of zopfli
+//!
+//! Instead of greedily taking matches, every position's matches are found
+//! up front and the cheapest way of covering the data is found with a shortest
+//! path search, where the cost of a literal or match is how many bits it
+//! would take to write.
+//!
+//! Costs depend on the huffman codes, which depend on the parse, so we start
+//! from the costs of the static huffman codes and then repeatedly re-parse using
+//! the symbol statistics of the previous parse, keeping whichever parse encodes
+//! smallest.
+//!
+//! Before the iterations, the data is split into blocks where the statistics
+//! change, so each block gets codes suited to its own content.
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bitstream::BitStreamWriter;
+use crate::constants::{
+    deflate_offset_slot, DEFLATE_EXTRA_LENGTH_BITS, DEFLATE_EXTRA_OFFSET_BITS, DEFLATE_LENGTH_SLOT,
+    DEFLATE_MAX_MATCH_LEN, DEFLATE_MIN_LENGTH, DEFLATE_NUM_OFFSET_SYMS
+};
+use crate::encoder::block_writer::{block_cost, write_block, BlockFrequencies};
+use crate::encoder::hc_matchfinder::HcMatchFinder;
+use crate::encoder::MatchSequence;
+
+/// Amount of data whose matches are kept in memory at a time
+const MASTER_BLOCK_SIZE: usize = 1 << 20;
+
+/// Maximum number of blocks a master block is split into
+const MAX_SPLIT_BLOCKS: usize = 15;
+
+/// Number of chain entries visited when looking for matches
+const OPTIMAL_SEARCH_DEPTH: usize = 1024;
+
+/// Costs are stored in 1/16th of a bit
+const COST_SHIFT: u32 = 4;
+
+/// `16 * log2(1 + i / 16)` rounded to the nearest integer
+const LOG2_FRACTION: [u32; 16] = [0, 1, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 15];
+
+/// Approximate `log2(value)` in 1/16th of a bit
+fn log2_fixed(value: u32) -> u32 {
+    debug_assert!(value != 0);
+
+    let int_part = 31 - value.leading_zeros();
+
+    let fraction = if int_part >= 4 {
+        (value >> (int_part - 4)) & 15
+    } else {
+        (value << (4 - int_part)) & 15
+    };
+    (int_part << COST_SHIFT) + LOG2_FRACTION[fraction as usize]
+}
+
+/// Cost of each literal, match length and match offset
+struct CostModel {
+    literal: [u32; 256],
+    /// Cost of the length symbol and its extra bits, indexed by match length
+    length:  [u32; DEFLATE_MAX_MATCH_LEN + 1],
+    /// Cost of the offset symbol and its extra bits, indexed by offset slot
+    offset:  [u32; DEFLATE_NUM_OFFSET_SYMS]
+}
+
+impl CostModel {
+    /// Costs of the static huffman codes
+    fn fixed() -> CostModel {
+        let mut model = CostModel {
+            literal: [0; 256],
+            length:  [0; DEFLATE_MAX_MATCH_LEN + 1],
+            offset:  [0; DEFLATE_NUM_OFFSET_SYMS]
+        };
+        for (byte, cost) in model.literal.iter_mut().enumerate() {
+            *cost = if byte < 144 { 8 } else { 9 } << COST_SHIFT;
+        }
+        for (cost, slot) in model
+            .length
+            .iter_mut()
+            .zip(DEFLATE_LENGTH_SLOT.iter())
+            .skip(DEFLATE_MIN_LENGTH)
+        {
+            let slot = usize::from(*slot);
+            let symbol_bits = if 257 + slot < 280 { 7 } else { 8 };
+
+            *cost = (symbol_bits + u32::from(DEFLATE_EXTRA_LENGTH_BITS[slot])) << COST_SHIFT;
+        }
+        for (cost, extra) in model
+            .offset
+            .iter_mut()
+            .zip(DEFLATE_EXTRA_OFFSET_BITS.iter())
+        {
+            *cost = (5 + u32::from(*extra)) << COST_SHIFT;
+        }
+        model
+    }
+    /// Costs estimated from the symbol statistics of a previous parse
+    ///
+    /// A symbol seen `n` times out of `total` costs `log2(total / n)` bits,
+    /// unseen symbols are treated as if they were seen once.
+    fn from_sequences(data: &[u8], sequences: &[MatchSequence]) -> CostModel {
+        let freqs = BlockFrequencies::from_sequences(data, sequences);
+
+        let litlen_total = log2_fixed(freqs.litlen.iter().sum::<u32>());
+        let offset_total = log2_fixed(freqs.offset.iter().sum::<u32>().max(1));
+
+        let litlen_cost = |symbol: usize| litlen_total - log2_fixed(freqs.litlen[symbol].max(1));
+
+        let mut model = CostModel {
+            literal: [0; 256],
+            length:  [0; DEFLATE_MAX_MATCH_LEN + 1],
+            offset:  [0; DEFLATE_NUM_OFFSET_SYMS]
+        };
+        for (byte, cost) in model.literal.iter_mut().enumerate() {
+            *cost = litlen_cost(byte);
+        }
+        for (cost, slot) in model
+            .length
+            .iter_mut()
+            .zip(DEFLATE_LENGTH_SLOT.iter())
+            .skip(DEFLATE_MIN_LENGTH)
+        {
+            let slot = usize::from(*slot);
+
+            *cost = litlen_cost(257 + slot)
+                + (u32::from(DEFLATE_EXTRA_LENGTH_BITS[slot]) << COST_SHIFT);
+        }
+        for ((cost, freq), extra) in model
+            .offset
+            .iter_mut()
+            .zip(freqs.offset.iter())
+            .zip(DEFLATE_EXTRA_OFFSET_BITS.iter())
+        {
+            *cost = offset_total - log2_fixed((*freq).max(1)) + (u32::from(*extra) << COST_SHIFT);
+        }
+        model
+    }
+}
+
+/// The matches found at each position of a master block
+struct MatchCache {
+    /// `matches[starts[i]..starts[i + 1]]` are the matches of position `i`
+    starts:  Vec<u32>,
+    /// `(length, offset)` pairs, see [`HcMatchFinder::find_matches`]
+    matches: Vec<(u16, u16)>
+}
+
+impl MatchCache {
+    /// Find matches for every position in `data[start..end]`, matches
+    /// never extend past `end`
+    fn new(data: &[u8], start: usize, end: usize, table: &mut HcMatchFinder) -> MatchCache {
+        let mut cache = MatchCache {
+            starts:  Vec::with_capacity(end - start + 1),
+            matches: Vec::with_capacity(end - start)
+        };
+        for position in start..end {
+            cache.starts.push(cache.matches.len() as u32);
+
+            let max_len = DEFLATE_MAX_MATCH_LEN.min(end - position);
+
+            if max_len >= DEFLATE_MIN_LENGTH {
+                table.find_matches(data, position, max_len, &mut cache.matches);
+            }
+        }
+        cache.starts.push(cache.matches.len() as u32);
+        cache
+    }
+
+    fn matches_at(&self, index: usize) -> &[(u16, u16)] {
+        &self.matches[self.starts[index] as usize..self.starts[index + 1] as usize]
+    }
+}
+
+/// Find the cheapest parse of `data[start..end]` under `model`
+///
+/// # Arguments
+/// - `cache_start`: The position the first entry of `cache` refers to
+/// - `sequences`: Output sequences, cleared before parsing
+fn parse(
+    data: &[u8], start: usize, end: usize, cache: &MatchCache, cache_start: usize,
+    model: &CostModel, sequences: &mut Vec<MatchSequence>
+) {
+    let length = end - start;
+    // cheapest cost to reach each position, and the (length, offset) of the
+    // step that got there, offset being zero for literals
+    let mut costs = vec![u32::MAX; length + 1];
+    let mut steps = vec![(0_u16, 0_u16); length + 1];
+
+    costs[0] = 0;
+
+    // number of times each byte repeats from its position onwards
+    let mut run_lengths = vec![1_u32; length + 1];
+
+    for i in (0..length.saturating_sub(1)).rev() {
+        if data[start + i] == data[start + i + 1] {
+            run_lengths[i] = run_lengths[i + 1] + 1;
+        }
+    }
+    let longest_run_cost = model.length[DEFLATE_MAX_MATCH_LEN] + model.offset[0];
+
+    let mut i = 0;
+
+    while i < length {
+        // deep inside a long run of the same byte, the best we can do is
+        // chain maximum length matches with an offset of one, so skip
+        // the search for the next few positions
+        if i > DEFLATE_MAX_MATCH_LEN
+            && i + 2 * DEFLATE_MAX_MATCH_LEN < length
+            && run_lengths[i] as usize > 2 * DEFLATE_MAX_MATCH_LEN
+            && run_lengths[i - DEFLATE_MAX_MATCH_LEN] as usize > DEFLATE_MAX_MATCH_LEN
+        {
+            for _ in 0..DEFLATE_MAX_MATCH_LEN {
+                costs[i + DEFLATE_MAX_MATCH_LEN] = costs[i] + longest_run_cost;
+                steps[i + DEFLATE_MAX_MATCH_LEN] = (DEFLATE_MAX_MATCH_LEN as u16, 1);
+                i += 1;
+            }
+        }
+        let cost = costs[i];
+        let literal_cost = cost + model.literal[usize::from(data[start + i])];
+
+        if literal_cost < costs[i + 1] {
+            costs[i + 1] = literal_cost;
+            steps[i + 1] = (1, 0);
+        }
+        let max_len = length - i;
+        let mut shortest = DEFLATE_MIN_LENGTH;
+
+        for (match_len, offset) in cache.matches_at(start - cache_start + i) {
+            let match_len = usize::from(*match_len).min(max_len);
+            let offset_cost = cost + model.offset[deflate_offset_slot(usize::from(*offset))];
+
+            // every length up to the match length can use this offset, shorter lengths
+            // were already given a closer offset
+            for len in shortest..=match_len {
+                let match_cost = offset_cost + model.length[len];
+
+                if match_cost < costs[i + len] {
+                    costs[i + len] = match_cost;
+                    steps[i + len] = (len as u16, *offset);
+                }
+            }
+            shortest = match_len + 1;
+        }
+        i += 1;
+    }
+    // walk back from the end to recover the steps taken
+    let mut path = Vec::new();
+    let mut position = length;
+
+    while position > 0 {
+        let step = steps[position];
+        path.push(step);
+        position -= usize::from(step.0);
+    }
+
+    sequences.clear();
+
+    let mut position = start;
+    let mut literals_start = start;
+
+    for (len, offset) in path.into_iter().rev() {
+        let len = usize::from(len);
+
+        if offset != 0 {
+            sequences.push(MatchSequence {
+                start: literals_start,
+                ll:    position - literals_start,
+                ml:    len,
+                ol:    usize::from(offset)
+            });
+            literals_start = position + len;
+        }
+        position += len;
+    }
+    if position > literals_start {
+        sequences.push(MatchSequence {
+            start: literals_start,
+            ll:    position - literals_start,
+            ml:    0,
+            ol:    0
+        });
+    }
+}
+
+/// Number of bytes covered by `sequences`
+fn sequences_length(sequences: &[MatchSequence]) -> usize {
+    sequences.iter().map(|seq| seq.ll + seq.ml).sum()
+}
+
+/// Find the index in `start..end` minimizing `cost`, assuming it's roughly
+/// convex
+///
+/// Returns the index and its cost
+fn find_minimum(cost: impl Fn(usize) -> usize, start: usize, end: usize) -> (usize, usize) {
+    const NUM_POINTS: usize = 9;
+
+    let (mut low, mut high) = (start, end);
+
+    if high - low < 1024 {
+        return (low..high)
+            .map(|i| (i, cost(i)))
+            .min_by_key(|(_, c)| *c)
+            .unwrap();
+    }
+    let mut best = (low, usize::MAX);
+
+    // evaluate evenly spaced points, and zoom in to the area around the best one
+    while high - low > NUM_POINTS {
+        let points: Vec<usize> = (0..NUM_POINTS)
+            .map(|i| low + (i + 1) * (high - low) / (NUM_POINTS + 1))
+            .collect();
+
+        let (best_point, best_cost) = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i, cost(*p)))
+            .min_by_key(|(_, c)| *c)
+            .unwrap();
+
+        if best_cost >= best.1 {
+            break;
+        }
+        best = (points[best_point], best_cost);
+
+        if best_point > 0 {
+            low = points[best_point - 1];
+        }
+        if best_point < NUM_POINTS - 1 {
+            high = points[best_point + 1];
+        }
+    }
+    best
+}
+
+/// Choose where to split a parse into blocks
+///
+/// Returns the sequence indices each block starts at, excluding the first block
+fn split_blocks(data: &[u8], sequences: &[MatchSequence]) -> Vec<usize> {
+    let range_cost = |from: usize, to: usize| {
+        let block = &sequences[from..to];
+        block_cost(data, sequences_length(block), block)
+    };
+    let mut splits: Vec<usize> = Vec::new();
+    // ranges that can't be split further, identified by their start
+    let mut done: Vec<usize> = Vec::new();
+
+    while splits.len() + 1 < MAX_SPLIT_BLOCKS {
+        // bounds of every block
+        let bounds: Vec<(usize, usize)> = core::iter::once(0)
+            .chain(splits.iter().copied())
+            .zip(
+                splits
+                    .iter()
+                    .copied()
+                    .chain(core::iter::once(sequences.len()))
+            )
+            .collect();
+        // try splitting the largest block not yet tried
+        let candidate = bounds
+            .iter()
+            .filter(|(from, _)| !done.contains(from))
+            .max_by_key(|(from, to)| sequences_length(&sequences[*from..*to]));
+
+        let Some(&(from, to)) = candidate else {
+            break;
+        };
+        if to - from < 10 {
+            done.push(from);
+            continue;
+        }
+        let (split, split_cost) =
+            find_minimum(|i| range_cost(from, i) + range_cost(i, to), from + 1, to);
+
+        if split_cost < range_cost(from, to) {
+            let position = splits.partition_point(|x| *x < split);
+            splits.insert(position, split);
+        } else {
+            done.push(from);
+        }
+    }
+    splits
+}
+
+/// Compress `data[start..]` into deflate blocks using optimal parsing
+///
+/// # Arguments
+/// - `iterations`: Number of times the data is re-parsed with updated costs
+/// - `data`: History followed by the data to compress
+/// - `start`: Where the data to compress starts
+/// - `is_final`: Whether the last block written should be marked as the final block
+/// - `writer`: Where to write the compressed blocks to
+pub fn compress_optimal(
+    iterations: usize, data: &[u8], start: usize, is_final: bool, writer: &mut BitStreamWriter
+) {
+    let mut table = HcMatchFinder::new(OPTIMAL_SEARCH_DEPTH, DEFLATE_MAX_MATCH_LEN);
+    table.insert_up_to(data, start);
+
+    let mut current = Vec::new();
+    let mut best = Vec::new();
+
+    let mut master_start = start;
+
+    while master_start < data.len() {
+        let master_end = (master_start + MASTER_BLOCK_SIZE).min(data.len());
+        let cache = MatchCache::new(data, master_start, master_end, &mut table);
+
+        // a first parse with static costs is used to decide where to split
+        let mut initial = Vec::new();
+        parse(
+            data,
+            master_start,
+            master_end,
+            &cache,
+            master_start,
+            &CostModel::fixed(),
+            &mut initial
+        );
+        let splits = split_blocks(data, &initial);
+
+        let mut block_start = master_start;
+
+        for (from, to) in core::iter::once(0).chain(splits.iter().copied()).zip(
+            splits
+                .iter()
+                .copied()
+                .chain(core::iter::once(initial.len()))
+        ) {
+            let block_end = block_start + sequences_length(&initial[from..to]);
+
+            best.clear();
+            best.extend_from_slice(&initial[from..to]);
+
+            let mut best_cost = block_cost(data, block_end - block_start, &best);
+            let mut last_cost = best_cost;
+
+            current.clone_from(&best);
+
+            for _ in 0..iterations {
+                let model = CostModel::from_sequences(data, &current);
+
+                parse(
+                    data,
+                    block_start,
+                    block_end,
+                    &cache,
+                    master_start,
+                    &model,
+                    &mut current
+                );
+                let cost = block_cost(data, block_end - block_start, &current);
+
+                if cost < best_cost {
+                    best.clone_from(&current);
+                    best_cost = cost;
+                }
+                if cost == last_cost {
+                    // the parse converged
+                    break;
+                }
+                last_cost = cost;
+            }
+            let last_block = block_end == data.len();
+
+            write_block(
+                writer,
+                data,
+                block_start..block_end,
+                &best,
+                is_final && last_block
+            );
+            block_start = block_end;
+        }
+        master_start = master_end;
+    }
+}
+
+#[test]
+fn test_optimal_roundtrip() {
+    use crate::{DeflateDecoder, DeflateEncoder, DeflateEncodingOptions};
+
+    // text like data followed by noise, so splitting has something to do
+    let mut data: Vec<u8> = (0..200_000_u32)
+        .map(|x| b"the quick brown fox jumps over the lazy dog"[(x as usize * 7 / 5) % 43])
+        .collect();
+    data.extend((0..50_000_u32).map(|x| (x.wrapping_mul(2654435761) >> 24) as u8));
+
+    let normal =
+        DeflateEncoder::new_with_options(&data, DeflateEncodingOptions::default().set_level(9))
+            .encode_deflate();
+
+    let options = DeflateEncodingOptions::default().set_optimal_iterations(5);
+    let optimal = DeflateEncoder::new_with_options(&data, options).encode_deflate();
+
+    let decompressed = DeflateDecoder::new(&optimal).decode_deflate().unwrap();
+
+    assert!(decompressed == data);
+    assert!(optimal.len() <= normal.len());
+}
@@ -0,0 +1,108 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software; You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Multithreaded compression
+//!
+//! This follows what pigz does, the input is split into chunks which
+//! are compressed independently, each chunk uses the 32 KiB before it
+//! as history so matches can still cross chunk boundaries.
+//!
+//! Every chunk but the last one is terminated by an empty stored block,
+//! which byte aligns it, so the compressed chunks can be joined
+//! together into one valid deflate stream.
+#![cfg(feature = "std")]
+
+use alloc::vec::Vec;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::bitstream::BitStreamWriter;
+use crate::constants::DEFLATE_MAX_MATCH_OFFSET;
+use crate::encoder::{compress_deflate, write_sync_flush, DeflateEncodingOptions};
+
+/// Amount of data compressed by a single thread at a time
+///
+/// Matches can't cross into the previous chunk's history
+/// beyond 32 KiB so chunks should be much bigger than the window
+pub(crate) const PARALLEL_CHUNK_SIZE: usize = 1 << 17;
+
+/// Compress `data[start..]` into deflate blocks using the number of
+/// threads set in `options`
+///
+/// The stream written is terminated with a final block and
+/// ends byte aligned.
+///
+/// # Arguments
+/// - `options`: Options controlling compression
+/// - `data`: History followed by the data to compress
+/// - `start`: Where the data to compress starts
+/// - `writer`: Where to write the compressed blocks to, must be byte aligned
+pub(crate) fn compress_deflate_parallel(
+    options: &DeflateEncodingOptions, data: &[u8], start: usize, writer: &mut BitStreamWriter
+) {
+    let num_chunks = (data.len() - start).div_ceil(PARALLEL_CHUNK_SIZE);
+    let num_threads = options.get_num_threads().min(num_chunks);
+
+    let next_chunk = AtomicUsize::new(0);
+    let chunks = Mutex::new(vec![Vec::new(); num_chunks]);
+
+    std::thread::scope(|scope| {
+        for _ in 0..num_threads {
+            scope.spawn(|| loop {
+                let chunk = next_chunk.fetch_add(1, Ordering::Relaxed);
+
+                if chunk >= num_chunks {
+                    break;
+                }
+                let chunk_start = start + chunk * PARALLEL_CHUNK_SIZE;
+                let chunk_end = (chunk_start + PARALLEL_CHUNK_SIZE).min(data.len());
+                let history_start = chunk_start.saturating_sub(DEFLATE_MAX_MATCH_OFFSET);
+                let is_final = chunk_end == data.len();
+
+                let mut chunk_writer =
+                    BitStreamWriter::new(Vec::with_capacity(PARALLEL_CHUNK_SIZE / 2));
+
+                compress_deflate(
+                    options,
+                    &data[history_start..chunk_end],
+                    chunk_start - history_start,
+                    is_final,
+                    &mut chunk_writer
+                );
+                if !is_final {
+                    write_sync_flush(&mut chunk_writer);
+                }
+                let compressed = chunk_writer.finish();
+
+                chunks.lock().unwrap()[chunk] = compressed;
+            });
+        }
+    });
+
+    for chunk in chunks.into_inner().unwrap() {
+        writer.write_bytes(&chunk);
+    }
+}
+
+#[test]
+fn test_parallel_roundtrip() {
+    use crate::{DeflateDecoder, DeflateEncoder};
+
+    // a few chunks worth of data, with a tail that doesn't fill a chunk
+    let data: Vec<u8> = (0..PARALLEL_CHUNK_SIZE * 5 + 1234)
+        .map(|x| ((x / 7) ^ (x % 251) ^ (x >> 13)) as u8)
+        .collect();
+
+    for level in [0, 1, 6] {
+        let options = DeflateEncodingOptions::default()
+            .set_level(level)
+            .set_num_threads(4);
+        let compressed = DeflateEncoder::new_with_options(&data, options).encode_deflate();
+
+        let decompressed = DeflateDecoder::new(&compressed).decode_deflate().unwrap();
+        assert!(decompressed == data, "level {level} failed");
+    }
+}
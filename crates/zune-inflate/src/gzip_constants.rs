@@ -11,3 +11,9 @@ pub const GZIP_FOOTER_SIZE: usize = 8;
 pub const GZIP_FHCRC: u8 = 0x02;
 pub const GZIP_FNAME: u8 = 0x08;
 pub const GZIP_FCOMMENT: u8 = 0x10;
+
+/// Compression flags (XFL) for the slowest and fastest levels
+pub const GZIP_XFL_SLOWEST: u8 = 2;
+pub const GZIP_XFL_FASTEST: u8 = 4;
+/// Operating system field, unknown
+pub const GZIP_OS_UNKNOWN: u8 = 255;
@@ -0,0 +1,506 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! `std::io` adapters for decompressing and compressing streams
+//!
+//! [`InflateReader`] decompresses data pulled from any [`Read`] implementation
+//! using the [streaming decoder](crate::DeflateStreamDecoder), and [`DeflateWriter`]
+//! compresses data written to it and passes the result on to any [`Write`] implementation.
+#![cfg(feature = "std")]
+
+use std::io::{self, Read, Write};
+
+use crate::bitstream::BitStreamWriter;
+use crate::constants::DEFLATE_MAX_MATCH_OFFSET;
+use crate::decoder::DeflateOptions;
+#[cfg(feature = "zlib")]
+use crate::encoder::write_zlib_header;
+use crate::encoder::{compress_deflate, write_sync_flush, DeflateEncodingOptions};
+#[cfg(feature = "gzip")]
+use crate::encoder::{write_gzip_header, GzipHeader};
+use crate::streaming::{DeflateFormat, DeflateStreamDecoder, StreamStatus};
+
+/// Size of the buffer compressed data is read into
+const READ_BUFFER_SIZE: usize = 1 << 15;
+/// Amount of input gathered before it is compressed
+const WRITE_CHUNK_SIZE: usize = 1 << 17;
+
+/// A reader that decompresses a deflate, zlib or gzip stream
+/// read from an underlying reader
+///
+/// Reading returns `Ok(0)` once the end of the compressed stream is reached,
+/// a stream ending early results in an [`UnexpectedEof`](io::ErrorKind::UnexpectedEof) error
+/// and corrupt data in an [`InvalidData`](io::ErrorKind::InvalidData) error.
+///
+/// The reader buffers its input, hence it may read bytes past the end
+/// of the compressed stream from the underlying reader.
+///
+/// # Example
+/// ```
+/// use std::io::Read;
+///
+/// use zune_inflate::{DeflateEncoder, DeflateFormat, InflateReader};
+///
+/// let compressed = DeflateEncoder::new(b"Hello, hello, hello").encode_gzip();
+/// let mut reader = InflateReader::new(&compressed[..], DeflateFormat::Gzip);
+///
+/// let mut output = String::new();
+/// reader.read_to_string(&mut output).unwrap();
+///
+/// assert_eq!(output, "Hello, hello, hello");
+/// ```
+pub struct InflateReader<R: Read> {
+    reader:  R,
+    decoder: DeflateStreamDecoder,
+    buffer:  Vec<u8>,
+    // unconsumed input is buffer[start..end]
+    start:   usize,
+    end:     usize,
+    eof:     bool
+}
+
+impl<R: Read> InflateReader<R> {
+    /// Create a new reader decompressing data in `format` from `reader`
+    pub fn new(reader: R, format: DeflateFormat) -> InflateReader<R> {
+        InflateReader::new_with_options(reader, format, DeflateOptions::default())
+    }
+    /// Create a new reader decompressing data in `format` from `reader`
+    /// with the specified options
+    pub fn new_with_options(
+        reader: R, format: DeflateFormat, options: DeflateOptions
+    ) -> InflateReader<R> {
+        InflateReader {
+            reader,
+            decoder: DeflateStreamDecoder::new_with_options(format, options),
+            buffer: vec![0; READ_BUFFER_SIZE],
+            start: 0,
+            end: 0,
+            eof: false
+        }
+    }
+    /// Get a reference to the underlying reader
+    pub const fn get_ref(&self) -> &R {
+        &self.reader
+    }
+    /// Get a mutable reference to the underlying reader
+    ///
+    /// Reading from it directly will corrupt the stream
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+    /// Consume this reader and return the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read more input after the bytes not consumed yet,
+    /// which are moved to the start of the buffer
+    fn fill_buffer(&mut self) -> io::Result<()> {
+        self.buffer.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+
+        if self.end == self.buffer.len() {
+            self.buffer.resize(2 * self.buffer.len(), 0);
+        }
+        loop {
+            match self.reader.read(&mut self.buffer[self.end..]) {
+                Ok(0) => self.eof = true,
+                Ok(length) => self.end += length,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            }
+            return Ok(());
+        }
+    }
+}
+
+impl<R: Read> Read for InflateReader<R> {
+    fn read(&mut self, output: &mut [u8]) -> io::Result<usize> {
+        if output.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.start == self.end && !self.eof {
+                self.fill_buffer()?;
+            }
+            let result = self
+                .decoder
+                .decode(&self.buffer[self.start..self.end], output)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            self.start += result.consumed;
+
+            if result.written > 0 {
+                return Ok(result.written);
+            }
+            match result.status {
+                StreamStatus::Done => {
+                    // another gzip member may follow, but we need the two
+                    // bytes of its magic number to find out
+                    #[cfg(feature = "gzip")]
+                    if self.decoder.get_format() == DeflateFormat::Gzip
+                        && self.end - self.start < 2
+                        && !self.eof
+                    {
+                        self.fill_buffer()?;
+                        continue;
+                    }
+                    return Ok(0);
+                }
+                StreamStatus::NeedsInput if self.eof => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Compressed stream ended early"
+                    ));
+                }
+                // the decoder may need more than the bytes left to make progress
+                StreamStatus::NeedsInput => self.fill_buffer()?,
+                StreamStatus::OutputFull => continue
+            }
+        }
+    }
+}
+
+/// A writer that compresses data into a deflate, zlib or gzip stream
+/// and writes it to an underlying writer
+///
+/// Data is gathered into chunks which are compressed together, matches may
+/// reference the previous 32 KiB of data across chunks.
+///
+/// Calling [`flush`](Write::flush) compresses everything written so far and
+/// byte aligns the stream so that a reader can decode all of it,
+/// doing so often hurts compression.
+///
+/// The stream must be completed by calling [`finish`](Self::finish), otherwise
+/// it is finished when the writer is dropped, ignoring any errors.
+///
+/// # Example
+/// ```
+/// use std::io::Write;
+///
+/// use zune_inflate::{DeflateDecoder, DeflateFormat, DeflateWriter};
+///
+/// let mut writer = DeflateWriter::new(vec![], DeflateFormat::Zlib);
+///
+/// writer.write_all(b"Hello, ").unwrap();
+/// writer.write_all(b"hello, hello").unwrap();
+///
+/// let compressed = writer.finish().unwrap();
+/// let decompressed = DeflateDecoder::new(&compressed).decode_zlib().unwrap();
+///
+/// assert_eq!(&decompressed, b"Hello, hello, hello");
+/// ```
+pub struct DeflateWriter<W: Write> {
+    // taken out by finish
+    writer:         Option<W>,
+    finished:       bool,
+    format:         DeflateFormat,
+    options:        DeflateEncodingOptions,
+    bit_writer:     BitStreamWriter,
+    // history followed by data not yet compressed
+    input:          Vec<u8>,
+    history_length: usize,
+    header_written: bool,
+    total_in:       usize,
+    #[cfg(feature = "gzip")]
+    gzip_header:    GzipHeader,
+    #[cfg(feature = "gzip")]
+    crc:            u32,
+    #[cfg(feature = "zlib")]
+    adler:          simd_adler32::Adler32
+}
+
+impl<W: Write> DeflateWriter<W> {
+    /// Create a new writer compressing data into `format`
+    /// with the default options
+    pub fn new(writer: W, format: DeflateFormat) -> DeflateWriter<W> {
+        DeflateWriter::new_with_options(writer, format, DeflateEncodingOptions::default())
+    }
+    /// Create a new writer compressing data into `format`
+    /// with the specified options
+    pub fn new_with_options(
+        writer: W, format: DeflateFormat, options: DeflateEncodingOptions
+    ) -> DeflateWriter<W> {
+        DeflateWriter {
+            writer: Some(writer),
+            finished: false,
+            format,
+            options,
+            bit_writer: BitStreamWriter::new(Vec::new()),
+            input: Vec::new(),
+            history_length: 0,
+            header_written: false,
+            total_in: 0,
+            #[cfg(feature = "gzip")]
+            gzip_header: GzipHeader::default(),
+            #[cfg(feature = "gzip")]
+            crc: !0,
+            #[cfg(feature = "zlib")]
+            adler: simd_adler32::Adler32::new()
+        }
+    }
+    /// Set the fields written to the gzip header
+    ///
+    /// This is ignored for other formats, and has to be set before any data
+    /// is written
+    #[cfg(feature = "gzip")]
+    #[must_use]
+    pub fn set_gzip_header(mut self, header: GzipHeader) -> Self {
+        self.gzip_header = header;
+        self
+    }
+    /// Get a reference to the underlying writer
+    pub fn get_ref(&self) -> &W {
+        self.writer.as_ref().unwrap()
+    }
+    /// Get a mutable reference to the underlying writer
+    ///
+    /// Writing to it directly will corrupt the stream
+    pub fn get_mut(&mut self) -> &mut W {
+        self.writer.as_mut().unwrap()
+    }
+    /// Compress the remaining data, write the stream trailer and
+    /// return the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.writer.take().unwrap())
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.compress_pending(true)?;
+        self.bit_writer.align_to_byte();
+
+        match self.format {
+            DeflateFormat::Raw => (),
+            #[cfg(feature = "zlib")]
+            DeflateFormat::Zlib => {
+                let hash = self.adler.finish();
+                self.bit_writer.write_bytes(&hash.to_be_bytes());
+            }
+            #[cfg(feature = "gzip")]
+            DeflateFormat::Gzip => {
+                let crc = !self.crc;
+                self.bit_writer.write_bytes(&crc.to_le_bytes());
+                self.bit_writer
+                    .write_bytes(&(self.total_in as u32).to_le_bytes());
+            }
+        }
+        self.write_output()?;
+        self.get_mut().flush()?;
+        self.finished = true;
+
+        Ok(())
+    }
+
+    /// Compress all data not yet compressed, keeping the last
+    /// 32 KiB as history for the next chunk
+    fn compress_pending(&mut self, is_final: bool) -> io::Result<()> {
+        if !self.header_written {
+            self.write_header();
+        }
+        if is_final || self.input.len() > self.history_length {
+            compress_deflate(
+                &self.options,
+                &self.input,
+                self.history_length,
+                is_final,
+                &mut self.bit_writer
+            );
+        }
+        let keep = self.input.len().min(DEFLATE_MAX_MATCH_OFFSET);
+
+        self.input.drain(..self.input.len() - keep);
+        self.history_length = keep;
+
+        self.write_output()
+    }
+
+    fn write_header(&mut self) {
+        match self.format {
+            DeflateFormat::Raw => (),
+            #[cfg(feature = "zlib")]
+            DeflateFormat::Zlib => {
+                write_zlib_header(&self.options, &mut self.bit_writer.output, None);
+            }
+            #[cfg(feature = "gzip")]
+            DeflateFormat::Gzip => {
+                let header = &self.gzip_header;
+                write_gzip_header(&self.options, header, &mut self.bit_writer.output);
+            }
+        }
+        self.header_written = true;
+    }
+
+    /// Hand whole bytes produced so far to the underlying writer
+    fn write_output(&mut self) -> io::Result<()> {
+        let writer = self.writer.as_mut().unwrap();
+
+        writer.write_all(&self.bit_writer.output)?;
+        self.bit_writer.output.clear();
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for DeflateWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            return Err(io::Error::other("Cannot write to a finished stream"));
+        }
+        let pending = self.input.len() - self.history_length;
+        let length = buf.len().min(WRITE_CHUNK_SIZE - pending);
+        let data = &buf[..length];
+
+        self.input.extend_from_slice(data);
+        self.total_in += length;
+
+        match self.format {
+            DeflateFormat::Raw => (),
+            #[cfg(feature = "zlib")]
+            DeflateFormat::Zlib => self.adler.write(data),
+            #[cfg(feature = "gzip")]
+            DeflateFormat::Gzip => self.crc = crate::crc::crc32(data, self.crc)
+        }
+        if pending + length == WRITE_CHUNK_SIZE {
+            self.compress_pending(false)?;
+        }
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.compress_pending(false)?;
+        write_sync_flush(&mut self.bit_writer);
+        self.write_output()?;
+
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> Drop for DeflateWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+#[test]
+fn test_io_roundtrip() {
+    let data: Vec<u8> = (0..400_000_u32)
+        .map(|x| ((x / 3) % 97) as u8 ^ (x.wrapping_mul(2654435761) >> 30) as u8)
+        .collect();
+
+    let formats = [
+        DeflateFormat::Raw,
+        #[cfg(feature = "zlib")]
+        DeflateFormat::Zlib,
+        #[cfg(feature = "gzip")]
+        DeflateFormat::Gzip
+    ];
+    for format in formats {
+        for level in [0, 1, 6] {
+            let options = DeflateEncodingOptions::default().set_level(level);
+            let mut writer = DeflateWriter::new_with_options(Vec::new(), format, options);
+
+            for (i, chunk) in data.chunks(40_000 + level as usize).enumerate() {
+                writer.write_all(chunk).unwrap();
+
+                if i == 3 {
+                    // everything written so far must be decodable
+                    writer.flush().unwrap();
+
+                    let partial = writer.get_ref().clone();
+                    let mut reader = InflateReader::new(&partial[..], format);
+                    let mut output = vec![0; 4 * chunk.len()];
+
+                    reader.read_exact(&mut output).unwrap();
+                    assert!(output == data[..output.len()]);
+                }
+            }
+            let compressed = writer.finish().unwrap();
+
+            let mut reader = InflateReader::new(&compressed[..], format);
+            let mut output = vec![];
+            let mut buffer = [0; 1000];
+
+            loop {
+                let length = reader.read(&mut buffer).unwrap();
+
+                if length == 0 {
+                    break;
+                }
+                output.extend_from_slice(&buffer[..length]);
+            }
+            assert!(output == data, "{format:?} level {level}");
+
+            // truncated streams are errors
+            let mut reader = InflateReader::new(&compressed[..compressed.len() - 1], format);
+            assert!(reader.read_to_end(&mut Vec::new()).is_err());
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_io_byte_reader() {
+    use crate::DeflateEncoder;
+
+    /// A reader returning a single byte per call
+    struct ByteReader<'a>(&'a [u8]);
+
+    impl Read for ByteReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((&byte, rest)), Some(out)) => {
+                    *out = byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0)
+            }
+        }
+    }
+
+    let first: Vec<u8> = (0..10_000_u32).map(|x| (x * 7 % 253) as u8).collect();
+    let second = b"and a second gzip member".to_vec();
+
+    let mut compressed = DeflateEncoder::new(&first).encode_gzip();
+    compressed.extend_from_slice(&DeflateEncoder::new(&second).encode_gzip());
+
+    let mut expected = first.clone();
+    expected.extend_from_slice(&second);
+
+    let mut output = vec![];
+    InflateReader::new(ByteReader(&compressed), DeflateFormat::Gzip)
+        .read_to_end(&mut output)
+        .unwrap();
+    assert!(output == expected);
+
+    // a trailing byte which may start another member is ignored
+    let mut trailing = DeflateEncoder::new(&second).encode_gzip();
+    trailing.push(0x1F);
+
+    let mut output = vec![];
+    InflateReader::new(ByteReader(&trailing), DeflateFormat::Gzip)
+        .read_to_end(&mut output)
+        .unwrap();
+    assert!(output == second);
+
+    let mut reader = InflateReader::new(
+        ByteReader(&compressed[..compressed.len() - 3]),
+        DeflateFormat::Gzip
+    );
+    assert!(reader.read_to_end(&mut Vec::new()).is_err());
+}
@@ -6,7 +6,7 @@
 
 //! An incredibly spiffy deflate decoder.
 //!
-//! This crate features a deflate/zlib decoder inspired by
+//! This crate features a deflate/zlib decoder and encoder inspired by
 //! Eric Bigger's [libdeflate].
 //!
 //! This libary has a smaller set of features hence you should use it
@@ -15,25 +15,21 @@
 //! Use it if
 //! - You want a smaller library footprint when compared to flate/miniz-oxide
 //! - You want faster speeds than zlib-ng/zlib/miniz-oxide.
-//! - You do full buffer decompression and not streaming decompression.
-//! - You don't need compression support for now, it will come soon enough.
+//! - You do full buffer decompression, streaming decompression is supported but slower.
 //! - You want a 100% safe, pure rust implementation with above.
 //!
-//!Do not use it if
-//!  - You want compression support, not yet there
-//!  - You stream your data, not compatible with this library
-//!
 //! ## Alternatives
 //!- For the fastest speeds, check out [libdeflate] (C), if using Rust there is [libdeflater] which
 //! provides bindings to [libdeflate]
 //!
-//!- For streaming support use [flate2-rs] with an appropriate backend(zlib-ng is recommended for speed)
+//!- For more streaming options, e.g. streaming compression, use [flate2-rs] with an appropriate backend(zlib-ng is recommended for speed)
 //!  
 //! # Features
 //! You can disable features depending on what you need. the following are
 //! features present
-//! - gzip: Enable gzip decoding
-//! - zlib: Enable zlib decoding
+//! - gzip: Enable gzip decoding and encoding
+//! - zlib: Enable zlib decoding and encoding
+//! - std: Enable `std::io` adapters, [InflateReader] and [DeflateWriter]
 //!
 //! These features are enabled by default
 //!
@@ -82,6 +78,28 @@
 //!
 //! ```
 //!
+//! Decoding data that arrives in chunks
+//! ```no_run
+//! use zune_inflate::{DeflateFormat, DeflateStreamDecoder, StreamStatus};
+//! let chunk = [0; 23];
+//! let mut output = [0; 4096];
+//! let mut decoder = DeflateStreamDecoder::new(DeflateFormat::Zlib);
+//!
+//! let result = decoder.decode(&chunk, &mut output).unwrap();
+//! // pass the remaining input and more output space until result.status is StreamStatus::Done
+//! ```
+//!
+//! Compressing data into a zlib stream
+//! ```
+//! use zune_inflate::{DeflateEncoder, DeflateEncodingOptions};
+//! let data = [0; 100];
+//! // levels go from 0 (no compression) to 9 (smallest output)
+//! let options = DeflateEncodingOptions::default().set_level(9);
+//! let mut encoder = DeflateEncoder::new_with_options(&data, options);
+//!
+//! let compressed = encoder.encode_zlib();
+//! ```
+//!
 //! [libdeflate]: https://github.com/ebiggers/libdeflate
 //! [libdeflater]: https://github.com/adamkewley/libdeflater
 //! [flate2-rs]: https://github.com/rust-lang/flate2-rs
@@ -90,7 +108,12 @@
 extern crate alloc;
 
 pub use crate::decoder::{DeflateDecoder, DeflateOptions};
-pub use crate::encoder::DeflateEncoder;
+#[cfg(feature = "gzip")]
+pub use crate::encoder::GzipHeader;
+pub use crate::encoder::{DeflateEncoder, DeflateEncodingOptions, DeflateEncodingStrategy};
+#[cfg(feature = "std")]
+pub use crate::io::{DeflateWriter, InflateReader};
+pub use crate::streaming::{DeflateFormat, DeflateStreamDecoder, StreamResult, StreamStatus};
 
 mod bitstream;
 mod constants;
@@ -99,4 +122,6 @@ mod decoder;
 mod encoder;
 pub mod errors;
 mod gzip_constants;
+mod io;
+mod streaming;
 mod utils;
@@ -0,0 +1,1269 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Streaming (incremental) inflate
+//!
+//! Unlike [`DeflateDecoder`](crate::DeflateDecoder) which needs the whole
+//! compressed buffer upfront and returns the whole decompressed buffer, the
+//! decoder here accepts input in chunks of any size and writes to an output buffer
+//! supplied by the caller, pausing whenever it runs out of input or output space.
+//!
+//! The decoder is a state machine, every step (a block header, a single code length,
+//! a literal or a match, a header or trailer field) is only executed when all the bits
+//! it needs are present, otherwise the decoder saves its state and asks for more input.
+//!
+//! Decoded bytes are first written to an internal 64 KiB window that keeps
+//! the 32 KiB history needed by matches, and are then copied to the caller's buffer,
+//! hence memory usage is constant regardless of the size of the stream.
+#[cfg(feature = "zlib")]
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::constants::{
+    DEFLATE_BLOCKTYPE_DYNAMIC_HUFFMAN, DEFLATE_BLOCKTYPE_STATIC, DEFLATE_BLOCKTYPE_UNCOMPRESSED,
+    DEFLATE_MAX_CODEWORD_LENGTH, DEFLATE_MAX_LITLEN_CODEWORD_LENGTH, DEFLATE_MAX_MATCH_OFFSET,
+    DEFLATE_MAX_OFFSET_CODEWORD_LENGTH, DEFLATE_NUM_LITLEN_SYMS, DEFLATE_NUM_OFFSET_SYMS,
+    DEFLATE_NUM_PRECODE_SYMS, DEFLATE_PRECODE_LENS_PERMUTATION, DELFATE_MAX_LENS_OVERRUN,
+    HUFFDEC_END_OF_BLOCK, HUFFDEC_LITERAL, HUFFDEC_SUITABLE_POINTER, LITLEN_DECODE_RESULTS,
+    LITLEN_ENOUGH, LITLEN_TABLE_BITS, OFFSET_DECODE_RESULTS, OFFSET_ENOUGH, OFFSET_TABLEBITS,
+    PRECODE_DECODE_RESULTS, PRECODE_ENOUGH, PRECODE_TABLE_BITS
+};
+use crate::decoder::{build_decode_table_inner, DeflateOptions};
+use crate::errors::{DecodeErrorStatus, InflateDecodeErrors};
+#[cfg(feature = "gzip")]
+use crate::gzip_constants::{
+    GZIP_CM_DEFLATE, GZIP_FCOMMENT, GZIP_FEXTRA, GZIP_FHCRC, GZIP_FNAME, GZIP_FRESERVED, GZIP_ID1,
+    GZIP_ID2
+};
+
+/// Size of the internal window, enough to hold 32 KiB of history
+/// and up to [`MAX_PENDING`] bytes not yet handed out to the caller
+const WINDOW_SIZE: usize = 1 << 16;
+const WINDOW_MASK: usize = WINDOW_SIZE - 1;
+/// Stop decoding once this many decoded bytes are waiting to be copied out
+const MAX_PENDING: usize = 1 << 15;
+
+const LENS_COUNT: usize =
+    DEFLATE_NUM_LITLEN_SYMS + DEFLATE_NUM_OFFSET_SYMS + DELFATE_MAX_LENS_OVERRUN;
+
+/// The container format of a deflate stream
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeflateFormat {
+    /// Raw deflate data, without a header or a checksum
+    Raw,
+    /// Deflate data wrapped in a zlib header and an adler32 trailer
+    #[cfg(feature = "zlib")]
+    Zlib,
+    /// Deflate data wrapped in a gzip header and a crc32 trailer
+    #[cfg(feature = "gzip")]
+    Gzip
+}
+
+/// Reason a call to [`DeflateStreamDecoder::decode`] returned
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StreamStatus {
+    /// All input was consumed, call `decode` again with more input
+    ///
+    /// If there is no more input, the stream is truncated
+    NeedsInput,
+    /// The output buffer is full, call `decode` again with more output space
+    ///
+    /// This is also returned with the bytes decoded before an error,
+    /// the next call returns the error
+    OutputFull,
+    /// The end of the stream was reached and all decoded bytes
+    /// were written out
+    ///
+    /// For gzip, another member may follow, calling `decode` again with
+    /// input starting with a gzip header continues decoding. A single byte
+    /// left unconsumed may be the start of such a header, it should be passed
+    /// again along with more input if there is any
+    Done
+}
+
+/// Progress made by a single call to [`DeflateStreamDecoder::decode`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StreamResult {
+    /// Number of bytes consumed from the input
+    pub consumed: usize,
+    /// Number of bytes written to the output
+    pub written:  usize,
+    /// Why the decoder stopped
+    pub status:   StreamStatus
+}
+
+#[cfg(feature = "gzip")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum GzipHeaderState {
+    /// The fixed 10 byte header, with the number of bytes read so far
+    Fixed(usize),
+    ExtraLength,
+    /// Skipping the extra field, with the number of bytes remaining
+    Extra(usize),
+    Name,
+    Comment,
+    HeaderCrc
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    #[cfg(feature = "zlib")]
+    ZlibHeader,
+    #[cfg(feature = "gzip")]
+    GzipHeader(GzipHeaderState),
+    BlockHeader,
+    StoredHeader,
+    /// Copying a stored block, with the number of bytes remaining
+    Stored(usize),
+    DynamicHeader,
+    /// Reading precode lengths, with the number read so far
+    PrecodeLens(usize),
+    /// Reading litlen and offset codeword lengths, with the number read so far
+    CodeLens(usize),
+    HuffmanData,
+    /// Reading the trailer, with the number of 32 bit words read so far
+    Trailer(usize),
+    /// Waiting for all bytes to be written out before confirming the checksum
+    Check,
+    Done
+}
+
+/// A resumable deflate decoder
+///
+/// The decoder pulls compressed bytes from input chunks passed to
+/// [`decode`](Self::decode) and writes decompressed bytes to the output buffer
+/// passed along with them.
+/// Input and output may be of any size, including a single byte, the decoder
+/// pauses when either of them runs out and continues where it left off on the next call.
+///
+/// # Example
+/// ```
+/// use zune_inflate::{DeflateEncoder, DeflateFormat, DeflateStreamDecoder, StreamStatus};
+///
+/// let data = b"Hello there, hello there, hello there";
+/// let compressed = DeflateEncoder::new(data).encode_zlib();
+///
+/// let mut decoder = DeflateStreamDecoder::new(DeflateFormat::Zlib);
+/// let mut output = vec![];
+/// let mut buffer = [0; 16];
+///
+/// // feed the input in chunks of three bytes
+/// for chunk in compressed.chunks(3) {
+///     let mut chunk = &chunk[..];
+///
+///     loop {
+///         let result = decoder.decode(chunk, &mut buffer).unwrap();
+///
+///         chunk = &chunk[result.consumed..];
+///         output.extend_from_slice(&buffer[..result.written]);
+///
+///         if result.status != StreamStatus::OutputFull {
+///             break;
+///         }
+///     }
+/// }
+/// assert_eq!(&output, data);
+/// ```
+pub struct DeflateStreamDecoder {
+    format:              DeflateFormat,
+    options:             DeflateOptions,
+    state:               State,
+    bit_buffer:          u64,
+    bits_left:           u8,
+    window:              Vec<u8>,
+    // total number of bytes decoded into the window
+    written:             usize,
+    // total number of bytes copied out of the window
+    flushed:             usize,
+    is_last_block:       bool,
+    static_codes_loaded: bool,
+    num_litlen_syms:     usize,
+    num_offset_syms:     usize,
+    num_precode_lens:    usize,
+    precode_lens:        [u8; DEFLATE_NUM_PRECODE_SYMS],
+    lens:                [u8; LENS_COUNT],
+    precode_table:       [u32; PRECODE_ENOUGH],
+    litlen_table:        [u32; LITLEN_ENOUGH],
+    offset_table:        [u32; OFFSET_ENOUGH],
+    trailer:             [u32; 2],
+    #[cfg(feature = "gzip")]
+    gzip_header:         [u8; 10],
+    // bytes of the preset dictionary at the start of the window
+    dictionary_size:     usize,
+    #[cfg(feature = "zlib")]
+    dictionary_id:       Option<u32>,
+    #[cfg(feature = "gzip")]
+    crc:                 u32,
+    // value of `written` when the current gzip member started
+    #[cfg(feature = "gzip")]
+    member_start:        usize,
+    #[cfg(feature = "zlib")]
+    adler:               simd_adler32::Adler32,
+    // error found while decoded bytes were still waiting to be
+    // handed out, returned once they are
+    error:               Option<DecodeErrorStatus>
+}
+
+impl DeflateStreamDecoder {
+    /// Create a new streaming decoder for data in `format`
+    /// with the default options
+    pub fn new(format: DeflateFormat) -> DeflateStreamDecoder {
+        DeflateStreamDecoder::new_with_options(format, DeflateOptions::default())
+    }
+    /// Create a new streaming decoder for data in `format`
+    ///
+    /// # Arguments
+    /// - `format`: The container format of the stream
+    /// - `options`: Options for decoding, the limit and checksum confirmation
+    ///   are respected, the size hint is ignored since the decoder never
+    ///   allocates output
+    pub fn new_with_options(
+        format: DeflateFormat, options: DeflateOptions
+    ) -> DeflateStreamDecoder {
+        let state = match format {
+            DeflateFormat::Raw => State::BlockHeader,
+            #[cfg(feature = "zlib")]
+            DeflateFormat::Zlib => State::ZlibHeader,
+            #[cfg(feature = "gzip")]
+            DeflateFormat::Gzip => State::GzipHeader(GzipHeaderState::Fixed(0))
+        };
+        DeflateStreamDecoder {
+            format,
+            options,
+            state,
+            bit_buffer: 0,
+            bits_left: 0,
+            window: vec![0; WINDOW_SIZE],
+            written: 0,
+            flushed: 0,
+            is_last_block: false,
+            static_codes_loaded: false,
+            num_litlen_syms: 0,
+            num_offset_syms: 0,
+            num_precode_lens: 0,
+            precode_lens: [0; DEFLATE_NUM_PRECODE_SYMS],
+            lens: [0; LENS_COUNT],
+            precode_table: [0; PRECODE_ENOUGH],
+            litlen_table: [0; LITLEN_ENOUGH],
+            offset_table: [0; OFFSET_ENOUGH],
+            trailer: [0; 2],
+            #[cfg(feature = "gzip")]
+            gzip_header: [0; 10],
+            dictionary_size: 0,
+            #[cfg(feature = "zlib")]
+            dictionary_id: None,
+            #[cfg(feature = "gzip")]
+            crc: !0,
+            #[cfg(feature = "gzip")]
+            member_start: 0,
+            #[cfg(feature = "zlib")]
+            adler: simd_adler32::Adler32::new(),
+            error: None
+        }
+    }
+    /// Set a preset dictionary
+    ///
+    /// The dictionary is used as history for matches at the start of the stream,
+    /// for zlib streams its adler32 must match the dictionary id stored in the header.
+    /// Only the last 32 KiB of the dictionary are used.
+    ///
+    /// This must be called before the first call to [`decode`](Self::decode)
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) {
+        let history = &dictionary[dictionary.len().saturating_sub(DEFLATE_MAX_MATCH_OFFSET)..];
+
+        self.write_window(history);
+        self.flushed = self.written;
+        self.dictionary_size = history.len();
+
+        #[cfg(feature = "gzip")]
+        {
+            self.member_start = self.written;
+        }
+        #[cfg(feature = "zlib")]
+        {
+            self.dictionary_id = Some(crate::utils::calc_adler_hash(dictionary));
+        }
+    }
+    /// Get the container format this decoder expects
+    pub const fn get_format(&self) -> DeflateFormat {
+        self.format
+    }
+    /// Total number of decompressed bytes produced so far
+    pub const fn total_out(&self) -> usize {
+        self.written - self.dictionary_size
+    }
+    /// Decode as much of `input` as possible into `output`
+    ///
+    /// # Arguments
+    /// - `input`: The next chunk of compressed data, bytes not consumed
+    ///   by this call should be passed again on the next call
+    /// - `output`: Buffer to write decompressed data to
+    ///
+    /// # Returns
+    /// - `Ok(StreamResult)`: How many bytes were consumed and written,
+    ///   and why the decoder stopped.
+    ///   When the stream ends, bytes following it are not consumed.
+    /// - `Err(InflateDecodeErrors)`: The stream is corrupt, the decoder cannot be used after this.
+    ///   Bytes decoded before the error are written out first, with
+    ///   [`OutputFull`](StreamStatus::OutputFull) as the status, and the error
+    ///   is returned by the call after them
+    pub fn decode(
+        &mut self, input: &[u8], output: &mut [u8]
+    ) -> Result<StreamResult, InflateDecodeErrors> {
+        let mut consumed = 0;
+        let mut written = 0;
+
+        let status = loop {
+            written += self.flush(&mut output[written..]);
+
+            let pending = self.written - self.flushed;
+
+            if pending > 0
+                && (pending >= MAX_PENDING
+                    || matches!(self.state, State::Check | State::Done)
+                    || self.error.is_some())
+            {
+                // output is full, flush would have emptied the window otherwise
+                break StreamStatus::OutputFull;
+            }
+            if let Some(error) = self.error.take() {
+                if written > 0 {
+                    // hand out the bytes decoded before the error first
+                    self.error = Some(error);
+                    break StreamStatus::OutputFull;
+                }
+                return Err(InflateDecodeErrors::new_with_error(error));
+            }
+            if self.state == State::Done {
+                #[cfg(feature = "gzip")]
+                if self.format == DeflateFormat::Gzip {
+                    // like gzip -d, continue with the next member if there is one
+                    if self.next_gzip_member(input, consumed) {
+                        continue;
+                    }
+                }
+                break StreamStatus::Done;
+            }
+            match self.step(input, &mut consumed) {
+                Ok(true) => continue,
+                Ok(false) => {
+                    written += self.flush(&mut output[written..]);
+
+                    if self.written != self.flushed {
+                        break StreamStatus::OutputFull;
+                    }
+                    break StreamStatus::NeedsInput;
+                }
+                Err(error) => self.error = Some(error)
+            }
+        };
+        if status != StreamStatus::NeedsInput {
+            self.return_unused_bytes(&mut consumed);
+        }
+
+        Ok(StreamResult {
+            consumed,
+            written,
+            status
+        })
+    }
+
+    /// Run the state machine until it either finishes a state or
+    /// needs more input, returning `false` in the latter case
+    fn step(&mut self, input: &[u8], position: &mut usize) -> Result<bool, DecodeErrorStatus> {
+        match self.state {
+            #[cfg(feature = "zlib")]
+            State::ZlibHeader => {
+                self.refill(input, position);
+
+                if !self.has(16) {
+                    return Ok(false);
+                }
+                // with FDICT set, the dictionary id follows the header
+                let fdict = (self.bit_buffer >> 13) & 1 == 1;
+
+                if fdict && !self.has(16 + 32) {
+                    return Ok(false);
+                }
+                let cmf = self.get_bits(8) as u8;
+                let flg = self.get_bits(8) as u8;
+
+                let cm = cmf & 0xF;
+                let cinfo = cmf >> 4;
+
+                if cm != 8 {
+                    return Err(DecodeErrorStatus::GenericStr(format!(
+                        "Unknown zlib compression method {cm}"
+                    )));
+                }
+                if cinfo > 7 {
+                    return Err(DecodeErrorStatus::GenericStr(format!(
+                        "Unknown cinfo `{cinfo}` greater than 7, not allowed"
+                    )));
+                }
+                if ((u16::from(cmf) * 256) + u16::from(flg)) % 31 != 0 {
+                    return Err(DecodeErrorStatus::Generic("FCHECK integrity not preserved"));
+                }
+                if fdict {
+                    // stored big endian
+                    let id_expected = (self.get_bits(32) as u32).swap_bytes();
+
+                    match self.dictionary_id {
+                        None => {
+                            return Err(DecodeErrorStatus::Generic(
+                                "Stream needs a preset dictionary, use set_dictionary"
+                            ));
+                        }
+                        Some(id_found) if id_found != id_expected => {
+                            return Err(DecodeErrorStatus::GenericStr(format!(
+                                "Preset dictionary id mismatch, expected {id_expected} but found {id_found}"
+                            )));
+                        }
+                        _ => ()
+                    }
+                }
+                self.state = State::BlockHeader;
+            }
+            #[cfg(feature = "gzip")]
+            State::GzipHeader(header_state) => {
+                return self.decode_gzip_header(header_state, input, position)
+            }
+            State::BlockHeader => {
+                self.refill(input, position);
+
+                if !self.has(3) {
+                    return Ok(false);
+                }
+                self.is_last_block = self.get_bits(1) == 1;
+
+                let block_type = self.get_bits(2);
+
+                if block_type == DEFLATE_BLOCKTYPE_UNCOMPRESSED {
+                    self.state = State::StoredHeader;
+                } else if block_type == DEFLATE_BLOCKTYPE_STATIC {
+                    self.load_static_tables()?;
+                    self.state = State::HuffmanData;
+                } else if block_type == DEFLATE_BLOCKTYPE_DYNAMIC_HUFFMAN {
+                    self.state = State::DynamicHeader;
+                } else {
+                    return Err(DecodeErrorStatus::Generic(
+                        "Reserved block type 0b11 encountered"
+                    ));
+                }
+            }
+            State::StoredHeader => {
+                self.align_to_byte();
+                self.refill(input, position);
+
+                if !self.has(32) {
+                    return Ok(false);
+                }
+                let len = self.get_bits(16);
+                let nlen = self.get_bits(16);
+
+                if len != (!nlen & 0xFFFF) {
+                    return Err(DecodeErrorStatus::Generic("Len and nlen do not match"));
+                }
+                self.state = State::Stored(len as usize);
+            }
+            State::Stored(mut remaining) => {
+                while remaining > 0 {
+                    let space = MAX_PENDING.saturating_sub(self.written - self.flushed);
+
+                    if space == 0 {
+                        self.state = State::Stored(remaining);
+                        return Ok(true);
+                    }
+                    if self.has(8) {
+                        // bytes already pulled into the bit buffer
+                        let byte = self.get_bits(8) as u8;
+                        self.push_bytes(&[byte])?;
+                        remaining -= 1;
+                        continue;
+                    }
+                    let available = &input[*position..];
+
+                    if available.is_empty() {
+                        self.state = State::Stored(remaining);
+                        return Ok(false);
+                    }
+                    let count = remaining.min(space).min(available.len());
+
+                    self.push_bytes(&available[..count])?;
+                    *position += count;
+                    remaining -= count;
+                }
+                self.end_block();
+            }
+            State::DynamicHeader => {
+                self.refill(input, position);
+
+                if !self.has(5 + 5 + 4) {
+                    return Ok(false);
+                }
+                self.num_litlen_syms = 257 + self.get_bits(5) as usize;
+                self.num_offset_syms = 1 + self.get_bits(5) as usize;
+                self.num_precode_lens = 4 + self.get_bits(4) as usize;
+                self.precode_lens.fill(0);
+                self.static_codes_loaded = false;
+
+                self.state = State::PrecodeLens(0);
+            }
+            State::PrecodeLens(mut count) => {
+                while count < self.num_precode_lens {
+                    self.refill(input, position);
+
+                    if !self.has(3) {
+                        self.state = State::PrecodeLens(count);
+                        return Ok(false);
+                    }
+                    let index = usize::from(DEFLATE_PRECODE_LENS_PERMUTATION[count]);
+                    self.precode_lens[index] = self.get_bits(3) as u8;
+                    count += 1;
+                }
+                build_decode_table_inner(
+                    &self.precode_lens,
+                    &PRECODE_DECODE_RESULTS,
+                    &mut self.precode_table,
+                    PRECODE_TABLE_BITS,
+                    DEFLATE_NUM_PRECODE_SYMS,
+                    DEFLATE_MAX_CODEWORD_LENGTH
+                )?;
+                self.state = State::CodeLens(0);
+            }
+            State::CodeLens(count) => return self.decode_code_lens(count, input, position),
+            State::HuffmanData => return self.decode_huffman(input, position),
+            State::Trailer(mut words) => {
+                self.align_to_byte();
+
+                let num_words = match self.format {
+                    DeflateFormat::Raw => 0,
+                    #[cfg(feature = "zlib")]
+                    DeflateFormat::Zlib => 1,
+                    #[cfg(feature = "gzip")]
+                    DeflateFormat::Gzip => 2
+                };
+                while words < num_words {
+                    self.refill(input, position);
+
+                    if !self.has(32) {
+                        self.state = State::Trailer(words);
+                        return Ok(false);
+                    }
+                    self.trailer[words] = self.get_bits(32) as u32;
+                    words += 1;
+                }
+                self.state = State::Check;
+            }
+            State::Check => {
+                self.confirm_checksum()?;
+                // a following gzip member starts after the trailer
+                self.return_unused_bytes(position);
+                self.state = State::Done;
+            }
+            State::Done => ()
+        }
+        Ok(true)
+    }
+
+    /// Reset per member state so that a new gzip member can be decoded
+    #[cfg(feature = "gzip")]
+    fn start_gzip_member(&mut self) {
+        self.state = State::GzipHeader(GzipHeaderState::Fixed(0));
+        self.crc = !0;
+        self.member_start = self.written;
+        self.is_last_block = false;
+    }
+
+    /// Check whether another gzip member follows the end of the current one,
+    /// starting it if so
+    ///
+    /// The bit buffer is empty here, bytes read ahead were handed back
+    /// when the member ended
+    #[cfg(feature = "gzip")]
+    fn next_gzip_member(&mut self, input: &[u8], position: usize) -> bool {
+        if input[position..].starts_with(&[GZIP_ID1, GZIP_ID2]) {
+            self.start_gzip_member();
+            return true;
+        }
+        false
+    }
+
+    #[cfg(feature = "gzip")]
+    fn decode_gzip_header(
+        &mut self, mut header_state: GzipHeaderState, input: &[u8], position: &mut usize
+    ) -> Result<bool, DecodeErrorStatus> {
+        loop {
+            self.refill(input, position);
+
+            match header_state {
+                GzipHeaderState::Fixed(mut count) => {
+                    while count < self.gzip_header.len() {
+                        if !self.has(8) {
+                            self.refill(input, position);
+
+                            if !self.has(8) {
+                                self.state = State::GzipHeader(GzipHeaderState::Fixed(count));
+                                return Ok(false);
+                            }
+                        }
+                        self.gzip_header[count] = self.get_bits(8) as u8;
+                        count += 1;
+                    }
+                    let header = &self.gzip_header;
+
+                    if header[0] != GZIP_ID1
+                        || header[1] != GZIP_ID2
+                        || header[2] != GZIP_CM_DEFLATE
+                        || (header[3] & GZIP_FRESERVED) != 0
+                    {
+                        return Err(DecodeErrorStatus::CorruptData);
+                    }
+                }
+                GzipHeaderState::ExtraLength => {
+                    if !self.has(16) {
+                        self.state = State::GzipHeader(header_state);
+                        return Ok(false);
+                    }
+                    let length = self.get_bits(16) as usize;
+                    header_state = GzipHeaderState::Extra(length);
+                    continue;
+                }
+                GzipHeaderState::Extra(mut remaining) => {
+                    while remaining > 0 {
+                        if !self.has(8) {
+                            self.refill(input, position);
+
+                            if !self.has(8) {
+                                self.state = State::GzipHeader(GzipHeaderState::Extra(remaining));
+                                return Ok(false);
+                            }
+                        }
+                        self.drop_bits(8);
+                        remaining -= 1;
+                    }
+                }
+                GzipHeaderState::Name | GzipHeaderState::Comment => loop {
+                    // zero terminated strings
+                    if !self.has(8) {
+                        self.refill(input, position);
+
+                        if !self.has(8) {
+                            self.state = State::GzipHeader(header_state);
+                            return Ok(false);
+                        }
+                    }
+                    if self.get_bits(8) == 0 {
+                        break;
+                    }
+                },
+                GzipHeaderState::HeaderCrc => {
+                    if !self.has(16) {
+                        self.state = State::GzipHeader(header_state);
+                        return Ok(false);
+                    }
+                    self.drop_bits(16);
+                }
+            }
+            // move to the next field present in the header
+            let flags = self.gzip_header[3];
+            let done = match header_state {
+                GzipHeaderState::Fixed(_) => 0,
+                GzipHeaderState::ExtraLength | GzipHeaderState::Extra(_) => 1,
+                GzipHeaderState::Name => 2,
+                GzipHeaderState::Comment => 3,
+                GzipHeaderState::HeaderCrc => 4
+            };
+            header_state = if done < 1 && (flags & GZIP_FEXTRA) != 0 {
+                GzipHeaderState::ExtraLength
+            } else if done < 2 && (flags & GZIP_FNAME) != 0 {
+                GzipHeaderState::Name
+            } else if done < 3 && (flags & GZIP_FCOMMENT) != 0 {
+                GzipHeaderState::Comment
+            } else if done < 4 && (flags & GZIP_FHCRC) != 0 {
+                GzipHeaderState::HeaderCrc
+            } else {
+                self.state = State::BlockHeader;
+                return Ok(true);
+            };
+        }
+    }
+
+    fn decode_code_lens(
+        &mut self, mut count: usize, input: &[u8], position: &mut usize
+    ) -> Result<bool, DecodeErrorStatus> {
+        let total = self.num_litlen_syms + self.num_offset_syms;
+
+        while count < total {
+            self.refill(input, position);
+
+            let entry =
+                self.precode_table[(self.bit_buffer & ((1 << PRECODE_TABLE_BITS) - 1)) as usize];
+            let presym = entry >> 16;
+            let codeword_bits = entry as u8;
+
+            let extra_bits = match presym {
+                16 => 2,
+                17 => 3,
+                18 => 7,
+                _ => 0
+            };
+            if !self.has(codeword_bits + extra_bits) {
+                self.state = State::CodeLens(count);
+                return Ok(false);
+            }
+            self.drop_bits(codeword_bits);
+
+            if presym < 16 {
+                self.lens[count] = presym as u8;
+                count += 1;
+                continue;
+            }
+            // the lens array has room for the worst case overrun,
+            // see build_decode_table in the decoder
+            let (value, repeat) = match presym {
+                16 => {
+                    if count == 0 {
+                        return Err(DecodeErrorStatus::CorruptData);
+                    }
+                    (self.lens[count - 1], 3 + self.get_bits(2) as usize)
+                }
+                17 => (0, 3 + self.get_bits(3) as usize),
+                _ => (0, 11 + self.get_bits(7) as usize)
+            };
+            self.lens[count..count + repeat].fill(value);
+            count += repeat;
+        }
+        build_decode_table_inner(
+            &self.lens[self.num_litlen_syms..],
+            &OFFSET_DECODE_RESULTS,
+            &mut self.offset_table,
+            OFFSET_TABLEBITS,
+            self.num_offset_syms,
+            DEFLATE_MAX_OFFSET_CODEWORD_LENGTH
+        )?;
+        build_decode_table_inner(
+            &self.lens,
+            &LITLEN_DECODE_RESULTS,
+            &mut self.litlen_table,
+            LITLEN_TABLE_BITS,
+            self.num_litlen_syms,
+            DEFLATE_MAX_LITLEN_CODEWORD_LENGTH
+        )?;
+        self.state = State::HuffmanData;
+
+        Ok(true)
+    }
+
+    fn load_static_tables(&mut self) -> Result<(), DecodeErrorStatus> {
+        if self.static_codes_loaded {
+            return Ok(());
+        }
+        let mut lens = [0_u8; DEFLATE_NUM_LITLEN_SYMS + DEFLATE_NUM_OFFSET_SYMS];
+
+        lens[000..144].fill(8);
+        lens[144..256].fill(9);
+        lens[256..280].fill(7);
+        lens[280..288].fill(8);
+        lens[288..].fill(5);
+
+        build_decode_table_inner(
+            &lens[DEFLATE_NUM_LITLEN_SYMS..],
+            &OFFSET_DECODE_RESULTS,
+            &mut self.offset_table,
+            OFFSET_TABLEBITS,
+            DEFLATE_NUM_OFFSET_SYMS,
+            DEFLATE_MAX_OFFSET_CODEWORD_LENGTH
+        )?;
+        build_decode_table_inner(
+            &lens,
+            &LITLEN_DECODE_RESULTS,
+            &mut self.litlen_table,
+            LITLEN_TABLE_BITS,
+            DEFLATE_NUM_LITLEN_SYMS,
+            DEFLATE_MAX_LITLEN_CODEWORD_LENGTH
+        )?;
+        self.static_codes_loaded = true;
+
+        Ok(())
+    }
+
+    /// Decode literals and matches until the block ends, the window
+    /// has enough pending bytes or we run out of input
+    fn decode_huffman(
+        &mut self, input: &[u8], position: &mut usize
+    ) -> Result<bool, DecodeErrorStatus> {
+        loop {
+            if self.written - self.flushed >= MAX_PENDING {
+                return Ok(true);
+            }
+            self.check_limit()?;
+            self.refill(input, position);
+
+            let Some((entry, entry_bits, extra)) = lookup(
+                &self.litlen_table,
+                LITLEN_TABLE_BITS,
+                self.bit_buffer,
+                self.bits_left
+            ) else {
+                return Ok(false);
+            };
+
+            if entry & HUFFDEC_LITERAL != 0 {
+                self.drop_bits(entry_bits);
+                self.window[self.written & WINDOW_MASK] = (entry >> 16) as u8;
+                self.written += 1;
+                continue;
+            }
+            if entry & HUFFDEC_END_OF_BLOCK != 0 {
+                self.drop_bits(entry_bits);
+                self.end_block();
+                return Ok(true);
+            }
+            let length = (entry >> 16) as usize + extra as usize;
+
+            // the offset codeword follows the length, only consume
+            // both once we know the whole match is present
+            let Some((offset_entry, offset_bits, offset_extra)) = lookup(
+                &self.offset_table,
+                OFFSET_TABLEBITS,
+                self.bit_buffer >> entry_bits,
+                self.bits_left - entry_bits
+            ) else {
+                return Ok(false);
+            };
+            let offset = (offset_entry >> 16) as usize + offset_extra as usize;
+
+            if offset > self.written {
+                return Err(DecodeErrorStatus::CorruptData);
+            }
+            self.drop_bits(entry_bits + offset_bits);
+            self.copy_match(offset, length);
+        }
+    }
+
+    fn end_block(&mut self) {
+        self.state = if self.is_last_block { State::Trailer(0) } else { State::BlockHeader };
+    }
+
+    fn confirm_checksum(&mut self) -> Result<(), DecodeErrorStatus> {
+        if !self.options.get_confirm_checksum() {
+            return Ok(());
+        }
+        match self.format {
+            DeflateFormat::Raw => (),
+            #[cfg(feature = "zlib")]
+            DeflateFormat::Zlib => {
+                // adler is stored big endian
+                let expected = self.trailer[0].swap_bytes();
+                let found = self.adler.finish();
+
+                if expected != found {
+                    return Err(DecodeErrorStatus::MismatchedAdler(expected, found));
+                }
+            }
+            #[cfg(feature = "gzip")]
+            DeflateFormat::Gzip => {
+                let expected = self.trailer[0];
+                let found = !self.crc;
+
+                if expected != found {
+                    return Err(DecodeErrorStatus::MismatchedCRC(expected, found));
+                }
+                if self.trailer[1] != (self.written - self.member_start) as u32 {
+                    return Err(DecodeErrorStatus::Generic(
+                        "ISIZE does not match actual bytes"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy pending bytes from the window to `output`, returning
+    /// the number of bytes copied
+    fn flush(&mut self, output: &mut [u8]) -> usize {
+        let mut count = 0;
+
+        while self.flushed < self.written && count < output.len() {
+            let start = self.flushed & WINDOW_MASK;
+            let length = (self.written - self.flushed)
+                .min(WINDOW_SIZE - start)
+                .min(output.len() - count);
+
+            let bytes = &self.window[start..start + length];
+
+            output[count..count + length].copy_from_slice(bytes);
+
+            if self.options.get_confirm_checksum() {
+                match self.format {
+                    DeflateFormat::Raw => (),
+                    #[cfg(feature = "zlib")]
+                    DeflateFormat::Zlib => self.adler.write(bytes),
+                    #[cfg(feature = "gzip")]
+                    DeflateFormat::Gzip => self.crc = crate::crc::crc32(bytes, self.crc)
+                }
+            }
+            self.flushed += length;
+            count += length;
+        }
+        count
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) -> Result<(), DecodeErrorStatus> {
+        self.write_window(bytes);
+        self.check_limit()
+    }
+
+    fn write_window(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let start = self.written & WINDOW_MASK;
+            let length = bytes.len().min(WINDOW_SIZE - start);
+
+            self.window[start..start + length].copy_from_slice(&bytes[..length]);
+            self.written += length;
+            bytes = &bytes[length..];
+        }
+    }
+
+    fn check_limit(&self) -> Result<(), DecodeErrorStatus> {
+        if self.total_out() > self.options.get_limit() {
+            return Err(DecodeErrorStatus::OutputLimitExceeded(
+                self.options.get_limit(),
+                self.total_out()
+            ));
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn copy_match(&mut self, offset: usize, length: usize) {
+        let dest = self.written & WINDOW_MASK;
+        let src = (self.written - offset) & WINDOW_MASK;
+
+        if offset >= length && dest + length <= WINDOW_SIZE && src + length <= WINDOW_SIZE {
+            self.window.copy_within(src..src + length, dest);
+        } else {
+            // overlapping match or one wrapping around the window
+            for i in 0..length {
+                self.window[(dest + i) & WINDOW_MASK] = self.window[(src + i) & WINDOW_MASK];
+            }
+        }
+        self.written += length;
+    }
+
+    /// Hand whole bytes of the bit buffer back to the input they were pulled from
+    ///
+    /// Refilling reads ahead, so the bit buffer may hold bytes past the end of
+    /// the stream. These are returned whenever a call ends without needing input,
+    /// the bits left when input runs out all belong to the symbol being decoded.
+    /// This way, the bit buffer never carries whole bytes from an earlier call
+    /// which may lie past the end of the stream
+    fn return_unused_bytes(&mut self, position: &mut usize) {
+        let returned = usize::from(self.bits_left / 8).min(*position);
+
+        *position -= returned;
+        self.bits_left -= (returned * 8) as u8;
+        self.bit_buffer &= (1 << self.bits_left) - 1;
+    }
+
+    /// Pull bytes from `input` until the bit buffer holds at least 56 bits
+    /// or input runs out
+    #[inline(always)]
+    fn refill(&mut self, input: &[u8], position: &mut usize) {
+        if self.bits_left >= 56 {
+            return;
+        }
+        if let Some(bytes) = input.get(*position..*position + 8) {
+            let new_buffer = u64::from_le_bytes(bytes.try_into().unwrap());
+            let num_bytes = (63 - self.bits_left) >> 3;
+
+            self.bit_buffer |= new_buffer << self.bits_left;
+            self.bits_left += num_bytes << 3;
+            // remove bits of bytes we did not consume
+            self.bit_buffer &= (1 << self.bits_left) - 1;
+
+            *position += usize::from(num_bytes);
+        } else {
+            while self.bits_left < 56 && *position < input.len() {
+                self.bit_buffer |= u64::from(input[*position]) << self.bits_left;
+                self.bits_left += 8;
+                *position += 1;
+            }
+        }
+    }
+
+    #[inline(always)]
+    const fn has(&self, bits: u8) -> bool {
+        self.bits_left >= bits
+    }
+
+    #[inline(always)]
+    fn get_bits(&mut self, bits: u8) -> u64 {
+        let value = self.bit_buffer & ((1 << bits) - 1);
+        self.drop_bits(bits);
+        value
+    }
+
+    #[inline(always)]
+    fn drop_bits(&mut self, bits: u8) {
+        debug_assert!(self.bits_left >= bits);
+        self.bit_buffer >>= bits;
+        self.bits_left -= bits;
+    }
+
+    fn align_to_byte(&mut self) {
+        self.drop_bits(self.bits_left & 7);
+    }
+}
+
+/// Look up the next symbol in a litlen or offset decode table
+///
+/// Returns the table entry, the number of bits taken by the codeword and its extra bits,
+/// and the value of the extra bits, or `None` if `bits_left` is too small to hold them
+#[inline(always)]
+fn lookup(
+    table: &[u32], table_bits: usize, bit_buffer: u64, bits_left: u8
+) -> Option<(u32, u8, u32)> {
+    let mut entry = table[(bit_buffer & ((1 << table_bits) - 1)) as usize];
+    let mut main_bits = 0;
+
+    if entry & HUFFDEC_SUITABLE_POINTER != 0 {
+        // codeword is longer than table_bits, continue in the subtable
+        main_bits = entry as u8;
+
+        if main_bits > bits_left {
+            return None;
+        }
+        let subtable_bits = (entry >> 8) & 0x3F;
+        let index = ((bit_buffer >> main_bits) & ((1 << subtable_bits) - 1)) as usize;
+
+        entry = table[(entry >> 16) as usize + index];
+    }
+    // the low byte holds the codeword length plus the number of extra bits,
+    // the next one the codeword length alone
+    let total_bits = main_bits + entry as u8;
+
+    if total_bits > bits_left {
+        return None;
+    }
+    let bits = bit_buffer >> main_bits;
+    let extra = (bits & ((1 << (entry as u8)) - 1)) >> ((entry >> 8) & 0x3F);
+
+    Some((entry, total_bits, extra as u32))
+}
+
+#[cfg(feature = "zlib")]
+#[test]
+fn test_streaming_roundtrip() {
+    use crate::{DeflateEncoder, DeflateEncodingOptions};
+
+    let mut data = vec![];
+    let mut state = 0x1234_5678_u32;
+
+    for i in 0..300_000_u32 {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        // mix of random bytes and repeating runs to get literals, matches and stored blocks
+        let byte = match (i / 10_000) % 3 {
+            0 => (state >> 24) as u8,
+            1 => (i % 251) as u8,
+            _ => ((state >> 28) as u8) + b'a'
+        };
+        data.push(byte);
+    }
+
+    for level in [0, 1, 6, 9] {
+        let options = DeflateEncodingOptions::default().set_level(level);
+
+        let raw = DeflateEncoder::new_with_options(&data, options).encode_deflate();
+        let zlib = DeflateEncoder::new_with_options(&data, options).encode_zlib();
+
+        for (format, compressed) in [(DeflateFormat::Raw, raw), (DeflateFormat::Zlib, zlib)] {
+            for (in_chunk, out_chunk) in [(1, 1), (7, 300), (5000, 3), (1 << 20, 1 << 20)] {
+                let mut decoder = DeflateStreamDecoder::new(format);
+                let mut output = vec![];
+                let mut buffer = vec![0; out_chunk];
+                let mut input = &compressed[..];
+                let mut status = StreamStatus::NeedsInput;
+
+                while status != StreamStatus::Done {
+                    let chunk = &input[..in_chunk.min(input.len())];
+                    let result = decoder.decode(chunk, &mut buffer).unwrap();
+
+                    input = &input[result.consumed..];
+                    output.extend_from_slice(&buffer[..result.written]);
+                    status = result.status;
+                }
+                assert!(input.is_empty());
+                assert!(
+                    output == data,
+                    "level {level} format {format:?} chunks {in_chunk} {out_chunk}"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_streaming_gzip_members() {
+    use crate::DeflateEncoder;
+
+    let first: Vec<u8> = (0..20_000_u32).map(|x| (x * x % 251) as u8).collect();
+    let second = b"a second member, after the trailer of the first".to_vec();
+
+    let mut stream = DeflateEncoder::new(&first).encode_gzip();
+    stream.extend_from_slice(&DeflateEncoder::new(&second).encode_gzip());
+
+    let mut expected = first.clone();
+    expected.extend_from_slice(&second);
+
+    // small output buffers pause the decoder between reading
+    // the trailer and confirming the checksum
+    for (in_chunk, out_chunk) in [
+        (1, 1),
+        (1, 1 << 20),
+        (7, 1),
+        (13, 5),
+        (5000, 1),
+        (1 << 20, 2)
+    ] {
+        let mut decoder = DeflateStreamDecoder::new(DeflateFormat::Gzip);
+        let mut output = vec![];
+        let mut buffer = vec![0; out_chunk];
+        let mut input = &stream[..];
+        let mut length = in_chunk;
+
+        loop {
+            let chunk = &input[..length.min(input.len())];
+            let result = decoder.decode(chunk, &mut buffer).unwrap();
+
+            assert!(
+                result.consumed > 0 || result.written > 0 || result.status == StreamStatus::Done,
+                "no progress with chunks {in_chunk} {out_chunk}"
+            );
+            input = &input[result.consumed..];
+            output.extend_from_slice(&buffer[..result.written]);
+
+            // a member may end with the chunk, in which case decoding
+            // continues with the next member on the following call,
+            // once there is enough input to recognise its header
+            if result.status == StreamStatus::Done && result.consumed == 0 {
+                if chunk.len() == input.len() {
+                    break;
+                }
+                length += in_chunk;
+            } else {
+                length = in_chunk;
+            }
+        }
+        assert!(input.is_empty(), "chunks {in_chunk} {out_chunk}");
+        assert!(output == expected, "chunks {in_chunk} {out_chunk}");
+    }
+}
+
+#[cfg(feature = "zlib")]
+#[test]
+fn test_streaming_trailing_data() {
+    use crate::DeflateEncoder;
+
+    let data: Vec<u8> = (0..40_000_u32).map(|x| (x * x % 251) as u8).collect();
+    let trailing = b"the next record, not part of the stream";
+
+    for format in [DeflateFormat::Raw, DeflateFormat::Zlib] {
+        let mut stream = match format {
+            DeflateFormat::Raw => DeflateEncoder::new(&data).encode_deflate(),
+            _ => DeflateEncoder::new(&data).encode_zlib()
+        };
+        let length = stream.len();
+        stream.extend_from_slice(trailing);
+
+        // small output buffers end calls with bytes read ahead in the bit buffer
+        for (in_chunk, out_chunk) in [(1, 1), (9, 1), (5000, 1), (1 << 20, 1), (1 << 20, 3)] {
+            let mut decoder = DeflateStreamDecoder::new(format);
+            let mut output = vec![];
+            let mut buffer = vec![0; out_chunk];
+            let mut position = 0;
+
+            loop {
+                let chunk = &stream[position..(position + in_chunk).min(stream.len())];
+                let result = decoder.decode(chunk, &mut buffer).unwrap();
+
+                position += result.consumed;
+                output.extend_from_slice(&buffer[..result.written]);
+
+                if result.status == StreamStatus::Done {
+                    break;
+                }
+            }
+            assert_eq!(position, length, "{format:?} chunks {in_chunk} {out_chunk}");
+            assert!(output == data, "{format:?} chunks {in_chunk} {out_chunk}");
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_streaming_gzip_trailing_byte() {
+    use crate::DeflateEncoder;
+
+    let mut stream = DeflateEncoder::new(b"hello").encode_gzip();
+    let length = stream.len();
+    // could be the start of another member, but nothing follows
+    stream.push(GZIP_ID1);
+
+    let mut decoder = DeflateStreamDecoder::new(DeflateFormat::Gzip);
+    let mut buffer = [0; 16];
+    let result = decoder.decode(&stream, &mut buffer).unwrap();
+
+    assert_eq!(result.status, StreamStatus::Done);
+    assert_eq!(result.consumed, length);
+    assert_eq!(&buffer[..result.written], b"hello");
+
+    // still done when the byte is passed again
+    let result = decoder.decode(&stream[length..], &mut buffer).unwrap();
+
+    assert_eq!(result.status, StreamStatus::Done);
+    assert_eq!((result.consumed, result.written), (0, 0));
+}
+
+#[cfg(feature = "zlib")]
+#[test]
+fn test_streaming_error_keeps_output() {
+    use crate::DeflateEncoder;
+
+    let data: Vec<u8> = (0..3000_u32).map(|x| (x * x % 251) as u8).collect();
+    let mut stream = DeflateEncoder::new(&data).encode_zlib();
+    // a wrong checksum is found once everything is decoded
+    let length = stream.len();
+    stream[length - 1] ^= 1;
+
+    for out_chunk in [1, 100, 1 << 20] {
+        let mut decoder = DeflateStreamDecoder::new(DeflateFormat::Zlib);
+        let mut output = vec![];
+        let mut buffer = vec![0; out_chunk];
+        let mut input = &stream[..];
+
+        let error = loop {
+            match decoder.decode(input, &mut buffer) {
+                Ok(result) => {
+                    assert_eq!(result.status, StreamStatus::OutputFull);
+                    input = &input[result.consumed..];
+                    output.extend_from_slice(&buffer[..result.written]);
+                }
+                Err(error) => break error
+            }
+        };
+        assert!(matches!(
+            error.error,
+            DecodeErrorStatus::MismatchedAdler(..)
+        ));
+        assert!(output == data, "chunk {out_chunk}");
+    }
+}
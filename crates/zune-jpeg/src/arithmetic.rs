@@ -0,0 +1,530 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Decoding of arithmetic coded (QM coder) images
+//!
+//! Arithmetic coding replaces Huffman coding for sequential (SOF9) and
+//! progressive (SOF10) images, the coefficients and how they are laid out
+//! stay the same.
+//!
+//! Every decision is decoded with an adaptive probability estimate, called a
+//! statistics bin, which is picked from the context of the decision,
+//! see Annex D and Annex F.1.4.4 of the specification
+//! (<https://www.w3.org/Graphics/JPEG/itu-t81.pdf>).
+//!
+//! This follows the decoder in libjpeg(`jdarith.c`).
+#![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+
+use alloc::format;
+
+use zune_core::bytestream::{ZByteReaderTrait, ZReader};
+
+use crate::bitstream::BitStream;
+use crate::decoder::MAX_COMPONENTS;
+use crate::errors::DecodeErrors;
+use crate::marker::Marker;
+use crate::mcu::DCT_BLOCK;
+use crate::misc::UN_ZIGZAG;
+
+/// Number of statistics bins for DC coefficients of a single table
+const DC_STAT_BINS: usize = 64;
+/// Number of statistics bins for AC coefficients of a single table
+const AC_STAT_BINS: usize = 256;
+/// Position of the first AC statistics bin
+const AC_STATS_START: usize = DC_STAT_BINS * MAX_COMPONENTS;
+/// A bin whose probability estimate never changes from 0.5,
+/// used for sign bits and DC refinement
+const FIXED_BIN: usize = AC_STATS_START + AC_STAT_BINS * MAX_COMPONENTS;
+
+/// Probability estimation state machine, table D.2 of the specification
+///
+/// Each entry holds `(Qe, Next_Index_LPS, Next_Index_MPS, Switch_MPS)`, the
+/// last entry is not part of the specification and is the estimate of
+/// [`FIXED_BIN`]
+#[rustfmt::skip]
+const QE_TABLE: [(u32, u8, u8, bool); 114] = [
+    (0x5a1d, 1, 1, true), (0x2586, 14, 2, false), (0x1114, 16, 3, false),
+    (0x080b, 18, 4, false), (0x03d8, 20, 5, false), (0x01da, 23, 6, false),
+    (0x00e5, 25, 7, false), (0x006f, 28, 8, false), (0x0036, 30, 9, false),
+    (0x001a, 33, 10, false), (0x000d, 35, 11, false), (0x0006, 9, 12, false),
+    (0x0003, 10, 13, false), (0x0001, 12, 13, false), (0x5a7f, 15, 15, true),
+    (0x3f25, 36, 16, false), (0x2cf2, 38, 17, false), (0x207c, 39, 18, false),
+    (0x17b9, 40, 19, false), (0x1182, 42, 20, false), (0x0cef, 43, 21, false),
+    (0x09a1, 45, 22, false), (0x072f, 46, 23, false), (0x055c, 48, 24, false),
+    (0x0406, 49, 25, false), (0x0303, 51, 26, false), (0x0240, 52, 27, false),
+    (0x01b1, 54, 28, false), (0x0144, 56, 29, false), (0x00f5, 57, 30, false),
+    (0x00b7, 59, 31, false), (0x008a, 60, 32, false), (0x0068, 62, 33, false),
+    (0x004e, 63, 34, false), (0x003b, 32, 35, false), (0x002c, 33, 9, false),
+    (0x5ae1, 37, 37, true), (0x484c, 64, 38, false), (0x3a0d, 65, 39, false),
+    (0x2ef1, 67, 40, false), (0x261f, 68, 41, false), (0x1f33, 69, 42, false),
+    (0x19a8, 70, 43, false), (0x1518, 72, 44, false), (0x1177, 73, 45, false),
+    (0x0e74, 74, 46, false), (0x0bfb, 75, 47, false), (0x09f8, 77, 48, false),
+    (0x0861, 78, 49, false), (0x0706, 79, 50, false), (0x05cd, 48, 51, false),
+    (0x04de, 50, 52, false), (0x040f, 50, 53, false), (0x0363, 51, 54, false),
+    (0x02d4, 52, 55, false), (0x025c, 53, 56, false), (0x01f8, 54, 57, false),
+    (0x01a4, 55, 58, false), (0x0160, 56, 59, false), (0x0125, 57, 60, false),
+    (0x00f6, 58, 61, false), (0x00cb, 59, 62, false), (0x00ab, 61, 63, false),
+    (0x008f, 61, 32, false), (0x5b12, 65, 65, true), (0x4d04, 80, 66, false),
+    (0x412c, 81, 67, false), (0x37d8, 82, 68, false), (0x2fe8, 83, 69, false),
+    (0x293c, 84, 70, false), (0x2379, 86, 71, false), (0x1edf, 87, 72, false),
+    (0x1aa9, 87, 73, false), (0x174e, 72, 74, false), (0x1424, 72, 75, false),
+    (0x119c, 74, 76, false), (0x0f6b, 74, 77, false), (0x0d51, 75, 78, false),
+    (0x0bb6, 77, 79, false), (0x0a40, 77, 48, false), (0x5832, 80, 81, true),
+    (0x4d1c, 88, 82, false), (0x438e, 89, 83, false), (0x3bdd, 90, 84, false),
+    (0x34ee, 91, 85, false), (0x2eae, 92, 86, false), (0x299a, 93, 87, false),
+    (0x2516, 86, 71, false), (0x5570, 88, 89, true), (0x4ca9, 95, 90, false),
+    (0x44d9, 96, 91, false), (0x3e22, 97, 92, false), (0x3824, 99, 93, false),
+    (0x32b4, 99, 94, false), (0x2e17, 93, 86, false), (0x56a8, 95, 96, true),
+    (0x4f46, 101, 97, false), (0x47e5, 102, 98, false), (0x41cf, 103, 99, false),
+    (0x3c3d, 104, 100, false), (0x375e, 99, 93, false), (0x5231, 105, 102, false),
+    (0x4c0f, 106, 103, false), (0x4639, 107, 104, false), (0x415e, 103, 99, false),
+    (0x5627, 105, 106, true), (0x50e7, 108, 107, false), (0x4b85, 109, 103, false),
+    (0x5597, 110, 109, false), (0x504f, 111, 107, false), (0x5a10, 110, 111, true),
+    (0x5522, 112, 109, false), (0x59eb, 112, 111, true), (0x5a1d, 113, 113, false)
+];
+
+/// Conditioning tables, set by the DAC marker
+#[derive(Copy, Clone)]
+pub(crate) struct ArithmeticConditioning {
+    /// Lower bound `L` of DC differences considered small
+    pub dc_lower: [u8; MAX_COMPONENTS],
+    /// Upper bound `U` of DC differences considered small
+    pub dc_upper: [u8; MAX_COMPONENTS],
+    /// `Kx`, the index where AC coefficients switch their magnitude
+    /// statistics bins
+    pub ac_kx:    [u8; MAX_COMPONENTS]
+}
+
+impl Default for ArithmeticConditioning {
+    fn default() -> Self {
+        ArithmeticConditioning {
+            dc_lower: [0; MAX_COMPONENTS],
+            dc_upper: [1; MAX_COMPONENTS],
+            ac_kx:    [5; MAX_COMPONENTS]
+        }
+    }
+}
+
+/// Bytes of an arithmetic coded segment
+struct Source<'a, T: ZByteReaderTrait> {
+    reader:      &'a mut ZReader<T>,
+    marker:      &'a mut Option<Marker>,
+    overread_by: &'a mut usize,
+    seen_eoi:    bool
+}
+
+impl<T: ZByteReaderTrait> Source<'_, T> {
+    /// Read the next byte of the segment, removing stuffed zeroes
+    ///
+    /// Unlike Huffman coding, the decoder may need bytes past the end of
+    /// the segment, once a marker is found we feed it zeroes
+    fn next_byte(&mut self) -> Result<u32, DecodeErrors> {
+        if self.marker.is_some() || self.seen_eoi {
+            return Ok(0);
+        }
+        let byte = self.reader.read_u8();
+        *self.overread_by += usize::from(self.reader.eof()?);
+
+        if byte != 0xFF {
+            return Ok(u32::from(byte));
+        }
+        let mut next_byte = self.reader.read_u8();
+
+        while next_byte == 0xFF {
+            next_byte = self.reader.read_u8();
+        }
+        if next_byte == 0 {
+            return Ok(0xFF);
+        }
+        *self.marker =
+            Some(Marker::from_u8(next_byte).ok_or_else(|| {
+                DecodeErrors::Format(format!("Unknown marker 0xFF{next_byte:X}"))
+            })?);
+        Ok(0)
+    }
+}
+
+/// State of an arithmetic decoder
+pub(crate) struct ArithmeticDecoder {
+    /// Code register
+    c:          u32,
+    /// Probability interval
+    a:          u32,
+    /// Bits left in `c`, negative before the first two bytes are read
+    ct:         i32,
+    /// Statistics bins of all tables, each holds an index into
+    /// [`QE_TABLE`] and the more probable symbol at the top bit
+    stats:      [u8; FIXED_BIN + 1],
+    /// Context of the next DC difference for each component
+    dc_context: [usize; MAX_COMPONENTS]
+}
+
+impl ArithmeticDecoder {
+    pub(crate) const fn new() -> ArithmeticDecoder {
+        let mut stats = [0; FIXED_BIN + 1];
+        stats[FIXED_BIN] = 113;
+
+        ArithmeticDecoder {
+            c: 0,
+            a: 0,
+            ct: -16,
+            stats,
+            dc_context: [0; MAX_COMPONENTS]
+        }
+    }
+
+    /// Reset the decoder at the start of a scan or after a restart marker
+    pub(crate) fn reset(&mut self) {
+        *self = ArithmeticDecoder::new();
+    }
+
+    /// Decode a single decision using the statistics bin at `st`
+    fn decode<T: ZByteReaderTrait>(
+        &mut self, source: &mut Source<T>, st: usize
+    ) -> Result<bool, DecodeErrors> {
+        // renormalization and data input, section D.2.6
+        while self.a < 0x8000 {
+            self.ct -= 1;
+
+            if self.ct < 0 {
+                self.c = (self.c << 8) | source.next_byte()?;
+                self.ct += 8;
+
+                if self.ct < 0 {
+                    self.ct += 1;
+                    // got the two initial bytes
+                    if self.ct == 0 {
+                        self.a = 0x8000;
+                    }
+                }
+            }
+            self.a <<= 1;
+        }
+        let state = self.stats[st];
+        let (qe, next_lps, next_mps, switch_mps) = QE_TABLE[usize::from(state & 0x7F)];
+        let mut symbol = state >> 7;
+
+        let estimate_after_mps = (state & 0x80) | next_mps;
+        let mut estimate_after_lps = (state & 0x80) ^ next_lps;
+
+        if switch_mps {
+            estimate_after_lps ^= 0x80;
+        }
+        // decoding and estimation, sections D.2.4 and D.2.5
+        self.a -= qe;
+        let temp = self.a << self.ct;
+
+        if self.c >= temp {
+            self.c -= temp;
+            // conditional exchange of the less probable symbol
+            if self.a < qe {
+                self.stats[st] = estimate_after_mps;
+            } else {
+                self.stats[st] = estimate_after_lps;
+                symbol ^= 1;
+            }
+            self.a = qe;
+        } else if self.a < 0x8000 {
+            // conditional exchange of the more probable symbol
+            if self.a < qe {
+                self.stats[st] = estimate_after_lps;
+                symbol ^= 1;
+            } else {
+                self.stats[st] = estimate_after_mps;
+            }
+        }
+        Ok(symbol == 1)
+    }
+
+    /// Decode the difference of a DC coefficient from its prediction, figure F.19
+    fn decode_dc_diff<T: ZByteReaderTrait>(
+        &mut self, source: &mut Source<T>, conditioning: &ArithmeticConditioning, table: usize,
+        component: usize
+    ) -> Result<i32, DecodeErrors> {
+        let table = table % MAX_COMPONENTS;
+        let component = component % MAX_COMPONENTS;
+        let stats = table * DC_STAT_BINS;
+        let mut st = stats + self.dc_context[component];
+
+        if !self.decode(source, st)? {
+            self.dc_context[component] = 0;
+            return Ok(0);
+        }
+        let sign = usize::from(self.decode(source, st + 1)?);
+        st += 2 + sign;
+
+        let mut m = 0;
+
+        if self.decode(source, st)? {
+            st = stats + 20;
+            m = self.decode_magnitude(source, &mut st, 1)?;
+        }
+        // conditioning category of the next difference, section F.1.4.4.1.2
+        let lower = (1 << conditioning.dc_lower[table]) >> 1;
+        let upper = (1 << conditioning.dc_upper[table]) >> 1;
+
+        self.dc_context[component] = if m < lower {
+            0
+        } else if m > upper {
+            12 + sign * 4
+        } else {
+            4 + sign * 4
+        };
+        let value = self.decode_magnitude_bits(source, st + 14, m)?;
+
+        if sign == 1 {
+            Ok(-value)
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Decode the magnitude category of a value, figure F.23
+    ///
+    /// Returns the largest power of two not above the magnitude,
+    /// `st` is left at the bin of the last decision
+    fn decode_magnitude<T: ZByteReaderTrait>(
+        &mut self, source: &mut Source<T>, st: &mut usize, mut m: i32
+    ) -> Result<i32, DecodeErrors> {
+        while self.decode(source, *st)? {
+            m <<= 1;
+
+            if m == 0x8000 {
+                return Err(DecodeErrors::FormatStatic(
+                    "Magnitude overflow in arithmetic coded data, corrupt JPEG"
+                ));
+            }
+            *st += 1;
+        }
+        Ok(m)
+    }
+
+    /// Decode the bits below the top bit of a magnitude, figure F.24
+    fn decode_magnitude_bits<T: ZByteReaderTrait>(
+        &mut self, source: &mut Source<T>, st: usize, m: i32
+    ) -> Result<i32, DecodeErrors> {
+        let mut value = m;
+        let mut bit = m >> 1;
+
+        while bit != 0 {
+            if self.decode(source, st)? {
+                value |= bit;
+            }
+            bit >>= 1;
+        }
+        Ok(value + 1)
+    }
+
+    /// Decode AC coefficients `start..=end` in zigzag order, figure F.20
+    ///
+    /// `write` receives the zigzag index and value of every non-zero
+    /// coefficient
+    fn decode_ac<T: ZByteReaderTrait>(
+        &mut self, source: &mut Source<T>, conditioning: &ArithmeticConditioning, table: usize,
+        start: usize, end: usize, mut write: impl FnMut(usize, i32)
+    ) -> Result<(), DecodeErrors> {
+        let table = table % MAX_COMPONENTS;
+        let stats = AC_STATS_START + table * AC_STAT_BINS;
+        let kx = usize::from(conditioning.ac_kx[table]);
+
+        let mut k = start;
+
+        while k <= end {
+            let mut st = stats + 3 * (k - 1);
+            // end of block
+            if self.decode(source, st)? {
+                break;
+            }
+            while !self.decode(source, st + 1)? {
+                st += 3;
+                k += 1;
+
+                if k > end {
+                    return Err(DecodeErrors::FormatStatic(
+                        "Spectral overflow in arithmetic coded data, corrupt JPEG"
+                    ));
+                }
+            }
+            let sign = self.decode(source, FIXED_BIN)?;
+            st += 2;
+
+            let mut m = 0;
+            // the first two magnitude decisions share a bin
+            if self.decode(source, st)? {
+                m = 1;
+
+                if self.decode(source, st)? {
+                    st = stats + if k <= kx { 189 } else { 217 };
+                    m = self.decode_magnitude(source, &mut st, 2)?;
+                }
+            }
+            let value = self.decode_magnitude_bits(source, st + 14, m)?;
+
+            write(k, if sign { -value } else { value });
+
+            k += 1;
+        }
+        Ok(())
+    }
+}
+
+impl BitStream {
+    /// Split the stream into the arithmetic decoder and its source of bytes
+    fn arithmetic_parts<'a, T: ZByteReaderTrait>(
+        &'a mut self, reader: &'a mut ZReader<T>
+    ) -> Result<(&'a mut ArithmeticDecoder, Source<'a, T>), DecodeErrors> {
+        let BitStream {
+            arithmetic,
+            marker,
+            overread_by,
+            seen_eoi,
+            ..
+        } = self;
+
+        let decoder = arithmetic.as_mut().ok_or(DecodeErrors::FormatStatic(
+            "Arithmetic decoder used on a Huffman coded image"
+        ))?;
+        let source = Source {
+            reader,
+            marker,
+            overread_by,
+            seen_eoi: *seen_eoi
+        };
+        Ok((decoder, source))
+    }
+
+    /// Decode a block of a sequential arithmetic coded image
+    ///
+    /// The arithmetic equivalent of [`BitStream::decode_mcu_block`]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn decode_arith_mcu_block<T: ZByteReaderTrait>(
+        &mut self, reader: &mut ZReader<T>, conditioning: &ArithmeticConditioning, dc_table: usize,
+        ac_table: usize, qt_table: &[i32; DCT_BLOCK], block: &mut [i32; 64],
+        dc_prediction: &mut i32, component: usize
+    ) -> Result<(), DecodeErrors> {
+        let (decoder, mut source) = self.arithmetic_parts(reader)?;
+
+        let diff = decoder.decode_dc_diff(&mut source, conditioning, dc_table, component)?;
+        // predictions are modulo 2^16
+        *dc_prediction = i32::from(dc_prediction.wrapping_add(diff) as i16);
+
+        block[0] = *dc_prediction * qt_table[0];
+
+        decoder.decode_ac(&mut source, conditioning, ac_table, 1, 63, |k, value| {
+            let pos = UN_ZIGZAG[k] & 63;
+            block[pos] = value * qt_table[pos];
+        })
+    }
+
+    /// Decode the DC coefficient of a block in the first progressive scan
+    pub(crate) fn decode_arith_dc_first<T: ZByteReaderTrait>(
+        &mut self, reader: &mut ZReader<T>, conditioning: &ArithmeticConditioning, dc_table: usize,
+        block: &mut i16, dc_prediction: &mut i32, component: usize
+    ) -> Result<(), DecodeErrors> {
+        let successive_low = self.successive_low;
+        let (decoder, mut source) = self.arithmetic_parts(reader)?;
+
+        let diff = decoder.decode_dc_diff(&mut source, conditioning, dc_table, component)?;
+        *dc_prediction = i32::from(dc_prediction.wrapping_add(diff) as i16);
+
+        *block = (*dc_prediction as i16).wrapping_mul(1_i16 << successive_low);
+        Ok(())
+    }
+
+    /// Refine the DC coefficient of a block by a single bit
+    pub(crate) fn decode_arith_dc_refine<T: ZByteReaderTrait>(
+        &mut self, reader: &mut ZReader<T>, block: &mut i16
+    ) -> Result<(), DecodeErrors> {
+        let successive_low = self.successive_low;
+        let (decoder, mut source) = self.arithmetic_parts(reader)?;
+
+        if decoder.decode(&mut source, FIXED_BIN)? {
+            *block |= 1 << successive_low;
+        }
+        Ok(())
+    }
+
+    /// Decode the AC coefficients of a block in its first progressive scan
+    pub(crate) fn decode_arith_ac_first<T: ZByteReaderTrait>(
+        &mut self, reader: &mut ZReader<T>, conditioning: &ArithmeticConditioning, ac_table: usize,
+        block: &mut [i16; 64]
+    ) -> Result<(), DecodeErrors> {
+        let (start, end) = (usize::from(self.spec_start), usize::from(self.spec_end));
+        let successive_low = self.successive_low;
+        let (decoder, mut source) = self.arithmetic_parts(reader)?;
+
+        decoder.decode_ac(
+            &mut source,
+            conditioning,
+            ac_table,
+            start,
+            end,
+            |k, value| {
+                block[UN_ZIGZAG[k] & 63] = (value << successive_low) as i16;
+            }
+        )
+    }
+
+    /// Refine the AC coefficients of a block by a single bit, section G.1.3.3
+    pub(crate) fn decode_arith_ac_refine<T: ZByteReaderTrait>(
+        &mut self, reader: &mut ZReader<T>, ac_table: usize, block: &mut [i16; 64]
+    ) -> Result<(), DecodeErrors> {
+        let (start, end) = (usize::from(self.spec_start), usize::from(self.spec_end));
+        let p1 = 1_i16 << self.successive_low;
+        let m1 = -1_i16 << self.successive_low;
+
+        let (decoder, mut source) = self.arithmetic_parts(reader)?;
+        let stats = AC_STATS_START + (ac_table % MAX_COMPONENTS) * AC_STAT_BINS;
+
+        // end of block of the previous stage
+        let eob = (1..=end)
+            .rev()
+            .find(|k| block[UN_ZIGZAG[*k] & 63] != 0)
+            .unwrap_or(0);
+
+        let mut k = start;
+
+        while k <= end {
+            let mut st = stats + 3 * (k - 1);
+
+            if k > eob && decoder.decode(&mut source, st)? {
+                break;
+            }
+            loop {
+                let coefficient = &mut block[UN_ZIGZAG[k] & 63];
+
+                if *coefficient != 0 {
+                    // correction bit of a previously non-zero coefficient
+                    if decoder.decode(&mut source, st + 2)? {
+                        if *coefficient < 0 {
+                            *coefficient = coefficient.wrapping_add(m1);
+                        } else {
+                            *coefficient = coefficient.wrapping_add(p1);
+                        }
+                    }
+                    break;
+                }
+                if decoder.decode(&mut source, st + 1)? {
+                    // newly non-zero coefficient
+                    *coefficient = if decoder.decode(&mut source, FIXED_BIN)? { m1 } else { p1 };
+                    break;
+                }
+                st += 3;
+                k += 1;
+
+                if k > end {
+                    return Err(DecodeErrors::FormatStatic(
+                        "Spectral overflow in arithmetic coded data, corrupt JPEG"
+                    ));
+                }
+            }
+            k += 1;
+        }
+        Ok(())
+    }
+}
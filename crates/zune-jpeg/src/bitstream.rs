@@ -52,6 +52,7 @@ use core::cmp::min;
 
 use zune_core::bytestream::{ZByteReaderTrait, ZReader};
 
+use crate::arithmetic::ArithmeticDecoder;
 use crate::errors::DecodeErrors;
 use crate::huffman::{HuffmanTable, HUFF_LOOKAHEAD};
 use crate::marker::Marker;
@@ -119,13 +120,16 @@ pub(crate) struct BitStream {
     /// Progressive decoding
     pub successive_high: u8,
     pub successive_low:  u8,
-    spec_start:          u8,
-    spec_end:            u8,
+    pub spec_start:      u8,
+    pub spec_end:        u8,
     pub eob_run:         i32,
     pub overread_by:     usize,
     /// True if we have seen end of image marker.
     /// Don't read anything after that.
-    pub seen_eoi:        bool
+    pub seen_eoi:        bool,
+
+    /// Decoder of arithmetic coded images, `None` for Huffman coded images
+    pub(crate) arithmetic: Option<ArithmeticDecoder>
 }
 
 impl BitStream {
@@ -142,7 +146,8 @@ impl BitStream {
             spec_end:        0,
             eob_run:         0,
             overread_by:     0,
-            seen_eoi:        false
+            seen_eoi:        false,
+            arithmetic:      None
         }
     }
 
@@ -160,7 +165,8 @@ impl BitStream {
             spec_end:        spec_end,
             eob_run:         0,
             overread_by:     0,
-            seen_eoi:        false
+            seen_eoi:        false,
+            arithmetic:      None
         }
     }
 
@@ -303,6 +309,35 @@ impl BitStream {
         return Ok(true);
     }
 
+    /// Decode the difference of a lossless sample from its prediction
+    ///
+    /// This is coded like a DC coefficient, except that the category
+    /// 16 has no extra bits and stands for a difference of 32768
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub(crate) fn decode_lossless_diff<T>(
+        &mut self, reader: &mut ZReader<T>, dc_table: &HuffmanTable
+    ) -> Result<i32, DecodeErrors>
+    where
+        T: ZByteReaderTrait
+    {
+        if self.bits_left < 32 {
+            self.refill(reader)?;
+        }
+        let mut symbol = self.peek_bits::<HUFF_LOOKAHEAD>();
+        symbol = dc_table.lookup[symbol as usize];
+
+        decode_huff!(self, symbol, dc_table);
+
+        match symbol {
+            0 => Ok(0),
+            16 => Ok(32768),
+            _ => {
+                let r = self.get_bits(symbol as u8);
+                Ok(huff_extend(r, symbol))
+            }
+        }
+    }
+
     /// Decode a Minimum Code Unit(MCU) as quickly as possible
     ///
     /// # Arguments
@@ -648,6 +683,10 @@ impl BitStream {
         self.buffer = 0;
         self.aligned_buffer = 0;
         self.eob_run = 0;
+
+        if let Some(arithmetic) = &mut self.arithmetic {
+            arithmetic.reset();
+        }
     }
 }
 
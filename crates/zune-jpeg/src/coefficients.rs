@@ -0,0 +1,308 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Quantized DCT coefficients of images
+//!
+//! The encoder converts pixels to coefficients before entropy coding them, while
+//! lossless transforms get them from the decoder by only entropy decoding the image.
+//!
+//! Both ends are public, [`JpegDecoder::decode_coefficients`] returns the coefficients
+//! of an image and [`JpegEncoder::encode_coefficients`](crate::JpegEncoder::encode_coefficients)
+//! writes them back, e.g. to analyse or modify images without the loss of decoding them
+//! to pixels.
+
+use alloc::format;
+use alloc::vec::Vec;
+
+use zune_core::bytestream::ZByteReaderTrait;
+use zune_core::colorspace::ColorSpace;
+
+use crate::bitstream::BitStream;
+use crate::decoder::MAX_COMPONENTS;
+use crate::errors::DecodeErrors;
+use crate::mcu::DCT_BLOCK;
+use crate::JpegDecoder;
+
+/// Quantization table which leaves coefficients quantized
+const NO_QUANTIZATION: [i32; DCT_BLOCK] = [1; DCT_BLOCK];
+
+/// Quantized DCT coefficients of an image
+#[derive(Clone)]
+pub struct JpegCoefficients {
+    /// Width of the image
+    pub width:      usize,
+    /// Height of the image
+    pub height:     usize,
+    /// Bits per sample, 8 or 12
+    pub precision:  u8,
+    /// Colorspace of the components, one of `Luma`, `YCbCr`, `RGB`, `CMYK` or `YCCK`
+    pub colorspace: ColorSpace,
+    /// The components of the image
+    pub components: Vec<ComponentCoefficients>
+}
+
+/// Quantized DCT coefficients of a single component
+#[derive(Clone)]
+pub struct ComponentCoefficients {
+    /// Component identifier stored in the frame header
+    pub id:                 u8,
+    /// Horizontal sampling factor
+    pub horizontal_sample:  usize,
+    /// Vertical sampling factor
+    pub vertical_sample:    usize,
+    /// Quantization table in natural order
+    pub quantization_table: [u16; 64],
+    /// Number of blocks in a row, including blocks padding the image to whole MCUs
+    pub width_in_blocks:    usize,
+    /// Number of block rows, including blocks padding the image to whole MCUs
+    pub height_in_blocks:   usize,
+    /// Coefficients of the blocks in raster order, 64 per block in natural order
+    pub coefficients:       Vec<i16>
+}
+
+impl ComponentCoefficients {
+    /// The coefficients of the block at `x`,`y`
+    #[must_use]
+    pub fn block(&self, x: usize, y: usize) -> &[i16] {
+        let start = (y * self.width_in_blocks + x) * 64;
+        &self.coefficients[start..start + 64]
+    }
+
+    /// The coefficients of the block at `x`,`y`
+    pub fn block_mut(&mut self, x: usize, y: usize) -> &mut [i16] {
+        let start = (y * self.width_in_blocks + x) * 64;
+        &mut self.coefficients[start..start + 64]
+    }
+
+    /// Set the blocks padding the component to whole MCUs, given the number of
+    /// blocks covering the image
+    ///
+    /// Like libjpeg, padding blocks only have a DC coefficient, which is the one of
+    /// the block to their left for blocks right of the image and the one of the block
+    /// above the MCU for blocks below the image, so they cost next to nothing to code.
+    pub(crate) fn fill_padding_blocks(&mut self, width_in_blocks: usize, height_in_blocks: usize) {
+        let h = self.horizontal_sample;
+
+        for y in 0..self.height_in_blocks {
+            for x in 0..self.width_in_blocks {
+                let dc = if y >= height_in_blocks {
+                    self.block(x / h * h + h - 1, y - 1)[0]
+                } else if x >= width_in_blocks {
+                    self.block(width_in_blocks - 1, y)[0]
+                } else {
+                    continue;
+                };
+                let block = self.block_mut(x, y);
+
+                block.fill(0);
+                block[0] = dc;
+            }
+        }
+    }
+}
+
+impl JpegCoefficients {
+    /// Largest horizontal and vertical sampling factors of the components
+    #[must_use]
+    pub fn max_sampling_factors(&self) -> (usize, usize) {
+        let h_max = self.components.iter().map(|c| c.horizontal_sample).max();
+        let v_max = self.components.iter().map(|c| c.vertical_sample).max();
+
+        (h_max.unwrap_or(1), v_max.unwrap_or(1))
+    }
+
+    /// Number of blocks in a row and of block rows of a component covering the image,
+    /// excluding blocks padding the image to whole MCUs
+    #[must_use]
+    pub fn image_blocks(&self, component: &ComponentCoefficients) -> (usize, usize) {
+        let (h_max, v_max) = self.max_sampling_factors();
+
+        let width = (self.width * component.horizontal_sample).div_ceil(h_max);
+        let height = (self.height * component.vertical_sample).div_ceil(v_max);
+
+        (width.div_ceil(8), height.div_ceil(8))
+    }
+}
+
+impl<T: ZByteReaderTrait> JpegDecoder<T> {
+    /// Entropy decode the image, returning its quantized DCT coefficients
+    ///
+    /// Coefficients are neither dequantized nor transformed to pixels, multiplying
+    /// them with the quantization table of their component gives the DCT coefficients.
+    /// Blocks padding the image to whole MCUs are included.
+    ///
+    /// The decoder cannot decode the image again afterwards.
+    ///
+    /// # Returns
+    /// - Ok(JpegCoefficients): The coefficients, quantization tables and sampling factors
+    ///   of each component
+    /// - Err(DecodeErrors): The image is corrupt or lossless, which has no coefficients
+    ///
+    /// # Example
+    /// - Count the coefficients which aren't zero
+    ///```no_run
+    /// use zune_core::bytestream::ZCursor;
+    /// use zune_jpeg::JpegDecoder;
+    ///
+    /// let data = std::fs::read("image.jpg").unwrap();
+    /// let mut decoder = JpegDecoder::new(ZCursor::new(&data));
+    /// let image = decoder.decode_coefficients().unwrap();
+    ///
+    /// for component in &image.components {
+    ///     let non_zero = component.coefficients.iter().filter(|x| **x != 0).count();
+    ///     println!("Component {}: {non_zero} non zero coefficients", component.id);
+    /// }
+    ///```
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn decode_coefficients(&mut self) -> Result<JpegCoefficients, DecodeErrors> {
+        self.decode_headers()?;
+
+        if self.info.sof.is_lossless() {
+            return Err(DecodeErrors::FormatStatic(
+                "Lossless images have no DCT coefficients"
+            ));
+        }
+        let blocks = self.decode_scans()?;
+
+        let width = usize::from(self.info.width);
+        let height = usize::from(self.info.height);
+        // not self.mcu_y, sampling factors of grayscale images may have been reset
+        let mcu_y = height.div_ceil(8 * self.v_max);
+
+        let components = self
+            .components
+            .iter()
+            .zip(blocks)
+            .take(self.input_colorspace.num_components())
+            .map(|(component, mut coefficients)| {
+                let width_in_blocks = component.width_stride / 8;
+                let height_in_blocks = mcu_y * component.vertical_sample;
+
+                coefficients.truncate(width_in_blocks * height_in_blocks * 64);
+
+                ComponentCoefficients {
+                    id: component.id,
+                    horizontal_sample: component.horizontal_sample,
+                    vertical_sample: component.vertical_sample,
+                    quantization_table: component.quantization_table.map(|x| x as u16),
+                    width_in_blocks,
+                    height_in_blocks,
+                    coefficients
+                }
+            })
+            .collect();
+
+        Ok(JpegCoefficients {
+            width,
+            height,
+            precision: self.info.pixel_density,
+            colorspace: self.input_colorspace,
+            components
+        })
+    }
+
+    /// Decode a scan of a sequential image into the coefficients of its components
+    ///
+    /// The sequential counterpart of `parse_entropy_coded_data` in `mcu_prog.rs`,
+    /// the coefficients are kept quantized.
+    #[allow(clippy::cast_possible_truncation)]
+    pub(crate) fn parse_sequential_coded_data(
+        &mut self, stream: &mut BitStream, buffer: &mut [Vec<i16>; MAX_COMPONENTS]
+    ) -> Result<(), DecodeErrors> {
+        let scan_components = self.z_order;
+        let scan_components = &scan_components[..usize::from(self.num_scans)];
+
+        for &k in scan_components {
+            if k >= self.components.len() || buffer[k].is_empty() {
+                return Err(DecodeErrors::Format(format!(
+                    "Cannot find component {k}, corrupt image"
+                )));
+            }
+            let component = &self.components[k];
+
+            if stream.arithmetic.is_none()
+                && (self.dc_huffman_tables[component.dc_huff_table % MAX_COMPONENTS].is_none()
+                    || self.ac_huffman_tables[component.ac_huff_table % MAX_COMPONENTS].is_none())
+            {
+                return Err(DecodeErrors::HuffmanDecode(format!(
+                    "No Huffman tables for component {:?}",
+                    component.component_id
+                )));
+            }
+        }
+        let interleaved = scan_components.len() > 1;
+
+        // interleaved scans code whole MCUs, the others only the blocks covering the image
+        let (mcu_columns, mcu_rows) = if interleaved {
+            (self.mcu_x, self.mcu_y)
+        } else {
+            self.non_interleaved_blocks(scan_components[0])
+        };
+        let mut tmp = [0_i32; DCT_BLOCK];
+
+        for i in 0..mcu_rows {
+            for j in 0..mcu_columns {
+                if self.restart_interval != 0 && self.todo == 0 {
+                    self.handle_rst(stream)?;
+                }
+                self.todo = self.todo.wrapping_sub(1);
+
+                for &k in scan_components {
+                    let component = &mut self.components[k];
+                    let (h_samples, v_samples) = if interleaved {
+                        (component.horizontal_sample, component.vertical_sample)
+                    } else {
+                        (1, 1)
+                    };
+
+                    for v_samp in 0..v_samples {
+                        for h_samp in 0..h_samples {
+                            tmp.fill(0);
+
+                            if stream.arithmetic.is_some() {
+                                stream.decode_arith_mcu_block(
+                                    &mut self.stream,
+                                    &self.arithmetic_tables,
+                                    component.dc_huff_table,
+                                    component.ac_huff_table,
+                                    &NO_QUANTIZATION,
+                                    &mut tmp,
+                                    &mut component.dc_pred,
+                                    k
+                                )?;
+                            } else {
+                                stream.decode_mcu_block(
+                                    &mut self.stream,
+                                    self.dc_huffman_tables
+                                        [component.dc_huff_table % MAX_COMPONENTS]
+                                        .as_ref()
+                                        .unwrap(),
+                                    self.ac_huffman_tables
+                                        [component.ac_huff_table % MAX_COMPONENTS]
+                                        .as_ref()
+                                        .unwrap(),
+                                    &NO_QUANTIZATION,
+                                    &mut tmp,
+                                    &mut component.dc_pred
+                                )?;
+                            }
+                            let x = j * h_samples + h_samp;
+                            let y = i * v_samples + v_samp;
+                            let start = 64 * (y * component.width_stride / 8 + x);
+
+                            for (out, coeff) in buffer[k][start..start + 64].iter_mut().zip(&tmp) {
+                                *out = *coeff as i16;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
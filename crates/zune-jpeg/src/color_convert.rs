@@ -24,6 +24,7 @@
 //! Currently supported conversions are
 //!
 //! - `YCbCr` to `RGB,RGBA,GRAYSCALE,RGBX`.
+//! - `RGB,RGBA` to `YCbCr` for the encoder.
 //!
 //!
 //! Hey there, if your reading this it means you probably need something, so let me help you.
@@ -58,6 +59,11 @@ use crate::decoder::ColorConvert16Ptr;
 
 mod avx;
 mod scalar;
+
+/// Color convert function used by the encoder, converts a row of RGB(A)
+/// pixels to separate rows of Y, Cb and Cr samples
+pub type RgbToYCbCrPtr = fn(&[u8], &mut [i16], &mut [i16], &mut [i16]);
+
 #[allow(unused_variables)]
 pub fn choose_ycbcr_to_rgb_convert_func(
     type_need: ColorSpace, options: &DecoderOptions
@@ -87,3 +93,25 @@ pub fn choose_ycbcr_to_rgb_convert_func(
         _ => None
     };
 }
+
+/// Choose the function converting RGB or RGBA pixels to YCbCr for the encoder
+///
+/// Returns `None` if `type_from` is not `RGB` or `RGBA`
+pub fn choose_rgb_to_ycbcr_convert_func(type_from: ColorSpace) -> Option<RgbToYCbCrPtr> {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[cfg(feature = "x86")]
+    {
+        if crate::unsafe_utils::avx2_available() {
+            match type_from {
+                ColorSpace::RGB => return Some(avx::rgb_to_ycbcr_avx2::<3>),
+                ColorSpace::RGBA => return Some(avx::rgb_to_ycbcr_avx2::<4>),
+                _ => ()
+            }
+        }
+    }
+    match type_from {
+        ColorSpace::RGB => Some(scalar::rgb_to_ycbcr_scalar::<3>),
+        ColorSpace::RGBA => Some(scalar::rgb_to_ycbcr_scalar::<4>),
+        _ => None
+    }
+}
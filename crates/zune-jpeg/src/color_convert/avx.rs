@@ -44,6 +44,8 @@ use core::arch::x86::*;
 #[cfg(target_arch = "x86_64")]
 use core::arch::x86_64::*;
 
+use crate::color_convert::scalar::*;
+
 pub union YmmRegister {
     // both are 32 when using std::mem::size_of
     mm256: __m256i,
@@ -325,6 +327,112 @@ unsafe fn ycbcr_to_rgba_unsafe(
     *offset += 64;
 }
 
+/// Convert a row of RGB pixels to YCbCr using AVX instructions
+///
+/// `N` is the number of components of a pixel, the fourth component of
+/// RGBA pixels is ignored. Results are identical to the scalar version.
+///
+///  # Note
+///**IT IS THE RESPONSIBILITY OF THE CALLER TO CALL THIS IN CPUS SUPPORTING
+/// AVX2 OTHERWISE THIS IS UB**
+#[inline(always)]
+pub fn rgb_to_ycbcr_avx2<const N: usize>(
+    input: &[u8], y: &mut [i16], cb: &mut [i16], cr: &mut [i16]
+) {
+    unsafe {
+        rgb_to_ycbcr_avx2_inner::<N>(input, y, cb, cr);
+    }
+}
+
+#[inline]
+#[target_feature(enable = "avx2")]
+#[allow(clippy::cast_possible_wrap)]
+unsafe fn rgb_to_ycbcr_avx2_inner<const N: usize>(
+    input: &[u8], y: &mut [i16], cb: &mut [i16], cr: &mut [i16]
+) {
+    // Spread 4 RGB pixels of each 128 bit lane into 32 bit integers
+    let rgb_to_rgbx = _mm256_setr_epi8(
+        0, 1, 2, -1, 3, 4, 5, -1, 6, 7, 8, -1, 9, 10, 11, -1, 0, 1, 2, -1, 3, 4, 5, -1, 6, 7, 8,
+        -1, 9, 10, 11, -1
+    );
+    let mask = _mm256_set1_epi32(0xFF);
+
+    let mut done = 0;
+
+    // RGB pixels are loaded as two halves of 16 bytes of which 12 are used, so
+    // stop where the last load would go past the end
+    while done + 8 <= y.len().min(cb.len()).min(cr.len())
+        && (done + 8) * N + (16 - 4 * N) <= input.len()
+    {
+        let pixels = &input[done * N..];
+
+        let rgbx = if N == 4 {
+            _mm256_loadu_si256(pixels.as_ptr().cast())
+        } else {
+            let low = _mm_loadu_si128(pixels.as_ptr().cast());
+            let high = _mm_loadu_si128(pixels[12..].as_ptr().cast());
+
+            _mm256_shuffle_epi8(_mm256_set_m128i(high, low), rgb_to_rgbx)
+        };
+        let r = _mm256_and_si256(rgbx, mask);
+        let g = _mm256_and_si256(_mm256_srli_epi32::<8>(rgbx), mask);
+        let b = _mm256_and_si256(_mm256_srli_epi32::<16>(rgbx), mask);
+
+        let y_c = weighted_sum(r, g, b, [FIX_0_29900, FIX_0_58700, FIX_0_11400], ONE_HALF);
+        let cb_c = weighted_sum(
+            r,
+            g,
+            b,
+            [-FIX_0_16874, -FIX_0_33126, FIX_0_50000],
+            CBCR_OFFSET + ONE_HALF - 1
+        );
+        let cr_c = weighted_sum(
+            r,
+            g,
+            b,
+            [FIX_0_50000, -FIX_0_41869, -FIX_0_08131],
+            CBCR_OFFSET + ONE_HALF - 1
+        );
+
+        _mm_storeu_si128(y[done..done + 8].as_mut_ptr().cast(), y_c);
+        _mm_storeu_si128(cb[done..done + 8].as_mut_ptr().cast(), cb_c);
+        _mm_storeu_si128(cr[done..done + 8].as_mut_ptr().cast(), cr_c);
+
+        done += 8;
+    }
+    rgb_to_ycbcr_scalar::<N>(
+        &input[(done * N).min(input.len())..],
+        &mut y[done..],
+        &mut cb[done..],
+        &mut cr[done..]
+    );
+}
+
+/// Compute `(weights[0] * r + weights[1] * g + weights[2] * b + offset) >> SCALE_BITS`
+/// and pack the result to 8 i16's
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn weighted_sum(
+    r: __m256i, g: __m256i, b: __m256i, weights: [i32; 3], offset: i32
+) -> __m128i {
+    let sum = _mm256_add_epi32(
+        _mm256_add_epi32(
+            _mm256_mullo_epi32(r, _mm256_set1_epi32(weights[0])),
+            _mm256_mullo_epi32(g, _mm256_set1_epi32(weights[1]))
+        ),
+        _mm256_add_epi32(
+            _mm256_mullo_epi32(b, _mm256_set1_epi32(weights[2])),
+            _mm256_set1_epi32(offset)
+        )
+    );
+    let shifted = _mm256_srai_epi32::<SCALE_BITS>(sum);
+    // pack to i16's and move the 8 values into the low lane
+    let packed =
+        _mm256_permute4x64_epi64::<{ shuffle(3, 1, 2, 0) }>(_mm256_packs_epi32(shifted, shifted));
+
+    _mm256_castsi256_si128(packed)
+}
+
 /// Clamp values between 0 and 255
 ///
 /// This function clamps all values in `reg` to be between 0 and 255
@@ -114,3 +114,40 @@ pub fn ycbcr_to_grayscale(y: &[i16], width: usize, padded_width: usize, output:
         }
     }
 }
+
+/// Fixed point precision of the RGB to YCbCr conversion
+pub const SCALE_BITS: i32 = 16;
+/// Offset added to Cb and Cr, 128 in fixed point
+pub const CBCR_OFFSET: i32 = 128 << SCALE_BITS;
+pub const ONE_HALF: i32 = 1 << (SCALE_BITS - 1);
+
+// RGB to YCbCr coefficients in fixed point, rounded like libjpeg does
+pub const FIX_0_29900: i32 = 19595;
+pub const FIX_0_58700: i32 = 38470;
+pub const FIX_0_11400: i32 = 7471;
+pub const FIX_0_16874: i32 = 11059;
+pub const FIX_0_33126: i32 = 21709;
+pub const FIX_0_50000: i32 = 32768;
+pub const FIX_0_41869: i32 = 27439;
+pub const FIX_0_08131: i32 = 5329;
+
+/// Convert a row of RGB pixels to YCbCr
+///
+/// `N` is the number of components of a pixel, the fourth component of
+/// RGBA pixels is ignored
+pub fn rgb_to_ycbcr_scalar<const N: usize>(
+    input: &[u8], y: &mut [i16], cb: &mut [i16], cr: &mut [i16]
+) {
+    for (((pixel, y), cb), cr) in input.chunks_exact(N).zip(y).zip(cb).zip(cr) {
+        let r = i32::from(pixel[0]);
+        let g = i32::from(pixel[1]);
+        let b = i32::from(pixel[2]);
+
+        *y =
+            ((FIX_0_29900 * r + FIX_0_58700 * g + FIX_0_11400 * b + ONE_HALF) >> SCALE_BITS) as i16;
+        *cb = ((-FIX_0_16874 * r - FIX_0_33126 * g + FIX_0_50000 * b + CBCR_OFFSET + ONE_HALF - 1)
+            >> SCALE_BITS) as i16;
+        *cr = ((FIX_0_50000 * r - FIX_0_41869 * g - FIX_0_08131 * b + CBCR_OFFSET + ONE_HALF - 1)
+            >> SCALE_BITS) as i16;
+    }
+}
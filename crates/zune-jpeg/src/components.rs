@@ -55,6 +55,12 @@ pub(crate) struct Components {
     pub up_sampler: UpSampler,
     /// How pixels do we need to go to get to the next line?
     pub width_stride: usize,
+    /// Width of the blocks the IDCT writes, 8 unless
+    /// the image is scaled down
+    pub dct_width: usize,
+    /// Height of the blocks the IDCT writes, 8 unless
+    /// the image is scaled down
+    pub dct_height: usize,
     /// Component ID for progressive
     pub id: u8,
     /// Whether we need to decode this image component.
@@ -145,6 +151,8 @@ impl Components {
             up_sampler: upsample_no_op,
             // set later
             width_stride: horizontal_sample,
+            dct_width: 8,
+            dct_height: 8,
             id: a[0],
             needed: true,
             raw_coeff: vec![],
@@ -159,6 +167,10 @@ impl Components {
             fix_an_annoying_bug: 1
         })
     }
+    /// Number of samples in a row of `raw_coeff`
+    pub fn scaled_stride(&self) -> usize {
+        self.width_stride / 8 * self.dct_width
+    }
     /// Setup space for upsampling
     ///
     /// During upsample, we need a reference of the last row so that upsampling can
@@ -12,17 +12,21 @@
 use alloc::string::ToString;
 use alloc::vec::Vec;
 use alloc::{format, vec};
+use core::ops::Range;
 
+use zune_core::bit_depth::BitDepth;
 use zune_core::bytestream::{ZByteReaderTrait, ZReader};
 use zune_core::colorspace::ColorSpace;
 use zune_core::log::{error, trace, warn};
 use zune_core::options::DecoderOptions;
 
+use crate::arithmetic::ArithmeticConditioning;
 use crate::color_convert::choose_ycbcr_to_rgb_convert_func;
 use crate::components::{Components, SampleRatios};
 use crate::errors::{DecodeErrors, UnsupportedSchemes};
 use crate::headers::{
-    parse_app1, parse_app14, parse_app2, parse_dqt, parse_huffman, parse_sos, parse_start_of_frame
+    parse_app1, parse_app14, parse_app2, parse_dac, parse_dqt, parse_huffman, parse_sos,
+    parse_start_of_frame
 };
 use crate::huffman::HuffmanTable;
 use crate::idct::choose_idct_func;
@@ -88,6 +92,8 @@ pub struct JpegDecoder<T: ZByteReaderTrait> {
     pub(crate) dc_huffman_tables: [Option<HuffmanTable>; MAX_COMPONENTS],
     /// AC Huffman Tables with a maximum of 4 tables for each component
     pub(crate) ac_huffman_tables: [Option<HuffmanTable>; MAX_COMPONENTS],
+    /// Conditioning tables of arithmetic coded images
+    pub(crate) arithmetic_tables: ArithmeticConditioning,
     /// Image components, holds information like DC prediction and quantization
     /// tables of a component
     pub(crate) components:        Vec<Components>,
@@ -163,6 +169,7 @@ where
             qt_tables:         [None, None, None, None],
             dc_huffman_tables: [None, None, None, None],
             ac_huffman_tables: [None, None, None, None],
+            arithmetic_tables: ArithmeticConditioning::default(),
             components:        vec![],
             // Interleaved information
             h_max:             1,
@@ -200,6 +207,9 @@ where
     /// The buffer should be a valid jpeg file, perhaps created by the command
     /// `std:::fs::read()` or a JPEG file downloaded from the internet.
     ///
+    /// Samples of images with more than 8 bits of precision are scaled down
+    /// to 8 bits, use [`decode_u16`](Self::decode_u16) to keep them.
+    ///
     /// # Errors
     /// See DecodeErrors for an explanation
     pub fn decode(&mut self) -> Result<Vec<u8>, DecodeErrors> {
@@ -254,9 +264,11 @@ where
     #[must_use]
     pub fn output_buffer_size(&self) -> Option<usize> {
         return if self.headers_decoded {
+            let (width, height) = self.dimensions()?;
+
             Some(
-                usize::from(self.width())
-                    .checked_mul(usize::from(self.height()))?
+                width
+                    .checked_mul(height)?
                     .checked_mul(self.options.jpeg_get_out_colorspace().num_components())?
             )
         } else {
@@ -264,6 +276,41 @@ where
         };
     }
 
+    /// Return the number of bits of a sample, between 2 and 16
+    ///
+    /// DCT images have 8 or 12 bits of precision, lossless images may have
+    /// any precision in that range
+    ///
+    /// # Returns
+    /// - `Some(precision)`: The precision of the image
+    /// - `None`: Indicates the headers weren't decoded
+    #[must_use]
+    pub const fn precision(&self) -> Option<u8> {
+        if self.headers_decoded {
+            Some(self.info.pixel_density)
+        } else {
+            None
+        }
+    }
+
+    /// Return the bit depth needed to hold the samples of the image
+    ///
+    /// This is [`BitDepth::Sixteen`] for images with more than 8 bits of
+    /// precision, which should be decoded with [`decode_u16`](Self::decode_u16)
+    /// to keep all bits.
+    ///
+    /// # Returns
+    /// - `Some(depth)`: The bit depth of the image
+    /// - `None`: Indicates the headers weren't decoded
+    #[must_use]
+    pub const fn depth(&self) -> Option<BitDepth> {
+        match self.precision() {
+            Some(9..) => Some(BitDepth::Sixteen),
+            Some(_) => Some(BitDepth::Eight),
+            None => None
+        }
+    }
+
     /// Get an immutable reference to the decoder options
     /// for the decoder instance
     ///
@@ -338,16 +385,22 @@ where
     ///  - SOF(O)
     ///  - DQT -> Quantization tables
     ///  - DHT -> Huffman tables
+    ///  - DAC -> Arithmetic conditioning tables
     ///  - SOS -> Start of Scan
     /// # Unsupported Headers
     ///  - SOF(n) -> Decoder images which are not baseline/progressive
-    ///  - DAC -> Images using Arithmetic tables
     ///  - JPG(n)
     fn decode_headers_internal(&mut self) -> Result<(), DecodeErrors> {
         if self.headers_decoded {
             trace!("Headers decoded!");
             return Ok(());
         }
+        if !matches!(self.options.jpeg_get_scale_denom(), 1 | 2 | 4 | 8) {
+            return Err(DecodeErrors::Format(format!(
+                "Unsupported scale denominator {}, expected 1, 2, 4 or 8",
+                self.options.jpeg_get_scale_denom()
+            )));
+        }
         // match output colorspace here
         // we know this will only be called once per image
         // so makes sense
@@ -469,6 +522,23 @@ where
                 // get components
                 parse_start_of_frame(marker, self)?;
             }
+            Marker::SOF(3) => {
+                let marker = SOFMarkers::LosslessHuffman;
+
+                trace!("Image encoding scheme =`{:?}`", marker);
+                parse_start_of_frame(marker, self)?;
+            }
+            Marker::SOF(9 | 10) => {
+                let marker = if m == Marker::SOF(9) {
+                    SOFMarkers::ExtendedSequentialDctArithmetic
+                } else {
+                    self.is_progressive = true;
+                    SOFMarkers::ProgressiveDctArithmetic
+                };
+
+                trace!("Image encoding scheme =`{:?}`", marker);
+                parse_start_of_frame(marker, self)?;
+            }
             // Start of Frame Segments not supported
             Marker::SOF(v) => {
                 let feature = UnsupportedSchemes::from_int(v);
@@ -527,7 +597,11 @@ where
             }
             Marker::EOI => return Err(DecodeErrors::FormatStatic("Premature End of image")),
 
-            Marker::DAC | Marker::DNL => {
+            // Arithmetic conditioning tables
+            Marker::DAC => {
+                parse_dac(self)?;
+            }
+            Marker::DNL => {
                 return Err(DecodeErrors::Format(format!(
                     "Parsing of the following header `{m:?}` is not supported,\
                                 cannot continue"
@@ -692,6 +766,7 @@ where
     /// ```
     ///
     ///
+    #[allow(clippy::cast_possible_truncation)]
     pub fn decode_into(&mut self, out: &mut [u8]) -> Result<(), DecodeErrors> {
         self.decode_headers_internal()?;
 
@@ -706,6 +781,18 @@ where
         let out_len = core::cmp::min(out.len(), expected_size);
         let out = &mut out[0..out_len];
 
+        if self.info.sof.is_lossless() || self.info.pixel_density > 8 {
+            // samples with more than 8 bits are scaled down
+            let shift = self.info.pixel_density.saturating_sub(8);
+            let mut samples = vec![0; out_len];
+
+            self.decode_into_u16(&mut samples)?;
+
+            for (pixel, sample) in out.iter_mut().zip(&samples) {
+                *pixel = (*sample >> shift) as u8;
+            }
+            return Ok(());
+        }
         if self.is_progressive {
             self.decode_mcu_ycbcr_progressive(out)
         } else {
@@ -713,6 +800,54 @@ where
         }
     }
 
+    /// Decode a buffer already in memory into 16 bit samples
+    ///
+    /// Samples keep the precision of the image, e.g. a 12 bit image
+    /// has values between 0 and 4095, see [`precision`](Self::precision).
+    ///
+    /// # Errors
+    /// See DecodeErrors for an explanation
+    pub fn decode_u16(&mut self) -> Result<Vec<u16>, DecodeErrors> {
+        self.decode_headers()?;
+        let size = self.output_buffer_size().unwrap();
+        let mut out = vec![0; size];
+        self.decode_into_u16(&mut out)?;
+        Ok(out)
+    }
+
+    /// Decode into a pre-allocated buffer of 16 bit samples
+    ///
+    /// The buffer should hold at least [`output_buffer_size()`](Self::output_buffer_size)
+    /// samples, see [`decode_u16`](Self::decode_u16) for the range of the samples
+    pub fn decode_into_u16(&mut self, out: &mut [u16]) -> Result<(), DecodeErrors> {
+        self.decode_headers_internal()?;
+
+        let expected_size = self.output_buffer_size().unwrap();
+
+        if out.len() < expected_size {
+            return Err(DecodeErrors::TooSmallOutput(expected_size, out.len()));
+        }
+        let out = &mut out[0..expected_size];
+
+        if self.info.sof.is_lossless() {
+            return self.decode_mcu_lossless(out);
+        }
+        if self.info.pixel_density > 8 {
+            return if self.is_progressive {
+                self.decode_mcu_ycbcr_progressive(out)
+            } else {
+                self.decode_mcu_ycbcr_baseline(out)
+            };
+        }
+        let mut pixels = vec![0; expected_size];
+        self.decode_into(&mut pixels)?;
+
+        for (sample, pixel) in out.iter_mut().zip(&pixels) {
+            *sample = u16::from(*pixel);
+        }
+        Ok(())
+    }
+
     /// Read only headers from a jpeg image buffer
     ///
     /// This allows you to extract important information like
@@ -826,16 +961,61 @@ where
     /// Get image dimensions as a tuple of width and height
     /// or `None` if the image hasn't been decoded.
     ///
+    /// These are the dimensions of the decoded image, which are smaller than the
+    /// dimensions in [`info`](Self::info) when decoding scaled down or only a region
+    /// of the image, see [`DecoderOptions::jpeg_set_scale_denom`] and
+    /// [`DecoderOptions::jpeg_set_crop`]
+    ///
     /// # Returns
     /// - `Some(width,height)`: Image dimensions
     /// -  None : The image headers haven't been decoded
     #[must_use]
     pub const fn dimensions(&self) -> Option<(usize, usize)> {
-        return if self.headers_decoded {
-            Some((self.info.width as usize, self.info.height as usize))
-        } else {
-            None
-        };
+        if !self.headers_decoded {
+            return None;
+        }
+        let (_, _, width, height) = self.crop_region();
+
+        Some((width, height))
+    }
+
+    /// Whether the image is decoded scaled down
+    pub(crate) const fn is_scaled(&self) -> bool {
+        self.options.jpeg_get_scale_denom() > 1
+    }
+
+    /// The region of the image that is decoded as x, y, width and height,
+    /// in the coordinates of the image scaled down
+    pub(crate) const fn crop_region(&self) -> (usize, usize, usize, usize) {
+        let denom = self.options.jpeg_get_scale_denom();
+
+        match self.options.jpeg_get_crop() {
+            Some(crop) => crop,
+            None => (
+                0,
+                0,
+                (self.info.width as usize).div_ceil(denom),
+                (self.info.height as usize).div_ceil(denom)
+            )
+        }
+    }
+
+    /// MCU rows and columns that are transformed and color converted to decode
+    /// the crop region
+    ///
+    /// This includes a MCU around the region, which the up-sampling filters
+    /// read from. The ranges may extend past the last MCU of the image.
+    pub(crate) fn mcu_region(&self) -> (Range<usize>, Range<usize>) {
+        let (x, y, width, height) = self.crop_region();
+        // width and height of a MCU in the output
+        let scale = 8 / self.options.jpeg_get_scale_denom();
+        let mcu_width = scale * self.h_max;
+        let mcu_height = scale * self.v_max;
+
+        let rows = (y / mcu_height).saturating_sub(1)..(y + height - 1) / mcu_height + 2;
+        let columns = (x / mcu_width).saturating_sub(1)..(x + width - 1) / mcu_width + 2;
+
+        (rows, columns)
     }
 }
 
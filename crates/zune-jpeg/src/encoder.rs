@@ -0,0 +1,739 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! A JPEG encoder
+//!
+//! Encoding happens in two steps, first pixels are converted to quantized
+//! DCT coefficients one MCU row at a time, which involves
+//!
+//! - Color conversion from RGB to YCbCr
+//! - Downsampling of chroma components
+//! - Forward DCT and quantization
+//!
+//! then the coefficients of the whole image are entropy coded, either in a
+//! single scan for baseline images or in several ones for progressive images.
+//!
+//! The conversion, downsampling and DCT follow libjpeg, images encoded with the same
+//! settings contain the same coefficients as those written by libjpeg.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use zune_core::bit_depth::BitDepth;
+use zune_core::bytestream::{ZByteWriterTrait, ZWriter};
+use zune_core::colorspace::ColorSpace;
+use zune_core::log::trace;
+use zune_core::options::EncoderOptions;
+
+use crate::coefficients::{ComponentCoefficients, JpegCoefficients};
+use crate::color_convert::choose_rgb_to_ycbcr_convert_func;
+use crate::encoder::bitstream::{BitWriter, SymbolCounter};
+use crate::encoder::headers::{
+    write_adobe, write_dht, write_dqt, write_dri, write_exif, write_icc, write_jfif, write_marker,
+    write_sof, write_sos
+};
+use crate::encoder::huffman::{HuffmanCodes, HuffmanSpec};
+use crate::encoder::scan::{encode_scan, progressive_scans, sequential_scans, Scan};
+use crate::errors::EncodeErrors;
+use crate::fdct::choose_fdct_func;
+use crate::marker::Marker;
+
+mod bitstream;
+mod headers;
+mod huffman;
+mod scan;
+
+/// Largest width or height of a JPEG image
+const MAX_DIMENSIONS: usize = 65535;
+
+/// Luminance quantization table of the specification (Annex K.1) in natural order
+#[rustfmt::skip]
+const STANDARD_LUMINANCE_TABLE: [u16; 64] = [
+    16, 11, 10, 16,  24,  40,  51,  61,
+    12, 12, 14, 19,  26,  58,  60,  55,
+    14, 13, 16, 24,  40,  57,  69,  56,
+    14, 17, 22, 29,  51,  87,  80,  62,
+    18, 22, 37, 56,  68, 109, 103,  77,
+    24, 35, 55, 64,  81, 104, 113,  92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103,  99
+];
+
+/// Chrominance quantization table of the specification (Annex K.1) in natural order
+#[rustfmt::skip]
+const STANDARD_CHROMINANCE_TABLE: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99
+];
+
+/// Scale a standard quantization table to the given quality, the way libjpeg does
+fn scale_quantization_table(table: &[u16; 64], quality: u8) -> [u16; 64] {
+    let quality = u32::from(quality.clamp(1, 100));
+
+    let scale = if quality < 50 { 5000 / quality } else { 200 - quality * 2 };
+    table.map(|x| ((u32::from(x) * scale + 50) / 100).clamp(1, 255) as u16)
+}
+
+/// A JPEG encoder
+///
+/// The encoder accepts 8 bit `Luma`, `RGB`, `RGBA`, `YCbCr`, `CMYK` and `YCCK` images,
+/// `RGB` and `RGBA` images are converted to `YCbCr` before encoding, dropping the alpha channel.
+///
+/// Options understood by the encoder are
+/// - [`quality`](EncoderOptions::quality): Scales the standard quantization tables
+/// - [`jpeg_encode_progressive`](EncoderOptions::jpeg_encode_progressive): Write a progressive image
+/// - [`jpeg_optimized_huffman_tables`](EncoderOptions::jpeg_optimized_huffman_tables): Use optimal Huffman tables
+/// - [`jpeg_subsampling`](EncoderOptions::jpeg_subsampling): Chroma subsampling
+/// - [`jpeg_restart_interval`](EncoderOptions::jpeg_restart_interval): MCUs between restart markers
+pub struct JpegEncoder<'a> {
+    data:                &'a [u8],
+    options:             EncoderOptions,
+    icc_profile:         Option<&'a [u8]>,
+    exif:                Option<&'a [u8]>,
+    quantization_tables: Option<([u16; 64], [u16; 64])>
+}
+
+impl<'a> JpegEncoder<'a> {
+    /// Create a new encoder which will encode the pixels in `data`
+    ///
+    /// # Arguments
+    ///  - `data`: Interleaved pixels to encode
+    ///  - `options`: Dimensions, colorspace and encoding options of the image
+    #[must_use]
+    pub fn new(data: &'a [u8], options: EncoderOptions) -> JpegEncoder<'a> {
+        JpegEncoder {
+            data,
+            options,
+            icc_profile: None,
+            exif: None,
+            quantization_tables: None
+        }
+    }
+
+    /// Add an ICC profile, which is written into APP2 segments
+    pub fn add_icc_profile(&mut self, icc_profile: &'a [u8]) {
+        self.icc_profile = Some(icc_profile);
+    }
+
+    /// Add EXIF data, which is written into an APP1 segment
+    ///
+    /// The data should not start with the `Exif\0\0` identifier, the encoder
+    /// adds it, this matches what [`JpegDecoder::exif`](crate::JpegDecoder::exif) returns.
+    pub fn add_exif(&mut self, exif: &'a [u8]) {
+        self.exif = Some(exif);
+    }
+
+    /// Use custom quantization tables instead of the standard ones scaled by quality
+    ///
+    /// Tables are in natural (row major) order and are used as they are,
+    /// the luminance table is used for `Luma`, `CMYK` and the `Y` and `K`
+    /// components of `YCbCr` and `YCCK` images.
+    ///
+    /// Values larger than 255 make the encoder write 16 bit tables in
+    /// an extended sequential or progressive image.
+    pub fn set_quantization_tables(&mut self, luminance: [u16; 64], chrominance: [u16; 64]) {
+        self.quantization_tables = Some((luminance, chrominance));
+    }
+
+    /// Encode the image into `sink`
+    ///
+    /// # Returns
+    /// - Ok(usize): The number of bytes written into `sink`
+    /// - Err(EncodeErrors): The image cannot be encoded or writing failed
+    ///
+    /// # Example
+    /// - Encode an RGB image of 10x10 pixels
+    ///```
+    /// use zune_core::bit_depth::BitDepth;
+    /// use zune_core::colorspace::ColorSpace;
+    /// use zune_core::options::EncoderOptions;
+    /// use zune_jpeg::JpegEncoder;
+    ///
+    /// let pixels = vec![128_u8; 10 * 10 * 3];
+    /// let options = EncoderOptions::new(10, 10, ColorSpace::RGB, BitDepth::Eight);
+    ///
+    /// let mut output = vec![];
+    /// JpegEncoder::new(&pixels, options).encode(&mut output).unwrap();
+    ///```
+    pub fn encode<T: ZByteWriterTrait>(&self, sink: T) -> Result<usize, EncodeErrors> {
+        let image = self.compute_coefficients()?;
+
+        self.write_coefficients(&image, sink)
+    }
+
+    /// Encode quantized DCT coefficients into `sink` instead of pixels
+    ///
+    /// Dimensions, colorspace, precision, sampling factors and quantization tables
+    /// come from `image`, so the pixels of the encoder and its depth, quality,
+    /// subsampling and quantization table settings are ignored. Progressive encoding,
+    /// restart intervals and optimized Huffman tables are used as set in the options.
+    ///
+    /// Coefficients returned by [`JpegDecoder::decode_coefficients`](crate::JpegDecoder::decode_coefficients)
+    /// can be modified and encoded again, those which aren't modified lose no quality.
+    ///
+    /// # Returns
+    /// - Ok(usize): The number of bytes written into `sink`
+    /// - Err(EncodeErrors): The coefficients don't describe a valid image or writing failed
+    ///
+    /// # Example
+    /// - Remove the highest frequencies of an image
+    ///```no_run
+    /// use zune_core::bit_depth::BitDepth;
+    /// use zune_core::bytestream::ZCursor;
+    /// use zune_core::options::EncoderOptions;
+    /// use zune_jpeg::{JpegDecoder, JpegEncoder};
+    ///
+    /// let data = std::fs::read("image.jpg").unwrap();
+    /// let mut image = JpegDecoder::new(ZCursor::new(&data))
+    ///     .decode_coefficients()
+    ///     .unwrap();
+    ///
+    /// for component in &mut image.components {
+    ///     for block in component.coefficients.chunks_exact_mut(64) {
+    ///         block[63] = 0;
+    ///     }
+    /// }
+    /// let options = EncoderOptions::new(image.width, image.height, image.colorspace, BitDepth::Eight);
+    ///
+    /// let mut output = vec![];
+    /// JpegEncoder::new(&[], options)
+    ///     .encode_coefficients(&image, &mut output)
+    ///     .unwrap();
+    ///```
+    pub fn encode_coefficients<T: ZByteWriterTrait>(
+        &self, image: &JpegCoefficients, sink: T
+    ) -> Result<usize, EncodeErrors> {
+        check_coefficients(image)?;
+
+        self.write_coefficients(image, sink)
+    }
+
+    /// Check the input and return the colorspace it is encoded in
+    fn jpeg_colorspace(&self) -> Result<ColorSpace, EncodeErrors> {
+        let (width, height) = (self.options.width(), self.options.height());
+        let colorspace = self.options.colorspace();
+
+        if self.options.depth() != BitDepth::Eight {
+            return Err(EncodeErrors::UnsupportedDepth(self.options.depth()));
+        }
+        if !(1..=MAX_DIMENSIONS).contains(&width) || !(1..=MAX_DIMENSIONS).contains(&height) {
+            return Err(EncodeErrors::InvalidDimensions(width, height));
+        }
+        let jpeg_colorspace = match colorspace {
+            ColorSpace::Luma | ColorSpace::YCbCr | ColorSpace::CMYK | ColorSpace::YCCK => {
+                colorspace
+            }
+            ColorSpace::RGB | ColorSpace::RGBA => ColorSpace::YCbCr,
+            _ => return Err(EncodeErrors::UnsupportedColorspace(colorspace))
+        };
+        let expected = width * height * colorspace.num_components();
+
+        if self.data.len() != expected {
+            return Err(EncodeErrors::WrongInputSize(expected, self.data.len()));
+        }
+        Ok(jpeg_colorspace)
+    }
+
+    /// Create the components of the image with all coefficients set to zero
+    #[allow(clippy::cast_possible_truncation)]
+    fn create_components(&self, jpeg_colorspace: ColorSpace) -> Vec<ComponentCoefficients> {
+        let (luma_table, chroma_table) = self.quantization_tables.unwrap_or_else(|| {
+            let quality = self.options.quality();
+            (
+                scale_quantization_table(&STANDARD_LUMINANCE_TABLE, quality),
+                scale_quantization_table(&STANDARD_CHROMINANCE_TABLE, quality)
+            )
+        });
+        let luma_factors = self.options.jpeg_subsampling().luma_sampling_factors();
+
+        // sampling factors and quantization table of each component, libjpeg's defaults
+        let layout = match jpeg_colorspace {
+            ColorSpace::Luma => vec![((1, 1), luma_table)],
+            ColorSpace::CMYK => vec![((1, 1), luma_table); 4],
+            ColorSpace::YCCK => vec![
+                (luma_factors, luma_table),
+                ((1, 1), chroma_table),
+                ((1, 1), chroma_table),
+                (luma_factors, luma_table),
+            ],
+            _ => vec![
+                (luma_factors, luma_table),
+                ((1, 1), chroma_table),
+                ((1, 1), chroma_table),
+            ]
+        };
+        let h_max = layout.iter().map(|((h, _), _)| *h).max().unwrap();
+        let v_max = layout.iter().map(|((_, v), _)| *v).max().unwrap();
+
+        let mcu_width = self.options.width().div_ceil(8 * h_max);
+        let mcu_height = self.options.height().div_ceil(8 * v_max);
+
+        layout
+            .iter()
+            .enumerate()
+            .map(|(i, ((h, v), table))| ComponentCoefficients {
+                id:                 i as u8 + 1,
+                horizontal_sample:  *h,
+                vertical_sample:    *v,
+                quantization_table: *table,
+                width_in_blocks:    mcu_width * h,
+                height_in_blocks:   mcu_height * v,
+                coefficients:       vec![0; mcu_width * h * mcu_height * v * 64]
+            })
+            .collect()
+    }
+
+    /// Convert the pixels to quantized DCT coefficients
+    fn compute_coefficients(&self) -> Result<JpegCoefficients, EncodeErrors> {
+        let jpeg_colorspace = self.jpeg_colorspace()?;
+
+        let mut image = JpegCoefficients {
+            width:      self.options.width(),
+            height:     self.options.height(),
+            precision:  8,
+            colorspace: jpeg_colorspace,
+            components: self.create_components(jpeg_colorspace)
+        };
+        let (h_max, v_max) = image.max_sampling_factors();
+
+        trace!(
+            "Encoding {:?} image as {jpeg_colorspace:?}",
+            self.options.colorspace()
+        );
+
+        // samples of an MCU row at full resolution, padded to whole MCUs
+        let padded_width = image.width.div_ceil(8 * h_max) * 8 * h_max;
+        let strip_height = 8 * v_max;
+
+        let mut planes = vec![vec![0_i16; padded_width * strip_height]; image.components.len()];
+        let mut downsampled = vec![0_i16; padded_width * strip_height];
+
+        let fdct = choose_fdct_func();
+        let mut dct = [0; 64];
+
+        let image_blocks: Vec<(usize, usize)> = image
+            .components
+            .iter()
+            .map(|c| image.image_blocks(c))
+            .collect();
+
+        for mcu_y in 0..image.height.div_ceil(strip_height) {
+            self.load_rows(&mut planes, mcu_y * strip_height, padded_width);
+
+            for ((plane, component), (width_in_blocks, height_in_blocks)) in
+                planes.iter().zip(&mut image.components).zip(&image_blocks)
+            {
+                let h_factor = h_max / component.horizontal_sample;
+                let v_factor = v_max / component.vertical_sample;
+
+                let samples = if h_factor == 1 && v_factor == 1 {
+                    plane
+                } else {
+                    downsample(plane, padded_width, h_factor, v_factor, &mut downsampled);
+                    &downsampled
+                };
+                let samples_width = padded_width / h_factor;
+
+                for y in 0..component.vertical_sample {
+                    let block_y = mcu_y * component.vertical_sample + y;
+
+                    if block_y >= *height_in_blocks {
+                        break;
+                    }
+                    for x in 0..*width_in_blocks {
+                        fdct(
+                            &samples[(y * samples_width + x) * 8..],
+                            samples_width,
+                            &mut dct
+                        );
+
+                        let table = component.quantization_table;
+                        quantize(&dct, &table, component.block_mut(x, block_y));
+                    }
+                }
+            }
+        }
+        for (component, (width_in_blocks, height_in_blocks)) in
+            image.components.iter_mut().zip(image_blocks)
+        {
+            component.fill_padding_blocks(width_in_blocks, height_in_blocks);
+        }
+        Ok(image)
+    }
+
+    /// Convert pixel rows starting at `first_row` to separate planes of samples,
+    /// as many as fit in the planes
+    ///
+    /// Rows below and columns right of the image repeat the last row and column
+    fn load_rows(&self, planes: &mut [Vec<i16>], first_row: usize, padded_width: usize) {
+        let (width, height) = (self.options.width(), self.options.height());
+        let colorspace = self.options.colorspace();
+
+        let convert = choose_rgb_to_ycbcr_convert_func(colorspace);
+        let stride = width * colorspace.num_components();
+
+        for row in 0..planes[0].len() / padded_width {
+            let y = (first_row + row).min(height - 1);
+            let pixels = &self.data[y * stride..(y + 1) * stride];
+            let start = row * padded_width;
+
+            if let (Some(convert), [y_plane, cb_plane, cr_plane]) = (convert, &mut *planes) {
+                convert(
+                    pixels,
+                    &mut y_plane[start..start + width],
+                    &mut cb_plane[start..start + width],
+                    &mut cr_plane[start..start + width]
+                );
+            } else {
+                let components = planes.len();
+
+                for (i, plane) in planes.iter_mut().enumerate() {
+                    for (sample, pixel) in plane[start..start + width]
+                        .iter_mut()
+                        .zip(pixels.chunks_exact(components))
+                    {
+                        *sample = i16::from(pixel[i]);
+                    }
+                }
+            }
+            for plane in planes.iter_mut() {
+                let last = plane[start + width - 1];
+                plane[start + width..start + padded_width].fill(last);
+            }
+        }
+    }
+
+    /// Write the headers and entropy coded coefficients of an image
+    pub(crate) fn write_coefficients<T: ZByteWriterTrait>(
+        &self, image: &JpegCoefficients, sink: T
+    ) -> Result<usize, EncodeErrors> {
+        let progressive = self.options.jpeg_encode_progressive();
+        let restart_interval = self.options.jpeg_restart_interval();
+
+        // assign ids to the distinct quantization tables
+        let mut quantization_tables: Vec<[u16; 64]> = Vec::new();
+        let mut table_ids = Vec::with_capacity(image.components.len());
+
+        for component in &image.components {
+            let table = &component.quantization_table;
+
+            if let Some(id) = quantization_tables.iter().position(|x| x == table) {
+                table_ids.push(id);
+            } else {
+                table_ids.push(quantization_tables.len());
+                quantization_tables.push(*table);
+            }
+        }
+        if quantization_tables.len() > 4 {
+            return Err(EncodeErrors::FormatStatic(
+                "Components use more than four quantization tables"
+            ));
+        }
+        if quantization_tables.iter().flatten().any(|x| *x == 0) {
+            return Err(EncodeErrors::FormatStatic(
+                "Quantization tables cannot contain zeroes"
+            ));
+        }
+        // luminance components use the first Huffman tables, the rest the second ones
+        let huffman_ids: Vec<usize> = table_ids.iter().map(|x| (*x).min(1)).collect();
+
+        // the standard Huffman tables cannot code large coefficients
+        let optimize = progressive
+            || self.options.jpeg_optimized_huffman_tables()
+            || !fits_standard_tables(image);
+
+        let sof = if progressive {
+            Marker::SOF(2)
+        } else if image.precision > 8 || quantization_tables.iter().flatten().any(|x| *x > 255) {
+            Marker::SOF(1)
+        } else {
+            Marker::SOF(0)
+        };
+
+        let mut writer = ZWriter::new(sink);
+
+        write_marker(&mut writer, Marker::SOI)?;
+
+        match image.colorspace {
+            ColorSpace::Luma | ColorSpace::YCbCr => write_jfif(&mut writer)?,
+            ColorSpace::YCCK => write_adobe(&mut writer, 2)?,
+            _ => write_adobe(&mut writer, 0)?
+        }
+        if let Some(exif) = self.exif {
+            write_exif(&mut writer, exif)?;
+        }
+        if let Some(icc_profile) = self.icc_profile {
+            write_icc(&mut writer, icc_profile)?;
+        }
+        write_dqt(&mut writer, &quantization_tables)?;
+        write_sof(&mut writer, sof, image, &table_ids)?;
+
+        let (luma_dc, luma_ac) = HuffmanSpec::standard(false);
+        let (chroma_dc, chroma_ac) = HuffmanSpec::standard(true);
+
+        if !optimize {
+            let mut tables = vec![(0, 0, &luma_dc), (1, 0, &luma_ac)];
+
+            if huffman_ids.contains(&1) {
+                tables.extend([(0, 1, &chroma_dc), (1, 1, &chroma_ac)]);
+            }
+            write_dht(&mut writer, &tables)?;
+        }
+        if restart_interval != 0 {
+            write_dri(&mut writer, restart_interval)?;
+        }
+        let scans = if progressive { progressive_scans(image) } else { sequential_scans(image) };
+        let settings = ScanSettings {
+            huffman_ids,
+            restart_interval: usize::from(restart_interval),
+            progressive
+        };
+
+        let dc = [HuffmanCodes::new(&luma_dc), HuffmanCodes::new(&chroma_dc)];
+        let ac = [HuffmanCodes::new(&luma_ac), HuffmanCodes::new(&chroma_ac)];
+
+        for scan in &scans {
+            if optimize {
+                write_optimized_scan(&mut writer, image, scan, &settings)?;
+            } else {
+                let mut bit_writer = BitWriter::new(
+                    [Some(&dc[0]), Some(&dc[1]), None, None],
+                    [Some(&ac[0]), Some(&ac[1]), None, None]
+                );
+                write_scan(&mut writer, image, scan, &settings, &mut bit_writer)?;
+            }
+        }
+        write_marker(&mut writer, Marker::EOI)?;
+
+        Ok(writer.bytes_written())
+    }
+}
+
+/// How the scans of an image are coded
+struct ScanSettings {
+    /// Huffman table id of each component
+    huffman_ids:      Vec<usize>,
+    /// Number of MCUs between restart markers or zero
+    restart_interval: usize,
+    progressive:      bool
+}
+
+/// Entropy code a scan with `bit_writer`, then write its header and data
+fn write_scan<T: ZByteWriterTrait>(
+    writer: &mut ZWriter<T>, image: &JpegCoefficients, scan: &Scan, settings: &ScanSettings,
+    bit_writer: &mut BitWriter
+) -> Result<(), EncodeErrors> {
+    encode_scan(
+        image,
+        scan,
+        &settings.huffman_ids,
+        settings.restart_interval,
+        settings.progressive,
+        bit_writer
+    );
+    bit_writer.flush();
+
+    write_sos(writer, image, scan, &settings.huffman_ids)?;
+    writer.write_all(&bit_writer.out)?;
+    Ok(())
+}
+
+/// Write a scan with Huffman tables optimized for it
+///
+/// The scan is coded once to count symbols, the tables built from the counts
+/// are written before the scan
+fn write_optimized_scan<T: ZByteWriterTrait>(
+    writer: &mut ZWriter<T>, image: &JpegCoefficients, scan: &Scan, settings: &ScanSettings
+) -> Result<(), EncodeErrors> {
+    let mut counter = SymbolCounter::new();
+
+    encode_scan(
+        image,
+        scan,
+        &settings.huffman_ids,
+        settings.restart_interval,
+        settings.progressive,
+        &mut counter
+    );
+    // refining DC scans don't use Huffman tables, DC scans don't use AC tables
+    let uses_dc = scan.spec_start == 0 && (!settings.progressive || scan.succ_high == 0);
+    let uses_ac = scan.spec_end != 0;
+
+    let mut ids: Vec<usize> = scan
+        .components
+        .iter()
+        .map(|x| settings.huffman_ids[*x])
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+
+    let mut specs = Vec::new();
+
+    for id in ids {
+        if uses_dc {
+            specs.push((0, id, HuffmanSpec::optimal(&counter.dc[id])));
+        }
+        if uses_ac {
+            specs.push((1, id, HuffmanSpec::optimal(&counter.ac[id])));
+        }
+    }
+    let tables: Vec<(u8, usize, &HuffmanSpec)> = specs
+        .iter()
+        .map(|(class, id, spec)| (*class, *id, spec))
+        .collect();
+
+    write_dht(writer, &tables)?;
+
+    let codes: Vec<HuffmanCodes> = specs
+        .iter()
+        .map(|(_, _, spec)| HuffmanCodes::new(spec))
+        .collect();
+
+    let mut dc = [None; 4];
+    let mut ac = [None; 4];
+
+    for ((class, id, _), codes) in specs.iter().zip(&codes) {
+        if *class == 0 {
+            dc[*id] = Some(codes);
+        } else {
+            ac[*id] = Some(codes);
+        }
+    }
+    write_scan(writer, image, scan, settings, &mut BitWriter::new(dc, ac))
+}
+
+/// Average `h_factor`x`v_factor` blocks of samples
+///
+/// Like libjpeg, the rounding bias alternates between columns to avoid
+/// shifting colors
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn downsample(input: &[i16], width: usize, h_factor: usize, v_factor: usize, output: &mut [i16]) {
+    let output_width = width / h_factor;
+    let count = h_factor * v_factor;
+
+    for (y, output) in output
+        .chunks_exact_mut(output_width)
+        .take(input.len() / width / v_factor)
+        .enumerate()
+    {
+        for (x, sample) in output.iter_mut().enumerate() {
+            let mut sum = 0_i32;
+
+            for row in input[y * v_factor * width..]
+                .chunks_exact(width)
+                .take(v_factor)
+            {
+                for value in &row[x * h_factor..(x + 1) * h_factor] {
+                    sum += i32::from(*value);
+                }
+            }
+            let bias = match count {
+                2 => x & 1,
+                4 => 1 + (x & 1),
+                _ => count / 2
+            };
+            *sample = ((sum + bias as i32) / count as i32) as i16;
+        }
+    }
+}
+
+/// Quantize the output of the forward DCT, rounding to the nearest integer
+#[allow(clippy::cast_possible_truncation)]
+fn quantize(dct: &[i32; 64], table: &[u16; 64], output: &mut [i16]) {
+    for ((out, coeff), quantizer) in output.iter_mut().zip(dct).zip(table) {
+        // the DCT output is scaled up by 8
+        let divisor = i32::from(*quantizer) << 3;
+        let value = (coeff.abs() + divisor / 2) / divisor;
+
+        *out = if *coeff < 0 { -value } else { value } as i16;
+    }
+}
+
+/// Whether the example Huffman tables of the specification can code all coefficients
+fn fits_standard_tables(image: &JpegCoefficients) -> bool {
+    image.components.iter().all(|component| {
+        component.coefficients.chunks_exact(64).all(|block| {
+            (-1024..1024).contains(&block[0])
+                && block[1..].iter().all(|x| (-1023..1024).contains(x))
+        })
+    })
+}
+
+/// Check that coefficients given by the caller describe an image which can be encoded
+fn check_coefficients(image: &JpegCoefficients) -> Result<(), EncodeErrors> {
+    let (width, height) = (image.width, image.height);
+
+    if !(1..=MAX_DIMENSIONS).contains(&width) || !(1..=MAX_DIMENSIONS).contains(&height) {
+        return Err(EncodeErrors::InvalidDimensions(width, height));
+    }
+    if !matches!(
+        image.colorspace,
+        ColorSpace::Luma
+            | ColorSpace::YCbCr
+            | ColorSpace::RGB
+            | ColorSpace::CMYK
+            | ColorSpace::YCCK
+    ) {
+        return Err(EncodeErrors::UnsupportedColorspace(image.colorspace));
+    }
+    if image.components.len() != image.colorspace.num_components() {
+        return Err(EncodeErrors::FormatStatic(
+            "Number of components doesn't match the colorspace"
+        ));
+    }
+    // largest DC and AC coefficients Huffman coding can code, DC differences
+    // need one bit more than the coefficients
+    let (dc_max, ac_max) = match image.precision {
+        8 => (1023, 1023),
+        12 => (16383, 16383),
+        _ => return Err(EncodeErrors::FormatStatic("Precision must be 8 or 12 bits"))
+    };
+    for (i, component) in image.components.iter().enumerate() {
+        if !(1..=4).contains(&component.horizontal_sample)
+            || !(1..=4).contains(&component.vertical_sample)
+        {
+            return Err(EncodeErrors::FormatStatic(
+                "Sampling factors must be between 1 and 4"
+            ));
+        }
+        if image.components[..i].iter().any(|c| c.id == component.id) {
+            return Err(EncodeErrors::FormatStatic("Component ids must be unique"));
+        }
+    }
+    let (h_max, v_max) = image.max_sampling_factors();
+
+    for component in &image.components {
+        let width_in_blocks = width.div_ceil(8 * h_max) * component.horizontal_sample;
+        let height_in_blocks = height.div_ceil(8 * v_max) * component.vertical_sample;
+
+        if component.width_in_blocks != width_in_blocks
+            || component.height_in_blocks != height_in_blocks
+            || component.coefficients.len() != width_in_blocks * height_in_blocks * 64
+        {
+            return Err(EncodeErrors::FormatStatic(
+                "Blocks of a component don't cover the image in whole MCUs"
+            ));
+        }
+        let in_range = component.coefficients.chunks_exact(64).all(|block| {
+            (-dc_max - 1..=dc_max).contains(&block[0])
+                && block[1..].iter().all(|x| (-ac_max..=ac_max).contains(x))
+        });
+        if !in_range {
+            return Err(EncodeErrors::FormatStatic(
+                "Coefficients are too large for the precision of the image"
+            ));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Destinations of entropy coded data
+//!
+//! Scans are coded twice when Huffman tables are optimized, first into a
+//! [`SymbolCounter`] gathering symbol frequencies and then into a [`BitWriter`]
+//! with the tables built from them, so block coders are generic over an [`EntropySink`].
+
+use alloc::vec::Vec;
+
+use crate::encoder::huffman::HuffmanCodes;
+
+/// Receives the symbols and raw bits of a scan
+pub trait EntropySink {
+    /// Code a symbol with the DC table `table`
+    fn put_dc(&mut self, table: usize, symbol: u8);
+    /// Code a symbol with the AC table `table`
+    fn put_ac(&mut self, table: usize, symbol: u8);
+    /// Append the lowest `count` bits of `bits`
+    fn put_bits(&mut self, bits: u32, count: u8);
+    /// Write restart marker `RSTn`, `n` being `marker`
+    fn restart(&mut self, marker: u8);
+}
+
+/// Counts how often each symbol occurs in a scan
+pub struct SymbolCounter {
+    pub dc: [[u32; 256]; 4],
+    pub ac: [[u32; 256]; 4]
+}
+
+impl SymbolCounter {
+    pub fn new() -> SymbolCounter {
+        SymbolCounter {
+            dc: [[0; 256]; 4],
+            ac: [[0; 256]; 4]
+        }
+    }
+}
+
+impl EntropySink for SymbolCounter {
+    #[inline]
+    fn put_dc(&mut self, table: usize, symbol: u8) {
+        self.dc[table][usize::from(symbol)] += 1;
+    }
+
+    #[inline]
+    fn put_ac(&mut self, table: usize, symbol: u8) {
+        self.ac[table][usize::from(symbol)] += 1;
+    }
+
+    #[inline]
+    fn put_bits(&mut self, _: u32, _: u8) {}
+
+    fn restart(&mut self, _: u8) {}
+}
+
+/// Writes Huffman coded data, stuffing a zero after every `0xFF` byte
+pub struct BitWriter<'a> {
+    dc:      [Option<&'a HuffmanCodes>; 4],
+    ac:      [Option<&'a HuffmanCodes>; 4],
+    buffer:  u64,
+    bits:    u8,
+    pub out: Vec<u8>
+}
+
+impl<'a> BitWriter<'a> {
+    pub fn new(dc: [Option<&'a HuffmanCodes>; 4], ac: [Option<&'a HuffmanCodes>; 4]) -> Self {
+        BitWriter {
+            dc,
+            ac,
+            buffer: 0,
+            bits: 0,
+            out: Vec::new()
+        }
+    }
+
+    #[inline]
+    fn put_code(&mut self, codes: Option<&HuffmanCodes>, symbol: u8) {
+        // scans only code symbols of the tables their components use
+        let codes = codes.unwrap();
+        let size = codes.sizes[usize::from(symbol)];
+
+        debug_assert!(size != 0, "No Huffman code for symbol {symbol}");
+        self.put_bits(u32::from(codes.codes[usize::from(symbol)]), size);
+    }
+
+    /// Pad the last byte with one bits
+    pub fn flush(&mut self) {
+        self.put_bits(0x7F, 7);
+        self.buffer = 0;
+        self.bits = 0;
+    }
+}
+
+impl EntropySink for BitWriter<'_> {
+    #[inline]
+    fn put_dc(&mut self, table: usize, symbol: u8) {
+        self.put_code(self.dc[table], symbol);
+    }
+
+    #[inline]
+    fn put_ac(&mut self, table: usize, symbol: u8) {
+        self.put_code(self.ac[table], symbol);
+    }
+
+    #[inline]
+    #[allow(clippy::cast_possible_truncation)]
+    fn put_bits(&mut self, bits: u32, count: u8) {
+        let mask = (1_u64 << count) - 1;
+
+        self.buffer = (self.buffer << count) | (u64::from(bits) & mask);
+        self.bits += count;
+
+        while self.bits >= 8 {
+            self.bits -= 8;
+            let byte = (self.buffer >> self.bits) as u8;
+
+            self.out.push(byte);
+
+            if byte == 0xFF {
+                self.out.push(0);
+            }
+        }
+    }
+
+    fn restart(&mut self, marker: u8) {
+        self.flush();
+        self.out.extend_from_slice(&[0xFF, 0xD0 + marker]);
+    }
+}
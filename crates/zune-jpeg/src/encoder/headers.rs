@@ -0,0 +1,203 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Writing of marker segments
+//!
+//! These are the counterparts of the parsers in `headers.rs`
+#![allow(clippy::cast_possible_truncation)]
+
+use zune_core::bytestream::{ZByteWriterTrait, ZWriter};
+
+use crate::coefficients::JpegCoefficients;
+use crate::encoder::huffman::HuffmanSpec;
+use crate::encoder::scan::Scan;
+use crate::errors::EncodeErrors;
+use crate::marker::Marker;
+use crate::misc::UN_ZIGZAG;
+
+/// Maximum size of a segment, excluding the marker
+const MAX_SEGMENT_LENGTH: usize = 65535;
+/// Identifier of ICC profile chunks in APP2 segments
+const ICC_IDENTIFIER: &[u8; 12] = b"ICC_PROFILE\0";
+/// Identifier of EXIF data in APP1 segments
+const EXIF_IDENTIFIER: &[u8; 6] = b"Exif\0\0";
+
+pub fn write_marker<T: ZByteWriterTrait>(
+    writer: &mut ZWriter<T>, marker: Marker
+) -> Result<(), EncodeErrors> {
+    writer.write_const_bytes(&[0xFF, marker.to_u8()])?;
+    Ok(())
+}
+
+/// Write a marker and the length of a segment with `length` bytes of content
+fn write_segment_start<T: ZByteWriterTrait>(
+    writer: &mut ZWriter<T>, marker: Marker, length: usize
+) -> Result<(), EncodeErrors> {
+    if length + 2 > MAX_SEGMENT_LENGTH {
+        return Err(EncodeErrors::FormatStatic("Too large marker segment"));
+    }
+    write_marker(writer, marker)?;
+    writer.write_u16_be_err((length + 2) as u16)?;
+    Ok(())
+}
+
+/// Write a JFIF APP0 segment, marking the image as grayscale or YCbCr
+pub fn write_jfif<T: ZByteWriterTrait>(writer: &mut ZWriter<T>) -> Result<(), EncodeErrors> {
+    write_segment_start(writer, Marker::APP(0), 14)?;
+    writer.write_all(b"JFIF\0")?;
+    // version 1.01, no units, a pixel aspect ratio of 1:1 and no thumbnail
+    writer.write_all(&[1, 1, 0, 0, 1, 0, 1, 0, 0])?;
+    Ok(())
+}
+
+/// Write an Adobe APP14 segment with the given color transform
+///
+/// The transform is 0 for images without color transform, e.g CMYK and
+/// 2 for YCCK images
+pub fn write_adobe<T: ZByteWriterTrait>(
+    writer: &mut ZWriter<T>, transform: u8
+) -> Result<(), EncodeErrors> {
+    write_segment_start(writer, Marker::APP(14), 12)?;
+    writer.write_all(b"Adobe")?;
+    // version 100 and no flags
+    writer.write_all(&[0, 100, 0, 0, 0, 0, transform])?;
+    Ok(())
+}
+
+/// Write EXIF data into an APP1 segment
+pub fn write_exif<T: ZByteWriterTrait>(
+    writer: &mut ZWriter<T>, exif: &[u8]
+) -> Result<(), EncodeErrors> {
+    write_segment_start(writer, Marker::APP(1), EXIF_IDENTIFIER.len() + exif.len())?;
+    writer.write_all(EXIF_IDENTIFIER)?;
+    writer.write_all(exif)?;
+    Ok(())
+}
+
+/// Write an ICC profile split over as many APP2 segments as needed
+pub fn write_icc<T: ZByteWriterTrait>(
+    writer: &mut ZWriter<T>, icc: &[u8]
+) -> Result<(), EncodeErrors> {
+    // identifier, sequence number and number of chunks
+    let max_chunk = MAX_SEGMENT_LENGTH - 2 - ICC_IDENTIFIER.len() - 2;
+    let chunks = icc.len().div_ceil(max_chunk);
+
+    if chunks > 255 {
+        return Err(EncodeErrors::FormatStatic("Too large ICC profile"));
+    }
+    for (i, chunk) in icc.chunks(max_chunk).enumerate() {
+        write_segment_start(
+            writer,
+            Marker::APP(2),
+            ICC_IDENTIFIER.len() + 2 + chunk.len()
+        )?;
+        writer.write_all(ICC_IDENTIFIER)?;
+        writer.write_const_bytes(&[i as u8 + 1, chunks as u8])?;
+        writer.write_all(chunk)?;
+    }
+    Ok(())
+}
+
+/// Write quantization tables in natural order, table `i` gets id `i`
+pub fn write_dqt<T: ZByteWriterTrait>(
+    writer: &mut ZWriter<T>, tables: &[[u16; 64]]
+) -> Result<(), EncodeErrors> {
+    for (id, table) in tables.iter().enumerate() {
+        // 16 bit precision is only used when needed
+        let sixteen_bit = table.iter().any(|x| *x > 255);
+
+        write_segment_start(writer, Marker::DQT, 1 + 64 * (1 + usize::from(sixteen_bit)))?;
+        writer.write_u8_err((u8::from(sixteen_bit) << 4) | id as u8)?;
+
+        for &k in &UN_ZIGZAG[..64] {
+            if sixteen_bit {
+                writer.write_u16_be_err(table[k])?;
+            } else {
+                writer.write_u8_err(table[k] as u8)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write the frame header
+///
+/// `quantization_tables` holds the quantization table id of each component
+pub fn write_sof<T: ZByteWriterTrait>(
+    writer: &mut ZWriter<T>, marker: Marker, image: &JpegCoefficients,
+    quantization_tables: &[usize]
+) -> Result<(), EncodeErrors> {
+    write_segment_start(writer, marker, 6 + 3 * image.components.len())?;
+    writer.write_u8_err(image.precision)?;
+    writer.write_u16_be_err(image.height as u16)?;
+    writer.write_u16_be_err(image.width as u16)?;
+    writer.write_u8_err(image.components.len() as u8)?;
+
+    for (component, table) in image.components.iter().zip(quantization_tables) {
+        writer.write_const_bytes(&[
+            component.id,
+            ((component.horizontal_sample << 4) | component.vertical_sample) as u8,
+            *table as u8
+        ])?;
+    }
+    Ok(())
+}
+
+/// Write Huffman tables, each given as its class (0 for DC, 1 for AC),
+/// its id and the table
+pub fn write_dht<T: ZByteWriterTrait>(
+    writer: &mut ZWriter<T>, tables: &[(u8, usize, &HuffmanSpec)]
+) -> Result<(), EncodeErrors> {
+    if tables.is_empty() {
+        return Ok(());
+    }
+    let length = tables
+        .iter()
+        .map(|(_, _, spec)| 17 + spec.values.len())
+        .sum();
+
+    write_segment_start(writer, Marker::DHT, length)?;
+
+    for (class, id, spec) in tables {
+        writer.write_u8_err((class << 4) | *id as u8)?;
+        writer.write_all(&spec.bits[1..])?;
+        writer.write_all(&spec.values)?;
+    }
+    Ok(())
+}
+
+/// Write the number of MCUs between restart markers
+pub fn write_dri<T: ZByteWriterTrait>(
+    writer: &mut ZWriter<T>, restart_interval: u16
+) -> Result<(), EncodeErrors> {
+    write_segment_start(writer, Marker::DRI, 2)?;
+    writer.write_u16_be_err(restart_interval)?;
+    Ok(())
+}
+
+/// Write a scan header
+///
+/// `tables` holds the Huffman table id of each component, which is used
+/// for both DC and AC tables
+pub fn write_sos<T: ZByteWriterTrait>(
+    writer: &mut ZWriter<T>, image: &JpegCoefficients, scan: &Scan, tables: &[usize]
+) -> Result<(), EncodeErrors> {
+    write_segment_start(writer, Marker::SOS, 4 + 2 * scan.components.len())?;
+    writer.write_u8_err(scan.components.len() as u8)?;
+
+    for &index in &scan.components {
+        let table = tables[index] as u8;
+        writer.write_const_bytes(&[image.components[index].id, (table << 4) | table])?;
+    }
+    writer.write_const_bytes(&[
+        scan.spec_start,
+        scan.spec_end,
+        (scan.succ_high << 4) | scan.succ_low
+    ])?;
+    Ok(())
+}
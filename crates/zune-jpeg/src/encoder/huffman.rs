@@ -0,0 +1,244 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Huffman tables used by the encoder
+//!
+//! Tables are described by the `BITS` and `HUFFVAL` lists stored in DHT segments
+//! and are either the example tables of the specification (Annex K.3) or optimal
+//! tables built from symbol frequencies (Annex K.2).
+#![allow(clippy::cast_possible_truncation)]
+
+use alloc::vec::Vec;
+
+use crate::misc::{
+    DEFAULT_AC_CHROMINANCE_BITS, DEFAULT_AC_CHROMINANCE_VALUES, DEFAULT_AC_LUMINANCE_BITS,
+    DEFAULT_AC_LUMINANCE_VALUES, DEFAULT_DC_CHROMINANCE_BITS, DEFAULT_DC_CHROMINANCE_VALUES,
+    DEFAULT_DC_LUMINANCE_BITS, DEFAULT_DC_LUMINANCE_VALUES
+};
+
+/// Longest code allowed by the specification
+const MAX_CODE_LENGTH: usize = 16;
+/// Longest code the optimal table builder may produce before lengths are limited
+const MAX_CODE_LENGTH_UNLIMITED: usize = 32;
+
+/// A Huffman table as stored in a DHT segment
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HuffmanSpec {
+    /// Number of codes of each length, `bits[0]` is unused
+    pub bits:   [u8; 17],
+    /// Symbols sorted by code length
+    pub values: Vec<u8>
+}
+
+impl HuffmanSpec {
+    /// The example luminance and chrominance tables of the specification
+    ///
+    /// Returns the DC and AC table, `chroma` selects the chrominance tables
+    pub fn standard(chroma: bool) -> (HuffmanSpec, HuffmanSpec) {
+        if chroma {
+            (
+                HuffmanSpec {
+                    bits:   DEFAULT_DC_CHROMINANCE_BITS,
+                    values: DEFAULT_DC_CHROMINANCE_VALUES.to_vec()
+                },
+                HuffmanSpec {
+                    bits:   DEFAULT_AC_CHROMINANCE_BITS,
+                    values: DEFAULT_AC_CHROMINANCE_VALUES.to_vec()
+                }
+            )
+        } else {
+            (
+                HuffmanSpec {
+                    bits:   DEFAULT_DC_LUMINANCE_BITS,
+                    values: DEFAULT_DC_LUMINANCE_VALUES.to_vec()
+                },
+                HuffmanSpec {
+                    bits:   DEFAULT_AC_LUMINANCE_BITS,
+                    values: DEFAULT_AC_LUMINANCE_VALUES.to_vec()
+                }
+            )
+        }
+    }
+
+    /// Build an optimal table for symbols occurring with the given frequencies
+    ///
+    /// This is the procedure of Annex K.2 as implemented by libjpeg, codes are
+    /// limited to 16 bits and no symbol gets a code of all ones.
+    pub fn optimal(frequencies: &[u32; 256]) -> HuffmanSpec {
+        let mut freq = [0_u64; 257];
+        let mut code_size = [0_usize; 257];
+        let mut others = [usize::MAX; 257];
+
+        for (out, freq) in freq.iter_mut().zip(frequencies) {
+            *out = u64::from(*freq);
+        }
+        if freq.iter().all(|x| *x == 0) {
+            // a table needs at least one symbol to be valid
+            freq[0] = 1;
+        }
+        // reserve one code point so that no real symbol gets a code of all ones
+        freq[256] = 1;
+
+        loop {
+            // find the two least frequent symbols, preferring larger values on ties
+            let mut c1 = usize::MAX;
+            let mut c2 = usize::MAX;
+            let mut v = u64::MAX;
+
+            for (i, f) in freq.iter().enumerate() {
+                if *f != 0 && *f <= v {
+                    v = *f;
+                    c1 = i;
+                }
+            }
+            v = u64::MAX;
+
+            for (i, f) in freq.iter().enumerate() {
+                if *f != 0 && *f <= v && i != c1 {
+                    v = *f;
+                    c2 = i;
+                }
+            }
+            if c2 == usize::MAX {
+                // done when only one tree remains
+                break;
+            }
+            // merge the two trees
+            freq[c1] += freq[c2];
+            freq[c2] = 0;
+
+            // increment the code sizes of everything in both trees
+            code_size[c1] += 1;
+            while others[c1] != usize::MAX {
+                c1 = others[c1];
+                code_size[c1] += 1;
+            }
+            // chain c2 onto c1's tree branch
+            others[c1] = c2;
+
+            code_size[c2] += 1;
+            while others[c2] != usize::MAX {
+                c2 = others[c2];
+                code_size[c2] += 1;
+            }
+        }
+
+        let mut bits = [0_u8; MAX_CODE_LENGTH_UNLIMITED + 1];
+
+        for size in code_size.iter().filter(|x| **x != 0) {
+            bits[*size] += 1;
+        }
+        // Limit code lengths to 16 bits by moving pairs of symbols from the longest
+        // length up the tree, see Annex K.3 figure K.3
+        for i in (MAX_CODE_LENGTH + 1..=MAX_CODE_LENGTH_UNLIMITED).rev() {
+            while bits[i] > 0 {
+                let mut j = i - 2;
+
+                while bits[j] == 0 {
+                    j -= 1;
+                }
+                bits[i] -= 2;
+                bits[i - 1] += 1;
+                bits[j + 1] += 2;
+                bits[j] -= 1;
+            }
+        }
+        // remove the reserved code point from the longest length
+        let mut i = MAX_CODE_LENGTH;
+
+        while bits[i] == 0 {
+            i -= 1;
+        }
+        bits[i] -= 1;
+
+        let mut values = Vec::with_capacity(256);
+
+        for size in 1..=MAX_CODE_LENGTH_UNLIMITED {
+            for (symbol, code_size) in code_size[..256].iter().enumerate() {
+                if *code_size == size {
+                    values.push(symbol as u8);
+                }
+            }
+        }
+
+        let mut spec_bits = [0; 17];
+        spec_bits.copy_from_slice(&bits[..17]);
+
+        HuffmanSpec {
+            bits: spec_bits,
+            values
+        }
+    }
+}
+
+/// Codes derived from a [`HuffmanSpec`] for each symbol
+pub struct HuffmanCodes {
+    /// Code of each symbol
+    pub codes: [u16; 256],
+    /// Length of the code of each symbol, zero if the symbol has no code
+    pub sizes: [u8; 256]
+}
+
+impl HuffmanCodes {
+    /// Generate the codes of a table (Annex C)
+    pub fn new(spec: &HuffmanSpec) -> HuffmanCodes {
+        let mut codes = [0; 256];
+        let mut sizes = [0; 256];
+
+        let mut code = 0_u16;
+        let mut values = spec.values.iter();
+
+        for (length, count) in spec.bits.iter().enumerate().skip(1) {
+            for value in values.by_ref().take(usize::from(*count)) {
+                codes[usize::from(*value)] = code;
+                sizes[usize::from(*value)] = length as u8;
+                code = code.wrapping_add(1);
+            }
+            code = code.wrapping_shl(1);
+        }
+        HuffmanCodes { codes, sizes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optimal_table_limits_code_lengths() {
+        // fibonacci frequencies produce the deepest possible tree
+        let mut frequencies = [0; 256];
+        let (mut a, mut b) = (1_u32, 1_u32);
+
+        for freq in frequencies.iter_mut().take(30) {
+            *freq = a;
+            (a, b) = (b, a + b);
+        }
+        let spec = HuffmanSpec::optimal(&frequencies);
+        let codes = HuffmanCodes::new(&spec);
+
+        assert_eq!(spec.values.len(), 30);
+        assert!(codes.sizes[..30].iter().all(|x| (1..=16).contains(x)));
+        // no code may consist of all ones
+        for symbol in 0..30 {
+            let size = codes.sizes[symbol];
+            assert_ne!(u32::from(codes.codes[symbol]), (1 << size) - 1);
+        }
+    }
+
+    #[test]
+    fn optimal_table_single_symbol() {
+        let mut frequencies = [0; 256];
+        frequencies[7] = 100;
+
+        let spec = HuffmanSpec::optimal(&frequencies);
+
+        assert_eq!(spec.values, [7]);
+        assert_eq!(spec.bits[1], 1);
+    }
+}
@@ -0,0 +1,432 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Scan scripts and entropy coding of quantized blocks
+//!
+//! The block coders mirror the decoders in `mcu.rs` and `mcu_prog.rs`, progressive
+//! coding follows libjpeg's `jcphuff.c` including its handling of end of band runs
+//! and buffered correction bits.
+#![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use zune_core::colorspace::ColorSpace;
+
+use crate::coefficients::JpegCoefficients;
+use crate::encoder::bitstream::EntropySink;
+use crate::misc::UN_ZIGZAG;
+
+/// Components in a scan may add up to this many blocks per MCU
+const MAX_BLOCKS_IN_MCU: usize = 10;
+/// Maximum number of components in a scan
+const MAX_COMPONENTS_IN_SCAN: usize = 4;
+/// Longest end of band run
+const MAX_EOB_RUN: u32 = 0x7FFF;
+/// Number of correction bits buffered before an end of band run is flushed
+const MAX_CORRECTION_BITS: usize = 1000;
+
+/// Parameters of a single scan
+#[derive(Clone, Debug)]
+pub struct Scan {
+    /// Indices of the components coded in the scan
+    pub components: Vec<usize>,
+    /// Start of spectral selection
+    pub spec_start: u8,
+    /// End of spectral selection
+    pub spec_end:   u8,
+    /// Successive approximation bit position high
+    pub succ_high:  u8,
+    /// Successive approximation bit position low
+    pub succ_low:   u8
+}
+
+impl Scan {
+    fn new(
+        components: Vec<usize>, spec_start: u8, spec_end: u8, succ_high: u8, succ_low: u8
+    ) -> Scan {
+        Scan {
+            components,
+            spec_start,
+            spec_end,
+            succ_high,
+            succ_low
+        }
+    }
+}
+
+/// Whether all components fit in a single interleaved scan
+fn can_interleave(image: &JpegCoefficients) -> bool {
+    let blocks: usize = image
+        .components
+        .iter()
+        .map(|c| c.horizontal_sample * c.vertical_sample)
+        .sum();
+
+    image.components.len() <= MAX_COMPONENTS_IN_SCAN && blocks <= MAX_BLOCKS_IN_MCU
+}
+
+/// Scans coding the DC coefficients of all components at the given bit positions
+fn dc_scans(image: &JpegCoefficients, succ_high: u8, succ_low: u8, scans: &mut Vec<Scan>) {
+    if can_interleave(image) {
+        let all = (0..image.components.len()).collect();
+        scans.push(Scan::new(all, 0, 0, succ_high, succ_low));
+    } else {
+        for i in 0..image.components.len() {
+            scans.push(Scan::new(vec![i], 0, 0, succ_high, succ_low));
+        }
+    }
+}
+
+/// Scans of a sequential image
+pub fn sequential_scans(image: &JpegCoefficients) -> Vec<Scan> {
+    if can_interleave(image) {
+        let all = (0..image.components.len()).collect();
+        vec![Scan::new(all, 0, 63, 0, 0)]
+    } else {
+        (0..image.components.len())
+            .map(|i| Scan::new(vec![i], 0, 63, 0, 0))
+            .collect()
+    }
+}
+
+/// Scans of a progressive image, this is the script of libjpeg's
+/// `jpeg_simple_progression`
+pub fn progressive_scans(image: &JpegCoefficients) -> Vec<Scan> {
+    let mut scans = Vec::new();
+
+    if image.colorspace == ColorSpace::YCbCr && image.components.len() == 3 {
+        // custom script for YCbCr color images
+        dc_scans(image, 0, 1, &mut scans);
+        // get some luma data out in a hurry
+        scans.push(Scan::new(vec![0], 1, 5, 0, 2));
+        // chroma data is too small to be worth expending many scans on
+        scans.push(Scan::new(vec![2], 1, 63, 0, 1));
+        scans.push(Scan::new(vec![1], 1, 63, 0, 1));
+        // complete spectral selection for luma AC
+        scans.push(Scan::new(vec![0], 6, 63, 0, 2));
+        // refine next bit of luma AC
+        scans.push(Scan::new(vec![0], 1, 63, 2, 1));
+        // finish DC successive approximation
+        dc_scans(image, 1, 0, &mut scans);
+        // finish AC successive approximation
+        scans.push(Scan::new(vec![2], 1, 63, 1, 0));
+        scans.push(Scan::new(vec![1], 1, 63, 1, 0));
+        // luma bottom bit comes last since it's usually the largest scan
+        scans.push(Scan::new(vec![0], 1, 63, 1, 0));
+    } else {
+        let components = image.components.len();
+
+        dc_scans(image, 0, 1, &mut scans);
+
+        for (spec_start, spec_end, succ_high, succ_low) in
+            [(1, 5, 0, 2), (6, 63, 0, 2), (1, 63, 2, 1)]
+        {
+            for i in 0..components {
+                scans.push(Scan::new(
+                    vec![i],
+                    spec_start,
+                    spec_end,
+                    succ_high,
+                    succ_low
+                ));
+            }
+        }
+        dc_scans(image, 1, 0, &mut scans);
+
+        for i in 0..components {
+            scans.push(Scan::new(vec![i], 1, 63, 1, 0));
+        }
+    }
+    scans
+}
+
+/// Number of bits needed to represent `value`
+#[inline]
+fn bit_length(value: u32) -> u8 {
+    (32 - value.leading_zeros()) as u8
+}
+
+/// Coding state of a scan
+struct ScanState {
+    /// Previous DC value of each component
+    last_dc:         [i32; 4],
+    /// Number of blocks in the pending end of band run
+    eobrun:          u32,
+    /// Correction bits of the blocks in the pending end of band run
+    correction_bits: Vec<u8>,
+    /// Correction bits of the current block
+    block_bits:      Vec<u8>
+}
+
+impl ScanState {
+    /// Code a DC difference with its category and additional bits
+    #[inline]
+    fn put_dc_diff<S: EntropySink>(sink: &mut S, table: usize, diff: i32) {
+        let size = bit_length(diff.unsigned_abs());
+        sink.put_dc(table, size);
+
+        if size != 0 {
+            // negative values are coded as their ones complement
+            let bits = if diff < 0 { diff - 1 } else { diff };
+            sink.put_bits(bits as u32, size);
+        }
+    }
+
+    /// Code an AC coefficient preceded by `run` zeroes
+    #[inline]
+    fn put_ac_value<S: EntropySink>(sink: &mut S, table: usize, mut run: u8, value: i32) {
+        while run > 15 {
+            sink.put_ac(table, 0xF0);
+            run -= 16;
+        }
+        let size = bit_length(value.unsigned_abs());
+        let bits = if value < 0 { value - 1 } else { value };
+
+        sink.put_ac(table, (run << 4) | size);
+        sink.put_bits(bits as u32, size);
+    }
+
+    /// Code the pending end of band run and its correction bits
+    fn flush_eobrun<S: EntropySink>(&mut self, sink: &mut S, table: usize) {
+        if self.eobrun > 0 {
+            let size = bit_length(self.eobrun) - 1;
+
+            sink.put_ac(table, size << 4);
+
+            if size != 0 {
+                sink.put_bits(self.eobrun, size);
+            }
+            self.eobrun = 0;
+
+            for bit in self.correction_bits.drain(..) {
+                sink.put_bits(u32::from(bit), 1);
+            }
+        }
+    }
+
+    fn put_block_bits<S: EntropySink>(&mut self, sink: &mut S) {
+        for bit in self.block_bits.drain(..) {
+            sink.put_bits(u32::from(bit), 1);
+        }
+    }
+
+    /// Code a block of a sequential image
+    fn encode_sequential<S: EntropySink>(
+        &mut self, sink: &mut S, block: &[i16], component: usize, table: usize
+    ) {
+        let dc = i32::from(block[0]);
+        Self::put_dc_diff(sink, table, dc - self.last_dc[component]);
+        self.last_dc[component] = dc;
+
+        let mut run = 0;
+
+        for &k in &UN_ZIGZAG[1..64] {
+            let value = i32::from(block[k]);
+
+            if value == 0 {
+                run += 1;
+            } else {
+                Self::put_ac_value(sink, table, run, value);
+                run = 0;
+            }
+        }
+        if run > 0 {
+            sink.put_ac(table, 0x00);
+        }
+    }
+
+    /// Code the DC coefficient of a block in the first DC scan
+    fn encode_dc_first<S: EntropySink>(
+        &mut self, sink: &mut S, block: &[i16], component: usize, table: usize, succ_low: u8
+    ) {
+        let dc = i32::from(block[0]) >> succ_low;
+
+        Self::put_dc_diff(sink, table, dc - self.last_dc[component]);
+        self.last_dc[component] = dc;
+    }
+
+    /// Code the next bit of the DC coefficient of a block
+    fn encode_dc_refine<S: EntropySink>(sink: &mut S, block: &[i16], succ_low: u8) {
+        sink.put_bits(u32::from((block[0] >> succ_low) as u16 & 1), 1);
+    }
+
+    /// Code the AC coefficients of a block in a first AC scan
+    fn encode_ac_first<S: EntropySink>(
+        &mut self, sink: &mut S, block: &[i16], table: usize, scan: &Scan
+    ) {
+        let mut run = 0;
+
+        for &k in &UN_ZIGZAG[usize::from(scan.spec_start)..=usize::from(scan.spec_end)] {
+            let coeff = i32::from(block[k]);
+            // point transform, rounding towards zero
+            let value =
+                if coeff < 0 { -((-coeff) >> scan.succ_low) } else { coeff >> scan.succ_low };
+            if value == 0 {
+                run += 1;
+                continue;
+            }
+            self.flush_eobrun(sink, table);
+            Self::put_ac_value(sink, table, run, value);
+            run = 0;
+        }
+        if run > 0 {
+            self.eobrun += 1;
+
+            if self.eobrun == MAX_EOB_RUN {
+                self.flush_eobrun(sink, table);
+            }
+        }
+    }
+
+    /// Code the next bit of the AC coefficients of a block
+    fn encode_ac_refine<S: EntropySink>(
+        &mut self, sink: &mut S, block: &[i16], table: usize, scan: &Scan
+    ) {
+        let zigzag = &UN_ZIGZAG[usize::from(scan.spec_start)..=usize::from(scan.spec_end)];
+        let mut absolute = [0_u16; 64];
+
+        // position of the last coefficient becoming non-zero in this scan
+        let mut eob = 0;
+
+        for (i, &k) in zigzag.iter().enumerate() {
+            absolute[i] = block[k].unsigned_abs() >> scan.succ_low;
+
+            if absolute[i] == 1 {
+                eob = i;
+            }
+        }
+        let mut run = 0_u8;
+
+        for (i, &k) in zigzag.iter().enumerate() {
+            let value = absolute[i];
+
+            if value == 0 {
+                run += 1;
+                continue;
+            }
+            while run > 15 && i <= eob {
+                self.flush_eobrun(sink, table);
+                sink.put_ac(table, 0xF0);
+                run -= 16;
+                self.put_block_bits(sink);
+            }
+            if value > 1 {
+                // previously non-zero coefficients only get a correction bit
+                self.block_bits.push((value & 1) as u8);
+                continue;
+            }
+            self.flush_eobrun(sink, table);
+            sink.put_ac(table, (run << 4) | 1);
+            sink.put_bits(u32::from(block[k] >= 0), 1);
+            self.put_block_bits(sink);
+            run = 0;
+        }
+        if run > 0 || !self.block_bits.is_empty() {
+            self.eobrun += 1;
+            self.correction_bits.append(&mut self.block_bits);
+
+            if self.eobrun == MAX_EOB_RUN
+                || self.correction_bits.len() > MAX_CORRECTION_BITS - 64 + 1
+            {
+                self.flush_eobrun(sink, table);
+            }
+        }
+    }
+}
+
+/// Entropy code a scan into `sink`
+///
+/// `tables` holds the Huffman table index of each component of the image,
+/// `restart_interval` is the number of MCUs between restart markers or zero
+#[allow(clippy::too_many_arguments)]
+pub fn encode_scan<S: EntropySink>(
+    image: &JpegCoefficients, scan: &Scan, tables: &[usize], restart_interval: usize,
+    progressive: bool, sink: &mut S
+) {
+    let mut state = ScanState {
+        last_dc:         [0; 4],
+        eobrun:          0,
+        correction_bits: Vec::new(),
+        block_bits:      Vec::new()
+    };
+    // the AC table of single component scans, used for end of band runs
+    let ac_table = tables[scan.components[0]];
+
+    let encode_block = |state: &mut ScanState, sink: &mut S, component: usize, block: &[i16]| {
+        let table = tables[component];
+
+        if !progressive {
+            state.encode_sequential(sink, block, component, table);
+        } else if scan.spec_start == 0 {
+            if scan.succ_high == 0 {
+                state.encode_dc_first(sink, block, component, table, scan.succ_low);
+            } else {
+                ScanState::encode_dc_refine(sink, block, scan.succ_low);
+            }
+        } else if scan.succ_high == 0 {
+            state.encode_ac_first(sink, block, table, scan);
+        } else {
+            state.encode_ac_refine(sink, block, table, scan);
+        }
+    };
+
+    let mut mcu_count = 0;
+    let mut restart_marker = 0_u8;
+
+    let mut start_mcu = |state: &mut ScanState, sink: &mut S| {
+        if restart_interval != 0 && mcu_count != 0 && mcu_count % restart_interval == 0 {
+            state.flush_eobrun(sink, ac_table);
+            sink.restart(restart_marker);
+            restart_marker = (restart_marker + 1) % 8;
+
+            state.last_dc = [0; 4];
+        }
+        mcu_count += 1;
+    };
+
+    let (h_max, v_max) = image.max_sampling_factors();
+
+    if scan.components.len() == 1 {
+        // non-interleaved scans cover just the blocks of the component inside the image
+        let index = scan.components[0];
+        let component = &image.components[index];
+        let (width, height) = image.image_blocks(component);
+
+        for y in 0..height {
+            for x in 0..width {
+                start_mcu(&mut state, sink);
+                encode_block(&mut state, sink, index, component.block(x, y));
+            }
+        }
+    } else {
+        let mcu_width = image.width.div_ceil(8 * h_max);
+        let mcu_height = image.height.div_ceil(8 * v_max);
+
+        for mcu_y in 0..mcu_height {
+            for mcu_x in 0..mcu_width {
+                start_mcu(&mut state, sink);
+
+                for &index in &scan.components {
+                    let component = &image.components[index];
+
+                    for v in 0..component.vertical_sample {
+                        for h in 0..component.horizontal_sample {
+                            let block = component.block(
+                                mcu_x * component.horizontal_sample + h,
+                                mcu_y * component.vertical_sample + v
+                            );
+                            encode_block(&mut state, sink, index, block);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    state.flush_eobrun(sink, ac_table);
+}
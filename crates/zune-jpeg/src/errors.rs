@@ -7,12 +7,14 @@
  */
 
 //! Contains most common errors that may be encountered in decoding a Decoder
-//! image
+//! image and errors of the encoder and of lossless transforms
 
 use alloc::string::String;
 use core::fmt::{Debug, Display, Formatter};
 
+use zune_core::bit_depth::BitDepth;
 use zune_core::bytestream::ZByteIoError;
+use zune_core::colorspace::ColorSpace;
 
 use crate::misc::{
     START_OF_FRAME_EXT_AR, START_OF_FRAME_EXT_SEQ, START_OF_FRAME_LOS_SEQ,
@@ -165,3 +167,109 @@ impl UnsupportedSchemes {
         }
     }
 }
+
+/// Errors that may occur while encoding an image
+pub enum EncodeErrors {
+    /// The colorspace of the input cannot be encoded
+    UnsupportedColorspace(ColorSpace),
+    /// The bit depth of the input cannot be encoded, only 8 bit images are supported
+    UnsupportedDepth(BitDepth),
+    /// The input length doesn't match the dimensions, expected and found lengths
+    WrongInputSize(usize, usize),
+    /// Width or height are zero or larger than JPEG supports
+    InvalidDimensions(usize, usize),
+    /// Any other error which doesn't need to allocate
+    FormatStatic(&'static str),
+
+    IoErrors(ZByteIoError)
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EncodeErrors {}
+
+impl From<&'static str> for EncodeErrors {
+    fn from(data: &'static str) -> Self {
+        return Self::FormatStatic(data);
+    }
+}
+
+impl From<ZByteIoError> for EncodeErrors {
+    fn from(data: ZByteIoError) -> Self {
+        return Self::IoErrors(data);
+    }
+}
+
+impl Debug for EncodeErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnsupportedColorspace(colorspace) => {
+                write!(f, "Cannot encode images in the {colorspace:?} colorspace")
+            }
+            Self::UnsupportedDepth(depth) => {
+                write!(f, "Cannot encode images with depth {depth:?}, only 8 bit images are supported")
+            }
+            Self::WrongInputSize(expected, found) => {
+                write!(f, "Input length {found} doesn't match the expected length {expected}")
+            }
+            Self::InvalidDimensions(width, height) => write!(
+                f,
+                "Invalid dimensions {width}x{height}, width and height should be between 1 and 65535"
+            ),
+            Self::FormatStatic(reason) => write!(f, "{reason:?}"),
+            Self::IoErrors(error) => write!(f, "I/O errors {error:?}")
+        }
+    }
+}
+
+impl Display for EncodeErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Errors that may occur while transforming an image
+pub enum TransformErrors {
+    /// The image couldn't be decoded
+    DecodeErrors(DecodeErrors),
+    /// The transformed image couldn't be written
+    EncodeErrors(EncodeErrors),
+    /// Any other error which doesn't need to allocate
+    FormatStatic(&'static str)
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TransformErrors {}
+
+impl From<&'static str> for TransformErrors {
+    fn from(data: &'static str) -> Self {
+        return Self::FormatStatic(data);
+    }
+}
+
+impl From<DecodeErrors> for TransformErrors {
+    fn from(data: DecodeErrors) -> Self {
+        return Self::DecodeErrors(data);
+    }
+}
+
+impl From<EncodeErrors> for TransformErrors {
+    fn from(data: EncodeErrors) -> Self {
+        return Self::EncodeErrors(data);
+    }
+}
+
+impl Debug for TransformErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::DecodeErrors(error) => write!(f, "Error decoding image: {error:?}"),
+            Self::EncodeErrors(error) => write!(f, "Error writing image: {error:?}"),
+            Self::FormatStatic(reason) => write!(f, "{reason:?}")
+        }
+    }
+}
+
+impl Display for TransformErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
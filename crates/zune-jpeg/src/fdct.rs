@@ -0,0 +1,94 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Routines for the forward DCT used by the encoder
+//!
+//! Like the IDCT, there is a scalar implementation and vector ones for AVX2 and Neon,
+//! all of them produce bit identical results.
+//!
+//! The vector versions keep a row of the block in a register and use the same register
+//! abstraction and transposes as the IDCT.
+#![allow(clippy::module_name_repetitions, clippy::wildcard_imports)]
+
+use zune_core::log::debug;
+
+use crate::fdct::scalar::fdct_int;
+
+#[cfg(feature = "x86")]
+pub mod avx2;
+#[cfg(feature = "neon")]
+pub mod neon;
+
+pub mod scalar;
+
+/// Forward DCT function, transforms the 8x8 block of samples starting at the
+/// first element of the slice with rows `usize` samples apart
+pub type FDCTPtr = fn(&[i16], usize, &mut [i32; 64]);
+
+/// Choose an appropriate forward DCT function
+pub fn choose_fdct_func() -> FDCTPtr {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[cfg(feature = "x86")]
+    {
+        if crate::unsafe_utils::avx2_available() {
+            debug!("Using vector integer FDCT");
+            return crate::fdct::avx2::fdct_avx2;
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    #[cfg(feature = "neon")]
+    {
+        debug!("Using vector integer FDCT");
+        return crate::fdct::neon::fdct_neon;
+    }
+    #[allow(unreachable_code)]
+    {
+        debug!("Using scalar integer FDCT");
+        fdct_int
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(samples: &[i16], stride: usize) {
+        let mut output_scalar = [0; 64];
+        let mut output_vector = [0; 64];
+
+        fdct_int(samples, stride, &mut output_scalar);
+        choose_fdct_func()(samples, stride, &mut output_vector);
+
+        assert_eq!(output_scalar, output_vector, "FDCT and scalar do not match");
+    }
+
+    #[test]
+    fn fdct_flat() {
+        check(&[200; 64], 8);
+
+        let output = &mut [0; 64];
+        fdct_int(&[200; 64], 8, output);
+
+        // flat blocks only have a DC coefficient, scaled up by 8
+        assert_eq!(output[0], (200 - 128) * 64);
+        assert!(output[1..].iter().all(|&x| x == 0));
+    }
+
+    #[test]
+    fn fdct_extremes() {
+        let samples: Vec<i16> = (0..64).map(|x| if x % 3 == 0 { 255 } else { 0 }).collect();
+        check(&samples, 8);
+    }
+
+    #[test]
+    fn fdct_stride() {
+        // a block in a plane 24 samples wide
+        let samples: Vec<i16> = (0..24 * 8).map(|x| ((x * 37) % 256) as i16).collect();
+        check(&samples[5..], 24);
+    }
+}
@@ -0,0 +1,165 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+#![cfg(target_arch = "aarch64")]
+//! Neon optimised forward DCT.
+//!
+//! This is the same implementation as the AVX one, with a register holding a row
+//! of the block and transposes before both passes so that the results are bit identical
+//! with the scalar code.
+#![cfg(feature = "neon")]
+
+use core::arch::aarch64::*;
+
+use crate::fdct::scalar::*;
+use crate::unsafe_utils::{transpose, YmmRegister};
+
+/// SAFETY
+/// ------
+///
+/// It is the responsibility of the CALLER to ensure that  this function is
+/// called in contexts where the CPU supports it
+///
+///
+/// For documentation see module docs.
+pub fn fdct_neon(in_vector: &[i16], stride: usize, out_vector: &mut [i32; 64]) {
+    unsafe {
+        // We don't call this method directly because we need to flag the code function
+        // with #[target_feature] so that the compiler does do weird stuff with
+        // it
+        fdct_int_neon_inner(in_vector, stride, out_vector);
+    }
+}
+
+/// Load 8 samples, sign extending them to i32's and level shifting them
+#[inline]
+#[target_feature(enable = "neon")]
+unsafe fn load_row(in_vector: &[i16]) -> YmmRegister {
+    let row: &[i16; 8] = in_vector[..8].try_into().unwrap();
+    let samples = vld1q_s16(row.as_ptr());
+
+    let low = vmovl_s16(vget_low_s16(samples));
+    let high = vmovl_high_s16(samples);
+
+    YmmRegister {
+        mm256: int32x4x2_t(low, high)
+    } + -128
+}
+
+/// Divide by `2^N` rounding to the nearest integer
+#[inline]
+#[target_feature(enable = "neon")]
+unsafe fn descale<const N: i32>(reg: YmmRegister) -> YmmRegister {
+    (reg + (1 << (N - 1))).const_shra::<N>()
+}
+
+#[target_feature(enable = "neon")]
+#[allow(clippy::too_many_lines, clippy::similar_names)]
+unsafe fn fdct_int_neon_inner(in_vector: &[i16], stride: usize, out_vector: &mut [i32; 64]) {
+    let mut row0 = load_row(&in_vector[0..]);
+    let mut row1 = load_row(&in_vector[stride..]);
+    let mut row2 = load_row(&in_vector[stride * 2..]);
+    let mut row3 = load_row(&in_vector[stride * 3..]);
+    let mut row4 = load_row(&in_vector[stride * 4..]);
+    let mut row5 = load_row(&in_vector[stride * 5..]);
+    let mut row6 = load_row(&in_vector[stride * 6..]);
+    let mut row7 = load_row(&in_vector[stride * 7..]);
+
+    macro_rules! fdct_pass {
+        ($even:ident, $odd:ident) => {
+            // A direct translation of the scalar code, see fdct_1d
+
+            // even part
+            let tmp0 = row0 + row7;
+            let tmp7 = row0 - row7;
+            let tmp1 = row1 + row6;
+            let tmp6 = row1 - row6;
+            let tmp2 = row2 + row5;
+            let tmp5 = row2 - row5;
+            let tmp3 = row3 + row4;
+            let tmp4 = row3 - row4;
+
+            let tmp10 = tmp0 + tmp3;
+            let tmp13 = tmp0 - tmp3;
+            let tmp11 = tmp1 + tmp2;
+            let tmp12 = tmp1 - tmp2;
+
+            let z1 = (tmp12 + tmp13) * FIX_0_541196100;
+
+            row0 = $even(tmp10 + tmp11);
+            row4 = $even(tmp10 - tmp11);
+            row2 = $odd(z1 + tmp13 * FIX_0_765366865);
+            row6 = $odd(z1 + tmp12 * -FIX_1_847759065);
+
+            // odd part
+            let z1 = tmp4 + tmp7;
+            let z2 = tmp5 + tmp6;
+            let z3 = tmp4 + tmp6;
+            let z4 = tmp5 + tmp7;
+            let z5 = (z3 + z4) * FIX_1_175875602;
+
+            let tmp4 = tmp4 * FIX_0_298631336;
+            let tmp5 = tmp5 * FIX_2_053119869;
+            let tmp6 = tmp6 * FIX_3_072711026;
+            let tmp7 = tmp7 * FIX_1_501321110;
+
+            let z1 = z1 * -FIX_0_899976223;
+            let z2 = z2 * -FIX_2_562915447;
+            let z3 = z3 * -FIX_1_961570560 + z5;
+            let z4 = z4 * -FIX_0_390180644 + z5;
+
+            row1 = $odd(tmp7 + z1 + z4);
+            row3 = $odd(tmp6 + z2 + z3);
+            row5 = $odd(tmp5 + z2 + z4);
+            row7 = $odd(tmp4 + z1 + z3);
+        };
+    }
+
+    // Process rows, results are scaled up by 2^PASS1_BITS
+    transpose(
+        &mut row0, &mut row1, &mut row2, &mut row3, &mut row4, &mut row5, &mut row6, &mut row7
+    );
+    fdct_pass!(pass1_even, pass1_odd);
+
+    // Process columns, removing the PASS1_BITS scaling
+    transpose(
+        &mut row0, &mut row1, &mut row2, &mut row3, &mut row4, &mut row5, &mut row6, &mut row7
+    );
+    fdct_pass!(pass2_even, pass2_odd);
+
+    for (out, row) in out_vector
+        .chunks_exact_mut(8)
+        .zip([row0, row1, row2, row3, row4, row5, row6, row7])
+    {
+        vst1q_s32_x2(out.as_mut_ptr(), row.mm256);
+    }
+}
+
+#[inline]
+#[target_feature(enable = "neon")]
+unsafe fn pass1_even(reg: YmmRegister) -> YmmRegister {
+    reg.const_shl::<PASS1_BITS>()
+}
+
+#[inline]
+#[target_feature(enable = "neon")]
+unsafe fn pass1_odd(reg: YmmRegister) -> YmmRegister {
+    descale::<{ CONST_BITS - PASS1_BITS }>(reg)
+}
+
+#[inline]
+#[target_feature(enable = "neon")]
+unsafe fn pass2_even(reg: YmmRegister) -> YmmRegister {
+    descale::<PASS1_BITS>(reg)
+}
+
+#[inline]
+#[target_feature(enable = "neon")]
+unsafe fn pass2_odd(reg: YmmRegister) -> YmmRegister {
+    descale::<{ CONST_BITS + PASS1_BITS }>(reg)
+}
@@ -0,0 +1,129 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Platform independent forward DCT.
+//!
+//! This is the accurate integer forward DCT of libjpeg (`jfdctint.c`), a two pass
+//! implementation of the Loeffler, Ligtenberg and Moschytz algorithm, the first pass
+//! transforms rows and the second one columns.
+//!
+//! The output is scaled up by 8 compared to a true DCT, quantization removes the factor.
+
+#![allow(clippy::unreadable_literal)]
+
+pub const CONST_BITS: i32 = 13;
+pub const PASS1_BITS: i32 = 2;
+
+pub const FIX_0_298631336: i32 = 2446;
+pub const FIX_0_390180644: i32 = 3196;
+pub const FIX_0_541196100: i32 = 4433;
+pub const FIX_0_765366865: i32 = 6270;
+pub const FIX_0_899976223: i32 = 7373;
+pub const FIX_1_175875602: i32 = 9633;
+pub const FIX_1_501321110: i32 = 12299;
+pub const FIX_1_847759065: i32 = 15137;
+pub const FIX_1_961570560: i32 = 16069;
+pub const FIX_2_053119869: i32 = 16819;
+pub const FIX_2_562915447: i32 = 20995;
+pub const FIX_3_072711026: i32 = 25172;
+
+/// Divide by `2^n` rounding to the nearest integer
+#[inline(always)]
+const fn descale(x: i32, n: i32) -> i32 {
+    (x + (1 << (n - 1))) >> n
+}
+
+/// One dimensional DCT of 8 samples
+///
+/// Outputs 0 and 4 are returned unscaled, the rest are scaled up by `CONST_BITS`
+#[inline(always)]
+fn fdct_1d(d: [i32; 8]) -> [i32; 8] {
+    // even part
+    let tmp0 = d[0] + d[7];
+    let tmp7 = d[0] - d[7];
+    let tmp1 = d[1] + d[6];
+    let tmp6 = d[1] - d[6];
+    let tmp2 = d[2] + d[5];
+    let tmp5 = d[2] - d[5];
+    let tmp3 = d[3] + d[4];
+    let tmp4 = d[3] - d[4];
+
+    let tmp10 = tmp0 + tmp3;
+    let tmp13 = tmp0 - tmp3;
+    let tmp11 = tmp1 + tmp2;
+    let tmp12 = tmp1 - tmp2;
+
+    let z1 = (tmp12 + tmp13) * FIX_0_541196100;
+
+    let out0 = tmp10 + tmp11;
+    let out4 = tmp10 - tmp11;
+    let out2 = z1 + tmp13 * FIX_0_765366865;
+    let out6 = z1 - tmp12 * FIX_1_847759065;
+
+    // odd part
+    let z1 = tmp4 + tmp7;
+    let z2 = tmp5 + tmp6;
+    let z3 = tmp4 + tmp6;
+    let z4 = tmp5 + tmp7;
+    let z5 = (z3 + z4) * FIX_1_175875602;
+
+    let tmp4 = tmp4 * FIX_0_298631336;
+    let tmp5 = tmp5 * FIX_2_053119869;
+    let tmp6 = tmp6 * FIX_3_072711026;
+    let tmp7 = tmp7 * FIX_1_501321110;
+
+    let z1 = z1 * -FIX_0_899976223;
+    let z2 = z2 * -FIX_2_562915447;
+    let z3 = z3 * -FIX_1_961570560 + z5;
+    let z4 = z4 * -FIX_0_390180644 + z5;
+
+    [
+        out0,
+        tmp7 + z1 + z4,
+        out2,
+        tmp6 + z2 + z3,
+        out4,
+        tmp5 + z2 + z4,
+        out6,
+        tmp4 + z1 + z3
+    ]
+}
+
+/// Forward DCT of the 8x8 block of samples starting at `in_vector[0]`
+/// with rows `stride` samples apart
+///
+/// Samples are level shifted before the transform, `out_vector` is in
+/// natural order
+pub fn fdct_int(in_vector: &[i16], stride: usize, out_vector: &mut [i32; 64]) {
+    // Pass 1: process rows, results are scaled up by 2^PASS1_BITS
+    for (i, out) in out_vector.chunks_exact_mut(8).enumerate() {
+        let row: &[i16; 8] = in_vector[i * stride..i * stride + 8].try_into().unwrap();
+        let d = fdct_1d(row.map(|x| i32::from(x) - 128));
+
+        for (j, (out, value)) in out.iter_mut().zip(d).enumerate() {
+            *out = if j % 4 == 0 {
+                value << PASS1_BITS
+            } else {
+                descale(value, CONST_BITS - PASS1_BITS)
+            };
+        }
+    }
+    // Pass 2: process columns, removing the PASS1_BITS scaling
+    for i in 0..8 {
+        let column = core::array::from_fn(|j| out_vector[j * 8 + i]);
+        let d = fdct_1d(column);
+
+        for (j, value) in d.into_iter().enumerate() {
+            out_vector[j * 8 + i] = if j % 4 == 0 {
+                descale(value, PASS1_BITS)
+            } else {
+                descale(value, CONST_BITS + PASS1_BITS)
+            };
+        }
+    }
+}
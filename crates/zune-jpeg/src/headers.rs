@@ -22,6 +22,8 @@ use crate::components::Components;
 use crate::decoder::{ICCChunk, JpegDecoder, MAX_COMPONENTS};
 use crate::errors::DecodeErrors;
 use crate::huffman::HuffmanTable;
+use crate::idct::scalar::idct_int_12bit;
+use crate::mcu_lossless::lossless_color_convert;
 use crate::misc::{SOFMarkers, UN_ZIGZAG};
 
 ///**B.2.4.2 Huffman table-specification syntax**
@@ -174,6 +176,67 @@ pub(crate) fn parse_dqt<T: ZByteReaderTrait>(img: &mut JpegDecoder<T>) -> Result
     return Ok(());
 }
 
+///**B.2.4.3 Arithmetic conditioning table-specification syntax**
+pub(crate) fn parse_dac<T: ZByteReaderTrait>(img: &mut JpegDecoder<T>) -> Result<(), DecodeErrors> {
+    let length = img
+        .stream
+        .get_u16_be_err()?
+        .checked_sub(2)
+        .ok_or(DecodeErrors::FormatStatic(
+            "Invalid DAC length. Length should be greater than 2"
+        ))?;
+
+    if length % 2 != 0 {
+        return Err(DecodeErrors::Format(format!(
+            "Invalid DAC length {length}, each table takes two bytes"
+        )));
+    }
+    // A single DAC header may have multiple tables
+    for _ in 0..length / 2 {
+        let table_info = img.stream.read_u8_err()?;
+        let value = img.stream.read_u8_err()?;
+        // top 4 bits are the table class, 0 = DC, 1 = AC
+        let class = table_info >> 4;
+        let table_position = usize::from(table_info & 0x0f);
+
+        if table_position >= MAX_COMPONENTS {
+            return Err(DecodeErrors::Format(format!(
+                "Too large table position for DAC :{table_position}, expected between 0 and 3"
+            )));
+        }
+        let conditioning = &mut img.arithmetic_tables;
+
+        match class {
+            0 => {
+                // lower 4 bits are L, upper 4 bits are U
+                let (lower, upper) = (value & 0x0f, value >> 4);
+
+                if lower > upper {
+                    return Err(DecodeErrors::Format(format!(
+                        "Invalid DC conditioning, L {lower} is larger than U {upper}"
+                    )));
+                }
+                conditioning.dc_lower[table_position] = lower;
+                conditioning.dc_upper[table_position] = upper;
+            }
+            1 => {
+                if !(1..=63).contains(&value) {
+                    return Err(DecodeErrors::Format(format!(
+                        "Invalid AC conditioning {value}, expected between 1 and 63"
+                    )));
+                }
+                conditioning.ac_kx[table_position] = value;
+            }
+            _ => {
+                return Err(DecodeErrors::Format(format!(
+                    "Expected DAC table class of either 0 or 1, found {class}"
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Section:`B.2.2 Frame header syntax`
 
 pub(crate) fn parse_start_of_frame<T: ZByteReaderTrait>(
@@ -186,13 +249,28 @@ pub(crate) fn parse_start_of_frame<T: ZByteReaderTrait>(
     }
     // Get length of the frame header
     let length = img.stream.get_u16_be_err()?;
-    // usually 8, but can be 12 and 16, we currently support only 8
-    // so sorry about that 12 bit images
+    // usually 8, but DCT images can also have 12 bits
+    //
+    // Lossless images can have between 2 and 16 bits
     let dt_precision = img.stream.read_u8_err()?;
 
-    if dt_precision != 8 {
+    if sof.is_lossless() && img.options.jpeg_get_scale_denom() != 1 {
+        return Err(DecodeErrors::SofError(
+            "Lossless images cannot be decoded scaled down".to_string()
+        ));
+    }
+    if sof.is_lossless() {
+        if !(2..=16).contains(&dt_precision) {
+            return Err(DecodeErrors::SofError(format!(
+                "Lossless images should have between 2 and 16 bits of precision, the image has {dt_precision}"
+            )));
+        }
+    } else if dt_precision == 12 {
+        // the vector IDCTs only produce 8 bit samples
+        img.idct_func = idct_int_12bit;
+    } else if dt_precision != 8 {
         return Err(DecodeErrors::SofError(format!(
-            "The library can only parse 8-bit images, the image has {dt_precision} bits of precision"
+            "DCT images should have 8 or 12 bits of precision, the image has {dt_precision}"
         )));
     }
 
@@ -221,6 +299,7 @@ pub(crate) fn parse_start_of_frame<T: ZByteReaderTrait>(
     if img_width == 0 || img_height == 0 {
         return Err(DecodeErrors::ZeroError);
     }
+    check_crop_region(img)?;
 
     // Number of components for the image.
     let num_components = img.stream.read_u8_err()?;
@@ -254,6 +333,22 @@ pub(crate) fn parse_start_of_frame<T: ZByteReaderTrait>(
         // https://entropymine.wordpress.com/2018/10/22/how-is-a-jpeg-images-color-type-determined/
         img.input_colorspace = ColorSpace::CMYK;
     }
+    if sof.is_lossless() {
+        // lossless samples are stored without a color transform
+        let colorspace = match num_components {
+            1 => ColorSpace::Luma,
+            2 => ColorSpace::LumaA,
+            3 => ColorSpace::RGB,
+            4 => ColorSpace::CMYK,
+            _ => {
+                return Err(DecodeErrors::SofError(format!(
+                    "Lossless images can have up to 4 components, found {num_components}"
+                )))
+            }
+        };
+        img.input_colorspace = colorspace;
+        lossless_color_convert(colorspace, img.options.jpeg_get_out_colorspace())?;
+    }
 
     // set number of components
     img.info.components = num_components;
@@ -388,9 +483,10 @@ pub(crate) fn parse_sos<T: ZByteReaderTrait>(
         )));
     }
     // successive approximation bit position low
+    // or point transform for lossless images
     image.succ_low = bit_approx & 0xF;
 
-    if image.succ_low > 13 {
+    if image.succ_low > 13 && !image.info.sof.is_lossless() {
         return Err(DecodeErrors::SosError(format!(
             "Invalid Al parameter {}, range should be 0-13",
             image.succ_low
@@ -527,6 +623,27 @@ pub(crate) fn parse_app2<T: ZByteReaderTrait>(
     Ok(())
 }
 
+/// Check that the crop region set in the options is inside the image
+fn check_crop_region<T: ZByteReaderTrait>(img: &JpegDecoder<T>) -> Result<(), DecodeErrors> {
+    if let Some((x, y, width, height)) = img.options.jpeg_get_crop() {
+        // the region is in the coordinates of the image scaled down
+        let denom = img.options.jpeg_get_scale_denom();
+        let image_width = usize::from(img.info.width).div_ceil(denom);
+        let image_height = usize::from(img.info.height).div_ceil(denom);
+
+        if width == 0
+            || height == 0
+            || x.saturating_add(width) > image_width
+            || y.saturating_add(height) > image_height
+        {
+            return Err(DecodeErrors::Format(format!(
+                "Crop region of {width}x{height} pixels at ({x}, {y}) is not inside the {image_width}x{image_height} image"
+            )));
+        }
+    }
+    Ok(())
+}
+
 /// Small utility function to print Un-zig-zagged quantization tables
 
 fn un_zig_zag<T>(a: &[T]) -> [i32; 64]
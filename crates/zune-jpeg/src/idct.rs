@@ -37,7 +37,7 @@ use zune_core::log::debug;
 use zune_core::options::DecoderOptions;
 
 use crate::decoder::IDCTPtr;
-use crate::idct::scalar::idct_int;
+use crate::idct::scalar::{idct_int, idct_int_reduced};
 
 #[cfg(feature = "x86")]
 pub mod avx2;
@@ -71,6 +71,40 @@ pub fn choose_idct_func(options: &DecoderOptions) -> IDCTPtr {
     return idct_int;
 }
 
+/// Choose the IDCT writing `width`x`height` samples for images scaled down,
+/// `idct` is the IDCT used for full sized blocks
+pub fn choose_scaled_idct_func(
+    width: usize, height: usize, precision: u8, idct: IDCTPtr
+) -> IDCTPtr {
+    if precision == 12 {
+        choose_reduced_idct::<12>(width, height, idct)
+    } else {
+        choose_reduced_idct::<8>(width, height, idct)
+    }
+}
+
+/// [`choose_scaled_idct_func`] for samples of `PRECISION` bits
+fn choose_reduced_idct<const PRECISION: u8>(width: usize, height: usize, idct: IDCTPtr) -> IDCTPtr {
+    match (width, height) {
+        (8, 4) => idct_int_reduced::<8, 4, PRECISION>,
+        (8, 2) => idct_int_reduced::<8, 2, PRECISION>,
+        (8, 1) => idct_int_reduced::<8, 1, PRECISION>,
+        (4, 8) => idct_int_reduced::<4, 8, PRECISION>,
+        (4, 4) => idct_int_reduced::<4, 4, PRECISION>,
+        (4, 2) => idct_int_reduced::<4, 2, PRECISION>,
+        (4, 1) => idct_int_reduced::<4, 1, PRECISION>,
+        (2, 8) => idct_int_reduced::<2, 8, PRECISION>,
+        (2, 4) => idct_int_reduced::<2, 4, PRECISION>,
+        (2, 2) => idct_int_reduced::<2, 2, PRECISION>,
+        (2, 1) => idct_int_reduced::<2, 1, PRECISION>,
+        (1, 8) => idct_int_reduced::<1, 8, PRECISION>,
+        (1, 4) => idct_int_reduced::<1, 4, PRECISION>,
+        (1, 2) => idct_int_reduced::<1, 2, PRECISION>,
+        (1, 1) => idct_int_reduced::<1, 1, PRECISION>,
+        _ => idct
+    }
+}
+
 #[cfg(test)]
 #[allow(unreachable_code)]
 #[allow(dead_code)]
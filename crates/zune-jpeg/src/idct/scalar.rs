@@ -210,3 +210,221 @@ fn fsh(x: i32) -> i32 {
 fn clamp(a: i32) -> i16 {
     a.clamp(0, 255) as i16
 }
+
+/// IDCT for images with 12 bits of precision
+///
+/// Follows [`idct_int`], but the larger coefficients of these images need
+/// 64 bit intermediates, and the samples are level shifted by 2048 and
+/// clamped between 0 and 4095.
+#[allow(clippy::cast_possible_truncation)]
+pub fn idct_int_12bit(in_vector: &mut [i32; 64], out_vector: &mut [i16], stride: usize) {
+    let mut columns = [0_i64; 64];
+
+    for ptr in 0..8 {
+        let column: [i64; 8] = core::array::from_fn(|i| i64::from(in_vector[ptr + i * 8]));
+
+        // bring the constants down, keeping 2 extra bits of precision
+        for (i, value) in idct_1d_12bit(&column).iter().enumerate() {
+            columns[ptr + i * 8] = (value + 512) >> 10;
+        }
+    }
+    for (row, out) in columns.chunks_exact(8).zip(out_vector.chunks_mut(stride)) {
+        let row: &[i64; 8] = row.try_into().unwrap();
+
+        // remove 1<<17, rounding and adding the level shift before the shift
+        for (value, out) in idct_1d_12bit(row).iter().zip(out.iter_mut()) {
+            *out = ((value + (1 << 16) + (2048 << 17)) >> 17).clamp(0, 4095) as i16;
+        }
+    }
+}
+
+/// A one dimensional IDCT of 8 values, scaled up by 1<<12
+fn idct_1d_12bit(s: &[i64; 8]) -> [i64; 8] {
+    // even part
+    let p1 = (s[2] + s[6]) * 2217;
+    let t2 = p1 + s[6] * -7567;
+    let t3 = p1 + s[2] * 3135;
+
+    let t0 = (s[0] + s[4]) << 12;
+    let t1 = (s[0] - s[4]) << 12;
+
+    let x0 = t0 + t3;
+    let x3 = t0 - t3;
+    let x1 = t1 + t2;
+    let x2 = t1 - t2;
+
+    // odd part
+    let (mut t0, mut t1, mut t2, mut t3) = (s[7], s[5], s[3], s[1]);
+
+    let p3 = t0 + t2;
+    let p4 = t1 + t3;
+    let p1 = t0 + t3;
+    let p2 = t1 + t2;
+    let p5 = (p3 + p4) * 4816;
+
+    t0 *= 1223;
+    t1 *= 8410;
+    t2 *= 12586;
+    t3 *= 6149;
+
+    let p1 = p5 + p1 * -3685;
+    let p2 = p5 + p2 * -10497;
+    let p3 = p3 * -8034;
+    let p4 = p4 * -1597;
+
+    t3 += p1 + p4;
+    t2 += p2 + p3;
+    t1 += p2 + p4;
+    t0 += p1 + p3;
+
+    [
+        x0 + t3,
+        x1 + t2,
+        x2 + t1,
+        x3 + t0,
+        x3 - t0,
+        x2 - t1,
+        x1 - t2,
+        x0 - t3
+    ]
+}
+
+/// Fixed point precision of the constants of the reduced IDCTs
+const CONST_BITS: u32 = 13;
+/// Extra precision kept between the passes of the reduced IDCTs
+const PASS1_BITS: u32 = 2;
+
+/// IDCT producing `W`x`H` samples for images scaled down, where `W` and `H`
+/// are 8, 4, 2 or 1
+///
+/// Every sample is the average of `8/W`x`8/H` samples of the full IDCT, the
+/// constants and layout follow `jidctint.c` and `jidctred.c` in libjpeg.
+#[allow(clippy::cast_possible_truncation)]
+pub fn idct_int_reduced<const W: usize, const H: usize, const PRECISION: u8>(
+    in_vector: &mut [i32; 64], out_vector: &mut [i16], stride: usize
+) {
+    let max = (1_i64 << PRECISION) - 1;
+    let level_shift = 1_i64 << (PRECISION - 1);
+
+    if (W == 1 && H == 1) || in_vector[1..] == [0; 63] {
+        // all samples are the average of the block
+        let value = ((i64::from(in_vector[0]) + 4) >> 3) + level_shift;
+        let value = value.clamp(0, max) as i16;
+
+        for out in out_vector.chunks_mut(stride).take(H) {
+            out[..W].fill(value);
+        }
+        return;
+    }
+    let mut columns = [[0_i64; H]; 8];
+
+    for (x, column) in columns.iter_mut().enumerate() {
+        // the columns skipped by the IDCT of the rows don't contribute to the output
+        if (W == 4 && x == 4) || (W == 2 && x % 2 == 0 && x != 0) || (W == 1 && x != 0) {
+            continue;
+        }
+        let input: [i64; 8] = core::array::from_fn(|y| i64::from(in_vector[y * 8 + x]));
+
+        if input[1..].iter().all(|&x| x == 0) {
+            *column = [input[0] << PASS1_BITS; H];
+            continue;
+        }
+        let shift = CONST_BITS - PASS1_BITS + extra_bits(H);
+
+        *column = idct_1d_reduced(input).map(|value| (value + (1 << (shift - 1))) >> shift);
+    }
+    for (y, out) in out_vector.chunks_mut(stride).take(H).enumerate() {
+        let row: [i64; W] = idct_1d_reduced(core::array::from_fn(|x| columns[x][y]));
+        // remove the constants, the pass 1 bits and the normalization,
+        // rounding and adding the level shift before the shift
+        let shift = CONST_BITS + PASS1_BITS + 3 + extra_bits(W);
+
+        for (value, out) in row.iter().zip(out.iter_mut()) {
+            let value = (value + (1 << (shift - 1)) + (level_shift << shift)) >> shift;
+            *out = value.clamp(0, max) as i16;
+        }
+    }
+}
+
+/// The outputs of a one dimensional IDCT of 8 coefficients producing
+/// `n` samples are scaled up by `1<<(CONST_BITS+extra_bits(n))`
+const fn extra_bits(n: usize) -> u32 {
+    match n {
+        8 => 0,
+        4 => 1,
+        2 => 2,
+        _ => 3
+    }
+}
+
+/// A one dimensional IDCT of 8 coefficients producing 8, 4, 2 or 1 samples,
+/// scaled up by `1<<(CONST_BITS+extra_bits(N))`
+fn idct_1d_reduced<const N: usize>(s: [i64; 8]) -> [i64; N] {
+    let mut out = [0; N];
+
+    if N == 8 {
+        // even part
+        let z1 = (s[2] + s[6]) * 4433;
+        let t2 = z1 - s[6] * 15137;
+        let t3 = z1 + s[2] * 6270;
+
+        let t0 = (s[0] + s[4]) << CONST_BITS;
+        let t1 = (s[0] - s[4]) << CONST_BITS;
+
+        let t10 = t0 + t3;
+        let t13 = t0 - t3;
+        let t11 = t1 + t2;
+        let t12 = t1 - t2;
+
+        // odd part
+        let z1 = (s[7] + s[1]) * -7373;
+        let z2 = (s[5] + s[3]) * -20995;
+        let z5 = (s[7] + s[3] + s[5] + s[1]) * 9633;
+        let z3 = (s[7] + s[3]) * -16069 + z5;
+        let z4 = (s[5] + s[1]) * -3196 + z5;
+
+        let t0 = s[7] * 2446 + z1 + z3;
+        let t1 = s[5] * 16819 + z2 + z4;
+        let t2 = s[3] * 25172 + z2 + z3;
+        let t3 = s[1] * 12299 + z1 + z4;
+
+        out.copy_from_slice(
+            &[
+                t10 + t3,
+                t11 + t2,
+                t12 + t1,
+                t13 + t0,
+                t13 - t0,
+                t12 - t1,
+                t11 - t2,
+                t10 - t3
+            ][..N]
+        );
+    } else if N == 4 {
+        // even part, the 4th coefficient doesn't contribute
+        let t0 = s[0] << (CONST_BITS + 1);
+        let t2 = s[2] * 15137 - s[6] * 6270;
+
+        let t10 = t0 + t2;
+        let t12 = t0 - t2;
+
+        // odd part
+        let t0 = -s[7] * 1730 + s[5] * 11893 - s[3] * 17799 + s[1] * 8697;
+        let t2 = -s[7] * 4176 - s[5] * 4926 + s[3] * 7373 + s[1] * 20995;
+
+        out[0] = t10 + t2;
+        out[1] = t12 + t0;
+        out[2] = t12 - t0;
+        out[3] = t10 - t2;
+    } else if N == 2 {
+        // only the odd coefficients contribute
+        let t10 = s[0] << (CONST_BITS + 2);
+        let t0 = -s[7] * 5906 + s[5] * 6967 - s[3] * 10426 + s[1] * 29692;
+
+        out[0] = t10 + t0;
+        out[1] = t10 - t0;
+    } else {
+        out[0] = s[0] << (CONST_BITS + 3);
+    }
+    out
+}
@@ -109,13 +109,20 @@ extern crate core;
 
 pub use zune_core;
 
+pub use crate::coefficients::{ComponentCoefficients, JpegCoefficients};
 pub use crate::decoder::{ImageInfo, JpegDecoder};
+pub use crate::encoder::JpegEncoder;
 pub use crate::marker::Marker;
+pub use crate::transform::{JpegTransformer, Transform};
+mod arithmetic;
 mod bitstream;
+mod coefficients;
 mod color_convert;
 mod components;
 mod decoder;
+mod encoder;
 pub mod errors;
+mod fdct;
 mod headers;
 mod huffman;
 #[cfg(not(fuzzing))]
@@ -124,8 +131,10 @@ mod idct;
 pub mod idct;
 mod marker;
 mod mcu;
+mod mcu_lossless;
 mod mcu_prog;
 mod misc;
+mod transform;
 mod unsafe_utils;
 mod unsafe_utils_avx2;
 mod unsafe_utils_neon;
@@ -62,7 +62,11 @@ impl Marker {
             0xC0 => Some(SOF(0)),
             0xC1 => Some(SOF(1)),
             0xC2 => Some(SOF(2)),
+            0xC3 => Some(SOF(3)),
             0xC4 => Some(DHT),
+            0xC9 => Some(SOF(9)),
+            0xCA => Some(SOF(10)),
+            0xCB => Some(SOF(11)),
             0xCC => Some(DAC),
             0xD0 => Some(RST(0)),
             0xD1 => Some(RST(1)),
@@ -85,4 +89,23 @@ impl Marker {
             _ => None
         }
     }
+
+    /// The second byte of the marker, the first one is always `0xFF`
+    #[must_use]
+    pub const fn to_u8(self) -> u8 {
+        match self {
+            Marker::SOF(n) => 0xC0 + n,
+            Marker::DHT => 0xC4,
+            Marker::DAC => 0xCC,
+            Marker::RST(n) => 0xD0 + n,
+            Marker::SOI => 0xD8,
+            Marker::EOI => 0xD9,
+            Marker::SOS => 0xDA,
+            Marker::DQT => 0xDB,
+            Marker::DNL => 0xDC,
+            Marker::DRI => 0xDD,
+            Marker::APP(n) => 0xE0 + n,
+            Marker::COM => 0xFE
+        }
+    }
 }
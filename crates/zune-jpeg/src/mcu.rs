@@ -8,19 +8,23 @@
 
 use alloc::{format, vec};
 use core::cmp::min;
+use core::ops::Range;
 
 use zune_core::bytestream::ZByteReaderTrait;
 use zune_core::colorspace::ColorSpace;
 use zune_core::colorspace::ColorSpace::Luma;
 use zune_core::log::{error, trace, warn};
 
+use crate::arithmetic::ArithmeticDecoder;
 use crate::bitstream::BitStream;
 use crate::components::SampleRatios;
 use crate::decoder::MAX_COMPONENTS;
 use crate::errors::DecodeErrors;
+use crate::idct::choose_scaled_idct_func;
 use crate::marker::Marker;
+use crate::mcu_prog::get_marker;
 use crate::misc::{calculate_padded_width, setup_component_params};
-use crate::worker::{color_convert, upsample};
+use crate::worker::{upsample, OutputSample};
 use crate::JpegDecoder;
 
 /// The size of a DC block for a MCU.
@@ -83,13 +87,15 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
         clippy::cast_possible_truncation
     )]
     #[inline(never)]
-    pub(crate) fn decode_mcu_ycbcr_baseline(
-        &mut self, pixels: &mut [u8]
+    pub(crate) fn decode_mcu_ycbcr_baseline<S: OutputSample>(
+        &mut self, pixels: &mut [S]
     ) -> Result<(), DecodeErrors> {
         setup_component_params(self)?;
 
-        // check dc and AC tables
-        self.check_tables()?;
+        // check dc and AC tables, arithmetic coded images don't have them
+        if !self.info.sof.is_arithmetic() {
+            self.check_tables()?;
+        }
 
         let (mut mcu_width, mut mcu_height);
 
@@ -106,6 +112,7 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
             mcu_height = ((self.info.height + 7) / 8) as usize;
         }
         if self.is_interleaved
+            && !self.is_scaled()
             && self.input_colorspace.num_components() > 1
             && self.options.jpeg_get_out_colorspace().num_components() == 1
             && (self.sub_sample_ratio == SampleRatios::V
@@ -148,6 +155,10 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
         let mut stream = BitStream::new();
         let mut tmp = [0_i32; DCT_BLOCK];
 
+        if self.info.sof.is_arithmetic() {
+            stream.arithmetic = Some(ArithmeticDecoder::new());
+        }
+
         let comp_len = self.components.len();
 
         for (pos, comp) in self.components.iter_mut().enumerate() {
@@ -164,8 +175,8 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
             {
                 // allocate enough space to hold a whole MCU width
                 // this means we should take into account sampling ratios
-                // `*8` is because each MCU spans 8 widths.
-                let len = comp.width_stride * comp.vertical_sample * 8;
+                // `*dct_height` is because each MCU spans dct_height(usually 8) rows.
+                let len = comp.scaled_stride() * comp.vertical_sample * comp.dct_height;
 
                 comp.needed = true;
                 comp.raw_coeff = vec![0; len];
@@ -174,13 +185,17 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
             }
         }
 
-        let mut pixels_written = 0;
+        let mut rows_written = 0;
+        let mut scaled_rows = Default::default();
 
         let is_hv = usize::from(self.is_interleaved);
         let upsampler_scratch_size = is_hv * self.components[0].width_stride;
         let mut upsampler_scratch_space = vec![0; upsampler_scratch_size];
 
-        for i in 0..mcu_height {
+        let (mcu_rows, mcu_columns) = self.mcu_region();
+
+        // stop after the last row needed by the crop region
+        for i in 0..mcu_height.min(mcu_rows.end) {
             // Report if we have no more bytes
             // This may generate false negatives since we over-read bytes
             // hence that why 37 is chosen(we assume if we over-read more than 37 bytes, we have a problem)
@@ -196,39 +211,55 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
             }
             // decode a whole MCU width,
             // this takes into account interleaved components.
-            self.decode_mcu_width(mcu_width, &mut tmp, &mut stream)?;
+            // MCUs above the crop region are only entropy decoded
+            let columns = if mcu_rows.contains(&i) { mcu_columns.clone() } else { 0..0 };
+
+            self.decode_mcu_width(mcu_width, columns, &mut tmp, &mut stream)?;
             // process that width up until it's impossible
-            self.post_process(
-                pixels,
-                i,
-                mcu_height,
-                width,
-                padded_width,
-                &mut pixels_written,
-                &mut upsampler_scratch_space
-            )?;
+            if self.is_scaled() {
+                self.post_process_scaled(pixels, i, &mut scaled_rows)?;
+            } else {
+                self.post_process(
+                    pixels,
+                    i,
+                    mcu_height,
+                    padded_width,
+                    &mut rows_written,
+                    &mut upsampler_scratch_space
+                )?;
+            }
         }
         // it may happen that some images don't have the whole buffer
         // so we can't panic in case of that
-        // assert_eq!(pixels_written, pixels.len());
+        // assert_eq!(rows_written, height);
 
         trace!("Finished decoding image");
 
         Ok(())
     }
+    /// Decode a row of MCUs, transforming the MCUs in `columns`
     fn decode_mcu_width(
-        &mut self, mcu_width: usize, tmp: &mut [i32; 64], stream: &mut BitStream
+        &mut self, mcu_width: usize, columns: Range<usize>, tmp: &mut [i32; 64],
+        stream: &mut BitStream
     ) -> Result<(), DecodeErrors> {
         for j in 0..mcu_width {
+            if stream.arithmetic.is_some() && self.restart_interval != 0 && self.todo == 0 {
+                // the arithmetic decoder doesn't read ahead, so it may not have
+                // seen the restart marker yet
+                self.handle_rst(stream)?;
+            }
             // iterate over components
-            for component in &mut self.components {
-                let dc_table = self.dc_huffman_tables[component.dc_huff_table % MAX_COMPONENTS]
-                    .as_ref()
-                    .unwrap();
+            for (pos, component) in self.components.iter_mut().enumerate() {
+                let dc_table =
+                    self.dc_huffman_tables[component.dc_huff_table % MAX_COMPONENTS].as_ref();
+
+                let ac_table =
+                    self.ac_huffman_tables[component.ac_huff_table % MAX_COMPONENTS].as_ref();
 
-                let ac_table = self.ac_huffman_tables[component.ac_huff_table % MAX_COMPONENTS]
-                    .as_ref()
-                    .unwrap();
+                let (width, height) = (component.dct_width, component.dct_height);
+                let stride = component.scaled_stride();
+                let idct =
+                    choose_scaled_idct_func(width, height, self.info.pixel_density, self.idct_func);
 
                 let qt_table = &component.quantization_table;
                 let channel = &mut component.raw_coeff;
@@ -242,27 +273,40 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
                         // a zero based array.
                         tmp.fill(0);
 
-                        stream.decode_mcu_block(
-                            &mut self.stream,
-                            dc_table,
-                            ac_table,
-                            qt_table,
-                            tmp,
-                            &mut component.dc_pred
-                        )?;
+                        if stream.arithmetic.is_some() {
+                            stream.decode_arith_mcu_block(
+                                &mut self.stream,
+                                &self.arithmetic_tables,
+                                component.dc_huff_table,
+                                component.ac_huff_table,
+                                qt_table,
+                                tmp,
+                                &mut component.dc_pred,
+                                pos
+                            )?;
+                        } else {
+                            stream.decode_mcu_block(
+                                &mut self.stream,
+                                dc_table.unwrap(),
+                                ac_table.unwrap(),
+                                qt_table,
+                                tmp,
+                                &mut component.dc_pred
+                            )?;
+                        }
 
-                        if component.needed {
+                        if component.needed && columns.contains(&j) {
                             let idct_position = {
                                 // derived from stb and rewritten for my tastes
-                                let c2 = v_samp * 8;
-                                let c3 = ((j * component.horizontal_sample) + h_samp) * 8;
+                                let c2 = v_samp * height;
+                                let c3 = ((j * component.horizontal_sample) + h_samp) * width;
 
-                                component.width_stride * c2 + c3
+                                stride * c2 + c3
                             };
 
                             let idct_pos = channel.get_mut(idct_position..).unwrap();
                             //  call idct.
-                            (self.idct_func)(tmp, idct_pos, component.width_stride);
+                            idct(tmp, idct_pos, stride);
                         }
                     }
                 }
@@ -318,6 +362,12 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
     pub(crate) fn handle_rst(&mut self, stream: &mut BitStream) -> Result<(), DecodeErrors> {
         self.todo = self.restart_interval;
 
+        if stream.marker.is_none() {
+            // the decoder stopped before the restart marker, the bits left in
+            // the segment are padding
+            stream.marker = Some(get_marker(&mut self.stream, stream)?);
+        }
+
         if let Some(marker) = stream.marker {
             // Found a marker
             // Read stream and see what marker is stored there
@@ -342,13 +392,16 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
         Ok(())
     }
     #[allow(clippy::too_many_lines, clippy::too_many_arguments)]
-    pub(crate) fn post_process(
-        &mut self, pixels: &mut [u8], i: usize, mcu_height: usize, width: usize,
-        padded_width: usize, pixels_written: &mut usize, upsampler_scratch_space: &mut [i16]
+    pub(crate) fn post_process<S: OutputSample>(
+        &mut self, pixels: &mut [S], i: usize, mcu_height: usize, padded_width: usize,
+        rows_written: &mut usize, upsampler_scratch_space: &mut [i16]
     ) -> Result<(), DecodeErrors> {
         let out_colorspace_components = self.options.jpeg_get_out_colorspace().num_components();
+        let (crop_x, crop_y, crop_width, crop_height) = self.crop_region();
+        let stride = crop_width * out_colorspace_components;
 
-        let mut px = *pixels_written;
+        // row of the image the next row of samples belongs to
+        let mut row = *rows_written;
         // indicates whether image is vertically up-sampled
         let is_vertically_sampled = self
             .components
@@ -366,28 +419,32 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
         }
         let mut color_conv_function =
             |num_iters: usize, samples: [&[i16]; 4]| -> Result<(), DecodeErrors> {
-                for (pos, output) in pixels[px..]
-                    .chunks_exact_mut(width * out_colorspace_components)
-                    .take(num_iters)
-                    .enumerate()
-                {
+                for pos in 0..num_iters {
+                    let y = row;
+                    row += 1;
+
+                    // only rows inside the crop region are color converted
+                    if !(crop_y..crop_y + crop_height).contains(&y) {
+                        continue;
+                    }
+                    let output = &mut pixels[(y - crop_y) * stride..(y - crop_y + 1) * stride];
                     let mut raw_samples: [&[i16]; 4] = [&[], &[], &[], &[]];
 
                     // iterate over each line, since color-convert needs only
                     // one line
                     for (j, samp) in raw_samples.iter_mut().enumerate().take(comp_len) {
-                        *samp = &samples[j][pos * padded_width..(pos + 1) * padded_width];
+                        let start = pos * padded_width + crop_x;
+                        *samp = &samples[j][start..start + crop_width];
                     }
-                    color_convert(
+                    S::color_convert(
                         &raw_samples,
                         self.color_convert_16,
                         self.input_colorspace,
                         self.options.jpeg_get_out_colorspace(),
                         output,
-                        width,
-                        padded_width
+                        crop_width,
+                        crop_width
                     )?;
-                    px += width * out_colorspace_components;
                 }
                 Ok(())
             };
@@ -486,7 +543,79 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
             color_conv_function(8 * self.coeff, channels_ref)?;
         }
 
-        *pixels_written = px;
+        *rows_written = row;
+        Ok(())
+    }
+    /// Color convert the rows of MCU row `i` of an image decoded scaled down
+    ///
+    /// The IDCT already scaled sub-sampled components up where possible, the
+    /// rest of the scaling repeats their samples. `rows` is scratch space
+    /// for the repeated samples.
+    pub(crate) fn post_process_scaled<S: OutputSample>(
+        &self, pixels: &mut [S], i: usize, rows: &mut [Vec<i16>; MAX_COMPONENTS]
+    ) -> Result<(), DecodeErrors> {
+        let scale = 8 / self.options.jpeg_get_scale_denom();
+        let (crop_x, crop_y, crop_width, crop_height) = self.crop_region();
+        let out_colorspace = self.options.jpeg_get_out_colorspace();
+        let stride = crop_width * out_colorspace.num_components();
+
+        let mcu_rows = self.v_max * scale;
+
+        for row in 0..mcu_rows {
+            let y = i * mcu_rows + row;
+
+            if y < crop_y {
+                continue;
+            }
+            if y >= crop_y + crop_height {
+                break;
+            }
+            // (row, times to repeat every sample) of each component
+            let mut sources: [(&[i16], usize); MAX_COMPONENTS] = [(&[], 1); MAX_COMPONENTS];
+
+            for (component, source) in self.components.iter().zip(sources.iter_mut()) {
+                if !component.needed {
+                    continue;
+                }
+                let component_stride = component.scaled_stride();
+                let x_repeat =
+                    self.h_max * scale / (component.horizontal_sample * component.dct_width);
+                let y_repeat =
+                    self.v_max * scale / (component.vertical_sample * component.dct_height);
+
+                let start = row / y_repeat * component_stride;
+                *source = (
+                    &component.raw_coeff[start..start + component_stride],
+                    x_repeat
+                );
+            }
+            for ((source, repeat), scratch) in sources.iter().zip(rows.iter_mut()) {
+                if *repeat > 1 {
+                    scratch.resize(source.len() * repeat, 0);
+
+                    for (out, sample) in scratch.chunks_exact_mut(*repeat).zip(source.iter()) {
+                        out.fill(*sample);
+                    }
+                }
+            }
+            let mut samples: [&[i16]; MAX_COMPONENTS] = [&[]; MAX_COMPONENTS];
+
+            for ((sample, (source, repeat)), scratch) in
+                samples.iter_mut().zip(sources).zip(rows.iter())
+            {
+                let row: &[i16] = if repeat > 1 { scratch } else { source };
+                *sample = row.get(crop_x..crop_x + crop_width).unwrap_or(&[]);
+            }
+            S::color_convert(
+                &samples,
+                self.color_convert_16,
+                self.input_colorspace,
+                out_colorspace,
+                &mut pixels[(y - crop_y) * stride..(y - crop_y + 1) * stride],
+                crop_width,
+                crop_width
+            )?;
+        }
         Ok(())
     }
 }
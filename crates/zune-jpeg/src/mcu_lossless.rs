@@ -0,0 +1,311 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Decoding of lossless (SOF3) images
+//!
+//! Lossless images store, for every sample, the difference between the sample
+//! and a prediction made from its left(`Ra`), top(`Rb`) and top left (`Rc`)
+//! neighbours, see Annex H of the specification
+//! (<https://www.w3.org/Graphics/JPEG/itu-t81.pdf>).
+//!
+//! Every MCU holds a single sample of each component in the scan.
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+use zune_core::bytestream::ZByteReaderTrait;
+use zune_core::colorspace::ColorSpace;
+use zune_core::log::{error, trace, warn};
+
+use crate::bitstream::BitStream;
+use crate::decoder::MAX_COMPONENTS;
+use crate::errors::DecodeErrors;
+use crate::headers::parse_sos;
+use crate::marker::Marker;
+use crate::mcu_prog::get_marker;
+use crate::JpegDecoder;
+
+impl<T: ZByteReaderTrait> JpegDecoder<T> {
+    /// Decode a lossless image into `output`, in the output colorspace
+    ///
+    /// Samples are stored without any color transform, so they are only
+    /// converted when the output colorspace differs from the input one.
+    #[allow(clippy::cast_possible_truncation)]
+    pub(crate) fn decode_mcu_lossless(&mut self, output: &mut [u16]) -> Result<(), DecodeErrors> {
+        let input_colorspace = self.input_colorspace;
+        let output_colorspace = self.options.jpeg_get_out_colorspace();
+
+        if input_colorspace == output_colorspace {
+            return self.decode_lossless_samples(output);
+        }
+        let convert = lossless_color_convert(input_colorspace, output_colorspace)?;
+        let max_value = (1_u32 << self.info.pixel_density) - 1;
+
+        let num_pixels = output.len() / output_colorspace.num_components();
+        let mut pixels = vec![0; num_pixels * input_colorspace.num_components()];
+
+        self.decode_lossless_samples(&mut pixels)?;
+
+        for (out, pix) in output
+            .chunks_exact_mut(output_colorspace.num_components())
+            .zip(pixels.chunks_exact(input_colorspace.num_components()))
+        {
+            let mut samples = [0; MAX_COMPONENTS];
+
+            for (sample, pix) in samples.iter_mut().zip(pix) {
+                *sample = u32::from(*pix);
+            }
+            for (out, sample) in out.iter_mut().zip(convert(samples, max_value)) {
+                *out = sample as u16;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode a lossless image into `pixels`, which holds
+    /// interleaved samples of all components
+    ///
+    /// Images may be split into multiple scans, each holding some components.
+    fn decode_lossless_samples(&mut self, pixels: &mut [u16]) -> Result<(), DecodeErrors> {
+        if self.components.len() > 1
+            && self
+                .components
+                .iter()
+                .any(|x| x.horizontal_sample != 1 || x.vertical_sample != 1)
+        {
+            return Err(DecodeErrors::FormatStatic(
+                "Lossless images with sub-sampled components are not supported"
+            ));
+        }
+        let mut stream = BitStream::new();
+        let mut seen_scans = 1;
+
+        self.decode_lossless_scan(&mut stream, pixels)?;
+
+        loop {
+            let marker = match get_marker(&mut self.stream, &mut stream) {
+                Ok(marker) => marker,
+                Err(e) => {
+                    if self.options.strict_mode() {
+                        return Err(e);
+                    }
+                    error!("{:?}", e);
+                    break;
+                }
+            };
+            match marker {
+                Marker::EOI => break,
+                Marker::SOS => {
+                    parse_sos(self)?;
+
+                    seen_scans += 1;
+
+                    if seen_scans > self.options.jpeg_get_max_scans() {
+                        return Err(DecodeErrors::Format(format!(
+                            "Too many scans, exceeded limit of {}",
+                            self.options.jpeg_get_max_scans()
+                        )));
+                    }
+                    self.decode_lossless_scan(&mut stream, pixels)?;
+                }
+                // tables and restart intervals may change between scans
+                _ => self.parse_marker_inner(marker)?
+            }
+        }
+        trace!("Finished decoding image");
+
+        Ok(())
+    }
+
+    /// Decode the samples of a single scan
+    #[allow(
+        clippy::too_many_lines,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn decode_lossless_scan(
+        &mut self, stream: &mut BitStream, pixels: &mut [u16]
+    ) -> Result<(), DecodeErrors> {
+        stream.reset();
+
+        let predictor = self.spec_start;
+        let point_transform = self.succ_low;
+        let precision = self.info.pixel_density;
+
+        if !(1..=7).contains(&predictor) {
+            // 0 is only used by hierarchical images
+            return Err(DecodeErrors::SosError(format!(
+                "Invalid lossless predictor {predictor}, range should be 1-7"
+            )));
+        }
+        if point_transform >= precision {
+            return Err(DecodeErrors::SosError(format!(
+                "Point transform {point_transform} should be less than the precision {precision}"
+            )));
+        }
+        let num_components = self.components.len();
+        let scan_components = &self.z_order[..usize::from(self.num_scans)];
+
+        for k in scan_components {
+            let table = self.components[*k].dc_huff_table;
+
+            if self
+                .dc_huffman_tables
+                .get(table)
+                .and_then(Option::as_ref)
+                .is_none()
+            {
+                return Err(DecodeErrors::HuffmanDecode(format!(
+                    "No Huffman DC table for component {:?}",
+                    self.components[*k].component_id
+                )));
+            }
+        }
+        let width = usize::from(self.info.width);
+        let height = usize::from(self.info.height);
+        // all samples are decoded since later scans follow this one,
+        // but only the ones inside the crop region are written
+        let (crop_x, crop_y, crop_width, crop_height) = self.crop_region();
+        // prediction of the first sample, and of samples after a restart
+        let initial = 1_i32 << (precision - point_transform - 1);
+        let max_value = (1_u32 << precision) - 1;
+
+        // current and previous row of each component in the scan,
+        // before the point transform
+        let mut rows: [Vec<i32>; MAX_COMPONENTS] = [vec![], vec![], vec![], vec![]];
+        let mut previous: [Vec<i32>; MAX_COMPONENTS] = [vec![], vec![], vec![], vec![]];
+
+        for i in 0..scan_components.len() {
+            rows[i] = vec![0; width];
+            previous[i] = vec![0; width];
+        }
+        // the first line after the start of the scan, or a restart
+        // is predicted from the left
+        let mut first_line = true;
+        let mut restart_x = 0;
+
+        self.todo = self.restart_interval;
+
+        for y in 0..height {
+            if stream.overread_by > 37 {
+                if self.options.strict_mode() {
+                    return Err(DecodeErrors::FormatStatic("Premature end of buffer"));
+                }
+
+                error!("Premature end of buffer");
+                break;
+            }
+            for x in 0..width {
+                if self.restart_interval != 0 && self.todo == 0 {
+                    let marker = match get_marker(&mut self.stream, stream) {
+                        Ok(marker) => marker,
+                        Err(e) => {
+                            if self.options.strict_mode() {
+                                return Err(e);
+                            }
+                            error!("{:?}", e);
+                            return Ok(());
+                        }
+                    };
+                    if let Marker::RST(_) = marker {
+                        stream.reset();
+                        self.todo = self.restart_interval;
+                        first_line = true;
+                        restart_x = x;
+                    } else {
+                        if self.options.strict_mode() {
+                            return Err(DecodeErrors::Format(format!(
+                                "Marker {marker:?} found where a restart marker was expected"
+                            )));
+                        }
+                        warn!("Expected a restart marker but found {:?}", marker);
+                        // let the caller handle it
+                        stream.marker = Some(marker);
+                        return Ok(());
+                    }
+                }
+                for (i, k) in scan_components.iter().enumerate() {
+                    let dc_table = self.dc_huffman_tables[self.components[*k].dc_huff_table]
+                        .as_ref()
+                        .unwrap();
+
+                    let diff = stream.decode_lossless_diff(&mut self.stream, dc_table)?;
+
+                    let row = &rows[i];
+                    let up = &previous[i];
+
+                    let prediction = if first_line && x == restart_x {
+                        initial
+                    } else if first_line {
+                        row[x - 1]
+                    } else if x == 0 {
+                        up[x]
+                    } else {
+                        let (ra, rb, rc) = (row[x - 1], up[x], up[x - 1]);
+
+                        match predictor {
+                            1 => ra,
+                            2 => rb,
+                            3 => rc,
+                            4 => ra + rb - rc,
+                            5 => ra + ((rb - rc) >> 1),
+                            6 => rb + ((ra - rc) >> 1),
+                            _ => (ra + rb) >> 1
+                        }
+                    };
+                    // reconstruction is modulo 2^16
+                    let sample = (prediction + diff) & 0xFFFF;
+
+                    rows[i][x] = sample;
+
+                    if (crop_x..crop_x + crop_width).contains(&x)
+                        && (crop_y..crop_y + crop_height).contains(&y)
+                    {
+                        let position = (y - crop_y) * crop_width + x - crop_x;
+
+                        pixels[position * num_components + *k] =
+                            ((sample as u32) << point_transform).min(max_value) as u16;
+                    }
+                }
+                self.todo = self.todo.saturating_sub(1);
+            }
+            first_line = false;
+            core::mem::swap(&mut rows, &mut previous);
+        }
+        Ok(())
+    }
+}
+
+/// Converts the samples of a pixel, given the largest value a sample can hold
+type LosslessConvert = fn([u32; MAX_COMPONENTS], u32) -> [u32; MAX_COMPONENTS];
+
+/// Get the function converting lossless samples from `input` to `output`
+///
+/// # Errors
+/// If there is no conversion between the two colorspaces
+pub(crate) fn lossless_color_convert(
+    input: ColorSpace, output: ColorSpace
+) -> Result<LosslessConvert, DecodeErrors> {
+    let convert: LosslessConvert = match (input, output) {
+        (input, output) if input == output => |x, _| x,
+        (ColorSpace::LumaA, ColorSpace::Luma) => |[l, ..], _| [l, 0, 0, 0],
+        (
+            ColorSpace::LumaA,
+            ColorSpace::RGB | ColorSpace::RGBA | ColorSpace::BGR | ColorSpace::BGRA
+        ) => |[l, a, ..], _| [l, l, l, a],
+        (ColorSpace::RGB, ColorSpace::RGBA) => |[r, g, b, _], max| [r, g, b, max],
+        (ColorSpace::RGB, ColorSpace::BGR | ColorSpace::BGRA) => |[r, g, b, _], max| [b, g, r, max],
+        (ColorSpace::CMYK, ColorSpace::RGB | ColorSpace::RGBA) => |[c, m, y, k], max| {
+            let mul = |a: u32| (a * k + max / 2) / max;
+            [mul(c), mul(m), mul(y), max]
+        },
+        _ => return Err(DecodeErrors::Format(format!(
+            "Unsupported output colorspace {output:?} for lossless images with {input:?} samples"
+        )))
+    };
+    Ok(convert)
+}
@@ -28,30 +28,44 @@ use zune_core::bytestream::{ZByteReaderTrait, ZReader};
 use zune_core::colorspace::ColorSpace;
 use zune_core::log::{debug, error, warn};
 
+use crate::arithmetic::ArithmeticDecoder;
 use crate::bitstream::BitStream;
-use crate::components::{ComponentID, SampleRatios};
+use crate::components::SampleRatios;
 use crate::decoder::{JpegDecoder, MAX_COMPONENTS};
 use crate::errors::DecodeErrors;
 use crate::errors::DecodeErrors::Format;
 use crate::headers::{parse_huffman, parse_sos};
+use crate::idct::choose_scaled_idct_func;
 use crate::marker::Marker;
 use crate::mcu::DCT_BLOCK;
 use crate::misc::{calculate_padded_width, setup_component_params};
+use crate::worker::OutputSample;
 
 impl<T: ZByteReaderTrait> JpegDecoder<T> {
     /// Decode a progressive image
     ///
     /// This routine decodes a progressive image, stopping if it finds any error.
+    #[inline(never)]
+    pub(crate) fn decode_mcu_ycbcr_progressive<S: OutputSample>(
+        &mut self, pixels: &mut [S]
+    ) -> Result<(), DecodeErrors> {
+        let block = self.decode_scans()?;
+
+        self.finish_progressive_decoding(&block, pixels)
+    }
+
+    /// Entropy decode all scans of the image, returning the coefficients of
+    /// each component
+    ///
+    /// Progressive images need all scans before blocks can be transformed, scans
+    /// of sequential images are decoded this way when only their coefficients are needed.
     #[allow(
         clippy::needless_range_loop,
         clippy::cast_sign_loss,
         clippy::redundant_else,
         clippy::too_many_lines
     )]
-    #[inline(never)]
-    pub(crate) fn decode_mcu_ycbcr_progressive(
-        &mut self, pixels: &mut [u8]
-    ) -> Result<(), DecodeErrors> {
+    pub(crate) fn decode_scans(&mut self) -> Result<[Vec<i16>; MAX_COMPONENTS], DecodeErrors> {
         setup_component_params(self)?;
 
         let mut mcu_height;
@@ -121,14 +135,23 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
             self.spec_end
         );
 
+        if self.info.sof.is_arithmetic() {
+            stream.arithmetic = Some(ArithmeticDecoder::new());
+        }
+
         // there are multiple scans in the stream, this should resolve the first scan
         self.parse_entropy_coded_data(&mut stream, &mut block)?;
 
         // extract marker
-        let mut marker = stream
-            .marker
-            .take()
-            .ok_or(DecodeErrors::FormatStatic("Marker missing where expected"))?;
+        let mut marker = if stream.arithmetic.is_some() {
+            // the arithmetic decoder may stop before the marker
+            get_marker(&mut self.stream, &mut stream)?
+        } else {
+            stream
+                .marker
+                .take()
+                .ok_or(DecodeErrors::FormatStatic("Marker missing where expected"))?
+        };
 
         // if marker is EOI, we are done, otherwise continue scanning.
         //
@@ -140,6 +163,10 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
                 Marker::DHT => {
                     parse_huffman(self)?;
                 }
+                // conditioning tables and restart intervals may change between scans
+                Marker::DAC | Marker::DRI => {
+                    self.parse_marker_inner(marker)?;
+                }
                 Marker::SOS => {
                     parse_sos(self)?;
 
@@ -195,7 +222,7 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
             }
         }
 
-        self.finish_progressive_decoding(&block, mcu_width, pixels)
+        Ok(block)
     }
 
     #[allow(clippy::too_many_lines, clippy::cast_sign_loss)]
@@ -204,6 +231,8 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
     ) -> Result<(), DecodeErrors> {
         stream.reset();
         self.components.iter_mut().for_each(|x| x.dc_pred = 0);
+        // restart intervals start anew in every scan
+        self.todo = self.restart_interval;
 
         if usize::from(self.num_scans) > self.input_colorspace.num_components() {
             return Err(Format(format!(
@@ -212,6 +241,9 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
                 self.input_colorspace.num_components()
             )));
         }
+        if !self.is_progressive {
+            return self.parse_sequential_coded_data(stream, buffer);
+        }
 
         if self.num_scans == 1 {
             // Safety checks
@@ -230,26 +262,17 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
                 )));
             }
 
-            let (mcu_width, mcu_height);
-
-            if self.components[k].component_id == ComponentID::Y
-                && (self.components[k].vertical_sample != 1
-                    || self.components[k].horizontal_sample != 1)
-                || !self.is_interleaved
-            {
-                // For Y channel  or non interleaved scans ,
-                // mcu's is the image dimensions divided by 8
-                mcu_width = ((self.info.width + 7) / 8) as usize;
-                mcu_height = ((self.info.height + 7) / 8) as usize;
-            } else {
-                // For other channels, in an interleaved mcu, number of MCU's
-                // are determined by some weird maths done in headers.rs->parse_sos()
-                mcu_width = self.mcu_x;
-                mcu_height = self.mcu_y;
-            }
+            // non interleaved scans only code the blocks covering the image
+            let (mcu_width, mcu_height) = self.non_interleaved_blocks(k);
 
             for i in 0..mcu_height {
                 for j in 0..mcu_width {
+                    if self.restart_interval != 0 && self.todo == 0 {
+                        self.handle_rst(stream)?;
+                    }
+                    // blocks skipped by EOB runs count towards the restart interval
+                    self.todo = self.todo.wrapping_sub(1);
+
                     if self.spec_start != 0 && self.succ_high == 0 && stream.eob_run > 0 {
                         // handle EOB runs here.
                         stream.eob_run -= 1;
@@ -265,7 +288,9 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
                         .try_into()
                         .unwrap();
 
-                    if self.spec_start == 0 {
+                    if stream.arithmetic.is_some() {
+                        self.decode_arith_prog_block(stream, k, data)?;
+                    } else if self.spec_start == 0 {
                         let pos = self.components[k].dc_huff_table & (MAX_COMPONENTS - 1);
                         let dc_table = self
                             .dc_huffman_tables
@@ -318,12 +343,6 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
                             stream.decode_mcu_ac_refine(&mut self.stream, ac_table, data)?;
                         }
                     }
-                    // + EOB and investigate effect.
-                    self.todo -= 1;
-
-                    if self.todo == 0 {
-                        self.handle_rst(stream)?;
-                    }
                 }
             }
         } else {
@@ -345,6 +364,9 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
                     )));
                 }
 
+                if stream.arithmetic.is_some() {
+                    continue;
+                }
                 let component = &mut self.components[n];
                 let _ = self
                     .dc_huffman_tables
@@ -369,10 +391,41 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
             // the DC coefficients in the first scan for each component of a progressive frame.
             for i in 0..self.mcu_y {
                 for j in 0..self.mcu_x {
+                    if self.restart_interval != 0 && self.todo == 0 {
+                        self.handle_rst(stream)?;
+                    }
+                    self.todo = self.todo.wrapping_sub(1);
+
                     // process scan n elements in order
                     for k in 0..self.num_scans {
                         let n = self.z_order[k as usize];
                         let component = &mut self.components[n];
+
+                        if stream.arithmetic.is_some() {
+                            for v_samp in 0..component.vertical_sample {
+                                for h_samp in 0..component.horizontal_sample {
+                                    let x2 = j * component.horizontal_sample + h_samp;
+                                    let y2 = i * component.vertical_sample + v_samp;
+                                    let position = 64 * (x2 + y2 * component.width_stride / 8);
+
+                                    let data = &mut buffer[n][position];
+
+                                    if self.succ_high == 0 {
+                                        stream.decode_arith_dc_first(
+                                            &mut self.stream,
+                                            &self.arithmetic_tables,
+                                            component.dc_huff_table,
+                                            data,
+                                            &mut component.dc_pred,
+                                            n
+                                        )?;
+                                    } else {
+                                        stream.decode_arith_dc_refine(&mut self.stream, data)?;
+                                    }
+                                }
+                            }
+                            continue;
+                        }
                         let huff_table = self
                             .dc_huffman_tables
                             .get(component.dc_huff_table)
@@ -403,23 +456,44 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
                             }
                         }
                     }
-                    // We want wrapping subtraction here because it means
-                    // we get a higher number in the case this underflows
-                    self.todo = self.todo.wrapping_sub(1);
-                    // after every scan that's a mcu, count down restart markers.
-                    if self.todo == 0 {
-                        self.handle_rst(stream)?;
-                    }
                 }
             }
         }
         return Ok(());
     }
 
+    /// Decode a block of a non-interleaved arithmetic coded scan
+    fn decode_arith_prog_block(
+        &mut self, stream: &mut BitStream, k: usize, data: &mut [i16; 64]
+    ) -> Result<(), DecodeErrors> {
+        let component = &mut self.components[k];
+
+        match (self.spec_start == 0, self.succ_high == 0) {
+            (true, true) => stream.decode_arith_dc_first(
+                &mut self.stream,
+                &self.arithmetic_tables,
+                component.dc_huff_table,
+                &mut data[0],
+                &mut component.dc_pred,
+                k
+            ),
+            (true, false) => stream.decode_arith_dc_refine(&mut self.stream, &mut data[0]),
+            (false, true) => stream.decode_arith_ac_first(
+                &mut self.stream,
+                &self.arithmetic_tables,
+                component.ac_huff_table,
+                data
+            ),
+            (false, false) => {
+                stream.decode_arith_ac_refine(&mut self.stream, component.ac_huff_table, data)
+            }
+        }
+    }
+
     #[allow(clippy::too_many_lines)]
     #[allow(clippy::needless_range_loop, clippy::cast_sign_loss)]
-    fn finish_progressive_decoding(
-        &mut self, block: &[Vec<i16>; MAX_COMPONENTS], _mcu_width: usize, pixels: &mut [u8]
+    fn finish_progressive_decoding<S: OutputSample>(
+        &mut self, block: &[Vec<i16>; MAX_COMPONENTS], pixels: &mut [S]
     ) -> Result<(), DecodeErrors> {
         // This function is complicated because we need to replicate
         // the function in mcu.rs
@@ -472,8 +546,8 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
             {
                 // allocate enough space to hold a whole MCU width
                 // this means we should take into account sampling ratios
-                // `*8` is because each MCU spans 8 widths.
-                let len = comp.width_stride * comp.vertical_sample * 8;
+                // `*dct_height` is because each MCU spans dct_height(usually 8) rows.
+                let len = comp.scaled_stride() * comp.vertical_sample * comp.dct_height;
 
                 comp.needed = true;
                 comp.raw_coeff = vec![0; len];
@@ -482,16 +556,24 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
             }
         }
 
-        let mut pixels_written = 0;
+        let mut rows_written = 0;
+        let mut scaled_rows = Default::default();
+
+        let (mcu_rows, mcu_columns) = self.mcu_region();
 
-        // dequantize, idct and color convert.
-        for i in 0..mcu_height {
+        // dequantize, idct and color convert, up to the last row needed by the crop region
+        for i in 0..mcu_height.min(mcu_rows.end) {
             'component: for (position, component) in &mut self.components.iter_mut().enumerate() {
-                if !component.needed {
+                if !component.needed || !mcu_rows.contains(&i) {
                     continue 'component;
                 }
                 let qt_table = &component.quantization_table;
 
+                let (width, height) = (component.dct_width, component.dct_height);
+                let stride = component.scaled_stride();
+                let idct =
+                    choose_scaled_idct_func(width, height, self.info.pixel_density, self.idct_func);
+
                 // step is the number of pixels this iteration wil be handling
                 // Given by the number of mcu's height and the length of the component block
                 // Since the component block contains the whole channel as raw pixels
@@ -516,6 +598,11 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
                 // iterate per every vertical sample.
                 for k in 0..component.vertical_sample {
                     for j in 0..mcu_x {
+                        // blocks of MCUs left and right of the crop region are skipped
+                        if !mcu_columns.contains(&(j / component.horizontal_sample)) {
+                            component.idct_pos += width;
+                            continue;
+                        }
                         // after writing a single stride, we need to skip 8 rows.
                         // This does the row calculation
                         let width_stride = k * 8 * component.width_stride;
@@ -532,37 +619,54 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
                         // determine where to write.
                         let sl = &mut temp_channel[component.idct_pos..];
 
-                        component.idct_pos += 8;
+                        component.idct_pos += width;
                         // tmp now contains a dequantized block so idct it
-                        (self.idct_func)(&mut tmp, sl, component.width_stride);
+                        idct(&mut tmp, sl, stride);
                     }
                     // after every write of 8, skip 7 since idct write stride wise 8 times.
                     //
                     // Remember each MCU is 8x8 block, so each idct will write 8 strides into
-                    // sl
+                    // sl, or dct_height strides when scaling down
                     //
                     // and component.idct_pos is one stride long
-                    component.idct_pos += 7 * component.width_stride;
+                    component.idct_pos += (height - 1) * stride;
                 }
                 component.idct_pos = 0;
             }
 
             // process that width up until it's impossible
-            self.post_process(
-                pixels,
-                i,
-                mcu_height,
-                width,
-                padded_width,
-                &mut pixels_written,
-                &mut upsampler_scratch_space
-            )?;
+            if self.is_scaled() {
+                self.post_process_scaled(pixels, i, &mut scaled_rows)?;
+            } else {
+                self.post_process(
+                    pixels,
+                    i,
+                    mcu_height,
+                    padded_width,
+                    &mut rows_written,
+                    &mut upsampler_scratch_space
+                )?;
+            }
         }
 
         debug!("Finished decoding image");
 
         return Ok(());
     }
+
+    /// Number of blocks in a row and of block rows coded by a scan holding only
+    /// component `k`, which cover the image without padding it to whole MCUs
+    pub(crate) fn non_interleaved_blocks(&self, k: usize) -> (usize, usize) {
+        let component = &self.components[k];
+        let width = usize::from(self.info.width) * component.horizontal_sample;
+        let height = usize::from(self.info.height) * component.vertical_sample;
+
+        (
+            width.div_ceil(self.h_max).div_ceil(8),
+            height.div_ceil(self.v_max).div_ceil(8)
+        )
+    }
+
     pub(crate) fn reset_params(&mut self) {
         /*
         Apparently, grayscale images which can be down sampled exists, which is weird in the sense
@@ -586,7 +690,9 @@ impl<T: ZByteReaderTrait> JpegDecoder<T> {
 ///Get a marker from the bit-stream.
 ///
 /// This reads until it gets a marker or end of file is encountered
-fn get_marker<T>(reader: &mut ZReader<T>, stream: &mut BitStream) -> Result<Marker, DecodeErrors>
+pub(crate) fn get_marker<T>(
+    reader: &mut ZReader<T>, stream: &mut BitStream
+) -> Result<Marker, DecodeErrors>
 where
     T: ZByteReaderTrait
 {
@@ -0,0 +1,388 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Lossless transforms of JPEG images
+//!
+//! Images are rotated, flipped and cropped by moving their quantized DCT
+//! coefficients around, which are then entropy coded again without being
+//! requantized, so no quality is lost.
+//!
+//! Mirroring a block negates its odd frequencies along the mirrored axis and
+//! transposing it transposes its coefficients. Blocks only move as parts of whole
+//! MCUs, so partial MCUs at the right or bottom edge which would end up at the
+//! left or top edge are trimmed, like `jpegtran -trim` does.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use zune_core::bit_depth::BitDepth;
+use zune_core::bytestream::{ZByteReaderTrait, ZByteWriterTrait};
+use zune_core::options::{DecoderOptions, EncoderOptions};
+
+use crate::coefficients::{ComponentCoefficients, JpegCoefficients};
+use crate::errors::TransformErrors;
+use crate::{JpegDecoder, JpegEncoder};
+
+/// EXIF tag holding the orientation of the image
+const ORIENTATION_TAG: u16 = 0x0112;
+/// EXIF type of 16 bit values
+const SHORT_TYPE: u16 = 3;
+
+/// A lossless transform of an image
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Transform {
+    /// Keep the image as it is, e.g. to only crop it
+    None,
+    /// Mirror the image left to right
+    FlipHorizontal,
+    /// Mirror the image top to bottom
+    FlipVertical,
+    /// Mirror the image across its top left to bottom right diagonal
+    Transpose,
+    /// Mirror the image across its top right to bottom left diagonal
+    Transverse,
+    /// Rotate the image by 90 degrees clockwise
+    Rotate90,
+    /// Rotate the image by 180 degrees
+    Rotate180,
+    /// Rotate the image by 270 degrees clockwise
+    Rotate270
+}
+
+impl Transform {
+    /// The transform which displays an image with the given EXIF orientation upright
+    ///
+    /// # Returns
+    /// - `Some(transform)`: The transform undoing the orientation
+    /// - `None`: The value isn't a valid orientation
+    #[must_use]
+    pub const fn from_exif_orientation(orientation: u16) -> Option<Transform> {
+        match orientation {
+            1 => Some(Transform::None),
+            2 => Some(Transform::FlipHorizontal),
+            3 => Some(Transform::Rotate180),
+            4 => Some(Transform::FlipVertical),
+            5 => Some(Transform::Transpose),
+            6 => Some(Transform::Rotate90),
+            7 => Some(Transform::Transverse),
+            8 => Some(Transform::Rotate270),
+            _ => None
+        }
+    }
+
+    /// Whether the transform transposes the image, then flips it horizontally
+    /// and vertically
+    const fn steps(self) -> (bool, bool, bool) {
+        match self {
+            Transform::None => (false, false, false),
+            Transform::FlipHorizontal => (false, true, false),
+            Transform::FlipVertical => (false, false, true),
+            Transform::Transpose => (true, false, false),
+            Transform::Transverse => (true, true, true),
+            Transform::Rotate90 => (true, true, false),
+            Transform::Rotate180 => (false, true, true),
+            Transform::Rotate270 => (true, false, true)
+        }
+    }
+}
+
+/// Transforms JPEG images without decoding them to pixels
+///
+/// Baseline images stay baseline and progressive images stay progressive, both are
+/// written with optimized Huffman tables. ICC profiles and EXIF data are kept.
+///
+/// # Example
+/// - Rotate an image by 90 degrees
+///```
+/// use zune_core::bit_depth::BitDepth;
+/// use zune_core::bytestream::ZCursor;
+/// use zune_core::colorspace::ColorSpace;
+/// use zune_core::options::EncoderOptions;
+/// use zune_jpeg::{JpegDecoder, JpegEncoder, JpegTransformer, Transform};
+///
+/// let pixels = vec![128_u8; 64 * 32 * 3];
+/// let options = EncoderOptions::new(64, 32, ColorSpace::RGB, BitDepth::Eight);
+/// let mut image = vec![];
+/// JpegEncoder::new(&pixels, options).encode(&mut image).unwrap();
+///
+/// let mut transformer = JpegTransformer::new(ZCursor::new(&image));
+/// transformer.set_transform(Transform::Rotate90);
+///
+/// let mut rotated = vec![];
+/// transformer.transform(&mut rotated).unwrap();
+///
+/// let mut decoder = JpegDecoder::new(ZCursor::new(&rotated));
+/// decoder.decode_headers().unwrap();
+/// assert_eq!(decoder.dimensions(), Some((32, 64)));
+///```
+pub struct JpegTransformer<T: ZByteReaderTrait> {
+    decoder:     JpegDecoder<T>,
+    transform:   Transform,
+    crop:        Option<(usize, usize, usize, usize)>,
+    auto_orient: bool,
+    perfect:     bool
+}
+
+impl<T: ZByteReaderTrait> JpegTransformer<T> {
+    /// Create a new transformer for the image in `source`
+    #[must_use]
+    pub fn new(source: T) -> JpegTransformer<T> {
+        JpegTransformer::new_with_options(source, DecoderOptions::default())
+    }
+
+    /// Create a new transformer for the image in `source`, which is decoded
+    /// with the given options
+    ///
+    /// Options concerning pixels, like the output colorspace or scaling, are ignored.
+    #[must_use]
+    pub fn new_with_options(source: T, options: DecoderOptions) -> JpegTransformer<T> {
+        JpegTransformer {
+            decoder:     JpegDecoder::new_with_options(source, options),
+            transform:   Transform::None,
+            crop:        None,
+            auto_orient: false,
+            perfect:     false
+        }
+    }
+
+    /// Set the transform to apply
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+    }
+
+    /// Crop the image to a region, given in the coordinates of the image before
+    /// it is transformed
+    ///
+    /// Cropping is done in whole MCUs, so the top left corner of the region moves
+    /// up and left to the closest MCU boundary, enlarging the region.
+    pub fn set_crop(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        self.crop = Some((x, y, width, height));
+    }
+
+    /// Choose the transform from the orientation in the EXIF data of the image
+    ///
+    /// This replaces the transform set with [`set_transform`](Self::set_transform),
+    /// the image is transformed to be upright and its EXIF orientation is reset.
+    /// Images without orientation are left as they are.
+    pub fn set_auto_orient(&mut self, yes: bool) {
+        self.auto_orient = yes;
+    }
+
+    /// Fail instead of trimming partial MCUs which the transform cannot move
+    pub fn set_perfect(&mut self, yes: bool) {
+        self.perfect = yes;
+    }
+
+    /// Transform the image and write it into `sink`
+    ///
+    /// # Returns
+    /// - Ok(usize): The number of bytes written into `sink`
+    /// - Err(TransformErrors): The image cannot be decoded or transformed
+    pub fn transform<W: ZByteWriterTrait>(&mut self, sink: W) -> Result<usize, TransformErrors> {
+        self.decoder.decode_headers()?;
+
+        let icc_profile = self.decoder.icc_profile();
+        let mut exif = self.decoder.exif().cloned();
+        let mut transform = self.transform;
+
+        if self.auto_orient {
+            transform = exif
+                .as_mut()
+                .and_then(|exif| reset_exif_orientation(exif))
+                .unwrap_or(Transform::None);
+        }
+        let image = self.decoder.decode_coefficients()?;
+        let image = transform_coefficients(&image, transform, self.crop, self.perfect)?;
+
+        let options =
+            EncoderOptions::new(image.width, image.height, image.colorspace, BitDepth::Eight)
+                .set_jpeg_encode_progressive(self.decoder.is_progressive)
+                .set_jpeg_optimized_huffman_tables(true);
+
+        let mut encoder = JpegEncoder::new(&[], options);
+
+        if let Some(icc_profile) = &icc_profile {
+            encoder.add_icc_profile(icc_profile);
+        }
+        if let Some(exif) = &exif {
+            encoder.add_exif(exif);
+        }
+        Ok(encoder.encode_coefficients(&image, sink)?)
+    }
+}
+
+/// Crop and transform the coefficients of an image
+///
+/// `crop` is given in the coordinates of `image`
+fn transform_coefficients(
+    image: &JpegCoefficients, transform: Transform, crop: Option<(usize, usize, usize, usize)>,
+    perfect: bool
+) -> Result<JpegCoefficients, TransformErrors> {
+    let (transpose, flip_h, flip_v) = transform.steps();
+    let (h_max, v_max) = image.max_sampling_factors();
+    let (mcu_width, mcu_height) = (8 * h_max, 8 * v_max);
+
+    let (x, y, width, height) = crop.unwrap_or((0, 0, image.width, image.height));
+
+    if width == 0
+        || height == 0
+        || x.saturating_add(width) > image.width
+        || y.saturating_add(height) > image.height
+    {
+        return Err(TransformErrors::FormatStatic(
+            "Crop region is empty or outside of the image"
+        ));
+    }
+    // move the region to start at a MCU boundary
+    let (left, top) = (x / mcu_width * mcu_width, y / mcu_height * mcu_height);
+    let (width, height) = (x + width - left, y + height - top);
+
+    // axes of the source image which are mirrored
+    let (flip_x, flip_y) = if transpose { (flip_v, flip_h) } else { (flip_h, flip_v) };
+
+    let trimmed_width = if flip_x { width / mcu_width * mcu_width } else { width };
+    let trimmed_height = if flip_y { height / mcu_height * mcu_height } else { height };
+
+    if trimmed_width == 0 || trimmed_height == 0 {
+        return Err(TransformErrors::FormatStatic(
+            "Image is smaller than a MCU, cannot transform it"
+        ));
+    }
+    if perfect && (trimmed_width != width || trimmed_height != height) {
+        return Err(TransformErrors::FormatStatic(
+            "Transform would trim partial MCUs off the image"
+        ));
+    }
+    let (width, height) = if transpose {
+        (trimmed_height, trimmed_width)
+    } else {
+        (trimmed_width, trimmed_height)
+    };
+
+    let mut output = JpegCoefficients {
+        width,
+        height,
+        precision: image.precision,
+        colorspace: image.colorspace,
+        components: Vec::with_capacity(image.components.len())
+    };
+    for component in &image.components {
+        let (horizontal_sample, vertical_sample, quantization_table) = if transpose {
+            (
+                component.vertical_sample,
+                component.horizontal_sample,
+                transpose_block(&component.quantization_table)
+            )
+        } else {
+            (
+                component.horizontal_sample,
+                component.vertical_sample,
+                component.quantization_table
+            )
+        };
+        output.components.push(ComponentCoefficients {
+            id: component.id,
+            horizontal_sample,
+            vertical_sample,
+            quantization_table,
+            width_in_blocks: 0,
+            height_in_blocks: 0,
+            coefficients: vec![]
+        });
+    }
+    let (out_h_max, out_v_max) = output.max_sampling_factors();
+    let image_blocks: Vec<(usize, usize)> = output
+        .components
+        .iter()
+        .map(|c| output.image_blocks(c))
+        .collect();
+
+    for ((source, component), (blocks_x, blocks_y)) in image
+        .components
+        .iter()
+        .zip(&mut output.components)
+        .zip(image_blocks)
+    {
+        component.width_in_blocks = width.div_ceil(8 * out_h_max) * component.horizontal_sample;
+        component.height_in_blocks = height.div_ceil(8 * out_v_max) * component.vertical_sample;
+        component.coefficients =
+            vec![0; component.width_in_blocks * component.height_in_blocks * 64];
+
+        // first block of the region in the source component
+        let first_x = left / mcu_width * source.horizontal_sample;
+        let first_y = top / mcu_height * source.vertical_sample;
+
+        for by in 0..blocks_y {
+            for bx in 0..blocks_x {
+                let tx = if flip_h { blocks_x - 1 - bx } else { bx };
+                let ty = if flip_v { blocks_y - 1 - by } else { by };
+                let (sx, sy) = if transpose { (ty, tx) } else { (tx, ty) };
+
+                let input = source.block(first_x + sx, first_y + sy);
+                let output = component.block_mut(bx, by);
+
+                for (i, out) in output.iter_mut().enumerate() {
+                    let (u, v) = (i % 8, i / 8);
+                    let coeff = if transpose { input[u * 8 + v] } else { input[i] };
+                    // mirroring flips the sign of odd frequencies
+                    let negate = (flip_h && u % 2 == 1) != (flip_v && v % 2 == 1);
+
+                    *out = if negate { coeff.wrapping_neg() } else { coeff };
+                }
+            }
+        }
+        component.fill_padding_blocks(blocks_x, blocks_y);
+    }
+    Ok(output)
+}
+
+/// Transpose a block in natural order
+fn transpose_block(block: &[u16; 64]) -> [u16; 64] {
+    core::array::from_fn(|i| block[(i % 8) * 8 + i / 8])
+}
+
+/// Reset the orientation in EXIF data to upright, returning the transform which
+/// makes the image upright
+///
+/// Returns `None` if the data has no valid orientation
+fn reset_exif_orientation(exif: &mut [u8]) -> Option<Transform> {
+    let big_endian = match exif.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None
+    };
+    let read_u16 = |exif: &[u8], offset: usize| {
+        let bytes = [*exif.get(offset)?, *exif.get(offset + 1)?];
+
+        Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    };
+    let first_ifd = if big_endian {
+        u32::from_be_bytes(exif.get(4..8)?.try_into().ok()?)
+    } else {
+        u32::from_le_bytes(exif.get(4..8)?.try_into().ok()?)
+    };
+    let first_ifd = usize::try_from(first_ifd).ok()?;
+    let entries = read_u16(exif, first_ifd)?;
+
+    for entry in 0..usize::from(entries) {
+        // each entry holds a tag, a type, a count and a value of four bytes
+        let offset = first_ifd + 2 + entry * 12;
+
+        if read_u16(exif, offset)? == ORIENTATION_TAG {
+            if read_u16(exif, offset + 2)? != SHORT_TYPE {
+                return None;
+            }
+            let transform = Transform::from_exif_orientation(read_u16(exif, offset + 8)?)?;
+            let upright = if big_endian { 1_u16.to_be_bytes() } else { 1_u16.to_le_bytes() };
+
+            exif[offset + 8..offset + 10].copy_from_slice(&upright);
+            return Some(transform);
+        }
+    }
+    None
+}
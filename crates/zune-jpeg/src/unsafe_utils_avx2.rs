@@ -23,6 +23,20 @@ const fn shuffle(z: i32, y: i32, x: i32, w: i32) -> i32 {
     (z << 6) | (y << 4) | (x << 2) | w
 }
 
+/// Whether the CPU supports AVX2, detected at runtime when the
+/// standard library is available
+#[inline]
+pub fn avx2_available() -> bool {
+    #[cfg(feature = "std")]
+    {
+        is_x86_feature_detected!("avx2")
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        cfg!(target_feature = "avx2")
+    }
+}
+
 /// An abstraction of an AVX ymm register that
 ///allows some things to not look ugly
 #[derive(Clone, Copy)]
@@ -100,6 +100,133 @@ pub(crate) fn color_convert(
     Ok(())
 }
 
+/// A sample type the post processing step can write
+///
+/// `u8` holds samples of 8 bit images and `u16` samples of 12 bit images
+pub(crate) trait OutputSample: Copy {
+    /// Color convert a row of components into `output`, see [`color_convert`]
+    fn color_convert(
+        unprocessed: &[&[i16]; MAX_COMPONENTS], color_convert_16: ColorConvert16Ptr,
+        input_colorspace: ColorSpace, output_colorspace: ColorSpace, output: &mut [Self],
+        width: usize, padded_width: usize
+    ) -> Result<(), DecodeErrors>;
+}
+
+impl OutputSample for u8 {
+    fn color_convert(
+        unprocessed: &[&[i16]; MAX_COMPONENTS], color_convert_16: ColorConvert16Ptr,
+        input_colorspace: ColorSpace, output_colorspace: ColorSpace, output: &mut [Self],
+        width: usize, padded_width: usize
+    ) -> Result<(), DecodeErrors> {
+        color_convert(
+            unprocessed,
+            color_convert_16,
+            input_colorspace,
+            output_colorspace,
+            output,
+            width,
+            padded_width
+        )
+    }
+}
+
+impl OutputSample for u16 {
+    fn color_convert(
+        unprocessed: &[&[i16]; MAX_COMPONENTS], _color_convert_16: ColorConvert16Ptr,
+        input_colorspace: ColorSpace, output_colorspace: ColorSpace, output: &mut [Self],
+        width: usize, padded_width: usize
+    ) -> Result<(), DecodeErrors> {
+        color_convert_12bit(
+            unprocessed,
+            input_colorspace,
+            output_colorspace,
+            output,
+            width,
+            padded_width
+        )
+    }
+}
+
+/// Largest value of a 12 bit sample
+const MAX_12BIT: i32 = 4095;
+
+/// 0..4095 * 0..4095 => 0..4095 rounded multiplication
+#[inline]
+fn mul_12bit(a: i32, b: i32) -> i32 {
+    (a * b + MAX_12BIT / 2) / MAX_12BIT
+}
+
+/// Convert a 12 bit YCbCr sample to RGB
+///
+/// JFIF conversion, with coefficients scaled by 1<<14
+#[inline]
+fn ycbcr_to_rgb(y: i32, cb: i32, cr: i32) -> [i32; 3] {
+    let cb = cb - 2048;
+    let cr = cr - 2048;
+
+    [
+        y + ((22970 * cr + 8192) >> 14),
+        y - ((5638 * cb + 11700 * cr + 8192) >> 14),
+        y + ((29032 * cb + 8192) >> 14)
+    ]
+}
+
+/// Color convert 12 bit samples
+///
+/// These images are rare, so this is a plain scalar version of [`color_convert`]
+/// working on one pixel at a time.
+#[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+fn color_convert_12bit(
+    unprocessed: &[&[i16]; MAX_COMPONENTS], input_colorspace: ColorSpace,
+    output_colorspace: ColorSpace, output: &mut [u16], width: usize, padded_width: usize
+) -> Result<(), DecodeErrors> {
+    let convert: fn([i32; MAX_COMPONENTS]) -> [i32; MAX_COMPONENTS] = match (
+        input_colorspace,
+        output_colorspace
+    ) {
+        (input, output) if input == output => |x| x,
+        (ColorSpace::YCbCr | ColorSpace::Luma, ColorSpace::Luma) => |x| x,
+        (ColorSpace::YCbCr, ColorSpace::RGB | ColorSpace::RGBA) => |[y, cb, cr, _]| {
+            let [r, g, b] = ycbcr_to_rgb(y, cb, cr);
+            [r, g, b, MAX_12BIT]
+        },
+        (ColorSpace::YCbCr, ColorSpace::BGR | ColorSpace::BGRA) => |[y, cb, cr, _]| {
+            let [r, g, b] = ycbcr_to_rgb(y, cb, cr);
+            [b, g, r, MAX_12BIT]
+        },
+        (ColorSpace::YCCK, ColorSpace::RGB | ColorSpace::RGBA) => |[y, cb, cr, k]| {
+            let [r, g, b] = ycbcr_to_rgb(y, cb, cr).map(|x| MAX_12BIT - x.clamp(0, MAX_12BIT));
+            [mul_12bit(r, k), mul_12bit(g, k), mul_12bit(b, k), MAX_12BIT]
+        },
+        (ColorSpace::CMYK, ColorSpace::RGB | ColorSpace::RGBA) => {
+            |[c, m, y, k]| [mul_12bit(c, k), mul_12bit(m, k), mul_12bit(y, k), MAX_12BIT]
+        }
+        _ => {
+            let msg = format!(
+                    "Unimplemented colorspace mapping from {input_colorspace:?} to {output_colorspace:?}");
+
+            return Err(DecodeErrors::Format(msg));
+        }
+    };
+    let num_components = output_colorspace.num_components();
+
+    for (row, out) in output.chunks_exact_mut(width * num_components).enumerate() {
+        if unprocessed[0].len() < (row + 1) * padded_width {
+            break;
+        }
+        for (x, pix) in out.chunks_exact_mut(num_components).enumerate() {
+            let position = row * padded_width + x;
+            // components not needed for the output colorspace are empty
+            let samples = unprocessed.map(|c| i32::from(c.get(position).copied().unwrap_or(0)));
+
+            for (pix, sample) in pix.iter_mut().zip(convert(samples)) {
+                *pix = sample.clamp(0, MAX_12BIT) as u16;
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Copy a block to output removing padding bytes from input
 /// if necessary
 #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
@@ -0,0 +1,46 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! The arithmetic coded images are transcoded from the Huffman coded
+//! references without touching the coefficients, so both decode to the
+//! same pixels
+
+use std::path::Path;
+
+use zune_core::bytestream::ZCursor;
+use zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+fn decode(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../test-images/jpeg")
+        .join(name);
+    let data = std::fs::read(path).unwrap();
+    let options = DecoderOptions::default().set_strict_mode(true);
+
+    JpegDecoder::new_with_options(ZCursor::new(&data), options)
+        .decode()
+        .unwrap()
+}
+
+#[test]
+fn test_arithmetic_sequential() {
+    // restarts every 5 MCUs and non-default conditioning tables
+    assert!(decode("arithmetic_sequential.jpg") == decode("arithmetic_reference.jpg"));
+}
+
+#[test]
+fn test_arithmetic_progressive() {
+    // restarts every MCU row, with successive approximation
+    assert!(decode("arithmetic_progressive.jpg") == decode("arithmetic_reference.jpg"));
+}
+
+#[test]
+fn test_arithmetic_grayscale() {
+    assert!(decode("arithmetic_grayscale.jpg") == decode("arithmetic_grayscale_reference.jpg"));
+}
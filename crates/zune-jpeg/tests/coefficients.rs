@@ -0,0 +1,321 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Coefficients of decoded images should be encoded back without
+//! any change
+
+use zune_core::bit_depth::BitDepth;
+use zune_core::bytestream::ZCursor;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::{DecoderOptions, EncoderOptions, JpegSubsampling};
+use zune_jpeg::{ComponentCoefficients, JpegCoefficients, JpegDecoder, JpegEncoder};
+
+use crate::common::{gradient, read_image};
+
+mod common;
+
+/// Sharp edges with noise on top, so most blocks have
+/// many non zero AC coefficients
+fn texture(width: usize, height: usize, components: usize) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(width * height * components);
+    let mut state = 0x1234_5678_u32;
+
+    for y in 0..height {
+        for x in 0..width {
+            for c in 0..components {
+                // xorshift
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+
+                let edge = if (x / 3 + y / 5 + c) % 2 == 0 { 40 } else { 190 };
+                pixels.push(edge + (state % 48) as u8);
+            }
+        }
+    }
+    pixels
+}
+
+/// Number of blocks of a component with a non zero AC coefficient
+fn blocks_with_ac(component: &ComponentCoefficients) -> usize {
+    component
+        .coefficients
+        .chunks_exact(64)
+        .filter(|x| x[1..].iter().any(|x| *x != 0))
+        .count()
+}
+
+fn decode_coefficients(data: &[u8]) -> JpegCoefficients {
+    JpegDecoder::new(ZCursor::new(data))
+        .decode_coefficients()
+        .unwrap()
+}
+
+fn decode(data: &[u8]) -> Vec<u8> {
+    let options = DecoderOptions::default().set_strict_mode(true);
+
+    JpegDecoder::new_with_options(ZCursor::new(data), options)
+        .decode()
+        .unwrap()
+}
+
+fn encode_coefficients(image: &JpegCoefficients, progressive: bool) -> Vec<u8> {
+    let options = EncoderOptions::new(image.width, image.height, image.colorspace, BitDepth::Eight)
+        .set_jpeg_encode_progressive(progressive);
+    let mut output = vec![];
+
+    let written = JpegEncoder::new(&[], options)
+        .encode_coefficients(image, &mut output)
+        .unwrap();
+
+    assert_eq!(written, output.len());
+    output
+}
+
+fn assert_same_coefficients(a: &JpegCoefficients, b: &JpegCoefficients) {
+    assert_eq!(
+        (a.width, a.height, a.precision),
+        (b.width, b.height, b.precision)
+    );
+    assert_eq!(a.colorspace, b.colorspace);
+    assert_eq!(a.components.len(), b.components.len());
+
+    for (x, y) in a.components.iter().zip(&b.components) {
+        assert_eq!(x.id, y.id);
+        assert_eq!(
+            (x.horizontal_sample, x.vertical_sample),
+            (y.horizontal_sample, y.vertical_sample)
+        );
+        assert_eq!(x.quantization_table, y.quantization_table);
+        assert_eq!(
+            (x.width_in_blocks, x.height_in_blocks),
+            (y.width_in_blocks, y.height_in_blocks)
+        );
+        // padding blocks outside of the image are only partly coded by some scans
+        let (blocks_x, blocks_y) = a.image_blocks(x);
+
+        for by in 0..blocks_y {
+            for bx in 0..blocks_x {
+                assert_eq!(x.block(bx, by), y.block(bx, by), "block {bx},{by}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_coefficients_round_trip() {
+    for name in [
+        "sampling_factors.jpg",
+        "down_sampled_grayscale_prog.jpg",
+        "arithmetic_sequential.jpg",
+        "arithmetic_progressive.jpg",
+        "four_components.jpg",
+        "huffman_third_index.jpg"
+    ] {
+        let data = read_image(name);
+        let image = decode_coefficients(&data);
+
+        assert!(
+            blocks_with_ac(&image.components[0]) > 0,
+            "No AC coefficients in {name}"
+        );
+
+        for progressive in [false, true] {
+            let encoded = encode_coefficients(&image, progressive);
+
+            assert_same_coefficients(&image, &decode_coefficients(&encoded));
+            assert!(
+                decode(&data) == decode(&encoded),
+                "Pixels differ for {name}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_progressive_subsampled_restarts() {
+    // luma is sampled 2x2 and chroma 1x2, so each non interleaved chroma scan
+    // codes half as many block columns as luma but just as many rows
+    let data = read_image("sampling_factors.jpg");
+    let image = decode_coefficients(&data);
+    let expected = decode(&data);
+
+    assert_eq!(
+        image
+            .components
+            .iter()
+            .map(|c| (c.horizontal_sample, c.vertical_sample))
+            .collect::<Vec<_>>(),
+        [(2, 2), (1, 2), (1, 2)]
+    );
+
+    for interval in [1, 3, 7] {
+        let options =
+            EncoderOptions::new(image.width, image.height, image.colorspace, BitDepth::Eight)
+                .set_jpeg_encode_progressive(true)
+                .set_jpeg_restart_interval(interval);
+        let mut encoded = vec![];
+
+        JpegEncoder::new(&[], options)
+            .encode_coefficients(&image, &mut encoded)
+            .unwrap();
+
+        assert!(
+            decode(&encoded) == expected,
+            "Pixels differ for restart interval {interval}"
+        );
+    }
+}
+
+#[test]
+fn test_coefficients_layout() {
+    let (width, height) = (50, 20);
+    let options = EncoderOptions::new(width, height, ColorSpace::RGB, BitDepth::Eight)
+        .set_jpeg_subsampling(JpegSubsampling::S420);
+    let mut table = [2; 64];
+    table[63] = 300;
+
+    let pixels = texture(width, height, 3);
+    let mut encoder = JpegEncoder::new(&pixels, options);
+    encoder.set_quantization_tables(table, [3; 64]);
+
+    let mut data = vec![];
+    encoder.encode(&mut data).unwrap();
+
+    let image = decode_coefficients(&data);
+
+    assert_eq!((image.width, image.height, image.precision), (50, 20, 8));
+    assert_eq!(image.colorspace, ColorSpace::YCbCr);
+    assert_eq!(image.max_sampling_factors(), (2, 2));
+
+    let layout: Vec<_> = image
+        .components
+        .iter()
+        .map(|c| {
+            (
+                c.horizontal_sample,
+                c.vertical_sample,
+                c.width_in_blocks,
+                c.height_in_blocks,
+                c.coefficients.len()
+            )
+        })
+        .collect();
+    // four by two MCUs of 16x16 pixels
+    assert_eq!(
+        layout,
+        [
+            (2, 2, 8, 4, 8 * 4 * 64),
+            (1, 1, 4, 2, 4 * 2 * 64),
+            (1, 1, 4, 2, 4 * 2 * 64)
+        ]
+    );
+    assert_eq!(image.image_blocks(&image.components[0]), (7, 3));
+    assert_eq!(image.image_blocks(&image.components[1]), (4, 2));
+
+    // the padding blocks past the right and bottom edges are flat
+    assert_eq!(blocks_with_ac(&image.components[0]), 7 * 3);
+    assert_eq!(blocks_with_ac(&image.components[1]), 4 * 2);
+
+    assert_eq!(image.components[0].quantization_table, table);
+    assert_eq!(image.components[2].quantization_table, [3; 64]);
+}
+
+#[test]
+fn test_coefficients_modified() {
+    let options = EncoderOptions::new(40, 24, ColorSpace::Luma, BitDepth::Eight);
+    let mut data = vec![];
+    JpegEncoder::new(&texture(40, 24, 1), options)
+        .encode(&mut data)
+        .unwrap();
+
+    let mut image = decode_coefficients(&data);
+    assert_eq!(blocks_with_ac(&image.components[0]), 5 * 3);
+
+    // without AC coefficients every block has a single color
+    for block in image.components[0].coefficients.chunks_exact_mut(64) {
+        block[1..].fill(0);
+    }
+    let pixels = decode(&encode_coefficients(&image, false));
+
+    for y in 0..24 {
+        for x in 0..40 {
+            assert_eq!(pixels[y * 40 + x], pixels[y / 8 * 8 * 40 + x / 8 * 8]);
+        }
+    }
+}
+
+#[test]
+fn test_coefficients_twelve_bit() {
+    let mut coefficients = vec![0; 3 * 2 * 64];
+
+    for (i, block) in coefficients.chunks_exact_mut(64).enumerate() {
+        block[0] = -16384 + 5000 * i as i16;
+        block[1] = 16383;
+        block[63] = -16383;
+    }
+    let image = JpegCoefficients {
+        width:      20,
+        height:     10,
+        precision:  12,
+        colorspace: ColorSpace::Luma,
+        components: vec![ComponentCoefficients {
+            id: 1,
+            horizontal_sample: 1,
+            vertical_sample: 1,
+            quantization_table: [1; 64],
+            width_in_blocks: 3,
+            height_in_blocks: 2,
+            coefficients
+        }]
+    };
+    for progressive in [false, true] {
+        let encoded = encode_coefficients(&image, progressive);
+        assert_same_coefficients(&image, &decode_coefficients(&encoded));
+    }
+}
+
+#[test]
+fn test_coefficients_errors() {
+    let options = EncoderOptions::new(16, 16, ColorSpace::Luma, BitDepth::Eight);
+    let mut data = vec![];
+    JpegEncoder::new(&gradient(16, 16, 1), options)
+        .encode(&mut data)
+        .unwrap();
+
+    let image = decode_coefficients(&data);
+    let encoder = JpegEncoder::new(&[], options);
+
+    let mut wrong = image.clone();
+    wrong.components[0].coefficients[1] = 1024;
+    assert!(encoder.encode_coefficients(&wrong, vec![]).is_err());
+
+    // allowed in 12 bit images
+    wrong.precision = 12;
+    assert!(encoder.encode_coefficients(&wrong, vec![]).is_ok());
+
+    wrong = image.clone();
+    wrong.precision = 10;
+    assert!(encoder.encode_coefficients(&wrong, vec![]).is_err());
+
+    wrong = image.clone();
+    wrong.width = 17;
+    assert!(encoder.encode_coefficients(&wrong, vec![]).is_err());
+
+    wrong = image.clone();
+    wrong.colorspace = ColorSpace::YCbCr;
+    assert!(encoder.encode_coefficients(&wrong, vec![]).is_err());
+
+    wrong = image.clone();
+    wrong.components[0].quantization_table[5] = 0;
+    assert!(encoder.encode_coefficients(&wrong, vec![]).is_err());
+
+    wrong = image;
+    wrong.components[0].vertical_sample = 5;
+    assert!(encoder.encode_coefficients(&wrong, vec![]).is_err());
+}
@@ -0,0 +1,99 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+fn test_image(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../test-images/jpeg")
+        .join(name)
+}
+
+/// Read a file from the jpeg test images
+pub fn read_image(name: &str) -> Vec<u8> {
+    std::fs::read(test_image(name)).unwrap()
+}
+
+/// Samples of a binary PPM or PGM reference image, which stores
+/// the output of another decoder
+pub fn read_reference(name: &str) -> Vec<u16> {
+    let data = read_image(name);
+    // magic number, width, height and maximum value, each
+    // followed by a single whitespace byte
+    let header: Vec<&[u8]> = data.split(u8::is_ascii_whitespace).take(4).collect();
+    let header_size: usize = header.iter().map(|x| x.len() + 1).sum();
+    let max_value: u16 = std::str::from_utf8(header[3]).unwrap().parse().unwrap();
+    let samples = &data[header_size..];
+
+    if max_value > 255 {
+        samples
+            .chunks_exact(2)
+            .map(|x| u16::from_be_bytes([x[0], x[1]]))
+            .collect()
+    } else {
+        samples.iter().map(|x| u16::from(*x)).collect()
+    }
+}
+
+/// A smooth image with `components` components, which survives
+/// compression well
+pub fn gradient(width: usize, height: usize, components: usize) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(width * height * components);
+
+    for y in 0..height {
+        for x in 0..width {
+            for c in 0..components {
+                let value = (x * 255 / width + y * 255 / height + c * 60) / 3;
+                pixels.push(value as u8);
+            }
+        }
+    }
+    pixels
+}
+
+/// Writes bits MSB first, stuffing zeroes after 0xFF bytes
+#[derive(Default)]
+pub struct BitWriter {
+    pub out: Vec<u8>,
+    buffer:  u64,
+    bits:    u32
+}
+
+impl BitWriter {
+    pub fn put(&mut self, value: u32, bits: u32) {
+        self.buffer = (self.buffer << bits) | u64::from(value & ((1 << bits) - 1));
+        self.bits += bits;
+
+        while self.bits >= 8 {
+            let byte = (self.buffer >> (self.bits - 8)) as u8;
+            self.out.push(byte);
+
+            if byte == 0xFF {
+                self.out.push(0);
+            }
+            self.bits -= 8;
+        }
+    }
+    /// Pad the last byte with ones
+    pub fn flush(&mut self) {
+        if self.bits > 0 {
+            self.put(0x7F, 8 - self.bits);
+        }
+    }
+    /// Write a Huffman symbol followed by the bits of the value
+    pub fn put_value(&mut self, code: u32, code_length: u32, value: i32) {
+        let category = 32 - value.unsigned_abs().leading_zeros();
+        let bits = if value < 0 { value - 1 } else { value };
+
+        self.put(code, code_length);
+        self.put(bits as u32, category);
+    }
+}
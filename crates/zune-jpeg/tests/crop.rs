@@ -0,0 +1,110 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Cropped images should hold the same pixels as the same region of the
+//! whole image
+
+use std::path::Path;
+
+use zune_core::bytestream::ZCursor;
+use zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+fn read(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../test-images/jpeg")
+        .join(name);
+    std::fs::read(path).unwrap()
+}
+
+/// Decode an image, returning its dimensions and pixels
+fn decode(data: &[u8], options: DecoderOptions) -> (usize, usize, Vec<u8>) {
+    let mut decoder = JpegDecoder::new_with_options(ZCursor::new(data), options);
+    let pixels = decoder.decode().unwrap();
+    let (width, height) = decoder.dimensions().unwrap();
+
+    (width, height, pixels)
+}
+
+fn check(name: &str, scale_denom: usize) {
+    let data = read(name);
+    let options = DecoderOptions::default()
+        .set_strict_mode(true)
+        .jpeg_set_scale_denom(scale_denom);
+    let (width, height, full) = decode(&data, options);
+    let components = full.len() / (width * height);
+
+    // regions touching the corners, a single pixel and one in the middle
+    let regions = [
+        (0, 0, width / 3, height / 4),
+        (
+            width / 2,
+            height / 2,
+            width - width / 2,
+            height - height / 2
+        ),
+        (width - 1, height / 3, 1, 1),
+        (width / 5, height / 5, width / 2, height / 3)
+    ];
+    for (x, y, crop_width, crop_height) in regions {
+        let (out_width, out_height, pixels) =
+            decode(&data, options.jpeg_set_crop(x, y, crop_width, crop_height));
+
+        assert_eq!((out_width, out_height), (crop_width, crop_height));
+        assert_eq!(pixels.len(), crop_width * crop_height * components);
+
+        let stride = width * components;
+
+        for (row, full_row) in pixels
+            .chunks_exact(crop_width * components)
+            .zip(full.chunks_exact(stride).skip(y))
+        {
+            assert!(
+                row == &full_row[x * components..(x + crop_width) * components],
+                "{name} differs in the region at ({x}, {y})"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_crop_baseline() {
+    check("2029.jpg", 1);
+}
+
+#[test]
+fn test_crop_subsampled() {
+    check("sampling_factors.jpg", 1);
+    check("medium_vertical_samp_2500x1786.jpg", 1);
+}
+
+#[test]
+fn test_crop_progressive() {
+    check("Kiara_limited_progressive_four_components.jpg", 1);
+    check("down_sampled_grayscale_prog.jpg", 1);
+}
+
+#[test]
+fn test_crop_scaled() {
+    check("sampling_factors.jpg", 4);
+    check("arithmetic_progressive.jpg", 2);
+}
+
+#[test]
+fn test_crop_outside_image() {
+    let data = read("2029.jpg");
+
+    // the image is 388x477
+    for (x, y, width, height) in [(0, 0, 389, 10), (300, 400, 10, 78), (10, 10, 0, 10)] {
+        let options = DecoderOptions::default().jpeg_set_crop(x, y, width, height);
+
+        assert!(JpegDecoder::new_with_options(ZCursor::new(&data), options)
+            .decode()
+            .is_err());
+    }
+}
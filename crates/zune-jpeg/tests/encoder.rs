@@ -0,0 +1,233 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Images written by the encoder are decoded again and should be close
+//! to the pixels they were encoded from
+
+use zune_core::bit_depth::BitDepth;
+use zune_core::bytestream::ZCursor;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::{DecoderOptions, EncoderOptions, JpegSubsampling};
+use zune_jpeg::errors::EncodeErrors;
+use zune_jpeg::{JpegDecoder, JpegEncoder};
+
+use crate::common::gradient;
+
+mod common;
+
+fn encode(pixels: &[u8], options: EncoderOptions) -> Vec<u8> {
+    let mut output = vec![];
+    let written = JpegEncoder::new(pixels, options)
+        .encode(&mut output)
+        .unwrap();
+
+    assert_eq!(written, output.len());
+    output
+}
+
+fn decode(data: &[u8], out_colorspace: ColorSpace) -> (usize, usize, Vec<u8>) {
+    let options = DecoderOptions::default()
+        .set_strict_mode(true)
+        .jpeg_set_out_colorspace(out_colorspace);
+    let mut decoder = JpegDecoder::new_with_options(ZCursor::new(data), options);
+    let pixels = decoder.decode().unwrap();
+    let (width, height) = decoder.dimensions().unwrap();
+
+    (width, height, pixels)
+}
+
+/// Encode an image and check that decoding it gives back similar pixels
+fn round_trip(options: EncoderOptions, out_colorspace: ColorSpace, tolerance: u8) {
+    let (width, height) = (options.width(), options.height());
+    let pixels = gradient(width, height, options.colorspace().num_components());
+
+    let data = encode(&pixels, options);
+    let (out_width, out_height, decoded) = decode(&data, out_colorspace);
+
+    assert_eq!((out_width, out_height), (width, height));
+    assert_eq!(decoded.len(), pixels.len());
+
+    let max_difference = pixels
+        .iter()
+        .zip(&decoded)
+        .map(|(a, b)| a.abs_diff(*b))
+        .max()
+        .unwrap();
+
+    assert!(
+        max_difference <= tolerance,
+        "Pixels differ by {max_difference} for {options:?}"
+    );
+}
+
+#[test]
+fn test_encode_subsampling() {
+    // dimensions not divisible by the MCU size
+    let options = EncoderOptions::new(99, 61, ColorSpace::RGB, BitDepth::Eight).set_quality(95);
+
+    for subsampling in [
+        JpegSubsampling::S444,
+        JpegSubsampling::S422,
+        JpegSubsampling::S420
+    ] {
+        for progressive in [false, true] {
+            let options = options
+                .set_jpeg_subsampling(subsampling)
+                .set_jpeg_encode_progressive(progressive);
+
+            round_trip(options, ColorSpace::RGB, 8);
+            round_trip(
+                options.set_jpeg_optimized_huffman_tables(true),
+                ColorSpace::RGB,
+                8
+            );
+        }
+    }
+}
+
+#[test]
+fn test_encode_restart_interval() {
+    let options = EncoderOptions::new(131, 47, ColorSpace::RGB, BitDepth::Eight)
+        .set_quality(95)
+        .set_jpeg_restart_interval(3);
+
+    round_trip(options, ColorSpace::RGB, 8);
+    round_trip(
+        options.set_jpeg_encode_progressive(true),
+        ColorSpace::RGB,
+        8
+    );
+}
+
+#[test]
+fn test_encode_grayscale() {
+    let options = EncoderOptions::new(65, 33, ColorSpace::Luma, BitDepth::Eight).set_quality(95);
+
+    round_trip(options, ColorSpace::Luma, 4);
+    round_trip(
+        options.set_jpeg_encode_progressive(true),
+        ColorSpace::Luma,
+        4
+    );
+}
+
+#[test]
+fn test_encode_rgba() {
+    let options = EncoderOptions::new(40, 30, ColorSpace::RGBA, BitDepth::Eight).set_quality(95);
+    let pixels = gradient(40, 30, 4);
+
+    let (_, _, decoded) = decode(&encode(&pixels, options), ColorSpace::RGB);
+    // alpha is dropped
+    assert_eq!(decoded.len(), 40 * 30 * 3);
+}
+
+#[test]
+fn test_encode_cmyk() {
+    let options = EncoderOptions::new(50, 20, ColorSpace::CMYK, BitDepth::Eight).set_quality(95);
+
+    round_trip(options, ColorSpace::CMYK, 4);
+    round_trip(
+        options.set_jpeg_encode_progressive(true),
+        ColorSpace::CMYK,
+        4
+    );
+}
+
+#[test]
+fn test_encode_lossless_quantization() {
+    let options = EncoderOptions::new(37, 29, ColorSpace::Luma, BitDepth::Eight);
+    let pixels = gradient(37, 29, 1);
+
+    let mut encoder = JpegEncoder::new(&pixels, options);
+    encoder.set_quantization_tables([1; 64], [1; 64]);
+
+    let mut data = vec![];
+    encoder.encode(&mut data).unwrap();
+
+    let (_, _, decoded) = decode(&data, ColorSpace::Luma);
+    let max_difference = pixels.iter().zip(&decoded).map(|(a, b)| a.abs_diff(*b));
+
+    assert!(max_difference.max().unwrap() <= 1);
+}
+
+#[test]
+fn test_encode_sixteen_bit_quantization() {
+    // values larger than 255 need an extended sequential image
+    let options = EncoderOptions::new(32, 32, ColorSpace::RGB, BitDepth::Eight);
+    let pixels = gradient(32, 32, 3);
+    let mut table = [300; 64];
+    table[0] = 4;
+
+    let mut encoder = JpegEncoder::new(&pixels, options);
+    encoder.set_quantization_tables(table, table);
+
+    let mut data = vec![];
+    encoder.encode(&mut data).unwrap();
+    // SOF1 marker
+    assert!(data.windows(2).any(|x| x == [0xFF, 0xC1]));
+
+    let (width, height, _) = decode(&data, ColorSpace::RGB);
+    assert_eq!((width, height), (32, 32));
+}
+
+#[test]
+fn test_encode_metadata() {
+    let options = EncoderOptions::new(16, 16, ColorSpace::RGB, BitDepth::Eight);
+    let pixels = gradient(16, 16, 3);
+    // large enough to be split over two segments
+    let icc: Vec<u8> = (0..100_000).map(|x| (x % 251) as u8).collect();
+    let exif = b"MM\0\x2a\0\0\0\x08\0\0".to_vec();
+
+    let mut encoder = JpegEncoder::new(&pixels, options);
+    encoder.add_icc_profile(&icc);
+    encoder.add_exif(&exif);
+
+    let mut data = vec![];
+    encoder.encode(&mut data).unwrap();
+
+    let mut decoder = JpegDecoder::new(ZCursor::new(&data));
+    decoder.decode_headers().unwrap();
+
+    assert_eq!(decoder.icc_profile(), Some(icc));
+    assert_eq!(decoder.exif(), Some(&exif));
+}
+
+#[test]
+fn test_encode_errors() {
+    let pixels = gradient(10, 10, 3);
+
+    let options = EncoderOptions::new(10, 11, ColorSpace::RGB, BitDepth::Eight);
+    let err = JpegEncoder::new(&pixels, options)
+        .encode(vec![])
+        .unwrap_err();
+    assert!(matches!(err, EncodeErrors::WrongInputSize(330, 300)));
+
+    let options = EncoderOptions::new(10, 10, ColorSpace::RGB, BitDepth::Sixteen);
+    let err = JpegEncoder::new(&pixels, options)
+        .encode(vec![])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        EncodeErrors::UnsupportedDepth(BitDepth::Sixteen)
+    ));
+
+    let options = EncoderOptions::new(0, 10, ColorSpace::RGB, BitDepth::Eight);
+    let err = JpegEncoder::new(&pixels, options)
+        .encode(vec![])
+        .unwrap_err();
+    assert!(matches!(err, EncodeErrors::InvalidDimensions(0, 10)));
+
+    let options = EncoderOptions::new(10, 10, ColorSpace::HSV, BitDepth::Eight);
+    let err = JpegEncoder::new(&pixels, options)
+        .encode(vec![])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        EncodeErrors::UnsupportedColorspace(ColorSpace::HSV)
+    ));
+}
@@ -0,0 +1,426 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Lossless images are mostly encoded here, with all predictors, point
+//! transforms and restart intervals. The `lossless_*.jpg` files are
+//! checked against their decodes by the `jpeg-decoder` crate
+
+use zune_core::bit_depth::BitDepth;
+use zune_core::bytestream::ZCursor;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+use crate::common::{read_image, read_reference, BitWriter};
+
+mod common;
+
+/// Settings of a lossless image
+#[derive(Copy, Clone)]
+struct Lossless {
+    width:            usize,
+    height:           usize,
+    components:       usize,
+    precision:        u8,
+    predictor:        u8,
+    point_transform:  u8,
+    restart_interval: usize,
+    /// Store every component in its own scan
+    separate_scans:   bool
+}
+
+impl Default for Lossless {
+    fn default() -> Self {
+        Lossless {
+            width:            37,
+            height:           23,
+            components:       1,
+            precision:        8,
+            predictor:        1,
+            point_transform:  0,
+            restart_interval: 0,
+            separate_scans:   false
+        }
+    }
+}
+
+/// Huffman table for the 17 difference categories, the first eight have
+/// 4 bit codes and the rest 12 bit codes
+const BITS: [u8; 16] = [0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 9, 0, 0, 0, 0];
+
+fn huffman_code(category: u32) -> (u32, u32) {
+    if category < 8 {
+        (category, 4)
+    } else {
+        ((8 << 8) + category - 8, 12)
+    }
+}
+
+fn predict(
+    predictor: u8, row: &[i32], up: &[i32], x: usize, first_line: bool, restart_x: usize,
+    initial: i32
+) -> i32 {
+    if first_line && x == restart_x {
+        initial
+    } else if first_line {
+        row[x - 1]
+    } else if x == 0 {
+        up[x]
+    } else {
+        let (ra, rb, rc) = (row[x - 1], up[x], up[x - 1]);
+
+        match predictor {
+            1 => ra,
+            2 => rb,
+            3 => rc,
+            4 => ra + rb - rc,
+            5 => ra + ((rb - rc) >> 1),
+            6 => rb + ((ra - rc) >> 1),
+            _ => (ra + rb) >> 1
+        }
+    }
+}
+
+fn write_difference(writer: &mut BitWriter, difference: i32) {
+    // differences are modulo 2^16, between -32767 and 32768
+    let mut difference = difference & 0xFFFF;
+
+    if difference > 32768 {
+        difference -= 65536;
+    }
+    let category = 32 - difference.unsigned_abs().leading_zeros();
+    let (code, length) = huffman_code(category);
+
+    writer.put(code, length);
+
+    if (1..16).contains(&category) {
+        let bits = if difference < 0 { difference - 1 } else { difference };
+        writer.put(bits as u32, category);
+    }
+}
+
+/// Encode interleaved samples as a lossless jpeg
+fn encode(settings: Lossless, samples: &[u16]) -> Vec<u8> {
+    let Lossless {
+        width,
+        height,
+        components,
+        precision,
+        predictor,
+        point_transform,
+        restart_interval,
+        separate_scans
+    } = settings;
+
+    let mut out = vec![0xFF, 0xD8];
+    // start of frame
+    out.extend_from_slice(&[0xFF, 0xC3]);
+    out.extend_from_slice(&(8 + 3 * components as u16).to_be_bytes());
+    out.push(precision);
+    out.extend_from_slice(&(height as u16).to_be_bytes());
+    out.extend_from_slice(&(width as u16).to_be_bytes());
+    out.push(components as u8);
+
+    for i in 0..components {
+        out.extend_from_slice(&[i as u8 + 1, 0x11, 0]);
+    }
+    // huffman table
+    out.extend_from_slice(&[0xFF, 0xC4, 0, 36, 0]);
+    out.extend_from_slice(&BITS);
+    out.extend(0..=16);
+
+    if restart_interval != 0 {
+        out.extend_from_slice(&[0xFF, 0xDD, 0, 4]);
+        out.extend_from_slice(&(restart_interval as u16).to_be_bytes());
+    }
+    let scans: Vec<Vec<usize>> = if separate_scans {
+        (0..components).map(|x| vec![x]).collect()
+    } else {
+        vec![(0..components).collect()]
+    };
+    let initial = 1 << (precision - point_transform - 1);
+
+    for scan in scans {
+        out.extend_from_slice(&[0xFF, 0xDA]);
+        out.extend_from_slice(&(6 + 2 * scan.len() as u16).to_be_bytes());
+        out.push(scan.len() as u8);
+
+        for i in &scan {
+            out.extend_from_slice(&[*i as u8 + 1, 0]);
+        }
+        out.extend_from_slice(&[predictor, 0, point_transform]);
+
+        let mut writer = BitWriter::default();
+        let mut rows = vec![vec![0; width]; scan.len()];
+        let mut previous = vec![vec![0; width]; scan.len()];
+        let (mut first_line, mut restart_x, mut num_mcus, mut num_restarts) = (true, 0, 0, 0);
+
+        for y in 0..height {
+            for x in 0..width {
+                if restart_interval != 0 && num_mcus == restart_interval {
+                    writer.flush();
+                    writer
+                        .out
+                        .extend_from_slice(&[0xFF, 0xD0 + (num_restarts % 8)]);
+                    num_restarts += 1;
+                    num_mcus = 0;
+                    first_line = true;
+                    restart_x = x;
+                }
+                for (i, k) in scan.iter().enumerate() {
+                    let sample =
+                        i32::from(samples[(y * width + x) * components + k] >> point_transform);
+                    let prediction = predict(
+                        predictor,
+                        &rows[i],
+                        &previous[i],
+                        x,
+                        first_line,
+                        restart_x,
+                        initial
+                    );
+                    write_difference(&mut writer, sample - prediction);
+                    rows[i][x] = sample;
+                }
+                num_mcus += 1;
+            }
+            first_line = false;
+            std::mem::swap(&mut rows, &mut previous);
+        }
+        writer.flush();
+        out.extend_from_slice(&writer.out);
+    }
+    out.extend_from_slice(&[0xFF, 0xD9]);
+    out
+}
+
+/// Noisy gradient samples of an image
+fn samples(settings: Lossless) -> Vec<u16> {
+    let max_value = (1_u32 << settings.precision) - 1;
+    let mut state = 0x1234_5678_u32;
+
+    (0..settings.width * settings.height * settings.components)
+        .map(|i| {
+            // xorshift
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+
+            let gradient = (i as u32 * 7) % (max_value + 1);
+            let value = if i % 5 == 0 { state & max_value } else { gradient };
+            // the point transform drops the lower bits
+            (value >> settings.point_transform << settings.point_transform) as u16
+        })
+        .collect()
+}
+
+fn decode(data: &[u8]) -> Vec<u16> {
+    decode_to(data, ColorSpace::RGB)
+}
+
+fn decode_to(data: &[u8], colorspace: ColorSpace) -> Vec<u16> {
+    let options = DecoderOptions::default()
+        .set_strict_mode(true)
+        .jpeg_set_out_colorspace(colorspace);
+    JpegDecoder::new_with_options(ZCursor::new(data), options)
+        .decode_u16()
+        .unwrap()
+}
+
+#[test]
+fn test_lossless_predictors() {
+    for precision in [2, 8, 12, 16] {
+        for predictor in 1..=7 {
+            for components in [1, 3] {
+                let settings = Lossless {
+                    components,
+                    precision,
+                    predictor,
+                    ..Default::default()
+                };
+                let expected = samples(settings);
+
+                assert!(
+                    decode(&encode(settings, &expected)) == expected,
+                    "predictor {predictor} with {precision} bits and {components} components differs"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_lossless_point_transform() {
+    for point_transform in [1, 3, 11] {
+        let settings = Lossless {
+            precision: 12,
+            predictor: 6,
+            point_transform,
+            ..Default::default()
+        };
+        let expected = samples(settings);
+
+        assert!(decode(&encode(settings, &expected)) == expected);
+    }
+}
+
+#[test]
+fn test_lossless_restarts() {
+    // a row and less than a row between restarts
+    for restart_interval in [37, 10] {
+        let settings = Lossless {
+            components: 3,
+            precision: 10,
+            predictor: 4,
+            restart_interval,
+            ..Default::default()
+        };
+        let expected = samples(settings);
+
+        assert!(decode(&encode(settings, &expected)) == expected);
+    }
+}
+
+#[test]
+fn test_lossless_separate_scans() {
+    let settings = Lossless {
+        components: 4,
+        precision: 16,
+        predictor: 7,
+        separate_scans: true,
+        ..Default::default()
+    };
+    let expected = samples(settings);
+    let data = encode(settings, &expected);
+
+    assert!(decode_to(&data, ColorSpace::CMYK) == expected);
+}
+
+#[test]
+fn test_lossless_colorspaces() {
+    for (components, colorspace, convert) in [
+        (
+            2,
+            ColorSpace::LumaA,
+            (|x| x.to_vec()) as fn(&[u16]) -> Vec<u16>
+        ),
+        (2, ColorSpace::Luma, |x| vec![x[0]]),
+        (2, ColorSpace::RGBA, |x| vec![x[0], x[0], x[0], x[1]]),
+        (3, ColorSpace::RGBA, |x| vec![x[0], x[1], x[2], 4095]),
+        (3, ColorSpace::BGR, |x| vec![x[2], x[1], x[0]]),
+        (4, ColorSpace::RGB, |x| {
+            x[..3]
+                .iter()
+                .map(|c| ((u32::from(*c) * u32::from(x[3]) + 2047) / 4095) as u16)
+                .collect()
+        })
+    ] {
+        let settings = Lossless {
+            components,
+            precision: 12,
+            ..Default::default()
+        };
+        let stored = samples(settings);
+        let expected: Vec<u16> = stored.chunks_exact(components).flat_map(convert).collect();
+
+        assert!(
+            decode_to(&encode(settings, &stored), colorspace) == expected,
+            "{components} components to {colorspace:?}"
+        );
+    }
+
+    // no conversion from RGB samples
+    let settings = Lossless {
+        components: 3,
+        ..Default::default()
+    };
+    let data = encode(settings, &samples(settings));
+
+    for colorspace in [ColorSpace::Luma, ColorSpace::CMYK, ColorSpace::YCbCr] {
+        let options = DecoderOptions::default().jpeg_set_out_colorspace(colorspace);
+        let mut decoder = JpegDecoder::new_with_options(ZCursor::new(&data), options);
+
+        assert!(decoder.decode_headers().is_err(), "{colorspace:?}");
+    }
+}
+
+#[test]
+fn test_lossless_u8_output() {
+    for precision in [6, 8, 12] {
+        let settings = Lossless {
+            components: 3,
+            precision,
+            predictor: 5,
+            ..Default::default()
+        };
+        let expected = samples(settings);
+        let data = encode(settings, &expected);
+
+        let mut decoder = JpegDecoder::new(ZCursor::new(&data));
+        let pixels = decoder.decode().unwrap();
+
+        assert_eq!(decoder.precision(), Some(precision));
+        assert_eq!(decoder.output_colorspace(), Some(ColorSpace::RGB));
+
+        if precision > 8 {
+            assert_eq!(decoder.depth(), Some(BitDepth::Sixteen));
+        } else {
+            assert_eq!(decoder.depth(), Some(BitDepth::Eight));
+        }
+        let shift = precision.saturating_sub(8);
+
+        assert!(pixels
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| u16::from(*a) == *b >> shift));
+    }
+}
+
+#[test]
+fn test_lossless_crop() {
+    let settings = Lossless {
+        components: 3,
+        predictor: 4,
+        separate_scans: true,
+        ..Default::default()
+    };
+    let expected = samples(settings);
+    let data = encode(settings, &expected);
+
+    let (x, y, width, height) = (5, 3, 20, 11);
+    let options = DecoderOptions::default()
+        .set_strict_mode(true)
+        .jpeg_set_crop(x, y, width, height);
+    let mut decoder = JpegDecoder::new_with_options(ZCursor::new(&data), options);
+    let pixels = decoder.decode_u16().unwrap();
+
+    assert_eq!(decoder.dimensions(), Some((width, height)));
+
+    for (row, expected_row) in pixels
+        .chunks_exact(width * 3)
+        .zip(expected.chunks_exact(settings.width * 3).skip(y))
+    {
+        assert_eq!(row, &expected_row[x * 3..(x + width) * 3]);
+    }
+}
+
+#[test]
+fn test_lossless_references() {
+    // 8 bit RGB with predictor 1, 16 bit grayscale with predictor 6
+    // and 12 bit RGB with predictor 7
+    for (name, reference) in [
+        ("lossless_rgb.jpg", "lossless_rgb.ppm"),
+        ("lossless_gray16.jpg", "lossless_gray16.pgm"),
+        ("lossless_twelve_bit.jpg", "lossless_twelve_bit.ppm")
+    ] {
+        assert!(
+            decode(&read_image(name)) == read_reference(reference),
+            "{name} differs"
+        );
+    }
+}
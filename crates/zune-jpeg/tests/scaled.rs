@@ -0,0 +1,165 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Images decoded scaled down should be close to the full size image
+//! averaged over blocks of the scale
+
+use std::path::Path;
+
+use zune_core::bit_depth::BitDepth;
+use zune_core::bytestream::ZCursor;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::{DecoderOptions, EncoderOptions, JpegSubsampling};
+use zune_jpeg::{JpegDecoder, JpegEncoder};
+
+fn read(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../test-images/jpeg")
+        .join(name);
+    std::fs::read(path).unwrap()
+}
+
+/// Decode an image, returning its dimensions, number of components and pixels
+fn decode(
+    data: &[u8], colorspace: ColorSpace, scale_denom: usize
+) -> (usize, usize, usize, Vec<u8>) {
+    let options = DecoderOptions::default()
+        .set_strict_mode(true)
+        .jpeg_set_out_colorspace(colorspace)
+        .jpeg_set_scale_denom(scale_denom);
+    let mut decoder = JpegDecoder::new_with_options(ZCursor::new(data), options);
+    let pixels = decoder.decode().unwrap();
+    let (width, height) = decoder.dimensions().unwrap();
+    let components = decoder.output_colorspace().unwrap().num_components();
+
+    assert_eq!(pixels.len(), width * height * components);
+
+    (width, height, components, pixels)
+}
+
+/// Average the full size image over `scale_denom`x`scale_denom` blocks
+fn average(
+    pixels: &[u8], width: usize, height: usize, components: usize, scale_denom: usize
+) -> Vec<u8> {
+    let (out_width, out_height) = (width.div_ceil(scale_denom), height.div_ceil(scale_denom));
+    let mut out = vec![0; out_width * out_height * components];
+
+    for y in 0..out_height {
+        for x in 0..out_width {
+            for c in 0..components {
+                let (mut sum, mut count) = (0, 0);
+
+                for sy in y * scale_denom..((y + 1) * scale_denom).min(height) {
+                    for sx in x * scale_denom..((x + 1) * scale_denom).min(width) {
+                        sum += usize::from(pixels[(sy * width + sx) * components + c]);
+                        count += 1;
+                    }
+                }
+                out[(y * out_width + x) * components + c] = ((sum + count / 2) / count) as u8;
+            }
+        }
+    }
+    out
+}
+
+/// Compare the image scaled down to the averaged full size image, in the
+/// colorspace of the image since color conversion isn't linear
+fn check(name: &str, colorspace: ColorSpace) {
+    check_data(name, &read(name), colorspace);
+}
+
+fn check_data(name: &str, data: &[u8], colorspace: ColorSpace) {
+    let (width, height, components, full) = decode(data, colorspace, 1);
+
+    for scale_denom in [2, 4, 8] {
+        let (scaled_width, scaled_height, _, scaled) = decode(data, colorspace, scale_denom);
+
+        assert_eq!(scaled_width, width.div_ceil(scale_denom));
+        assert_eq!(scaled_height, height.div_ceil(scale_denom));
+
+        let expected = average(&full, width, height, components, scale_denom);
+        let difference: usize = scaled
+            .iter()
+            .zip(&expected)
+            .map(|(a, b)| usize::from(a.abs_diff(*b)))
+            .sum();
+
+        // single samples may differ more where the full size image is
+        // clamped, so only look at the average
+        assert!(
+            difference < scaled.len(),
+            "{name} scaled down by {scale_denom} differs by {difference}"
+        );
+    }
+}
+
+#[test]
+fn test_scaled_baseline() {
+    check("2029.jpg", ColorSpace::YCbCr);
+}
+
+#[test]
+fn test_scaled_subsampled() {
+    check("sampling_factors.jpg", ColorSpace::YCbCr);
+}
+
+#[test]
+fn test_scaled_h2v1() {
+    // chroma is scaled up horizontally only, like in most camera images
+    let (width, height) = (64, 64);
+    // the hue changes along x, so chroma isn't flat
+    let pixels: Vec<u8> = (0..width * height)
+        .flat_map(|i| {
+            let (x, y) = ((i % width * 4) as u8, (i / width * 4) as u8);
+            [x, y / 2 + 64, 255 - x]
+        })
+        .collect();
+
+    for subsampling in [
+        JpegSubsampling::S422,
+        JpegSubsampling::S420,
+        JpegSubsampling::S444
+    ] {
+        for progressive in [false, true] {
+            let options = EncoderOptions::new(width, height, ColorSpace::RGB, BitDepth::Eight)
+                .set_quality(95)
+                .set_jpeg_subsampling(subsampling)
+                .set_jpeg_encode_progressive(progressive);
+            let mut data = vec![];
+            JpegEncoder::new(&pixels, options)
+                .encode(&mut data)
+                .unwrap();
+
+            let name = format!("{subsampling:?} gradient, progressive: {progressive}");
+            check_data(&name, &data, ColorSpace::RGB);
+        }
+    }
+}
+
+#[test]
+fn test_scaled_progressive() {
+    check(
+        "Kiara_limited_progressive_four_components.jpg",
+        ColorSpace::YCCK
+    );
+}
+
+#[test]
+fn test_scaled_grayscale() {
+    check("down_sampled_grayscale_prog.jpg", ColorSpace::Luma);
+}
+
+#[test]
+fn test_scaled_invalid_denominator() {
+    let data = read("2029.jpg");
+    let options = DecoderOptions::default().jpeg_set_scale_denom(3);
+
+    assert!(JpegDecoder::new_with_options(ZCursor::new(&data), options)
+        .decode()
+        .is_err());
+}
@@ -0,0 +1,265 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Transformed images should decode to the transformed pixels of the
+//! source image
+
+use std::path::Path;
+
+use zune_core::bit_depth::BitDepth;
+use zune_core::bytestream::ZCursor;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::{DecoderOptions, EncoderOptions, JpegSubsampling};
+use zune_jpeg::{JpegDecoder, JpegEncoder, JpegTransformer, Transform};
+
+const TRANSFORMS: [Transform; 8] = [
+    Transform::None,
+    Transform::FlipHorizontal,
+    Transform::FlipVertical,
+    Transform::Transpose,
+    Transform::Transverse,
+    Transform::Rotate90,
+    Transform::Rotate180,
+    Transform::Rotate270
+];
+
+fn read(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../test-images/jpeg")
+        .join(name);
+    std::fs::read(path).unwrap()
+}
+
+/// An image which looks different in every orientation
+fn pattern(width: usize, height: usize) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(width * height * 3);
+
+    for y in 0..height {
+        for x in 0..width {
+            pixels.push((x * 200 / width + y * 50 / height) as u8);
+            pixels.push((y * 200 / height) as u8);
+            pixels.push(((x + y) * 100 / (width + height)) as u8);
+        }
+    }
+    pixels
+}
+
+fn encode(width: usize, height: usize, subsampling: JpegSubsampling, progressive: bool) -> Vec<u8> {
+    let options = EncoderOptions::new(width, height, ColorSpace::RGB, BitDepth::Eight)
+        .set_quality(95)
+        .set_jpeg_subsampling(subsampling)
+        .set_jpeg_encode_progressive(progressive);
+    let mut output = vec![];
+
+    JpegEncoder::new(&pattern(width, height), options)
+        .encode(&mut output)
+        .unwrap();
+    output
+}
+
+/// Decode an image, returning its dimensions and pixels
+fn decode(data: &[u8], options: DecoderOptions) -> (usize, usize, Vec<u8>) {
+    let mut decoder = JpegDecoder::new_with_options(ZCursor::new(data), options);
+    let pixels = decoder.decode().unwrap();
+    let (width, height) = decoder.dimensions().unwrap();
+
+    (width, height, pixels)
+}
+
+fn transform(data: &[u8], transform: Transform) -> Vec<u8> {
+    let mut transformer = JpegTransformer::new(ZCursor::new(data));
+    transformer.set_transform(transform);
+
+    let mut output = vec![];
+    let written = transformer.transform(&mut output).unwrap();
+
+    assert_eq!(written, output.len());
+    output
+}
+
+/// Pixel of the source image which moves to `x`,`y` in the transformed image,
+/// which is `width` by `height` pixels
+fn source_position(
+    transform: Transform, x: usize, y: usize, width: usize, height: usize
+) -> (usize, usize) {
+    match transform {
+        Transform::None => (x, y),
+        Transform::FlipHorizontal => (width - 1 - x, y),
+        Transform::FlipVertical => (x, height - 1 - y),
+        Transform::Transpose => (y, x),
+        Transform::Transverse => (height - 1 - y, width - 1 - x),
+        Transform::Rotate90 => (y, width - 1 - x),
+        Transform::Rotate180 => (width - 1 - x, height - 1 - y),
+        Transform::Rotate270 => (height - 1 - y, x)
+    }
+}
+
+#[test]
+fn test_transform_pixels() {
+    // whole MCUs, so nothing is trimmed
+    let (width, height) = (64, 48);
+
+    for subsampling in [JpegSubsampling::S444, JpegSubsampling::S420] {
+        for progressive in [false, true] {
+            let data = encode(width, height, subsampling, progressive);
+            let (_, _, source) = decode(&data, DecoderOptions::default());
+
+            for t in TRANSFORMS {
+                let (out_width, out_height, pixels) =
+                    decode(&transform(&data, t), DecoderOptions::default());
+                let transposed = matches!(
+                    t,
+                    Transform::Transpose
+                        | Transform::Transverse
+                        | Transform::Rotate90
+                        | Transform::Rotate270
+                );
+                let expected = if transposed { (height, width) } else { (width, height) };
+
+                assert_eq!((out_width, out_height), expected);
+
+                let mut max_difference = 0;
+
+                for y in 0..out_height {
+                    for x in 0..out_width {
+                        let (sx, sy) = source_position(t, x, y, out_width, out_height);
+
+                        for c in 0..3 {
+                            let a = pixels[(y * out_width + x) * 3 + c];
+                            let b = source[(sy * width + sx) * 3 + c];
+                            max_difference = max_difference.max(a.abs_diff(b));
+                        }
+                    }
+                }
+                // rounding in the IDCT and upsampling isn't symmetric
+                assert!(
+                    max_difference <= 4,
+                    "Pixels differ by {max_difference} for {t:?} {subsampling:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_transform_round_trip() {
+    // transposing twice gives back the same coefficients
+    for name in [
+        "sampling_factors.jpg",
+        "down_sampled_grayscale_prog.jpg",
+        "arithmetic_sequential.jpg",
+        "arithmetic_progressive.jpg",
+        "four_components.jpg"
+    ] {
+        let data = read(name);
+        let transposed = transform(
+            &transform(&data, Transform::Transpose),
+            Transform::Transpose
+        );
+
+        let (width, height, expected) = decode(&data, DecoderOptions::default());
+        let (out_width, out_height, pixels) = decode(&transposed, DecoderOptions::default());
+
+        assert_eq!((out_width, out_height), (width, height), "{name}");
+        assert!(pixels == expected, "Pixels differ for {name}");
+    }
+
+    let data = encode(96, 64, JpegSubsampling::S422, true);
+    let mut rotated = data.clone();
+
+    for _ in 0..4 {
+        rotated = transform(&rotated, Transform::Rotate90);
+    }
+    assert!(
+        decode(&data, DecoderOptions::default()) == decode(&rotated, DecoderOptions::default())
+    );
+}
+
+#[test]
+fn test_transform_trim() {
+    // 4:2:0 MCUs are 16x16 pixels, partial MCUs moving to the top or left are trimmed
+    let data = encode(99, 61, JpegSubsampling::S420, false);
+
+    for (t, dimensions) in [
+        (Transform::FlipHorizontal, (96, 61)),
+        (Transform::FlipVertical, (99, 48)),
+        (Transform::Transpose, (61, 99)),
+        (Transform::Rotate90, (48, 99)),
+        (Transform::Rotate180, (96, 48)),
+        (Transform::Rotate270, (61, 96))
+    ] {
+        let (width, height, _) = decode(&transform(&data, t), DecoderOptions::default());
+        assert_eq!((width, height), dimensions, "{t:?}");
+
+        let mut transformer = JpegTransformer::new(ZCursor::new(&data));
+        transformer.set_transform(t);
+        transformer.set_perfect(true);
+
+        let result = transformer.transform(vec![]);
+        assert_eq!(result.is_ok(), t == Transform::Transpose, "{t:?}");
+    }
+}
+
+#[test]
+fn test_transform_crop() {
+    let data = encode(100, 70, JpegSubsampling::S444, false);
+
+    // the corner moves to the closest MCU boundary, (16, 8)
+    let mut transformer = JpegTransformer::new(ZCursor::new(&data));
+    transformer.set_crop(21, 13, 40, 30);
+
+    let mut cropped = vec![];
+    transformer.transform(&mut cropped).unwrap();
+
+    // no upsampling, so the blocks decode to exactly the same pixels
+    let (width, height, pixels) = decode(&cropped, DecoderOptions::default());
+    let options = DecoderOptions::default().jpeg_set_crop(16, 8, 45, 35);
+
+    assert_eq!((width, height), (45, 35));
+    assert!(pixels == decode(&data, options).2);
+
+    transformer = JpegTransformer::new(ZCursor::new(&data));
+    transformer.set_crop(90, 0, 11, 10);
+    assert!(transformer.transform(vec![]).is_err());
+}
+
+#[test]
+fn test_transform_auto_orient() {
+    let pixels = pattern(64, 32);
+    let options = EncoderOptions::new(64, 32, ColorSpace::RGB, BitDepth::Eight);
+    // a single orientation entry of 6, rotated by 90 degrees
+    let exif = b"MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0".to_vec();
+
+    let mut encoder = JpegEncoder::new(&pixels, options);
+    encoder.add_exif(&exif);
+
+    let mut data = vec![];
+    encoder.encode(&mut data).unwrap();
+
+    let mut transformer = JpegTransformer::new(ZCursor::new(&data));
+    // replaced by the orientation
+    transformer.set_transform(Transform::FlipVertical);
+    transformer.set_auto_orient(true);
+
+    let mut output = vec![];
+    transformer.transform(&mut output).unwrap();
+
+    let mut decoder = JpegDecoder::new(ZCursor::new(&output));
+    decoder.decode_headers().unwrap();
+
+    let mut upright = exif.clone();
+    upright[19] = 1;
+
+    assert_eq!(decoder.dimensions(), Some((32, 64)));
+    assert_eq!(decoder.exif(), Some(&upright));
+    assert_eq!(
+        Transform::from_exif_orientation(6),
+        Some(Transform::Rotate90)
+    );
+    assert_eq!(Transform::from_exif_orientation(9), None);
+}
@@ -0,0 +1,486 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! 12 bit images are mostly encoded here, since the usual encoders only
+//! write 8 bit images. The `twelve_bit_*.jpg` files are encoded by a 12 bit
+//! build of libjpeg, and checked against its decodes
+
+use std::f64::consts::PI;
+
+use zune_core::bit_depth::BitDepth;
+use zune_core::bytestream::ZCursor;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+use crate::common::{read_image, read_reference, BitWriter};
+
+mod common;
+
+/// Settings of a 12 bit image
+#[derive(Copy, Clone)]
+struct Settings {
+    width:       usize,
+    height:      usize,
+    components:  usize,
+    /// Store the chroma components at half the width and height
+    subsampled:  bool,
+    progressive: bool
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            width:       37,
+            height:      23,
+            components:  1,
+            subsampled:  false,
+            progressive: false
+        }
+    }
+}
+
+impl Settings {
+    /// Horizontal and vertical sampling factors of a component
+    fn sampling(self, component: usize) -> usize {
+        if self.subsampled && component == 0 {
+            2
+        } else {
+            1
+        }
+    }
+    /// Width and height of a component
+    fn dimensions(self, component: usize) -> (usize, usize) {
+        let max = self.sampling(0);
+        let sampling = self.sampling(component);
+
+        (
+            (self.width * sampling).div_ceil(max),
+            (self.height * sampling).div_ceil(max)
+        )
+    }
+}
+
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63
+];
+
+/// Quantization value of a coefficient in zigzag order
+fn quantization(k: usize) -> i32 {
+    1 + k as i32 / 16
+}
+
+/// Every DC category has a 5 bit code equal to the category
+fn dc_code(value: i32) -> u32 {
+    32 - value.unsigned_abs().leading_zeros()
+}
+
+/// AC symbols, each with an 8 bit code equal to its position
+fn ac_symbols() -> Vec<u8> {
+    let mut symbols = vec![0x00, 0xF0];
+
+    for run in 0..16 {
+        symbols.extend((1..=14).map(|size| (run << 4) | size));
+    }
+    symbols
+}
+
+fn ac_code(run: usize, value: i32) -> u32 {
+    let size = 32 - value.unsigned_abs().leading_zeros();
+    2 + run as u32 * 14 + size - 1
+}
+
+/// Encode the AC coefficients `start..64` of a block in zigzag order
+fn encode_ac(writer: &mut BitWriter, block: &[i32; 64], start: usize) {
+    let mut run = 0;
+
+    for &coefficient in &block[start..] {
+        if coefficient == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 15 {
+            writer.put(1, 8);
+            run -= 16;
+        }
+        writer.put_value(ac_code(run, coefficient), 8, coefficient);
+        run = 0;
+    }
+    if run > 0 {
+        writer.put(0, 8);
+    }
+}
+
+/// Smooth samples of a component, with some texture
+fn samples(settings: Settings, component: usize) -> Vec<i32> {
+    let (width, height) = settings.dimensions(component);
+    let mut samples = vec![0; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            let (fx, fy) = (x as i32, y as i32);
+
+            samples[y * width + x] = match component {
+                0 => 100 + fx * 60 + fy * 70 + (fx * fy) % 7 * 10,
+                1 => 1500 + fx * 20,
+                _ => 2600 - fy * 15
+            };
+        }
+    }
+    samples
+}
+
+/// Quantized coefficients of all blocks of a component in zigzag order,
+/// covering all MCUs
+fn blocks(settings: Settings, component: usize) -> (usize, Vec<[i32; 64]>) {
+    let max = settings.sampling(0);
+    let sampling = settings.sampling(component);
+    let (width, height) = settings.dimensions(component);
+    let samples = samples(settings, component);
+
+    let blocks_x = settings.width.div_ceil(8 * max) * sampling;
+    let blocks_y = settings.height.div_ceil(8 * max) * sampling;
+    let mut blocks = vec![[0; 64]; blocks_x * blocks_y];
+
+    for (i, block) in blocks.iter_mut().enumerate() {
+        let (bx, by) = (i % blocks_x * 8, i / blocks_x * 8);
+
+        // repeat the edges into the padding
+        let sample = |x: usize, y: usize| {
+            let x = (bx + x).min(width - 1);
+            let y = (by + y).min(height - 1);
+            f64::from(samples[y * width + x] - 2048)
+        };
+        for (k, coefficient) in block.iter_mut().enumerate() {
+            let (u, v) = (ZIGZAG[k] % 8, ZIGZAG[k] / 8);
+            let scale = |f: usize| if f == 0 { 0.5_f64.sqrt() } else { 1.0 };
+            let mut sum = 0.0;
+
+            for y in 0..8 {
+                for x in 0..8 {
+                    sum += sample(x, y)
+                        * (((2 * x + 1) * u) as f64 * PI / 16.0).cos()
+                        * (((2 * y + 1) * v) as f64 * PI / 16.0).cos();
+                }
+            }
+            let value = sum * scale(u) * scale(v) / 4.0;
+            *coefficient = (value / f64::from(quantization(k))).round() as i32;
+        }
+    }
+    (blocks_x, blocks)
+}
+
+fn start_of_scan(out: &mut Vec<u8>, components: &[usize], start: u8, end: u8) {
+    out.extend_from_slice(&[0xFF, 0xDA]);
+    out.extend_from_slice(&(6 + 2 * components.len() as u16).to_be_bytes());
+    out.push(components.len() as u8);
+
+    for i in components {
+        out.extend_from_slice(&[*i as u8 + 1, 0]);
+    }
+    out.extend_from_slice(&[start, end, 0]);
+}
+
+/// Encode a 12 bit image as an extended sequential or progressive jpeg
+fn encode(settings: Settings) -> Vec<u8> {
+    let components = settings.components;
+    let blocks: Vec<_> = (0..components).map(|c| blocks(settings, c)).collect();
+
+    let mut out = vec![0xFF, 0xD8];
+    // 16 bit quantization table, which 12 bit images may use
+    out.extend_from_slice(&[0xFF, 0xDB, 0, 131, 0x10]);
+
+    for k in 0..64 {
+        out.extend_from_slice(&(quantization(k) as u16).to_be_bytes());
+    }
+    // start of frame
+    let sof = if settings.progressive { 0xC2 } else { 0xC1 };
+    out.extend_from_slice(&[0xFF, sof]);
+    out.extend_from_slice(&(8 + 3 * components as u16).to_be_bytes());
+    out.push(12);
+    out.extend_from_slice(&(settings.height as u16).to_be_bytes());
+    out.extend_from_slice(&(settings.width as u16).to_be_bytes());
+    out.push(components as u8);
+
+    for i in 0..components {
+        let sampling = settings.sampling(i) as u8;
+        out.extend_from_slice(&[i as u8 + 1, (sampling << 4) | sampling, 0]);
+    }
+    // huffman tables
+    let symbols = ac_symbols();
+
+    out.extend_from_slice(&[0xFF, 0xC4, 0, 35, 0x00]);
+    out.extend_from_slice(&[0, 0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    out.extend(0..16);
+
+    out.extend_from_slice(&[0xFF, 0xC4]);
+    out.extend_from_slice(&(19 + symbols.len() as u16).to_be_bytes());
+    out.push(0x10);
+    out.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, symbols.len() as u8]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&symbols);
+
+    let mcus_x = settings.width.div_ceil(8 * settings.sampling(0));
+    let mcus_y = settings.height.div_ceil(8 * settings.sampling(0));
+
+    // blocks of each MCU, with their component
+    let mut mcu_order = vec![];
+
+    if components == 1 {
+        // non-interleaved scans only cover the component
+        let (width, height) = settings.dimensions(0);
+
+        for y in 0..height.div_ceil(8) {
+            for x in 0..width.div_ceil(8) {
+                mcu_order.push((0, y * blocks[0].0 + x));
+            }
+        }
+    } else {
+        for mcu_y in 0..mcus_y {
+            for mcu_x in 0..mcus_x {
+                for (c, (blocks_x, _)) in blocks.iter().enumerate() {
+                    let sampling = settings.sampling(c);
+
+                    for v in 0..sampling {
+                        for h in 0..sampling {
+                            let y = mcu_y * sampling + v;
+                            let x = mcu_x * sampling + h;
+                            mcu_order.push((c, y * blocks_x + x));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    let all: Vec<usize> = (0..components).collect();
+    let mut writer = BitWriter::default();
+    let mut predictions = vec![0; components];
+
+    start_of_scan(&mut out, &all, 0, if settings.progressive { 0 } else { 63 });
+
+    for (c, block) in mcu_order {
+        let block = &blocks[c].1[block];
+        let difference = block[0] - predictions[c];
+
+        writer.put_value(dc_code(difference), 5, difference);
+        predictions[c] = block[0];
+
+        if !settings.progressive {
+            encode_ac(&mut writer, block, 1);
+        }
+    }
+    writer.flush();
+    out.extend_from_slice(&writer.out);
+
+    if settings.progressive {
+        for (c, (blocks_x, blocks)) in blocks.iter().enumerate() {
+            let (width, height) = settings.dimensions(c);
+            let mut writer = BitWriter::default();
+
+            start_of_scan(&mut out, &[c], 1, 63);
+
+            for y in 0..height.div_ceil(8) {
+                for x in 0..width.div_ceil(8) {
+                    encode_ac(&mut writer, &blocks[y * blocks_x + x], 1);
+                }
+            }
+            writer.flush();
+            out.extend_from_slice(&writer.out);
+        }
+    }
+    out.extend_from_slice(&[0xFF, 0xD9]);
+    out
+}
+
+fn decode(data: &[u8], colorspace: ColorSpace) -> Vec<u16> {
+    let options = DecoderOptions::default()
+        .set_strict_mode(true)
+        .jpeg_set_out_colorspace(colorspace);
+
+    JpegDecoder::new_with_options(ZCursor::new(data), options)
+        .decode_u16()
+        .unwrap()
+}
+
+/// Check that the decoded components are within `tolerance` of the samples
+fn check(settings: Settings, pixels: &[u16], tolerance: i32) {
+    let components = settings.components;
+
+    for c in 0..components {
+        let expected = samples(settings, c);
+        let (width, _) = settings.dimensions(c);
+        let scale = settings.sampling(0) / settings.sampling(c);
+
+        for (i, pixel) in pixels.iter().skip(c).step_by(components).enumerate() {
+            let (x, y) = (i % settings.width / scale, i / settings.width / scale);
+            let difference = (i32::from(*pixel) - expected[y * width + x]).abs();
+
+            assert!(
+                difference <= tolerance,
+                "component {c} at {i} differs by {difference}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_12bit_sequential() {
+    for components in [1, 3] {
+        let settings = Settings {
+            components,
+            ..Default::default()
+        };
+        let colorspace = if components == 1 { ColorSpace::Luma } else { ColorSpace::YCbCr };
+        check(settings, &decode(&encode(settings), colorspace), 3);
+    }
+}
+
+#[test]
+fn test_12bit_progressive() {
+    for components in [1, 3] {
+        let settings = Settings {
+            components,
+            progressive: true,
+            ..Default::default()
+        };
+        let colorspace = if components == 1 { ColorSpace::Luma } else { ColorSpace::YCbCr };
+        check(settings, &decode(&encode(settings), colorspace), 3);
+    }
+}
+
+#[test]
+fn test_12bit_subsampled() {
+    for progressive in [false, true] {
+        let settings = Settings {
+            components: 3,
+            subsampled: true,
+            progressive,
+            ..Default::default()
+        };
+        // the chroma samples are interpolated between their neighbours
+        check(settings, &decode(&encode(settings), ColorSpace::YCbCr), 20);
+    }
+}
+
+#[test]
+fn test_12bit_rgb() {
+    let settings = Settings {
+        components: 3,
+        ..Default::default()
+    };
+    let data = encode(settings);
+    let ycbcr = decode(&data, ColorSpace::YCbCr);
+    let rgb = decode(&data, ColorSpace::RGB);
+
+    for (ycbcr, rgb) in ycbcr.chunks_exact(3).zip(rgb.chunks_exact(3)) {
+        let [y, cb, cr] = [0, 1, 2].map(|i| f64::from(ycbcr[i]));
+        let expected = [
+            y + 1.402 * (cr - 2048.0),
+            y - 0.344136 * (cb - 2048.0) - 0.714136 * (cr - 2048.0),
+            y + 1.772 * (cb - 2048.0)
+        ];
+        for (expected, rgb) in expected.iter().zip(rgb) {
+            assert!((expected.clamp(0.0, 4095.0) - f64::from(*rgb)).abs() <= 1.0);
+        }
+    }
+}
+
+#[test]
+fn test_12bit_u8_output() {
+    let settings = Settings {
+        components: 3,
+        subsampled: true,
+        ..Default::default()
+    };
+    let data = encode(settings);
+
+    let mut decoder = JpegDecoder::new(ZCursor::new(&data));
+    let pixels = decoder.decode().unwrap();
+
+    assert_eq!(decoder.precision(), Some(12));
+    assert_eq!(decoder.depth(), Some(BitDepth::Sixteen));
+
+    let samples = decode(&data, ColorSpace::RGB);
+
+    assert!(pixels
+        .iter()
+        .zip(&samples)
+        .all(|(a, b)| u16::from(*a) == *b >> 4));
+}
+
+#[test]
+fn test_12bit_scaled() {
+    let settings = Settings::default();
+    let data = encode(settings);
+    let full = decode(&data, ColorSpace::Luma);
+
+    let options = DecoderOptions::default()
+        .set_strict_mode(true)
+        .jpeg_set_scale_denom(2);
+    let scaled = JpegDecoder::new_with_options(ZCursor::new(&data), options)
+        .decode_u16()
+        .unwrap();
+
+    let (width, height) = (settings.width, settings.height);
+    assert_eq!(scaled.len(), width.div_ceil(2) * height.div_ceil(2));
+
+    for (i, pixel) in scaled.iter().enumerate() {
+        let (x, y) = (i % width.div_ceil(2) * 2, i / width.div_ceil(2) * 2);
+        let (mut sum, mut count) = (0, 0);
+
+        for sy in y..(y + 2).min(height) {
+            for sx in x..(x + 2).min(width) {
+                sum += i32::from(full[sy * width + sx]);
+                count += 1;
+            }
+        }
+        let difference = (i32::from(*pixel) - sum / count).abs();
+        assert!(difference <= 3, "sample {i} differs by {difference}");
+    }
+}
+
+#[test]
+fn test_12bit_references() {
+    // 2x2 subsampled with restarts, 2x1 subsampled progressive and grayscale,
+    // libjpeg uses other IDCT and upsampling approximations
+    for (name, reference, colorspace, tolerance) in [
+        (
+            "twelve_bit_sequential.jpg",
+            "twelve_bit_sequential.ppm",
+            ColorSpace::RGB,
+            8
+        ),
+        (
+            "twelve_bit_progressive.jpg",
+            "twelve_bit_progressive.ppm",
+            ColorSpace::RGB,
+            8
+        ),
+        (
+            "twelve_bit_grayscale.jpg",
+            "twelve_bit_grayscale.pgm",
+            ColorSpace::Luma,
+            2
+        )
+    ] {
+        let pixels = decode(&read_image(name), colorspace);
+        let expected = read_reference(reference);
+
+        assert_eq!(pixels.len(), expected.len());
+
+        for (i, (pixel, expected)) in pixels.iter().zip(&expected).enumerate() {
+            let difference = pixel.abs_diff(*expected);
+            assert!(
+                difference <= tolerance,
+                "{name} at {i} differs by {difference}"
+            );
+        }
+    }
+}
@@ -57,6 +57,12 @@ impl BlendOp {
             _ => Err(PngDecodeErrors::GenericStatic("Unknown blend operation"))
         }
     }
+    pub fn to_int(self) -> u8 {
+        match self {
+            BlendOp::Source => 0,
+            BlendOp::Over => 1
+        }
+    }
 }
 
 impl DisposeOp {
@@ -68,6 +74,13 @@ impl DisposeOp {
             _ => Err(PngDecodeErrors::GenericStatic("Unknown blend operation"))
         }
     }
+    pub fn to_int(self) -> u8 {
+        match self {
+            DisposeOp::None => 0,
+            DisposeOp::Background => 1,
+            DisposeOp::Previous => 2
+        }
+    }
 }
 
 /// Describes a single frame
@@ -10,21 +10,25 @@ use core::cmp::min;
 
 use zune_core::bit_depth::{BitDepth, ByteEndian};
 use zune_core::bytestream::{ZByteReaderTrait, ZReader};
-use zune_core::colorspace::ColorSpace;
-use zune_core::log::trace;
+use zune_core::colorspace::{
+    CodingIndependentCodePoints, ColorSpace, ContentLightLevel, MasteringDisplayColorVolume,
+    RenderingIntent
+};
+use zune_core::log::{trace, warn};
 use zune_core::options::DecoderOptions;
 use zune_core::result::DecodingResult;
-use zune_inflate::DeflateOptions;
+use zune_inflate::{DeflateFormat, DeflateOptions, DeflateStreamDecoder, StreamStatus};
 
 use crate::apng::{ActlChunk, FrameInfo, SingleFrame};
 use crate::constants::PNG_SIGNATURE;
 use crate::enums::{FilterMethod, InterlaceMethod, PngChunkType, PngColor};
-use crate::error::PngDecodeErrors;
 use crate::error::PngDecodeErrors::GenericStatic;
+use crate::error::{PngDecodeErrors, PngErrorReport};
 use crate::filters::de_filter::{
     handle_avg, handle_avg_first, handle_paeth, handle_paeth_first, handle_sub, handle_up
 };
 use crate::options::default_chunk_handler;
+use crate::rows::{image_passes, Pass};
 use crate::utils::{
     add_alpha, convert_be_to_target_endian_u16, convert_u16_to_u8_slice, expand_bits_to_byte,
     expand_palette, expand_trns, is_le
@@ -34,11 +38,12 @@ use crate::utils::{
 ///
 /// The alpha field is used if the image has a tRNS
 /// chunk and pLTE chunk.
-#[derive(Copy, Clone, Debug)]
-pub(crate) struct PLTEEntry {
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PLTEEntry {
     pub red:   u8,
     pub green: u8,
     pub blue:  u8,
+    /// Alpha from the tRNS chunk, 255 for entries it doesn't cover
     pub alpha: u8
 }
 
@@ -108,6 +113,34 @@ pub struct ZtxtChunk {
     pub text:    Vec<u8>
 }
 
+/// cHRM details
+///
+/// CIE 1931 x and y chromaticities of the white point
+/// and the red, green and blue primaries
+///
+/// Extracted from cHRM chunk where present
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ChrmChunk {
+    pub white_point: (f32, f32),
+    pub red:         (f32, f32),
+    pub green:       (f32, f32),
+    pub blue:        (f32, f32)
+}
+
+/// pHYs details
+///
+/// Intended pixel size, or pixel aspect ratio if
+/// the unit is unknown
+///
+/// Extracted from pHYs chunk where present
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct PhysChunk {
+    pub x_pixels_per_unit: u32,
+    pub y_pixels_per_unit: u32,
+    /// Whether the unit is the meter, otherwise it's unknown
+    pub unit_is_meter:     bool
+}
+
 /// Represents PNG information that can be extracted
 /// from a png file.
 #[derive(Default, Clone)]
@@ -132,6 +165,18 @@ pub struct PngInfo {
     pub ztxt_chunk:           Vec<ZtxtChunk>,
     /// tEXt chunk
     pub text_chunk:           Vec<TextChunk>,
+    /// Chromaticities of the primaries and white point
+    pub chromaticities:       Option<ChrmChunk>,
+    /// Rendering intent, present if the image is in the sRGB colorspace
+    pub srgb_intent:          Option<RenderingIntent>,
+    /// Coding independent code points, present for e.g HDR images
+    pub cicp:                 Option<CodingIndependentCodePoints>,
+    /// Mastering display color volume
+    pub mastering_display:    Option<MasteringDisplayColorVolume>,
+    /// Content light level information
+    pub content_light_level:  Option<ContentLightLevel>,
+    /// Physical pixel dimensions
+    pub physical_dimensions:  Option<PhysChunk>,
     // no need to expose these ones
     pub(crate) depth:         u8,
     // use bit_depth
@@ -165,6 +210,7 @@ where
     pub(crate) options:                 DecoderOptions,
     pub(crate) png_info:                PngInfo,
     pub(crate) palette:                 Vec<PLTEEntry>,
+    pub(crate) palette_len:             usize,
     pub(crate) frames:                  Vec<SingleFrame>,
     pub(crate) actl_info:               Option<ActlChunk>,
     pub(crate) previous_stride:         Vec<u8>,
@@ -175,7 +221,10 @@ where
     pub(crate) seen_trns:               bool,
     pub(crate) seen_iend:               bool,
     pub(crate) current_frame:           usize,
-    pub(crate) called_from_decode_into: bool
+    pub(crate) called_from_decode_into: bool,
+    /// Offset of the last chunk whose header was read
+    pub(crate) chunk_start:             usize,
+    pub(crate) error_report:            Option<PngErrorReport>
 }
 
 impl<T: ZByteReaderTrait> PngDecoder<T> {
@@ -210,6 +259,7 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
             stream:                  ZReader::new(data),
             options:                 options,
             palette:                 Vec::new(),
+            palette_len:             0,
             png_info:                PngInfo::default(),
             actl_info:               None,
             previous_stride:         vec![],
@@ -220,7 +270,9 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
             seen_iend:               false,
             trns_bytes:              [0; 4],
             current_frame:           0,
-            called_from_decode_into: true
+            called_from_decode_into: true,
+            chunk_start:             0,
+            error_report:            None
         }
     }
 
@@ -274,6 +326,9 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
         if !self.seen_hdr {
             return None;
         }
+        if self.is_indexed_output() {
+            return Some(ColorSpace::Luma);
+        }
         if self.options.png_get_add_alpha_channel() {
             return match self.png_info.color {
                 PngColor::Luma | PngColor::LumaA => Some(ColorSpace::LumaA),
@@ -303,6 +358,32 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
             }
         }
     }
+    /// Return the palette of the image
+    ///
+    /// The alpha of every entry comes from the tRNS chunk, entries
+    /// it doesn't cover are opaque.
+    ///
+    /// RGB images may also contain a palette suggesting colors to use on
+    /// displays with few colors, it is returned too.
+    ///
+    /// To get pixels as indexes into this palette instead of colors, set
+    /// [`DecoderOptions::png_set_keep_palette`](zune_core::options::DecoderOptions::png_set_keep_palette)
+    ///
+    /// # Returns
+    /// - `Some(palette)`: The palette entries, as many as the PLTE chunk contains
+    /// - `None`: The headers haven't been decoded or the image has no palette
+    pub fn palette(&self) -> Option<&[PLTEEntry]> {
+        if !self.seen_ptle {
+            return None;
+        }
+        Some(&self.palette[..self.palette_len])
+    }
+
+    /// Return true if palette indexes are returned instead of colors
+    pub(crate) const fn is_indexed_output(&self) -> bool {
+        self.options.png_get_keep_palette() && matches!(self.png_info.color, PngColor::Palette)
+    }
+
     /// Returns true if the image is animated
     ///
     /// # Note
@@ -324,6 +405,7 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
     }
 
     pub(crate) fn read_chunk_header(&mut self) -> Result<PngChunk, PngDecodeErrors> {
+        self.chunk_start = self.stream.position()? as usize;
         // Format is length - chunk type - [data] -  crc chunk, load crc chunk now
         let chunk_length = self.stream.get_u32_be_err()? as usize;
         let chunk_type_int = self.stream.get_u32_be_err()?.to_be_bytes();
@@ -343,6 +425,11 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
             b"IDAT" => PngChunkType::IDAT,
             b"IEND" => PngChunkType::IEND,
             b"pHYs" => PngChunkType::pHYs,
+            b"cHRM" => PngChunkType::cHRM,
+            b"sRGB" => PngChunkType::sRGB,
+            b"cICP" => PngChunkType::cICP,
+            b"mDCV" => PngChunkType::mDCV,
+            b"cLLI" => PngChunkType::cLLI,
             b"tIME" => PngChunkType::tIME,
             b"gAMA" => PngChunkType::gAMA,
             b"acTL" => PngChunkType::acTL,
@@ -368,7 +455,16 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
             let calc_crc = !crc32_slice8(bytes, u32::MAX);
 
             if crc != calc_crc {
-                return Err(PngDecodeErrors::BadCrc(crc, calc_crc));
+                let error = PngDecodeErrors::BadCrc(crc, calc_crc);
+
+                if self.options.strict_mode()
+                    || !matches!(chunk_type, PngChunkType::IDAT | PngChunkType::fdAT)
+                {
+                    return Err(error);
+                }
+                // keep corrupt image data, it's decoded up to the first zlib error
+                warn!("Recovering from error {:?}", error);
+                self.report_error(error, Some(self.chunk_start));
             }
             // go point after the chunk type
             // The other parts expect the bit-reader to point to the
@@ -394,23 +490,21 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
             return Ok(());
         }
         if !self.seen_hdr {
-            // READ PNG signature
-            let signature = self.stream.get_u64_be_err()?;
-
-            if signature != PNG_SIGNATURE {
-                return Err(PngDecodeErrors::BadSignature);
-            }
-            // check if first chunk is ihdr here
-            if self.stream.peek_at(4, 4)? != b"IHDR" {
-                return Err(PngDecodeErrors::GenericStatic(
-                    "First chunk not IHDR, Corrupt PNG"
-                ));
-            }
+            self.decode_signature()?;
         }
         loop {
-            let header = self.read_chunk_header()?;
+            let header = match self.read_chunk_header() {
+                Ok(header) => header,
+                Err(error) => {
+                    self.recover_from_chunk_error(error)?;
+                    break;
+                }
+            };
 
-            self.parse_header(header)?;
+            if let Err(error) = self.parse_header(header) {
+                self.recover_from_chunk_error(error)?;
+                break;
+            }
 
             if header.chunk_type == PngChunkType::IEND {
                 break;
@@ -425,6 +519,93 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
         Ok(())
     }
 
+    /// Handle an error found while reading chunks
+    ///
+    /// In strict mode, or if no image data was read yet, this returns the error.
+    /// Otherwise, the error is kept in the error report and whatever was read is
+    /// decoded, including what's left of a truncated IDAT chunk.
+    fn recover_from_chunk_error(&mut self, error: PngDecodeErrors) -> Result<(), PngDecodeErrors> {
+        if self.options.strict_mode() || self.frames.is_empty() {
+            return Err(error);
+        }
+        if matches!(error, PngDecodeErrors::IoErrors(_)) {
+            // the file ends early, keep the image data in the
+            // last chunk, if it's an IDAT chunk
+            self.stream.set_position(self.chunk_start)?;
+
+            let mut header = [0; 8];
+
+            if self.stream.read_bytes(&mut header)? == 8 && &header[4..] == b"IDAT" {
+                let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+
+                let chunk = self.frames[0].chunk_mut();
+                let start = chunk.len();
+                let mut buffer = [0; 4096];
+
+                loop {
+                    let bytes_read = self.stream.read_bytes(&mut buffer)?;
+
+                    if bytes_read == 0 {
+                        break;
+                    }
+                    chunk.extend_from_slice(&buffer[..bytes_read]);
+                }
+                // part of the crc may be present
+                chunk.truncate(start + length);
+            }
+        }
+        match self.frames.get(self.current_frame) {
+            Some(frame) if !frame.fdat.is_empty() => {}
+            // nothing to decode
+            _ => return Err(error)
+        }
+        warn!("Recovering from error {:?}", error);
+
+        self.report_error(error, Some(self.chunk_start));
+        // nothing can be read after the error
+        self.seen_iend = true;
+        Ok(())
+    }
+
+    /// Keep the first error the decoder recovered from
+    fn report_error(&mut self, error: PngDecodeErrors, position: Option<usize>) {
+        if self.error_report.is_none() {
+            self.error_report = Some(PngErrorReport {
+                error,
+                position,
+                rows_decoded: 0,
+                total_rows: 0
+            });
+        }
+    }
+
+    /// Return a report of the error the decoder recovered from, if any
+    ///
+    /// When not in [strict mode](zune_core::options::DecoderOptions::set_strict_mode),
+    /// decoding a truncated file or one with corrupt image data returns the rows decoded
+    /// before the error, the remaining rows are zero. This returns what went wrong and
+    /// where, `None` indicates the image was decoded without errors.
+    pub fn error_report(&self) -> Option<&PngErrorReport> {
+        self.error_report.as_ref()
+    }
+
+    /// Read the PNG signature and check that the first chunk is IHDR
+    pub(crate) fn decode_signature(&mut self) -> Result<(), PngDecodeErrors> {
+        // READ PNG signature
+        let signature = self.stream.get_u64_be_err()?;
+
+        if signature != PNG_SIGNATURE {
+            return Err(PngDecodeErrors::BadSignature);
+        }
+        // check if first chunk is ihdr here
+        if self.stream.peek_at(4, 4)? != b"IHDR" {
+            return Err(PngDecodeErrors::GenericStatic(
+                "First chunk not IHDR, Corrupt PNG"
+            ));
+        }
+        Ok(())
+    }
+
     pub(crate) fn parse_header(&mut self, header: PngChunk) -> Result<(), PngDecodeErrors> {
         match header.chunk_type {
             PngChunkType::IHDR => {
@@ -448,6 +629,24 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
             PngChunkType::tIME => {
                 self.parse_time(header)?;
             }
+            PngChunkType::cHRM => {
+                self.parse_chrm(header)?;
+            }
+            PngChunkType::sRGB => {
+                self.parse_srgb(header)?;
+            }
+            PngChunkType::cICP => {
+                self.parse_cicp(header)?;
+            }
+            PngChunkType::mDCV => {
+                self.parse_mdcv(header)?;
+            }
+            PngChunkType::cLLI => {
+                self.parse_clli(header)?;
+            }
+            PngChunkType::pHYs => {
+                self.parse_phys(header)?;
+            }
             PngChunkType::eXIf => {
                 self.parse_exif(header)?;
             }
@@ -641,13 +840,43 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
         let out = &mut out[..image_len];
 
         // go parse IDAT chunks returning the inflate
-        let deflate_data = self.inflate()?;
+        let mut deflate_data = match self.inflate() {
+            Ok(data) => data,
+            Err(PngDecodeErrors::ZlibDecodeErrors(error)) if !self.options.strict_mode() => {
+                warn!("Recovering from error {:?}", error);
+                self.report_error(PngDecodeErrors::ZlibDecodeErrors(error), None);
+                // keep what can be decoded before the error, a chunk
+                // holds the longest possible scanline
+                self.inflate_partial(self.png_info.width * 8 + 1)
+            }
+            Err(error) => return Err(error)
+        };
 
         // then release it, we no longer need it
         self.frames[self.current_frame].fdat = vec![];
         // remove idat chunks from memory
         // we are already done with them.
 
+        let passes = image_passes(info.width, info.height, &png_info);
+        let (rows_decoded, total_rows) = decoded_scanlines(&passes, &png_info, deflate_data.len());
+
+        if rows_decoded < total_rows && !self.options.strict_mode() {
+            self.report_error(
+                PngDecodeErrors::GenericStatic("Not enough pixels, image data ends early"),
+                None
+            );
+            // decode zeros in place of the missing data, the rows are cleared later
+            let image_len = passes
+                .iter()
+                .map(|p| p.scanline_size(&png_info) * p.height)
+                .sum();
+            deflate_data.resize(image_len, 0);
+        }
+        if let Some(report) = &mut self.error_report {
+            report.rows_decoded = rows_decoded;
+            report.total_rows = total_rows;
+        }
+
         if png_info.interlace_method == InterlaceMethod::Standard {
             // allocate out to be enough to hold raw decoded bytes
             let dims = self.frame_info().unwrap();
@@ -656,6 +885,10 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
         } else if png_info.interlace_method == InterlaceMethod::Adam7 {
             self.decode_interlaced(&deflate_data, out, &png_info, &info)?;
         }
+        if rows_decoded < total_rows {
+            let pixel_size = out.len() / (info.width * info.height);
+            clear_missing_scanlines(out, &passes, rows_decoded, info.width, pixel_size);
+        }
 
         // convert to set endian if need be
         if self.depth().unwrap() == BitDepth::Sixteen {
@@ -979,23 +1212,11 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
         let chunks = deflate_data.chunks_exact(chunk_size);
 
         // Begin doing loop un-filtering.
-        let width_stride = chunk_size - 1;
-
         let mut prev_row_start = 0;
         let mut first_row = true;
         let mut out_position = 0;
 
-        let mut will_post_process = self.seen_trns | self.seen_ptle | (info.depth < 8);
-
-        let add_alpha_channel =
-            self.options.png_get_add_alpha_channel() && (!self.png_info.color.has_alpha());
-
-        will_post_process |= add_alpha_channel;
-
-        if will_post_process && self.previous_stride.len() < out_chunk_size {
-            self.previous_stride.resize(out_chunk_size, 0);
-        }
-        let n_components = usize::from(info.color.num_components());
+        let will_post_process = self.needs_post_processing();
 
         for (i, in_stride) in chunks.take(height).enumerate() {
             // Split output into current and previous
@@ -1007,9 +1228,9 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
             current = &mut current[0..out_chunk_size];
 
             // get the previous row.
-            //Set this to a dummy to handle special case of first row, if we aren't in the first
+            // Set this to an empty slice to handle special case of first row, if we aren't in the first
             // row, we actually take the real slice a line down
-            let mut prev_row: &[u8] = &[0_u8];
+            let mut prev_row: &[u8] = &[];
 
             if !first_row {
                 // normal bit depth, use the previous row as normal
@@ -1019,251 +1240,201 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
 
             out_position += out_chunk_size;
 
-            // take filter
-            let filter_byte = in_stride[0];
-            // raw image bytes
-            let raw = &in_stride[1..];
+            unfilter_row(in_stride, prev_row, current, components, use_sse2, use_sse4)?;
+            first_row = false;
 
-            // get its type
-            let mut filter = FilterMethod::from_int(filter_byte)
-                .ok_or_else(|| PngDecodeErrors::Generic(format!("Unknown filter {filter_byte}")))?;
-
-            if first_row {
-                // match our filters to special filters for first row
-                // these special filters do not need the previous scanline and treat it
-                // as zero
-
-                if filter == FilterMethod::Paeth {
-                    filter = FilterMethod::PaethFirst;
-                }
-                if filter == FilterMethod::Up {
-                    // up for the first row becomes a memcpy
-                    filter = FilterMethod::None;
-                }
-                if filter == FilterMethod::Average {
-                    filter = FilterMethod::AvgFirst;
-                }
+            if will_post_process && i > 0 {
+                // run the post processor two scanlines behind so that we
+                // don't mess with any filters that require previous row
+                let to_filter_row = &mut prev[(i - 1) * out_chunk_size..(i) * out_chunk_size];
 
-                first_row = false;
+                self.post_process_row(to_filter_row, width, info)?;
             }
+        }
 
-            match filter {
-                FilterMethod::None => current[0..width_stride].copy_from_slice(raw),
+        if will_post_process && height > 0 {
+            // the last row has no row after it, so it's processed here
+            let to_filter_row = &mut out[(height - 1) * out_chunk_size..height * out_chunk_size];
 
-                FilterMethod::Average => handle_avg(prev_row, raw, current, components, use_sse4),
+            self.post_process_row(to_filter_row, width, info)?;
+        }
+        Ok(())
+    }
 
-                FilterMethod::Sub => handle_sub(raw, current, components, use_sse2),
+    /// Return true if de-filtered rows need to be expanded before
+    /// they are in the output colorspace
+    pub(crate) fn needs_post_processing(&self) -> bool {
+        if self.is_indexed_output() {
+            // only indexes less than 8 bits need unpacking
+            return self.png_info.depth < 8;
+        }
+        let add_alpha_channel =
+            self.options.png_get_add_alpha_channel() && (!self.png_info.color.has_alpha());
 
-                FilterMethod::Up => handle_up(prev_row, raw, current),
+        self.seen_trns | self.seen_ptle | (self.png_info.depth < 8) | add_alpha_channel
+    }
 
-                FilterMethod::Paeth => handle_paeth(prev_row, raw, current, components, use_sse4),
+    /// Expand a de-filtered row into the output colorspace in place
+    ///
+    /// `row` starts with the de-filtered bytes of a row `width` pixels wide and
+    /// must be large enough to hold the expanded row. Samples are expanded to
+    /// 8 bits, palette and tRNS entries are applied and an alpha channel is added
+    /// if requested
+    pub(crate) fn post_process_row(
+        &mut self, row: &mut [u8], width: usize, info: &PngInfo
+    ) -> Result<(), PngDecodeErrors> {
+        let n_components = usize::from(info.color.num_components());
+        let width_stride = (width * n_components * usize::from(info.depth)).div_ceil(8);
 
-                FilterMethod::PaethFirst => handle_paeth_first(raw, current, components),
+        let add_alpha_channel =
+            self.options.png_get_add_alpha_channel() && (!self.png_info.color.has_alpha());
 
-                FilterMethod::AvgFirst => handle_avg_first(raw, current, components),
+        if self.previous_stride.len() < row.len() {
+            self.previous_stride.resize(row.len(), 0);
+        }
 
-                FilterMethod::Unknown => unreachable!()
+        if self.is_indexed_output() {
+            // separate indexes less than 8 bits into a byte each
+            if info.depth < 8 {
+                self.previous_stride[..width_stride].copy_from_slice(&row[..width_stride]);
+
+                expand_bits_to_byte(
+                    width,
+                    usize::from(info.depth),
+                    1,
+                    true,
+                    &self.previous_stride,
+                    row
+                );
             }
+            return Ok(());
+        }
 
-            if will_post_process && i > 0 {
-                // run the post processor two scanlines behind so that we
-                // don't mess with any filters that require previous row
-
-                // read the row we are about to filter
-                let to_filter_row = &mut prev[(i - 1) * out_chunk_size..(i) * out_chunk_size];
-
-                if info.depth < 8 {
-                    // check if we will run any other transform
-                    let extra_transform = self.seen_ptle | self.seen_trns | add_alpha_channel;
-
-                    if extra_transform {
-                        // input data is  in_to_filter_row,
-                        // we write output to previous_stride
-                        // since other parts use previous_stride
-                        expand_bits_to_byte(
-                            width,
-                            usize::from(info.depth),
-                            n_components,
-                            self.seen_ptle,
-                            to_filter_row,
-                            &mut self.previous_stride
-                        )
-                    } else {
-                        // no extra transform, just depth upscaling, so let's
-                        // do that,
-
-                        // copy the row to a temporary space
-                        self.previous_stride[..width_stride]
-                            .copy_from_slice(&to_filter_row[..width_stride]);
-
-                        expand_bits_to_byte(
-                            width,
-                            usize::from(info.depth),
-                            n_components,
-                            self.seen_ptle,
-                            &self.previous_stride,
-                            to_filter_row
-                        )
-                    }
-                } else {
-                    // copy the row to a temporary space
-                    self.previous_stride[..width_stride]
-                        .copy_from_slice(&to_filter_row[..width_stride]);
-                }
-
-                if self.seen_trns && self.png_info.color != PngColor::Palette {
-                    // the expansion is a trns expansion
-                    // bytes are already in position, so finish the business
-
-                    if info.depth <= 8 {
-                        expand_trns::<false>(
-                            &self.previous_stride,
-                            to_filter_row,
-                            info.color,
-                            self.trns_bytes,
-                            info.depth
-                        );
-                    } else if info.depth == 16 {
-                        // Tested by test_palette_trns_16bit.
-                        expand_trns::<true>(
-                            &self.previous_stride,
-                            to_filter_row,
-                            info.color,
-                            self.trns_bytes,
-                            info.depth
-                        );
-                    }
-                }
-
-                if self.seen_ptle && self.png_info.color == PngColor::Palette {
-                    if self.palette.is_empty() {
-                        return Err(PngDecodeErrors::EmptyPalette);
-                    }
-                    let plte_entry: &[PLTEEntry; 256] = self.palette[..256].try_into().unwrap();
-
-                    // so now we have two things
-                    // the palette entries stored in self.previous_stride
-                    // the row to fill the palette sored in to_filter row,
-                    // so we can finally expand the entries
-
-                    if self.seen_trns | add_alpha_channel {
-                        // if tRNS chunk is present in paletted images, it contains
-                        // alpha byte values, so that means we create alpha data from
-                        // raw bytes
-
-                        // if we are to add alpha channel for palette images , we simply just
-                        // read four entries from the palette.
-                        //
-                        // The palette is set that the alpha channel is initialized as 255 for non alpha
-                        // images,
-                        expand_palette(&self.previous_stride, to_filter_row, plte_entry, 4);
-                    } else {
-                        // Normal expansion
-                        expand_palette(&self.previous_stride, to_filter_row, plte_entry, 3);
-                    }
-                } else if add_alpha_channel {
-                    // the image is a normal RGB/ Luma image, which we need to add the alpha channel
-                    // do it here
-                    add_alpha(
-                        &self.previous_stride,
-                        to_filter_row,
-                        self.png_info.color,
-                        self.depth().unwrap()
-                    );
-                }
+        if info.depth < 8 {
+            // check if we will run any other transform
+            let extra_transform = self.seen_ptle | self.seen_trns | add_alpha_channel;
+
+            if extra_transform {
+                // input data is  in row,
+                // we write output to previous_stride
+                // since other parts use previous_stride
+                expand_bits_to_byte(
+                    width,
+                    usize::from(info.depth),
+                    n_components,
+                    self.seen_ptle,
+                    row,
+                    &mut self.previous_stride
+                )
+            } else {
+                // no extra transform, just depth upscaling, so let's
+                // do that,
+
+                // copy the row to a temporary space
+                self.previous_stride[..width_stride].copy_from_slice(&row[..width_stride]);
+
+                expand_bits_to_byte(
+                    width,
+                    usize::from(info.depth),
+                    n_components,
+                    self.seen_ptle,
+                    &self.previous_stride,
+                    row
+                )
             }
+        } else {
+            // copy the row to a temporary space
+            self.previous_stride[..width_stride].copy_from_slice(&row[..width_stride]);
         }
 
-        if will_post_process {
-            for i in height..height + min(height, 1) {
-                let to_filter_row = &mut out[(i - 1) * out_chunk_size..i * out_chunk_size];
-
-                // check if we will run any other transform
-                let extra_transform = self.seen_ptle | self.seen_trns;
-
-                if info.depth < 8 {
-                    if extra_transform {
-                        // input data is  in_to_filter_row,
-                        // we write output to previous_stride
-                        // since other parts use previous_stride
-                        expand_bits_to_byte(
-                            width,
-                            usize::from(info.depth),
-                            n_components,
-                            self.seen_ptle,
-                            to_filter_row,
-                            &mut self.previous_stride
-                        )
-                    } else {
-                        // no extra transform, just depth upscaling, so let's
-                        // do that,
-
-                        // copy the row to a temporary space
-                        self.previous_stride[..width_stride]
-                            .copy_from_slice(&to_filter_row[..width_stride]);
-
-                        expand_bits_to_byte(
-                            width,
-                            usize::from(info.depth),
-                            n_components,
-                            self.seen_ptle,
-                            &self.previous_stride,
-                            to_filter_row
-                        )
-                    }
-                } else {
-                    // copy the row to a temporary space
-                    self.previous_stride[..width_stride]
-                        .copy_from_slice(&to_filter_row[..width_stride]);
-                }
-                if self.seen_trns && self.png_info.color != PngColor::Palette {
-                    // the expansion is a trns expansion
-                    // bytes are already in position, so finish the business
-
-                    if info.depth <= 8 {
-                        expand_trns::<false>(
-                            &self.previous_stride,
-                            to_filter_row,
-                            info.color,
-                            self.trns_bytes,
-                            info.depth
-                        );
-                    } else if info.depth == 16 {
-                        // Tested by test_palette_trns_16bit.
-                        expand_trns::<true>(
-                            &self.previous_stride,
-                            to_filter_row,
-                            info.color,
-                            self.trns_bytes,
-                            info.depth
-                        );
-                    }
-                }
-                if self.seen_ptle && self.png_info.color == PngColor::Palette {
-                    if self.palette.is_empty() {
-                        return Err(PngDecodeErrors::EmptyPalette);
-                    }
-
-                    let plte_entry: &[PLTEEntry; 256] = self.palette[..256].try_into().unwrap();
+        if self.seen_trns && self.png_info.color != PngColor::Palette {
+            // the expansion is a trns expansion
+            // bytes are already in position, so finish the business
+
+            if info.depth <= 8 {
+                expand_trns::<false>(
+                    &self.previous_stride,
+                    row,
+                    info.color,
+                    self.trns_bytes,
+                    info.depth
+                );
+            } else if info.depth == 16 {
+                // Tested by test_palette_trns_16bit.
+                expand_trns::<true>(
+                    &self.previous_stride,
+                    row,
+                    info.color,
+                    self.trns_bytes,
+                    info.depth
+                );
+            }
+        }
 
-                    if self.seen_trns | add_alpha_channel {
-                        expand_palette(&self.previous_stride, to_filter_row, plte_entry, 4);
-                    } else {
-                        expand_palette(&self.previous_stride, to_filter_row, plte_entry, 3);
-                    }
-                } else if add_alpha_channel {
-                    add_alpha(
-                        &self.previous_stride,
-                        to_filter_row,
-                        self.png_info.color,
-                        self.depth().unwrap()
-                    );
-                }
+        if self.seen_ptle && self.png_info.color == PngColor::Palette {
+            if self.palette.is_empty() {
+                return Err(PngDecodeErrors::EmptyPalette);
+            }
+            let plte_entry: &[PLTEEntry; 256] = self.palette[..256].try_into().unwrap();
+
+            // so now we have two things
+            // the palette entries stored in self.previous_stride
+            // the row to fill the palette sored in row,
+            // so we can finally expand the entries
+
+            if self.seen_trns | add_alpha_channel {
+                // if tRNS chunk is present in paletted images, it contains
+                // alpha byte values, so that means we create alpha data from
+                // raw bytes
+
+                // if we are to add alpha channel for palette images , we simply just
+                // read four entries from the palette.
+                //
+                // The palette is set that the alpha channel is initialized as 255 for non alpha
+                // images,
+                expand_palette(&self.previous_stride, row, plte_entry, 4);
+            } else {
+                // Normal expansion
+                expand_palette(&self.previous_stride, row, plte_entry, 3);
             }
+        } else if add_alpha_channel {
+            // the image is a normal RGB/ Luma image, which we need to add the alpha channel
+            // do it here
+            add_alpha(
+                &self.previous_stride,
+                row,
+                self.png_info.color,
+                self.depth().unwrap()
+            );
         }
         Ok(())
     }
 
+    /// Inflate image data up to the first error or the end of the data
+    ///
+    /// Unlike [`inflate`](Self::inflate), which may decode past the end of truncated
+    /// data, every byte returned was decoded from data present in the stream. The
+    /// data is inflated `chunk_size` bytes at a time, the streaming decoder hands out
+    /// everything it decoded before returning an error.
+    fn inflate_partial(&mut self, chunk_size: usize) -> Vec<u8> {
+        let input = &self.frames[self.current_frame].fdat;
+
+        let mut decoder = DeflateStreamDecoder::new(DeflateFormat::Zlib);
+        let mut chunk = vec![0; chunk_size];
+        let mut consumed = 0;
+        let mut out = vec![];
+
+        while let Ok(result) = decoder.decode(&input[consumed..], &mut chunk) {
+            consumed += result.consumed;
+            out.extend_from_slice(&chunk[..result.written]);
+
+            if result.status != StreamStatus::OutputFull {
+                break;
+            }
+        }
+        out
+    }
+
     /// Undo deflate decoding
     #[allow(clippy::manual_memcpy)]
     fn inflate(&mut self) -> Result<Vec<u8>, PngDecodeErrors> {
@@ -1304,3 +1475,97 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
             .map_err(PngDecodeErrors::ZlibDecodeErrors)
     }
 }
+
+/// Undo the filter of a single scanline
+///
+/// `in_stride` is the filter byte followed by the filtered bytes, `prev_row`
+/// holds the de-filtered bytes of the previous row, or is empty for the first row
+/// of an image or interlace pass, in which case the filters treat it as zero
+pub(crate) fn unfilter_row(
+    in_stride: &[u8], prev_row: &[u8], current: &mut [u8], components: usize, use_sse2: bool,
+    use_sse4: bool
+) -> Result<(), PngDecodeErrors> {
+    // take filter
+    let filter_byte = in_stride[0];
+    // raw image bytes
+    let raw = &in_stride[1..];
+
+    // get its type
+    let mut filter = FilterMethod::from_int(filter_byte)
+        .ok_or_else(|| PngDecodeErrors::Generic(format!("Unknown filter {filter_byte}")))?;
+
+    if prev_row.is_empty() {
+        // match our filters to special filters for first row
+        // these special filters do not need the previous scanline and treat it
+        // as zero
+
+        if filter == FilterMethod::Paeth {
+            filter = FilterMethod::PaethFirst;
+        }
+        if filter == FilterMethod::Up {
+            // up for the first row becomes a memcpy
+            filter = FilterMethod::None;
+        }
+        if filter == FilterMethod::Average {
+            filter = FilterMethod::AvgFirst;
+        }
+    }
+
+    match filter {
+        FilterMethod::None => current[..raw.len()].copy_from_slice(raw),
+
+        FilterMethod::Average => handle_avg(prev_row, raw, current, components, use_sse4),
+
+        FilterMethod::Sub => handle_sub(raw, current, components, use_sse2),
+
+        FilterMethod::Up => handle_up(prev_row, raw, current),
+
+        FilterMethod::Paeth => handle_paeth(prev_row, raw, current, components, use_sse4),
+
+        FilterMethod::PaethFirst => handle_paeth_first(raw, current, components),
+
+        FilterMethod::AvgFirst => handle_avg_first(raw, current, components),
+
+        FilterMethod::Unknown => unreachable!()
+    }
+    Ok(())
+}
+
+/// Count the scanlines present in `data_len` bytes of de-compressed image data,
+/// returning the number of complete scanlines and the number of scanlines in the image
+fn decoded_scanlines(passes: &[Pass], info: &PngInfo, data_len: usize) -> (usize, usize) {
+    let mut remaining = data_len;
+    let mut rows_decoded = 0;
+    let mut total_rows = 0;
+
+    for pass in passes {
+        let scanline_size = pass.scanline_size(info);
+        let rows = min(pass.height, remaining / scanline_size);
+
+        rows_decoded += rows;
+        total_rows += pass.height;
+        remaining -= rows * scanline_size;
+    }
+    (rows_decoded, total_rows)
+}
+
+/// Zero the pixels of scanlines after the first `rows_decoded` scanlines
+fn clear_missing_scanlines(
+    out: &mut [u8], passes: &[Pass], rows_decoded: usize, width: usize, pixel_size: usize
+) {
+    let mut remaining = rows_decoded;
+
+    for pass in passes {
+        let rows = min(pass.height, remaining);
+        remaining -= rows;
+
+        for row in rows..pass.height {
+            let y = pass.y_start + row * pass.y_step;
+
+            for i in 0..pass.width {
+                let start = (y * width + pass.x_start + i * pass.x_step) * pixel_size;
+                out[start..start + pixel_size].fill(0);
+            }
+        }
+    }
+}
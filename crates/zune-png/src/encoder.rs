@@ -4,29 +4,80 @@
  * This software is free software; You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
  */
 
+use alloc::borrow::Cow;
+use alloc::vec;
 use alloc::vec::Vec;
 
+use zune_core::bit_depth::BitDepth;
 use zune_core::bytestream::{ZByteIoError, ZByteWriterTrait, ZWriter};
+use zune_core::colorspace::{
+    CodingIndependentCodePoints, ColorSpace, ContentLightLevel, MasteringDisplayColorVolume,
+    RenderingIntent
+};
 use zune_core::options::EncoderOptions;
-use zune_inflate::DeflateEncoder;
+use zune_inflate::{DeflateEncoder, DeflateEncodingOptions};
 
+use crate::apng::{BlendOp, DisposeOp, FrameInfo};
 use crate::constants::PNG_SIGNATURE;
-use crate::decoder::PngChunk;
-use crate::enums::{FilterMethod, PngChunkType};
-use crate::filters::{choose_compression_filter, filter_scanline};
+use crate::decoder::{
+    ChrmChunk, ItxtChunk, PLTEEntry, PhysChunk, PngChunk, TextChunk, TimeInfo, ZtxtChunk
+};
+use crate::encoder::reduce::{reduce_image, ImageLayout};
+use crate::enums::{FilterMethod, FilterStrategy, InterlaceMethod, PngChunkType, PngColor};
+use crate::filters::filter_row;
 use crate::headers::writers::{
-    write_chunk, write_exif, write_gamma, write_header_fn, write_iend, write_ihdr
+    write_actl, write_chrm, write_chunk, write_cicp, write_clli, write_exif, write_fctl,
+    write_gamma, write_header_fn, write_iccp, write_iend, write_ihdr, write_itxt, write_mdcv,
+    write_phys, write_plte, write_srgb, write_text, write_time, write_trns, write_ztxt
 };
 
+mod reduce;
+
+/// Pixels of an animation frame and its frame control
+type Frame<'a> = (Cow<'a, [u8]>, FrameInfo);
+
+/// Effort at which the encoder tries hardest to make small files,
+/// higher values are treated the same
+const MAX_EFFORT: u8 = 9;
+
+/// Number of optimal parsing iterations used at maximum effort
+const OPTIMAL_ITERATIONS: usize = 15;
+
 #[derive(Default)]
 pub struct PngEncoder<'a> {
-    pub(crate) options:         EncoderOptions,
-    pub(crate) data:            &'a [u8],
-    pub(crate) row_filter:      FilterMethod,
-    pub(crate) encoded_chunks:  Vec<u8>,
-    pub(crate) filter_scanline: Vec<u8>,
-    pub(crate) gamma:           Option<f32>,
-    pub(crate) exif:            Option<&'a [u8]>
+    pub(crate) options:          EncoderOptions,
+    pub(crate) data:             &'a [u8],
+    pub(crate) row_filter:       FilterMethod,
+    pub(crate) encoded_chunks:   Vec<u8>,
+    pub(crate) filter_scanline:  Vec<u8>,
+    pub(crate) gamma:            Option<f32>,
+    pub(crate) exif:             Option<&'a [u8]>,
+    pub(crate) icc_profile:      Option<&'a [u8]>,
+    pub(crate) chromaticities:   Option<ChrmChunk>,
+    pub(crate) srgb_intent:      Option<RenderingIntent>,
+    pub(crate) cicp:             Option<CodingIndependentCodePoints>,
+    pub(crate) display_volume:   Option<MasteringDisplayColorVolume>,
+    pub(crate) content_light:    Option<ContentLightLevel>,
+    pub(crate) physical_dims:    Option<PhysChunk>,
+    pub(crate) time:             Option<TimeInfo>,
+    pub(crate) text_chunks:      Vec<TextChunk>,
+    pub(crate) ztxt_chunks:      Vec<ZtxtChunk>,
+    pub(crate) itxt_chunks:      Vec<ItxtChunk>,
+    pub(crate) interlace_method: InterlaceMethod,
+    pub(crate) filter_strategy:  Option<FilterStrategy>,
+    pub(crate) reduce_colors:    bool,
+    /// Palette the image data indexes into, set by the caller
+    pub(crate) palette:          Option<&'a [PLTEEntry]>,
+    pub(crate) layout:           ImageLayout,
+    /// Image pixels after color reduction, followed by those
+    /// of each animation frame
+    pub(crate) reduced_pixels:   Vec<u8>,
+    /// Frame control of the default image in an animation
+    pub(crate) frame_info:       Option<FrameInfo>,
+    /// Animation frames following the default image
+    pub(crate) frames:           Vec<(&'a [u8], FrameInfo)>,
+    pub(crate) num_plays:        u32,
+    pub(crate) crop_frames:      bool
 }
 
 impl<'a> PngEncoder<'a> {
@@ -37,11 +88,23 @@ impl<'a> PngEncoder<'a> {
     /// If you are encoding 16 bit data, it is expected that
     /// the data is laid  out in big endian (in order to avoid a
     /// potentially expensive clone and conversion step)
+    ///
+    /// # Options
+    ///
+    /// - If [`num_threads`](EncoderOptions::num_threads) is greater than 1,
+    ///   compression of large images is split across that many threads
+    /// - The [`effort`](EncoderOptions::effort) picks how rows are filtered,
+    ///   see [`FilterStrategy::from_effort`]
+    /// - At an [`effort`](EncoderOptions::effort) of 9 or more, the image data
+    ///   is compressed with optimal parsing, which is much slower but
+    ///   produces smaller files
     pub fn new(data: &'a [u8], options: EncoderOptions) -> PngEncoder<'a> {
         PngEncoder {
             options,
             data,
             row_filter: FilterMethod::None,
+            interlace_method: InterlaceMethod::Standard,
+            layout: ImageLayout::new(options.colorspace(), options.depth()),
             ..Default::default()
         }
     }
@@ -51,6 +114,238 @@ impl<'a> PngEncoder<'a> {
         self.exif = Some(exif);
     }
 
+    /// Add an ICC profile which will be encoded in an iCCP chunk
+    ///
+    /// An image with an ICC profile doesn't get an sRGB chunk,
+    /// see [`set_srgb_intent`](Self::set_srgb_intent)
+    pub fn add_icc_profile(&mut self, profile: &'a [u8]) {
+        self.icc_profile = Some(profile);
+    }
+
+    /// Set the gamma written in the gAMA chunk
+    ///
+    /// This is the value used to encode the image samples, e.g `1.0/2.2`,
+    /// same as the one returned by the decoder in [`PngInfo::gamma`](crate::PngInfo::gamma)
+    pub fn set_gamma(&mut self, gamma: f32) {
+        self.gamma = Some(gamma);
+    }
+
+    /// Set chromaticities of the image primaries and white point,
+    /// written in the cHRM chunk
+    pub fn set_chromaticities(&mut self, chromaticities: ChrmChunk) {
+        self.chromaticities = Some(chromaticities);
+    }
+
+    /// Mark the image as being in the sRGB colorspace with the given
+    /// rendering intent, this writes an sRGB chunk
+    ///
+    /// This is ignored if an ICC profile was added, as the two
+    /// should not both be present
+    pub fn set_srgb_intent(&mut self, intent: RenderingIntent) {
+        self.srgb_intent = Some(intent);
+    }
+
+    /// Set the coding independent code points written in the cICP chunk
+    ///
+    /// Decoders which understand the chunk use it in place of
+    /// an ICC profile, sRGB, gAMA and cHRM chunks, this is how
+    /// HDR images (PQ or HLG) are tagged
+    pub fn set_cicp(&mut self, cicp: CodingIndependentCodePoints) {
+        self.cicp = Some(cicp);
+    }
+
+    /// Set the mastering display color volume written in the mDCV chunk
+    pub fn set_mastering_display(&mut self, mastering_display: MasteringDisplayColorVolume) {
+        self.display_volume = Some(mastering_display);
+    }
+
+    /// Set the content light level written in the cLLI chunk
+    pub fn set_content_light_level(&mut self, content_light: ContentLightLevel) {
+        self.content_light = Some(content_light);
+    }
+
+    /// Set the physical pixel dimensions written in the pHYs chunk
+    pub fn set_physical_dimensions(&mut self, dimensions: PhysChunk) {
+        self.physical_dims = Some(dimensions);
+    }
+
+    /// Set the last modification time written in the tIME chunk
+    pub fn set_time(&mut self, time: TimeInfo) {
+        self.time = Some(time);
+    }
+
+    /// Add Latin-1 text which will be written in a tEXt chunk
+    ///
+    /// The keyword must be 1 to 79 bytes long and neither
+    /// the keyword nor the text may contain a null byte
+    pub fn add_text_chunk(&mut self, chunk: TextChunk) {
+        self.text_chunks.push(chunk);
+    }
+
+    /// Add Latin-1 text which will be compressed and written in a zTXt chunk
+    ///
+    /// The keyword must be 1 to 79 bytes long without null bytes
+    pub fn add_ztxt_chunk(&mut self, chunk: ZtxtChunk) {
+        self.ztxt_chunks.push(chunk);
+    }
+
+    /// Add UTF-8 text which will be written in an iTXt chunk,
+    /// with no language tag and no translated keyword
+    ///
+    /// The keyword must be 1 to 79 bytes long without null bytes
+    pub fn add_itxt_chunk(&mut self, chunk: ItxtChunk) {
+        self.itxt_chunks.push(chunk);
+    }
+
+    /// Set the interlace method used when writing the image
+    ///
+    /// [`InterlaceMethod::Adam7`] lets viewers show a coarse version of the image
+    /// before it has fully loaded, at the cost of a somewhat larger file.
+    ///
+    /// The default is [`InterlaceMethod::Standard`], i.e no interlacing,
+    /// `Unknown` is treated as `Standard`
+    pub fn set_interlace_method(&mut self, method: InterlaceMethod) {
+        self.interlace_method = method;
+    }
+
+    /// Write the image using the smallest color type and bit depth
+    /// which still represents it exactly
+    ///
+    /// When enabled, the encoder
+    /// - writes images with 256 or fewer colors as indexed images, with
+    ///   palette alpha stored in a tRNS chunk, using 1, 2 or 4 bit indexes
+    ///   when there are few enough colors
+    /// - writes color images whose pixels are all gray as grayscale,
+    ///   using 1, 2 or 4 bits if the gray values allow it
+    /// - removes alpha channels where every pixel is fully opaque
+    /// - writes 16 bit images as 8 bit if the low byte of each sample is
+    ///   equal to the high byte
+    ///
+    /// A decoder will give back the same pixel values, but possibly in a
+    /// different colorspace, e.g an RGBA image with no transparency
+    /// decodes as RGB
+    ///
+    /// The default is `false`
+    pub fn set_reduce_colors(&mut self, reduce: bool) {
+        self.reduce_colors = reduce;
+    }
+
+    /// Write an indexed image using `palette`
+    ///
+    /// The image passed to [`new`](Self::new) and the animation frames are then
+    /// palette indexes, in the Luma colorspace with a depth of eight bits.
+    /// The entries are written in the same order, their alpha is stored in
+    /// a tRNS chunk and indexes use 1, 2 or 4 bits if the palette is small enough.
+    ///
+    /// This writes back images decoded with
+    /// [`DecoderOptions::png_set_keep_palette`](zune_core::options::DecoderOptions::png_set_keep_palette),
+    /// e.g. after editing their palette.
+    /// [`set_reduce_colors`](Self::set_reduce_colors) is ignored when a palette is set.
+    ///
+    /// Encoding fails if the palette is empty, has more than 256 entries
+    /// or if an index is past its end
+    pub fn set_palette(&mut self, palette: &'a [PLTEEntry]) {
+        self.palette = Some(palette);
+    }
+
+    /// Set how the image passed to [`new`](Self::new) is shown in an animation,
+    /// and write an animated PNG
+    ///
+    /// The size and offset of `info` are ignored, the image always covers
+    /// the whole canvas. If `info.is_part_of_seq` is false, the image is
+    /// only shown by decoders which don't support APNG, and the animation
+    /// is made up of the frames added with [`add_frame`](Self::add_frame)
+    ///
+    /// If frames are added but this isn't called, the image is the first
+    /// frame of the animation, shown for no time
+    pub fn set_frame_info(&mut self, info: FrameInfo) {
+        self.frame_info = Some(info);
+    }
+
+    /// Add a frame to the animation, making the image an animated PNG
+    ///
+    /// `data` holds `info.width * info.height` pixels in the image colorspace
+    /// and depth, drawn at `info.x_offset` and `info.y_offset` of the canvas,
+    /// the frame must fit in the image.
+    ///
+    /// `info.seq_number` and `info.is_part_of_seq` are ignored, frames are
+    /// shown in the order they were added
+    pub fn add_frame(&mut self, data: &'a [u8], info: FrameInfo) {
+        self.frames.push((data, info));
+    }
+
+    /// Set the number of times an animation is played, 0 meaning forever
+    ///
+    /// The default is 0
+    pub fn set_num_plays(&mut self, num_plays: u32) {
+        self.num_plays = num_plays;
+    }
+
+    /// Write animation frames as the area which changed from the frame before
+    ///
+    /// This only applies to frames covering the whole canvas with
+    /// [`BlendOp::Source`], not disposed with [`DisposeOp::Background`],
+    /// that follow such a frame disposed with [`DisposeOp::None`],
+    /// for those the cropped frame displays exactly the same.
+    /// This is the case for animations stored as a list of fully drawn frames,
+    /// which often only change in a small area.
+    ///
+    /// The default is `false`
+    pub fn set_crop_frames(&mut self, crop: bool) {
+        self.crop_frames = crop;
+    }
+
+    /// Set how the filter of each row is picked
+    ///
+    /// By default this follows the encoder [`effort`](EncoderOptions::effort),
+    /// see [`FilterStrategy::from_effort`]
+    pub fn set_filter_strategy(&mut self, strategy: FilterStrategy) {
+        self.filter_strategy = Some(strategy);
+    }
+
+    /// The strategy used to filter rows
+    pub fn filter_strategy(&self) -> FilterStrategy {
+        self.filter_strategy
+            .unwrap_or_else(|| FilterStrategy::from_effort(self.options.effort()))
+    }
+
+    /// Whether pixels were converted to a smaller layout
+    fn is_reduced(&self) -> bool {
+        self.reduce_colors && self.palette.is_none() && self.layout.color != PngColor::Unknown
+    }
+
+    fn is_animated(&self) -> bool {
+        self.frame_info.is_some() || !self.frames.is_empty()
+    }
+
+    /// Frame control of the default image
+    fn default_frame_info(&self) -> FrameInfo {
+        let info = self.frame_info.unwrap_or(FrameInfo {
+            seq_number:     0,
+            width:          0,
+            height:         0,
+            x_offset:       0,
+            y_offset:       0,
+            delay_num:      0,
+            delay_denom:    0,
+            dispose_op:     DisposeOp::None,
+            blend_op:       BlendOp::Source,
+            is_part_of_seq: true
+        });
+        FrameInfo {
+            width: self.options.width(),
+            height: self.options.height(),
+            x_offset: 0,
+            y_offset: 0,
+            ..info
+        }
+    }
+
+    /// Number of frames in the animation, as written in the acTL chunk
+    pub(crate) fn num_animation_frames(&self) -> usize {
+        self.frames.len() + usize::from(self.default_frame_info().is_part_of_seq)
+    }
+
     pub fn encode_headers<T: ZByteWriterTrait>(
         &self, writer: &mut ZWriter<T>
     ) -> Result<(), ZByteIoError> {
@@ -65,9 +360,50 @@ impl<'a> PngEncoder<'a> {
         if self.exif.is_some() {
             write_header_fn(self, writer, b"eXIf", write_exif)?;
         }
+        if self.icc_profile.is_some() {
+            write_header_fn(self, writer, b"iCCP", write_iccp)?;
+        } else if self.srgb_intent.is_some() {
+            write_header_fn(self, writer, b"sRGB", write_srgb)?;
+        }
         if self.gamma.is_some() {
             write_header_fn(self, writer, b"gAMA", write_gamma)?;
         }
+        if self.chromaticities.is_some() {
+            write_header_fn(self, writer, b"cHRM", write_chrm)?;
+        }
+        if self.cicp.is_some() {
+            write_header_fn(self, writer, b"cICP", write_cicp)?;
+        }
+        if self.display_volume.is_some() {
+            write_header_fn(self, writer, b"mDCV", write_mdcv)?;
+        }
+        if self.content_light.is_some() {
+            write_header_fn(self, writer, b"cLLI", write_clli)?;
+        }
+        if self.physical_dims.is_some() {
+            write_header_fn(self, writer, b"pHYs", write_phys)?;
+        }
+        if self.time.is_some() {
+            write_header_fn(self, writer, b"tIME", write_time)?;
+        }
+        for chunk in &self.text_chunks {
+            write_header_fn(self, writer, b"tEXt", |_, w| write_text(chunk, w))?;
+        }
+        for chunk in &self.ztxt_chunks {
+            write_header_fn(self, writer, b"zTXt", |_, w| write_ztxt(chunk, w))?;
+        }
+        for chunk in &self.itxt_chunks {
+            write_header_fn(self, writer, b"iTXt", |_, w| write_itxt(chunk, w))?;
+        }
+        if !self.layout.palette.is_empty() {
+            write_header_fn(self, writer, b"PLTE", write_plte)?;
+        }
+        if !self.layout.transparency.is_empty() {
+            write_header_fn(self, writer, b"tRNS", write_trns)?;
+        }
+        if self.is_animated() {
+            write_header_fn(self, writer, b"acTL", write_actl)?;
+        }
         Ok(())
     }
 
@@ -88,6 +424,29 @@ impl<'a> PngEncoder<'a> {
                 self.data.len()
             ));
         }
+        self.check_text_chunks()?;
+
+        let frames = self.prepare_frames()?;
+
+        if let Some(palette) = self.palette {
+            self.layout = self.palette_layout(palette, &frames)?;
+        }
+
+        if self.is_reduced() {
+            // frames share the palette, so they are reduced together
+            let pixels = if frames.is_empty() {
+                Cow::Borrowed(self.data)
+            } else {
+                let mut pixels = self.data.to_vec();
+                frames
+                    .iter()
+                    .for_each(|(data, _)| pixels.extend_from_slice(data));
+                Cow::Owned(pixels)
+            };
+            let reduced = reduce_image(&pixels, self.options.colorspace(), self.options.depth());
+            self.layout = reduced.layout;
+            self.reduced_pixels = reduced.pixels;
+        }
         let mut writer = ZWriter::new(sink);
 
         self.encode_headers(&mut writer)?;
@@ -95,8 +454,46 @@ impl<'a> PngEncoder<'a> {
         // encode filters
         self.add_filters();
 
+        // sequence number shared by fcTL and fdAT chunks
+        let mut sequence = 0;
+
+        if self.is_animated() && self.default_frame_info().is_part_of_seq {
+            let info = self.default_frame_info();
+            write_header_fn(self, &mut writer, b"fcTL", |_, w| {
+                write_fctl(sequence, &info, w)
+            })?;
+            sequence += 1;
+        }
         self.write_idat_chunks(&mut writer)?;
 
+        let pixel_size = self.layout.unpacked_pixel_size();
+        let mut offset = self.options.width() * self.options.height() * pixel_size;
+
+        for (data, info) in &frames {
+            let pixels = if self.is_reduced() {
+                let size = info.width * info.height * pixel_size;
+                offset += size;
+                &self.reduced_pixels[offset - size..offset]
+            } else {
+                data
+            };
+            let compressed = compress_image(
+                pixels,
+                info.width,
+                &self.layout,
+                self.interlace_method,
+                self.filter_strategy(),
+                &self.options,
+                &mut self.filter_scanline
+            );
+            write_header_fn(self, &mut writer, b"fcTL", |_, w| {
+                write_fctl(sequence, info, w)
+            })?;
+            sequence += 1;
+
+            write_fdat_chunks(&compressed, &mut sequence, &mut writer)?;
+        }
+
         write_header_fn(self, &mut writer, b"IEND", write_iend)?;
 
         // let position = writer.position();
@@ -105,52 +502,147 @@ impl<'a> PngEncoder<'a> {
         Ok(writer.bytes_written())
     }
 
-    const fn calculate_scanline_size(&self) -> usize {
-        self.options.width()
-            * self.options.depth().size_of()
-            * self.options.colorspace().num_components()
+    /// Check keywords and text of text chunks can be written
+    fn check_text_chunks(&self) -> Result<(), ZByteIoError> {
+        let keywords = self
+            .text_chunks
+            .iter()
+            .map(|x| &x.keyword)
+            .chain(self.ztxt_chunks.iter().map(|x| &x.keyword))
+            .chain(self.itxt_chunks.iter().map(|x| &x.keyword));
+
+        for keyword in keywords {
+            if keyword.is_empty() || keyword.len() > 79 || keyword.contains(&0) {
+                return Err(ZByteIoError::Generic(
+                    "Text chunk keywords must be 1 to 79 bytes without null bytes"
+                ));
+            }
+        }
+        if self.text_chunks.iter().any(|x| x.text.contains(&0)) {
+            return Err(ZByteIoError::Generic(
+                "tEXt chunk text contains a null byte"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Layout of an image indexing into `palette`, checking the indexes
+    fn palette_layout(
+        &self, palette: &[PLTEEntry], frames: &[Frame<'a>]
+    ) -> Result<ImageLayout, ZByteIoError> {
+        if self.options.colorspace() != ColorSpace::Luma || self.options.depth() != BitDepth::Eight
+        {
+            return Err(ZByteIoError::Generic(
+                "Palette indexes must be 8 bit Luma samples"
+            ));
+        }
+        if palette.is_empty() || palette.len() > 256 {
+            return Err(ZByteIoError::Generic(
+                "The palette must have 1 to 256 entries"
+            ));
+        }
+        let mut indexes = frames
+            .iter()
+            .flat_map(|(data, _)| data.iter())
+            .chain(self.data);
+
+        if indexes.any(|x| usize::from(*x) >= palette.len()) {
+            return Err(ZByteIoError::Generic("Palette index out of range"));
+        }
+        let depth = match palette.len() {
+            0..=2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            _ => 8
+        };
+        // the tRNS chunk can stop after the last translucent entry
+        let num_translucent = palette
+            .iter()
+            .rposition(|x| x.alpha != 255)
+            .map_or(0, |x| x + 1);
+
+        Ok(ImageLayout {
+            color: PngColor::Palette,
+            depth,
+            palette: palette.iter().map(|x| [x.red, x.green, x.blue]).collect(),
+            transparency: palette[..num_translucent].iter().map(|x| x.alpha).collect()
+        })
     }
 
-    fn add_filters(&mut self) {
-        let scanline_length = (self.calculate_scanline_size() + 1)
-            .checked_mul(self.options.height())
-            .unwrap();
-        let components =
-            self.options.colorspace().num_components() * self.options.depth().size_of();
+    /// Check animation frames fit in the image, cropping them
+    /// to the area which changed if asked to
+    fn prepare_frames(&self) -> Result<Vec<Frame<'a>>, ZByteIoError> {
+        let (width, height) = (self.options.width(), self.options.height());
+        let pixel_size =
+            self.options.depth().size_of() * self.options.colorspace().num_components();
 
-        // allocate space for filtered scanline
-        self.filter_scanline.resize(scanline_length, 0);
+        let is_canvas = |info: &FrameInfo| {
+            info.x_offset == 0
+                && info.y_offset == 0
+                && info.width == width
+                && info.height == height
+                && info.blend_op == BlendOp::Source
+        };
+        // what the canvas holds after the previous frame, if it's known
+        let default_info = self.default_frame_info();
+        let mut canvas = (default_info.is_part_of_seq
+            && default_info.dispose_op == DisposeOp::None
+            && default_info.blend_op == BlendOp::Source)
+            .then_some(self.data);
 
-        // one row above the current processing row
-        let mut previous_scanline: &[u8] = &[];
+        let mut frames = Vec::with_capacity(self.frames.len());
 
-        let scanline_size = self.calculate_scanline_size();
+        for &(data, info) in &self.frames {
+            if info.width == 0 || info.height == 0 {
+                return Err(ZByteIoError::Generic(
+                    "Animation frame with zero width or height"
+                ));
+            }
+            if info.x_offset + info.width > width || info.y_offset + info.height > height {
+                return Err(ZByteIoError::Generic(
+                    "Animation frame larger than the image"
+                ));
+            }
+            let expected_size = info.width * info.height * pixel_size;
 
-        for (i, filter_s) in self
-            .filter_scanline
-            .chunks_exact_mut(scanline_size + 1)
-            .take(self.options.height())
-            .enumerate()
-        {
-            let (previous, current) = self.data.split_at(i * scanline_size);
+            if data.len() != expected_size {
+                return Err(ZByteIoError::NotEnoughBytes(expected_size, data.len()));
+            }
+            let mut frame = (Cow::Borrowed(data), info);
+
+            if is_canvas(&info) {
+                // background disposal clears the frame area, which must stay the whole canvas
+                let crop = self.crop_frames && info.dispose_op != DisposeOp::Background;
 
-            if i > 0 {
-                // previous row now becomes defined
-                previous_scanline = &previous[(i - 1) * scanline_size..];
+                if let (Some(previous), true) = (canvas, crop) {
+                    frame = crop_frame(previous, data, info, pixel_size);
+                }
+                canvas = (info.dispose_op == DisposeOp::None).then_some(data);
+            } else {
+                canvas = None;
             }
-            let current_scanline = &current[0..scanline_size];
-            let filter = choose_compression_filter(previous_scanline, current_scanline);
-
-            filter_scanline(
-                current_scanline,
-                previous_scanline,
-                filter_s,
-                filter,
-                components
-            );
+            frames.push(frame);
         }
-        // encode filtered scanline
-        self.encoded_chunks = DeflateEncoder::new(&self.filter_scanline).encode_zlib();
+        Ok(frames)
+    }
+
+    fn add_filters(&mut self) {
+        let pixels: &[u8] = if self.is_reduced() {
+            let size =
+                self.options.width() * self.options.height() * self.layout.unpacked_pixel_size();
+            &self.reduced_pixels[..size]
+        } else {
+            self.data
+        };
+        self.encoded_chunks = compress_image(
+            pixels,
+            self.options.width(),
+            &self.layout,
+            self.interlace_method,
+            self.filter_strategy(),
+            &self.options,
+            &mut self.filter_scanline
+        );
     }
     fn write_idat_chunks<T: ZByteWriterTrait>(
         &self, writer: &mut ZWriter<T>
@@ -172,6 +664,218 @@ impl<'a> PngEncoder<'a> {
     }
 }
 
+/// Write zlib data of an animation frame as fdAT chunks
+fn write_fdat_chunks<T: ZByteWriterTrait>(
+    data: &[u8], sequence: &mut u32, writer: &mut ZWriter<T>
+) -> Result<(), ZByteIoError> {
+    let mut fdat = Vec::with_capacity(8196);
+
+    for chunk in data.chunks(8192) {
+        // fdAT is IDAT data prefixed with a sequence number
+        fdat.clear();
+        fdat.extend_from_slice(&sequence.to_be_bytes());
+        fdat.extend_from_slice(chunk);
+
+        let chunk_type = PngChunk {
+            length:     fdat.len(),
+            chunk_type: PngChunkType::fdAT,
+            chunk:      *b"fdAT",
+            crc:        0
+        };
+        write_chunk(chunk_type, &fdat, writer)?;
+        *sequence += 1;
+    }
+    Ok(())
+}
+
+/// Crop a frame covering the whole canvas to the area where it
+/// differs from `previous`, the frame shown before it
+fn crop_frame<'a>(
+    previous: &[u8], current: &'a [u8], info: FrameInfo, pixel_size: usize
+) -> Frame<'a> {
+    let row_size = info.width * pixel_size;
+    let (mut left, mut right) = (info.width, 0);
+    let (mut top, mut bottom) = (info.height, 0);
+
+    for (y, (a, b)) in previous
+        .chunks_exact(row_size)
+        .zip(current.chunks_exact(row_size))
+        .enumerate()
+    {
+        let changed =
+            |x: &usize| a[x * pixel_size..][..pixel_size] != b[x * pixel_size..][..pixel_size];
+
+        if let Some(first) = (0..info.width).find(changed) {
+            let last = (first..info.width).rfind(changed).unwrap();
+
+            left = left.min(first);
+            right = right.max(last + 1);
+            top = top.min(y);
+            bottom = y + 1;
+        }
+    }
+    if top >= bottom {
+        // nothing changed, frames can't be empty so keep a single pixel
+        (left, right, top, bottom) = (0, 1, 0, 1);
+    }
+    let mut cropped = Vec::with_capacity((right - left) * (bottom - top) * pixel_size);
+
+    for row in current.chunks_exact(row_size).take(bottom).skip(top) {
+        cropped.extend_from_slice(&row[left * pixel_size..right * pixel_size]);
+    }
+    let info = FrameInfo {
+        width: right - left,
+        height: bottom - top,
+        x_offset: left,
+        y_offset: top,
+        ..info
+    };
+    (Cow::Owned(cropped), info)
+}
+
+/// Filter and compress an image into a zlib stream
+///
+/// `filtered` is scratch space for the filtered rows
+fn compress_image(
+    pixels: &[u8], width: usize, layout: &ImageLayout, interlace_method: InterlaceMethod,
+    mut strategy: FilterStrategy, options: &EncoderOptions, filtered: &mut Vec<u8>
+) -> Vec<u8> {
+    const XORIG: [usize; 7] = [0, 4, 0, 2, 0, 1, 0];
+    const YORIG: [usize; 7] = [0, 0, 4, 0, 2, 0, 1];
+
+    const XSPC: [usize; 7] = [8, 8, 4, 4, 2, 2, 1];
+    const YSPC: [usize; 7] = [8, 8, 8, 4, 4, 2, 2];
+
+    let pixel_size = layout.unpacked_pixel_size();
+    let scanline_size = width * pixel_size;
+
+    if (layout.depth < 8 || !layout.palette.is_empty())
+        && matches!(strategy, FilterStrategy::MinSum | FilterStrategy::Entropy)
+    {
+        // palette indexes and packed samples don't predict well from
+        // their neighbours, libpng also leaves them unfiltered
+        strategy = FilterStrategy::None;
+    }
+    filtered.clear();
+
+    if interlace_method == InterlaceMethod::Adam7 {
+        // each pass is a smaller image made up of every n-th pixel
+        // of every m-th row, filtered on its own
+        let mut pass_pixels = Vec::new();
+
+        for p in 0..7 {
+            let pass_width = width.saturating_sub(XORIG[p]).div_ceil(XSPC[p]);
+
+            if pass_width == 0 {
+                continue;
+            }
+            pass_pixels.clear();
+
+            for row in pixels
+                .chunks_exact(scanline_size)
+                .skip(YORIG[p])
+                .step_by(YSPC[p])
+            {
+                for pixel in row.chunks_exact(pixel_size).skip(XORIG[p]).step_by(XSPC[p]) {
+                    pass_pixels.extend_from_slice(pixel);
+                }
+            }
+            filter_image(&pass_pixels, pass_width, layout, strategy, filtered);
+        }
+    } else {
+        filter_image(pixels, width, layout, strategy, filtered);
+    }
+    // encode filtered scanline, splitting the work across threads
+    // if we are allowed to
+    let mut deflate_options =
+        DeflateEncodingOptions::default().set_num_threads(usize::from(options.num_threads()));
+
+    if options.effort() >= MAX_EFFORT {
+        // slow, but squeezes out the most
+        deflate_options = deflate_options.set_optimal_iterations(OPTIMAL_ITERATIONS);
+    }
+    DeflateEncoder::new_with_options(filtered, deflate_options).encode_zlib()
+}
+
+/// Filter an image, `pixels` holding one byte per sample
+/// (or two for 16 bit samples), packing samples smaller than a byte
+/// before filtering
+fn filter_image(
+    pixels: &[u8], width: usize, layout: &ImageLayout, strategy: FilterStrategy,
+    output: &mut Vec<u8>
+) {
+    if width == 0 {
+        return;
+    }
+    // filters work on whole bytes
+    let bytes_per_pixel = (layout.bits_per_pixel() / 8).max(1);
+
+    if layout.depth < 8 {
+        let depth = usize::from(layout.depth);
+        let packed_row_size = (width * depth).div_ceil(8);
+        let mut packed = Vec::with_capacity(pixels.len() / width * packed_row_size);
+
+        for row in pixels.chunks_exact(width) {
+            // first sample goes into the most significant bits
+            for samples in row.chunks(8 / depth) {
+                let mut byte = 0;
+
+                for (i, sample) in samples.iter().enumerate() {
+                    byte |= sample << (8 - depth * (i + 1));
+                }
+                packed.push(byte);
+            }
+        }
+        filter_rows(&packed, packed_row_size, bytes_per_pixel, strategy, output);
+    } else {
+        filter_rows(
+            pixels,
+            width * layout.unpacked_pixel_size(),
+            bytes_per_pixel,
+            strategy,
+            output
+        );
+    }
+}
+
+/// Filter rows of `data`, each `row_size` bytes long, appending the
+/// filter type and filtered bytes of each row to `output`
+fn filter_rows(
+    data: &[u8], row_size: usize, components: usize, strategy: FilterStrategy, output: &mut Vec<u8>
+) {
+    if row_size == 0 {
+        return;
+    }
+    let num_rows = data.len() / row_size;
+    let mut start = output.len();
+
+    // allocate space for filtered scanline
+    output.resize(start + (row_size + 1) * num_rows, 0);
+
+    // the row above the first row is all zeroes
+    let zeroes = vec![0; row_size];
+    let mut previous_scanline: &[u8] = &zeroes;
+
+    let mut scratch = Vec::new();
+
+    for current_scanline in data.chunks_exact(row_size) {
+        let (history, rest) = output.split_at_mut(start);
+
+        filter_row(
+            strategy,
+            previous_scanline,
+            current_scanline,
+            components,
+            history,
+            &mut rest[..row_size + 1],
+            &mut scratch
+        );
+        // current row becomes the previous row of the next one
+        previous_scanline = current_scanline;
+        start += row_size + 1;
+    }
+}
+
 #[test]
 fn test_simple_write() {
     use zune_core::bit_depth::BitDepth;
@@ -198,3 +902,456 @@ fn test_simple_write() {
     let bytes = hello.decode_raw().unwrap();
     assert_eq!(&data, &bytes);
 }
+
+#[test]
+fn test_interlaced_write() {
+    use zune_core::bit_depth::{BitDepth, ByteEndian};
+    use zune_core::bytestream::ZCursor;
+    use zune_core::colorspace::ColorSpace;
+    use zune_core::options::DecoderOptions;
+
+    use crate::PngDecoder;
+
+    // odd sizes leave some passes empty or partially filled
+    for (width, height) in [(1, 1), (3, 2), (13, 9), (40, 17)] {
+        let data: Vec<u8> = (0..width * height * 6)
+            .map(|x| (x * 7 % 253) as u8)
+            .collect();
+
+        let options = EncoderOptions::default()
+            .set_colorspace(ColorSpace::RGB)
+            .set_width(width)
+            .set_height(height)
+            .set_depth(BitDepth::Sixteen);
+
+        let mut encoder = PngEncoder::new(&data, options);
+        encoder.set_interlace_method(InterlaceMethod::Adam7);
+
+        let mut sink = vec![];
+        encoder.encode(&mut sink).unwrap();
+
+        let options = DecoderOptions::default().set_byte_endian(ByteEndian::BE);
+        let mut decoder = PngDecoder::new_with_options(ZCursor::new(&sink), options);
+        let bytes = decoder.decode_raw().unwrap();
+
+        assert_eq!(
+            decoder.info().unwrap().interlace_method,
+            InterlaceMethod::Adam7
+        );
+        assert_eq!(&data, &bytes, "{width}x{height} failed");
+    }
+}
+
+#[test]
+fn test_animated_write() {
+    use zune_core::bit_depth::BitDepth;
+    use zune_core::bytestream::ZCursor;
+    use zune_core::colorspace::ColorSpace;
+
+    use crate::PngDecoder;
+
+    let (width, height) = (20, 12);
+    let first = [40, 40, 40, 255].repeat(width * height);
+
+    // second frame changes a 3x2 area, third is drawn at an offset
+    let mut second = first.clone();
+    for (y, x) in [(5, 7), (6, 9)] {
+        second[(y * width + x) * 4..][..4].copy_from_slice(&[1, 2, 3, 255]);
+    }
+    let third = [200, 100, 0, 255].repeat(4 * 3);
+
+    let info = |width, height, x_offset, y_offset| FrameInfo {
+        seq_number: 0,
+        width,
+        height,
+        x_offset,
+        y_offset,
+        delay_num: 1,
+        delay_denom: 10,
+        dispose_op: DisposeOp::None,
+        blend_op: BlendOp::Source,
+        is_part_of_seq: true
+    };
+    // (frame info, expected decoded pixels)
+    let expected = [
+        (info(width, height, 0, 0), first.clone()),
+        (
+            info(3, 2, 7, 5),
+            [
+                &second[(5 * width + 7) * 4..][..12],
+                &second[(6 * width + 7) * 4..][..12]
+            ]
+            .concat()
+        ),
+        (info(4, 3, 2, 8), third.clone())
+    ];
+
+    for reduce in [false, true] {
+        let options = EncoderOptions::default()
+            .set_colorspace(ColorSpace::RGBA)
+            .set_width(width)
+            .set_height(height)
+            .set_depth(BitDepth::Eight);
+
+        let mut encoder = PngEncoder::new(&first, options);
+        encoder.set_frame_info(info(width, height, 0, 0));
+        encoder.add_frame(&second, info(width, height, 0, 0));
+        encoder.add_frame(&third, info(4, 3, 2, 8));
+        encoder.set_crop_frames(true);
+        encoder.set_reduce_colors(reduce);
+
+        let mut sink = vec![];
+        encoder.encode(&mut sink).unwrap();
+
+        let mut decoder = PngDecoder::new(ZCursor::new(&sink));
+        decoder.decode_headers().unwrap();
+
+        let mut frames = vec![];
+
+        while decoder.more_frames() {
+            decoder.decode_headers().unwrap();
+            let frame_info = decoder.frame_info().unwrap();
+            let mut pixels = decoder.decode_raw().unwrap();
+
+            if reduce {
+                // opaque frames decode as RGB, add alpha back
+                pixels = pixels
+                    .chunks_exact(3)
+                    .flat_map(|x| [x[0], x[1], x[2], 255])
+                    .collect();
+            }
+            frames.push((frame_info, pixels));
+        }
+        assert_eq!(frames.len(), expected.len());
+
+        for ((info, pixels), (expected_info, expected_pixels)) in frames.iter().zip(&expected) {
+            assert_eq!(
+                (info.width, info.height, info.x_offset, info.y_offset),
+                (
+                    expected_info.width,
+                    expected_info.height,
+                    expected_info.x_offset,
+                    expected_info.y_offset
+                )
+            );
+            assert_eq!((info.delay_num, info.delay_denom), (1, 10));
+            assert!(pixels == expected_pixels, "reduce: {reduce}");
+        }
+    }
+}
+
+#[test]
+fn test_animated_crop_dispose() {
+    use zune_core::bit_depth::BitDepth;
+    use zune_core::bytestream::ZCursor;
+    use zune_core::colorspace::ColorSpace;
+
+    use crate::PngDecoder;
+
+    /// Canvas displayed for every frame, frames are RGBA and drawn with `BlendOp::Source`
+    fn render(frames: &[(FrameInfo, Vec<u8>)], width: usize, height: usize) -> Vec<Vec<u8>> {
+        let mut canvas = vec![0; width * height * 4];
+        let mut displayed = vec![];
+
+        for (info, pixels) in frames {
+            let saved = canvas.clone();
+            let rows = canvas
+                .chunks_exact_mut(width * 4)
+                .skip(info.y_offset)
+                .take(info.height);
+
+            for (row, src) in rows.zip(pixels.chunks_exact(info.width * 4)) {
+                row[info.x_offset * 4..][..info.width * 4].copy_from_slice(src);
+            }
+            displayed.push(canvas.clone());
+
+            match info.dispose_op {
+                DisposeOp::None => {}
+                DisposeOp::Background => {
+                    for row in canvas
+                        .chunks_exact_mut(width * 4)
+                        .skip(info.y_offset)
+                        .take(info.height)
+                    {
+                        row[info.x_offset * 4..][..info.width * 4].fill(0);
+                    }
+                }
+                DisposeOp::Previous => canvas = saved
+            }
+        }
+        displayed
+    }
+
+    let (width, height) = (20, 12);
+    let first = [40, 40, 40, 255].repeat(width * height);
+
+    // second frame changes a single pixel but clears the whole canvas after it,
+    // so the pixels around the third frame are transparent
+    let mut second = first.clone();
+    second[(5 * width + 7) * 4..][..4].copy_from_slice(&[1, 2, 3, 255]);
+    let third = [200, 100, 0, 255].repeat(4 * 3);
+
+    let info = |width, height, x_offset, y_offset, dispose_op| FrameInfo {
+        seq_number: 0,
+        width,
+        height,
+        x_offset,
+        y_offset,
+        delay_num: 1,
+        delay_denom: 10,
+        dispose_op,
+        blend_op: BlendOp::Source,
+        is_part_of_seq: true
+    };
+    let frames = [
+        (info(width, height, 0, 0, DisposeOp::None), first.clone()),
+        (
+            info(width, height, 0, 0, DisposeOp::Background),
+            second.clone()
+        ),
+        (info(4, 3, 2, 8, DisposeOp::None), third.clone())
+    ];
+    let expected = render(&frames, width, height);
+
+    for crop in [false, true] {
+        let options = EncoderOptions::default()
+            .set_colorspace(ColorSpace::RGBA)
+            .set_width(width)
+            .set_height(height)
+            .set_depth(BitDepth::Eight);
+
+        let mut encoder = PngEncoder::new(&first, options);
+        encoder.set_frame_info(frames[0].0);
+        encoder.add_frame(&second, frames[1].0);
+        encoder.add_frame(&third, frames[2].0);
+        encoder.set_crop_frames(crop);
+
+        let mut sink = vec![];
+        encoder.encode(&mut sink).unwrap();
+
+        let mut decoder = PngDecoder::new(ZCursor::new(&sink));
+        decoder.decode_headers().unwrap();
+
+        let mut decoded = vec![];
+
+        while decoder.more_frames() {
+            decoder.decode_headers().unwrap();
+            let frame_info = decoder.frame_info().unwrap();
+            decoded.push((frame_info, decoder.decode_raw().unwrap()));
+        }
+        assert_eq!((decoded[1].0.width, decoded[1].0.height), (width, height));
+        assert!(render(&decoded, width, height) == expected, "crop: {crop}");
+    }
+}
+
+#[test]
+fn test_ancillary_chunks() {
+    use zune_core::bit_depth::BitDepth;
+    use zune_core::bytestream::ZCursor;
+    use zune_core::colorspace::ColorSpace;
+
+    use crate::PngDecoder;
+
+    let data = vec![7; 5 * 3 * 3];
+    let profile: Vec<u8> = (0..300).map(|x| (x % 7) as u8).collect();
+    let chrm = ChrmChunk {
+        white_point: (0.3127, 0.329),
+        red:         (0.64, 0.33),
+        green:       (0.3, 0.6),
+        blue:        (0.15, 0.06)
+    };
+    let phys = PhysChunk {
+        x_pixels_per_unit: 2835,
+        y_pixels_per_unit: 2835,
+        unit_is_meter:     true
+    };
+    let time = TimeInfo {
+        year:   2023,
+        month:  7,
+        day:    14,
+        hour:   9,
+        minute: 30,
+        second: 1
+    };
+    let options = EncoderOptions::default()
+        .set_colorspace(ColorSpace::RGB)
+        .set_width(5)
+        .set_height(3)
+        .set_depth(BitDepth::Eight);
+
+    for with_icc in [false, true] {
+        let mut encoder = PngEncoder::new(&data, options);
+
+        if with_icc {
+            encoder.add_icc_profile(&profile);
+        }
+        encoder.set_srgb_intent(RenderingIntent::Saturation);
+        encoder.set_gamma(0.45455);
+        encoder.set_chromaticities(chrm);
+        encoder.set_physical_dimensions(phys);
+        encoder.set_time(time);
+        encoder.add_text_chunk(TextChunk {
+            keyword: b"Title".to_vec(),
+            text:    b"A test".to_vec()
+        });
+        encoder.add_ztxt_chunk(ZtxtChunk {
+            keyword: b"Comment".to_vec(),
+            text:    b"compressed ".repeat(20)
+        });
+        encoder.add_itxt_chunk(ItxtChunk {
+            keyword: b"Author".to_vec(),
+            text:    "Zoë".as_bytes().to_vec()
+        });
+        let mut sink = vec![];
+        encoder.encode(&mut sink).unwrap();
+
+        let mut decoder = PngDecoder::new(ZCursor::new(&sink));
+        assert_eq!(decoder.decode_raw().unwrap(), data);
+
+        let info = decoder.info().unwrap();
+
+        if with_icc {
+            // the profile takes precedence over sRGB
+            assert_eq!(info.icc_profile.as_ref(), Some(&profile));
+            assert_eq!(info.srgb_intent, None);
+        } else {
+            assert_eq!(info.icc_profile, None);
+            assert_eq!(info.srgb_intent, Some(RenderingIntent::Saturation));
+        }
+        assert_eq!(info.gamma, Some(0.45455));
+        assert_eq!(info.chromaticities, Some(chrm));
+        assert_eq!(info.physical_dimensions, Some(phys));
+        assert_eq!(info.time_info.map(|x| (x.year, x.second)), Some((2023, 1)));
+        assert_eq!(info.text_chunk[0].text, b"A test");
+        assert_eq!(info.ztxt_chunk[0].text, b"compressed ".repeat(20));
+        assert_eq!(info.itxt_chunk[0].keyword, b"Author");
+        assert_eq!(info.itxt_chunk[0].text, "Zoë".as_bytes());
+    }
+    // keywords can't be empty
+    let mut encoder = PngEncoder::new(&data, options);
+    encoder.add_text_chunk(TextChunk {
+        keyword: vec![],
+        text:    vec![]
+    });
+    assert!(encoder.encode(&mut vec![]).is_err());
+}
+
+#[test]
+fn test_hdr_chunks() {
+    use zune_core::bit_depth::BitDepth;
+    use zune_core::bytestream::ZCursor;
+    use zune_core::colorspace::ColorSpace;
+
+    use crate::PngDecoder;
+
+    let data: Vec<u8> = (0..4 * 2 * 3 * 2).map(|x| x as u8).collect();
+    // BT.2020 primaries with the PQ transfer function
+    let cicp = CodingIndependentCodePoints {
+        color_primaries:          9,
+        transfer_characteristics: CodingIndependentCodePoints::TRANSFER_PQ,
+        matrix_coefficients:      0,
+        full_range:               true
+    };
+    let mdcv = MasteringDisplayColorVolume {
+        red:           (35400, 14600),
+        green:         (8500, 39850),
+        blue:          (6550, 2300),
+        white_point:   (15635, 16450),
+        max_luminance: 10_000_000,
+        min_luminance: 1
+    };
+    let clli = ContentLightLevel {
+        max_content_light_level:       1000,
+        max_frame_average_light_level: 400
+    };
+    let options = EncoderOptions::default()
+        .set_colorspace(ColorSpace::RGB)
+        .set_width(4)
+        .set_height(2)
+        .set_depth(BitDepth::Sixteen);
+
+    let mut encoder = PngEncoder::new(&data, options);
+    encoder.set_cicp(cicp);
+    encoder.set_mastering_display(mdcv);
+    encoder.set_content_light_level(clli);
+
+    let mut sink = vec![];
+    encoder.encode(&mut sink).unwrap();
+
+    let mut decoder = PngDecoder::new(ZCursor::new(&sink));
+    decoder.decode_headers().unwrap();
+
+    let info = decoder.info().unwrap();
+    assert_eq!(info.cicp, Some(cicp));
+    assert!(info.cicp.unwrap().is_hdr());
+    assert_eq!(info.mastering_display, Some(mdcv));
+    assert_eq!(info.mastering_display.unwrap().max_luminance_nits(), 1000.0);
+    assert_eq!(info.content_light_level, Some(clli));
+}
+
+#[test]
+fn test_filter_strategies() {
+    use zune_core::bit_depth::BitDepth;
+    use zune_core::bytestream::ZCursor;
+    use zune_core::colorspace::ColorSpace;
+
+    use crate::PngDecoder;
+
+    // a smooth gradient, which filters predict well
+    let (width, height) = (67, 41);
+    let data: Vec<u8> = (0..width * height)
+        .flat_map(|i| {
+            let (x, y) = (i % width, i / width);
+            [(x * 3 + y) as u8, (y * 5) as u8, (x * y / 8) as u8]
+        })
+        .collect();
+
+    let options = EncoderOptions::new(width, height, ColorSpace::RGB, BitDepth::Eight);
+
+    let encode = |strategy: FilterStrategy, interlace: InterlaceMethod| {
+        let mut encoder = PngEncoder::new(&data, options);
+        encoder.set_filter_strategy(strategy);
+        encoder.set_interlace_method(interlace);
+
+        let mut sink = vec![];
+        encoder.encode(&mut sink).unwrap();
+
+        let decoded = PngDecoder::new(ZCursor::new(&sink)).decode_raw().unwrap();
+        assert!(decoded == data, "{strategy:?} {interlace:?}");
+        sink.len()
+    };
+    let strategies = [
+        FilterStrategy::None,
+        FilterStrategy::Sub,
+        FilterStrategy::Up,
+        FilterStrategy::Average,
+        FilterStrategy::Paeth,
+        FilterStrategy::MinSum,
+        FilterStrategy::Entropy,
+        FilterStrategy::BruteForce
+    ];
+    for interlace in [InterlaceMethod::Standard, InterlaceMethod::Adam7] {
+        for strategy in strategies {
+            encode(strategy, interlace);
+        }
+    }
+    let unfiltered = encode(FilterStrategy::None, InterlaceMethod::Standard);
+
+    for strategy in [
+        FilterStrategy::MinSum,
+        FilterStrategy::Entropy,
+        FilterStrategy::BruteForce
+    ] {
+        assert!(encode(strategy, InterlaceMethod::Standard) < unfiltered);
+    }
+    // the strategy follows the effort unless set
+    assert_eq!(
+        PngEncoder::new(&data, options).filter_strategy(),
+        FilterStrategy::Up
+    );
+    let mut encoder = PngEncoder::new(&data, options.set_effort(0));
+    assert_eq!(encoder.filter_strategy(), FilterStrategy::None);
+
+    encoder.set_filter_strategy(FilterStrategy::Paeth);
+    assert_eq!(encoder.filter_strategy(), FilterStrategy::Paeth);
+}
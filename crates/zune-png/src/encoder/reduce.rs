@@ -0,0 +1,382 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software; You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Lossless color type and bit depth reductions
+//!
+//! Before encoding, the image is inspected to find the smallest
+//! layout which still represents every pixel exactly, i.e
+//!
+//! - 16 bit images whose samples all have equal high and low bytes become 8 bit
+//! - Alpha channels which are fully opaque are removed
+//! - Color images where every pixel is gray become grayscale
+//! - Grayscale images whose values fit fewer bits are written with 1, 2 or 4 bits
+//! - Images with 256 or fewer colors are written as indexed images with a palette
+//!   and a tRNS chunk holding palette alpha, indexes use as few bits as possible
+use alloc::vec;
+use alloc::vec::Vec;
+
+use zune_core::bit_depth::BitDepth;
+use zune_core::colorspace::ColorSpace;
+
+use crate::enums::PngColor;
+
+/// How pixels are laid out in the written image
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ImageLayout {
+    pub(crate) color:        PngColor,
+    /// Bits per sample, or per index for palette images
+    pub(crate) depth:        u8,
+    /// Palette entries, empty for non palette images
+    pub(crate) palette:      Vec<[u8; 3]>,
+    /// Alpha of the first palette entries, entries past it are opaque
+    pub(crate) transparency: Vec<u8>
+}
+
+impl ImageLayout {
+    /// The layout of an image written as is
+    ///
+    /// Colorspaces PNG can't store have an `Unknown` color
+    pub(crate) fn new(colorspace: ColorSpace, depth: BitDepth) -> ImageLayout {
+        let color = match colorspace {
+            ColorSpace::Luma => PngColor::Luma,
+            ColorSpace::LumaA => PngColor::LumaA,
+            ColorSpace::RGB => PngColor::RGB,
+            ColorSpace::RGBA => PngColor::RGBA,
+            _ => PngColor::Unknown
+        };
+        ImageLayout {
+            color,
+            depth: depth.bit_size() as u8,
+            ..Default::default()
+        }
+    }
+    /// Number of bytes a pixel takes before sub byte samples are packed,
+    /// this is one byte per sample, or two for 16 bit images
+    pub(crate) fn unpacked_pixel_size(&self) -> usize {
+        usize::from(self.color.num_components()) * if self.depth == 16 { 2 } else { 1 }
+    }
+    /// Number of bits a pixel takes in the written image
+    pub(crate) fn bits_per_pixel(&self) -> usize {
+        usize::from(self.color.num_components()) * usize::from(self.depth)
+    }
+}
+
+/// An image converted to a smaller layout
+pub(crate) struct ReducedImage {
+    pub(crate) layout: ImageLayout,
+    /// Pixels in the new layout, one byte per sample (two for 16 bit samples),
+    /// sub byte samples are not yet packed
+    pub(crate) pixels: Vec<u8>
+}
+
+/// Find the smallest lossless layout for `data`
+///
+/// # Arguments
+/// - `data`: Image pixels, 16 bit samples are big endian
+/// - `colorspace`: Colorspace of `data`, one of Luma, LumaA, RGB or RGBA
+/// - `depth`: Depth of `data`, eight or sixteen bits
+pub(crate) fn reduce_image(data: &[u8], colorspace: ColorSpace, depth: BitDepth) -> ReducedImage {
+    let components = colorspace.num_components();
+    let has_alpha = colorspace.has_alpha();
+
+    let converted: Vec<u8>;
+
+    let (data, sample_size) = if depth.size_of() == 2 && data.chunks_exact(2).all(|x| x[0] == x[1])
+    {
+        // the low byte adds no information
+        converted = data.iter().step_by(2).copied().collect();
+        (&converted[..], 1)
+    } else {
+        (data, depth.size_of())
+    };
+    let pixels = || data.chunks_exact(components * sample_size);
+    let sample = |pixel: &[u8], channel: usize| -> u16 {
+        if sample_size == 2 {
+            u16::from_be_bytes([pixel[2 * channel], pixel[2 * channel + 1]])
+        } else {
+            u16::from(pixel[channel])
+        }
+    };
+    let max_sample = if sample_size == 2 { u16::MAX } else { 255 };
+
+    let is_gray = components < 3
+        || pixels().all(|p| sample(p, 0) == sample(p, 1) && sample(p, 1) == sample(p, 2));
+    let is_opaque = !has_alpha || pixels().all(|p| sample(p, components - 1) == max_sample);
+
+    let keep_alpha = has_alpha && !is_opaque;
+
+    let color = match (is_gray, keep_alpha) {
+        (true, false) => PngColor::Luma,
+        (true, true) => PngColor::LumaA,
+        (false, false) => PngColor::RGB,
+        (false, true) => PngColor::RGBA
+    };
+    let reduced_depth = match (sample_size, color) {
+        (2, _) => 16,
+        (_, PngColor::Luma) => smallest_gray_depth(pixels().map(|p| p[0])),
+        _ => 8
+    };
+    let direct = ImageLayout {
+        color,
+        depth: reduced_depth,
+        ..Default::default()
+    };
+
+    if sample_size == 1 {
+        if let Some(reduced) = try_palette(data, components, &direct) {
+            return reduced;
+        }
+    }
+    // keep the channels we need
+    let mut channels: Vec<usize> = if is_gray { vec![0] } else { vec![0, 1, 2] };
+
+    if keep_alpha {
+        channels.push(components - 1);
+    }
+    let mut out = Vec::with_capacity(data.len());
+    // 16 bit images are never reduced below 8 bits
+    let gray_scale = 255 / ((1_u16 << reduced_depth.min(8)) - 1) as u8;
+
+    for pixel in pixels() {
+        for channel in &channels {
+            let start = channel * sample_size;
+            let value = &pixel[start..start + sample_size];
+
+            if reduced_depth < 8 {
+                out.push(value[0] / gray_scale);
+            } else {
+                out.extend_from_slice(value);
+            }
+        }
+    }
+    ReducedImage {
+        layout: direct,
+        pixels: out
+    }
+}
+
+/// Smallest depth able to hold every gray value exactly
+fn smallest_gray_depth(values: impl Iterator<Item = u8>) -> u8 {
+    // a value fits depth n if it is a multiple of 255 / (2^n - 1),
+    // since that's how decoders scale them back up
+    let mut depth = 1;
+
+    for value in values {
+        while depth < 8 && value % (255 / ((1_u16 << depth) - 1) as u8) != 0 {
+            depth *= 2;
+        }
+        if depth == 8 {
+            break;
+        }
+    }
+    depth
+}
+
+/// Try converting an 8 bit image to an indexed one, returning `None`
+/// if it has too many colors or if `direct` would be smaller
+fn try_palette(data: &[u8], components: usize, direct: &ImageLayout) -> Option<ReducedImage> {
+    let to_rgba = |pixel: &[u8]| -> u32 {
+        let rgba = match components {
+            1 => [pixel[0], pixel[0], pixel[0], 255],
+            2 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+            3 => [pixel[0], pixel[1], pixel[2], 255],
+            _ => [pixel[0], pixel[1], pixel[2], pixel[3]]
+        };
+        u32::from_be_bytes(rgba)
+    };
+    // unique colors, kept sorted
+    let mut colors: Vec<u32> = Vec::with_capacity(256);
+    let mut previous = None;
+
+    for pixel in data.chunks_exact(components) {
+        let color = to_rgba(pixel);
+
+        // neighbouring pixels are often the same
+        if previous == Some(color) {
+            continue;
+        }
+        previous = Some(color);
+
+        if let Err(position) = colors.binary_search(&color) {
+            if colors.len() == 256 {
+                return None;
+            }
+            colors.insert(position, color);
+        }
+    }
+    let index_depth = match colors.len() {
+        0..=2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        _ => 8
+    };
+    // translucent entries go first so the tRNS chunk can stop early
+    let mut palette_order: Vec<u32> = colors.clone();
+    palette_order.sort_by_key(|x| (*x & 0xFF) == 255);
+
+    let num_translucent = palette_order.iter().filter(|x| (**x & 0xFF) != 255).count();
+
+    // compare uncompressed sizes, each chunk costs 12 bytes for
+    // its length, name and crc
+    let num_pixels = data.len() / components;
+    let mut palette_bytes = 12 + 3 * colors.len() + num_pixels * index_depth / 8;
+
+    if num_translucent > 0 {
+        palette_bytes += 12 + num_translucent;
+    }
+    if palette_bytes >= num_pixels * direct.bits_per_pixel() / 8 {
+        return None;
+    }
+
+    // map from sorted color position to palette index
+    let mut indexes = vec![0_u8; colors.len()];
+
+    for (index, color) in palette_order.iter().enumerate() {
+        let position = colors.binary_search(color).unwrap();
+        indexes[position] = index as u8;
+    }
+    let mut pixels = Vec::with_capacity(num_pixels);
+
+    for pixel in data.chunks_exact(components) {
+        let position = colors.binary_search(&to_rgba(pixel)).unwrap();
+        pixels.push(indexes[position]);
+    }
+    let layout = ImageLayout {
+        color:        PngColor::Palette,
+        depth:        index_depth as u8,
+        palette:      palette_order
+            .iter()
+            .map(|x| {
+                let [r, g, b, _] = x.to_be_bytes();
+                [r, g, b]
+            })
+            .collect(),
+        transparency: palette_order[..num_translucent]
+            .iter()
+            .map(|x| *x as u8)
+            .collect()
+    };
+    Some(ReducedImage { layout, pixels })
+}
+
+#[test]
+fn test_reduced_roundtrip() {
+    use zune_core::bytestream::ZCursor;
+    use zune_core::options::EncoderOptions;
+
+    use crate::{PngDecoder, PngEncoder};
+
+    let (width, height) = (37, 11);
+    let num_pixels = width * height;
+
+    let rgba = |colors: &[[u8; 4]]| -> Vec<u8> {
+        (0..num_pixels)
+            .flat_map(|x| colors[(x * 7 + x / 5) % colors.len()])
+            .collect()
+    };
+    let strip_alpha = |data: &[u8]| -> Vec<u8> {
+        data.chunks_exact(4)
+            .flat_map(|x| [x[0], x[1], x[2]])
+            .collect()
+    };
+    let gray = |data: &[u8]| -> Vec<u8> { data.chunks_exact(4).map(|x| x[0]).collect() };
+
+    let opaque = [[10, 20, 30, 255], [200, 100, 0, 255], [1, 2, 3, 255]];
+    let translucent: Vec<[u8; 4]> = (0..10).map(|x| [x * 20, 5, 9, x * 25]).collect();
+    let grays = [[0, 0, 0, 255], [85, 85, 85, 255], [255, 255, 255, 255]];
+    // every pixel a different color
+    let many: Vec<[u8; 4]> = (0..num_pixels)
+        .map(|x| [x as u8, (x >> 8) as u8, 7, 255])
+        .collect();
+
+    // (input, expected color, expected depth, expected decoded pixels)
+    let cases = [
+        (
+            rgba(&opaque),
+            PngColor::Palette,
+            2,
+            strip_alpha(&rgba(&opaque))
+        ),
+        (rgba(&translucent), PngColor::Palette, 4, rgba(&translucent)),
+        (rgba(&grays), PngColor::Luma, 2, gray(&rgba(&grays))),
+        (many.concat(), PngColor::RGB, 8, strip_alpha(&many.concat()))
+    ];
+    for (data, color, depth, expected) in cases {
+        let options = EncoderOptions::default()
+            .set_colorspace(ColorSpace::RGBA)
+            .set_width(width)
+            .set_height(height)
+            .set_depth(BitDepth::Eight);
+
+        let mut encoder = PngEncoder::new(&data, options);
+        encoder.set_reduce_colors(true);
+
+        let mut sink = vec![];
+        encoder.encode(&mut sink).unwrap();
+
+        let mut decoder = PngDecoder::new(ZCursor::new(&sink));
+        let pixels = decoder.decode_raw().unwrap();
+        let info = decoder.info().unwrap();
+
+        assert_eq!((info.color, info.depth), (color, depth));
+        assert!(pixels == expected, "{color:?} image differs");
+    }
+    // 16 bit samples with equal bytes become 8 bit
+    let data: Vec<u8> = (0..num_pixels * 3)
+        .flat_map(|x| [(x * 13) as u8; 2])
+        .collect();
+    let options = EncoderOptions::default()
+        .set_colorspace(ColorSpace::RGB)
+        .set_width(width)
+        .set_height(height)
+        .set_depth(BitDepth::Sixteen);
+
+    let mut encoder = PngEncoder::new(&data, options);
+    encoder.set_reduce_colors(true);
+
+    let mut sink = vec![];
+    encoder.encode(&mut sink).unwrap();
+
+    let mut decoder = PngDecoder::new(ZCursor::new(&sink));
+    let pixels = decoder.decode_raw().unwrap();
+
+    assert_eq!(decoder.info().unwrap().depth, 8);
+    assert!(pixels.iter().eq(data.iter().step_by(2)));
+}
+
+#[test]
+fn test_reduced_sixteen_bit_gray() {
+    use zune_core::bytestream::ZCursor;
+    use zune_core::options::EncoderOptions;
+
+    use crate::{PngDecoder, PngEncoder};
+
+    let (width, height) = (19, 7);
+    // gray samples whose bytes differ can't lose any bits
+    let gray: Vec<[u8; 2]> = (0..width * height)
+        .map(|x| [(x * 3) as u8, (x * 7 + 1) as u8])
+        .collect();
+    let data: Vec<u8> = gray.iter().flat_map(|x| [*x, *x, *x]).flatten().collect();
+
+    let options = EncoderOptions::default()
+        .set_colorspace(ColorSpace::RGB)
+        .set_width(width)
+        .set_height(height)
+        .set_depth(BitDepth::Sixteen);
+
+    let mut encoder = PngEncoder::new(&data, options);
+    encoder.set_reduce_colors(true);
+
+    let mut sink = vec![];
+    encoder.encode(&mut sink).unwrap();
+
+    let mut decoder = PngDecoder::new(ZCursor::new(&sink));
+    let pixels = decoder.decode_raw().unwrap();
+    let info = decoder.info().unwrap();
+
+    assert_eq!((info.color, info.depth), (PngColor::Luma, 16));
+    assert!(pixels == gray.concat());
+}
@@ -21,6 +21,9 @@ pub enum PngChunkType {
     iCCP,
     sBit,
     sRGB,
+    cICP,
+    mDCV,
+    cLLI,
     bKGD,
     hIST,
     tRNS,
@@ -42,7 +45,13 @@ impl PngChunkType {
     pub const fn should_appear_before_ptle(self) -> bool {
         matches!(
             self,
-            Self::cHRM | Self::gAMA | Self::iCCP | Self::sBit | Self::sRGB
+            Self::cHRM
+                | Self::gAMA
+                | Self::iCCP
+                | Self::sBit
+                | Self::sRGB
+                | Self::cICP
+                | Self::mDCV
         )
     }
     /// Return true if a chunk should appear
@@ -62,6 +71,9 @@ impl PngChunkType {
                 | Self::iCCP
                 | Self::sBit
                 | Self::sRGB
+                | Self::cICP
+                | Self::mDCV
+                | Self::cLLI
                 | Self::bKGD
                 | Self::hIST
                 | Self::tRNS
@@ -144,6 +156,67 @@ impl InterlaceMethod {
     }
 }
 
+/// How the encoder picks the filter applied to each row
+/// before compression
+///
+/// Filters predict each byte from its neighbours so that the
+/// compressor sees smaller, more repetitive values. Trying more
+/// filters per row gives smaller files at the cost of speed.
+///
+/// Unless set with [`PngEncoder::set_filter_strategy`](crate::PngEncoder::set_filter_strategy),
+/// the strategy follows the encoder [`effort`](zune_core::options::EncoderOptions::effort),
+/// see [`from_effort`](Self::from_effort)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FilterStrategy {
+    /// Don't filter rows, fastest
+    None,
+    /// Use the sub filter for every row
+    Sub,
+    /// Use the up filter for every row
+    Up,
+    /// Use the average filter for every row
+    Average,
+    /// Use the paeth filter for every row
+    Paeth,
+    /// Pick the filter whose output has the minimum sum of
+    /// absolute differences, the heuristic used by libpng
+    MinSum,
+    /// Pick the filter whose output has the lowest entropy
+    Entropy,
+    /// Compress every filtered row and keep the smallest,
+    /// slowest but usually produces the smallest files
+    BruteForce
+}
+
+impl FilterStrategy {
+    /// The strategy used for an encoder effort
+    ///
+    /// | Effort | Strategy     |
+    /// |--------|--------------|
+    /// | 0      | `None`       |
+    /// | 1-4    | `Up`         |
+    /// | 5      | `MinSum`     |
+    /// | 6-8    | `Entropy`    |
+    /// | 9+     | `BruteForce` |
+    ///
+    /// The default effort of 4 keeps the up filter the encoder
+    /// has always used, adaptive filtering has to be asked for
+    pub const fn from_effort(effort: u8) -> FilterStrategy {
+        match effort {
+            0 => Self::None,
+            1..=4 => Self::Up,
+            5 => Self::MinSum,
+            6..=8 => Self::Entropy,
+            _ => Self::BruteForce
+        }
+    }
+    /// Return true if the strategy picks a filter for
+    /// each row, rather than always using the same one
+    pub const fn is_adaptive(self) -> bool {
+        matches!(self, Self::MinSum | Self::Entropy | Self::BruteForce)
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum PngColor {
     Luma,
@@ -184,4 +257,14 @@ impl PngColor {
             _ => None
         }
     }
+    pub(crate) fn to_int(self) -> u8 {
+        match self {
+            PngColor::Luma => 0,
+            PngColor::RGB => 2,
+            PngColor::Palette => 3,
+            PngColor::LumaA => 4,
+            PngColor::RGBA => 6,
+            PngColor::Unknown => unreachable!()
+        }
+    }
 }
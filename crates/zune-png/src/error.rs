@@ -33,6 +33,25 @@ pub enum PngDecodeErrors {
     IoErrors(ZByteIoError)
 }
 
+/// An error the decoder recovered from
+///
+/// When not in strict mode, a truncated file or corrupt image data doesn't
+/// fail decoding, the rows decoded before the error are returned and the
+/// remaining rows are zero. This describes what went wrong and where.
+#[derive(Debug)]
+pub struct PngErrorReport {
+    /// The error the decoder recovered from
+    pub error:        PngDecodeErrors,
+    /// Offset from the start of the file of the chunk which couldn't be read,
+    /// or `None` if the error was found in the compressed image data
+    pub position:     Option<usize>,
+    /// Number of scanlines decoded before the error, for interlaced images
+    /// this counts scanlines of all passes in the order they are stored
+    pub rows_decoded: usize,
+    /// Number of scanlines in the image, counted the same way
+    pub total_rows:   usize
+}
+
 impl Display for PngDecodeErrors {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(f, "{:?}", self)
@@ -9,19 +9,116 @@
 //! scanlines.
 //!
 
-use crate::enums::FilterMethod;
+use alloc::vec::Vec;
+
+use zune_inflate::DeflateEncoder;
+
+use crate::enums::{FilterMethod, FilterStrategy};
 
 pub mod de_filter;
 mod filter;
 mod portable_simd;
 mod sse4;
 
-pub fn choose_compression_filter(_previous_row: &[u8], _current_row: &[u8]) -> FilterMethod {
-    if _previous_row.is_empty() {
-        // first row
-        return FilterMethod::None;
+/// Filters tried by the adaptive strategies
+const ALL_FILTERS: [FilterMethod; 5] = [
+    FilterMethod::None,
+    FilterMethod::Sub,
+    FilterMethod::Up,
+    FilterMethod::Average,
+    FilterMethod::Paeth
+];
+
+/// How many bytes of previously filtered rows brute force
+/// compresses each candidate row against
+const BRUTE_FORCE_WINDOW: usize = 8192;
+
+/// Filter `current_row` into `output`, which holds the filter
+/// type followed by the filtered row, picking the filter with `strategy`
+///
+/// - `previous_row`: The row above, all zeroes for the first row
+/// - `history`: Rows filtered so far, brute force compresses candidates after them
+/// - `scratch`: Space for trying out filters
+pub(crate) fn filter_row(
+    strategy: FilterStrategy, previous_row: &[u8], current_row: &[u8], components: usize,
+    history: &[u8], output: &mut [u8], scratch: &mut Vec<u8>
+) {
+    let filter = match strategy {
+        FilterStrategy::None => FilterMethod::None,
+        FilterStrategy::Sub => FilterMethod::Sub,
+        FilterStrategy::Up => FilterMethod::Up,
+        FilterStrategy::Average => FilterMethod::Average,
+        FilterStrategy::Paeth => FilterMethod::Paeth,
+        FilterStrategy::MinSum | FilterStrategy::Entropy | FilterStrategy::BruteForce => {
+            scratch.resize(output.len(), 0);
+
+            let window = &history[history.len().saturating_sub(BRUTE_FORCE_WINDOW)..];
+            let mut best_cost = usize::MAX;
+
+            for filter in ALL_FILTERS {
+                filter_scanline(current_row, previous_row, scratch, filter, components);
+
+                let cost = match strategy {
+                    FilterStrategy::MinSum => sum_of_absolute_differences(&scratch[1..]),
+                    FilterStrategy::Entropy => entropy(&scratch[1..]),
+                    _ => compressed_size(window, scratch)
+                };
+                if cost < best_cost {
+                    best_cost = cost;
+                    output.copy_from_slice(scratch);
+                }
+            }
+            return;
+        }
+    };
+    filter_scanline(current_row, previous_row, output, filter, components);
+}
+
+/// Sum of the filtered bytes seen as signed differences
+fn sum_of_absolute_differences(filtered: &[u8]) -> usize {
+    filtered
+        .iter()
+        .map(|x| usize::from((*x as i8).unsigned_abs()))
+        .sum()
+}
+
+/// Number of bits needed to store the filtered bytes with
+/// an ideal entropy coder, in 1/256ths of a bit
+fn entropy(filtered: &[u8]) -> usize {
+    let mut counts = [0_u32; 256];
+
+    for x in filtered {
+        counts[usize::from(*x)] += 1;
     }
-    FilterMethod::Up
+    let total = log2(filtered.len() as f32);
+
+    let bits: f32 = counts
+        .iter()
+        .filter(|x| **x != 0)
+        .map(|x| (*x as f32) * (total - log2(*x as f32)))
+        .sum();
+
+    (bits * 256.0) as usize
+}
+
+/// Approximate base 2 logarithm of a positive number
+///
+/// Good to about 0.01, which is enough to compare entropies,
+/// and unlike `f32::log2` doesn't need the standard library
+fn log2(x: f32) -> f32 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 255) as f32 - 127.0;
+    // mantissa, in [1, 2)
+    let m = f32::from_bits((bits & 0x007F_FFFF) | 0x3F80_0000);
+
+    exponent + (-0.344_848_43 * m + 2.024_665_8) * m - 0.674_877_6
+}
+
+/// Size of `row` compressed after `history`
+fn compressed_size(history: &[u8], row: &[u8]) -> usize {
+    DeflateEncoder::new(row)
+        .encode_zlib_with_dictionary(history)
+        .len()
 }
 
 pub fn filter_scanline(
@@ -35,6 +132,12 @@ pub fn filter_scanline(
         FilterMethod::None => filter_scanline.copy_from_slice(input),
         FilterMethod::Sub => filter::sub_filter(input, filter_scanline, components),
         FilterMethod::Up => filter::up_filter(input, previous_row, filter_scanline),
+        FilterMethod::Average => {
+            filter::avg_filter(input, previous_row, filter_scanline, components)
+        }
+        FilterMethod::Paeth => {
+            filter::paeth_filter(input, previous_row, filter_scanline, components)
+        }
 
         _ => unreachable!("Unexpected input")
     }
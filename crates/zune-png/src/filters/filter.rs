@@ -6,6 +6,8 @@
 
 //! Filtering algorithms for png encoder
 
+use crate::filters::de_filter::paeth;
+
 pub fn sub_filter(input: &[u8], output: &mut [u8], components: usize) {
     // copy leftmost byte from input to output
     output[..components].copy_from_slice(&input[..components]);
@@ -26,3 +28,33 @@ pub fn up_filter(input: &[u8], up: &[u8], output: &mut [u8]) {
         *x = (*in_).wrapping_sub(*up)
     }
 }
+
+pub fn avg_filter(input: &[u8], up: &[u8], output: &mut [u8], components: usize) {
+    debug_assert_eq!(input.len(), up.len());
+
+    let end = input.len().min(output.len()).min(components);
+
+    // leftmost pixel has nothing on its left, only average with the top
+    for i in 0..end {
+        output[i] = input[i].wrapping_sub(up[i] >> 1);
+    }
+    for i in end..input.len().min(output.len()) {
+        let avg = (u16::from(input[i - components]) + u16::from(up[i])) >> 1;
+        output[i] = input[i].wrapping_sub(avg as u8);
+    }
+}
+
+pub fn paeth_filter(input: &[u8], up: &[u8], output: &mut [u8], components: usize) {
+    debug_assert_eq!(input.len(), up.len());
+
+    let end = input.len().min(output.len()).min(components);
+
+    // leftmost pixel, paeth(0, b, 0) is always b
+    for i in 0..end {
+        output[i] = input[i].wrapping_sub(up[i]);
+    }
+    for i in end..input.len().min(output.len()) {
+        let predictor = paeth(input[i - components], up[i], up[i - components]);
+        output[i] = input[i].wrapping_sub(predictor);
+    }
+}
@@ -5,13 +5,19 @@
  */
 
 use alloc::{format, vec};
+use core::cmp::min;
 
 use zune_core::bytestream::ZByteReaderTrait;
+use zune_core::colorspace::{
+    CodingIndependentCodePoints, ContentLightLevel, MasteringDisplayColorVolume, RenderingIntent
+};
 use zune_core::log::{trace, warn};
 use zune_inflate::DeflateDecoder;
 
 use crate::apng::{ActlChunk, BlendOp, DisposeOp, FrameInfo, SingleFrame};
-use crate::decoder::{ItxtChunk, PLTEEntry, PngChunk, TextChunk, TimeInfo, ZtxtChunk};
+use crate::decoder::{
+    ChrmChunk, ItxtChunk, PLTEEntry, PhysChunk, PngChunk, TextChunk, TimeInfo, ZtxtChunk
+};
 use crate::enums::{FilterMethod, InterlaceMethod, PngChunkType, PngColor};
 use crate::error::PngDecodeErrors;
 use crate::PngDecoder;
@@ -142,6 +148,7 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
 
         // allocate palette
         self.palette.resize(256, PLTEEntry::default());
+        self.palette_len = min(chunk.length / 3, 256);
 
         for pal_chunk in self.palette.iter_mut().take(chunk.length / 3) {
             pal_chunk.red = self.stream.read_u8();
@@ -292,6 +299,182 @@ impl<T: ZByteReaderTrait> PngDecoder<T> {
         Ok(())
     }
 
+    /// Parse the cHRM chunk
+    pub(crate) fn parse_chrm(&mut self, chunk: PngChunk) -> Result<(), PngDecodeErrors> {
+        if chunk.length != 32 {
+            if self.options.strict_mode() {
+                return Err(PngDecodeErrors::GenericStatic("Invalid cHRM chunk length"));
+            }
+            warn!("Invalid cHRM chunk length {:?}", chunk.length);
+            // skip chunk + crc
+            self.stream.skip(chunk.length + 4)?;
+            return Ok(());
+        }
+        // values are stored times 100000
+        let mut point = || {
+            let x = self.stream.get_u32_be() as f32 / 100000.0;
+            let y = self.stream.get_u32_be() as f32 / 100000.0;
+            (x, y)
+        };
+        let chrm = ChrmChunk {
+            white_point: point(),
+            red:         point(),
+            green:       point(),
+            blue:        point()
+        };
+        self.png_info.chromaticities = Some(chrm);
+        // skip past crc
+        self.stream.skip(4)?;
+
+        Ok(())
+    }
+
+    /// Parse the sRGB chunk
+    pub(crate) fn parse_srgb(&mut self, chunk: PngChunk) -> Result<(), PngDecodeErrors> {
+        if chunk.length != 1 {
+            if self.options.strict_mode() {
+                return Err(PngDecodeErrors::GenericStatic("Invalid sRGB chunk length"));
+            }
+            warn!("Invalid sRGB chunk length {:?}", chunk.length);
+            // skip chunk + crc
+            self.stream.skip(chunk.length + 4)?;
+            return Ok(());
+        }
+        let intent = match self.stream.read_u8() {
+            0 => RenderingIntent::Perceptual,
+            1 => RenderingIntent::RelativeColorimetric,
+            2 => RenderingIntent::Saturation,
+            3 => RenderingIntent::AbsoluteColorimetric,
+            _ => {
+                if self.options.strict_mode() {
+                    return Err(PngDecodeErrors::GenericStatic(
+                        "[strict-mode]: Unknown sRGB rendering intent"
+                    ));
+                }
+                warn!("Unknown sRGB rendering intent, using perceptual");
+                RenderingIntent::Perceptual
+            }
+        };
+        self.png_info.srgb_intent = Some(intent);
+        // skip past crc
+        self.stream.skip(4)?;
+
+        Ok(())
+    }
+
+    /// Parse the cICP chunk
+    pub(crate) fn parse_cicp(&mut self, chunk: PngChunk) -> Result<(), PngDecodeErrors> {
+        if chunk.length != 4 {
+            if self.options.strict_mode() {
+                return Err(PngDecodeErrors::GenericStatic("Invalid cICP chunk length"));
+            }
+            warn!("Invalid cICP chunk length {:?}", chunk.length);
+            // skip chunk + crc
+            self.stream.skip(chunk.length + 4)?;
+            return Ok(());
+        }
+        let color_primaries = self.stream.read_u8();
+        let transfer_characteristics = self.stream.read_u8();
+        let matrix_coefficients = self.stream.read_u8();
+        let range = self.stream.read_u8();
+
+        // PNG only stores RGB, and the range is a flag
+        if matrix_coefficients != 0 || range > 1 {
+            if self.options.strict_mode() {
+                return Err(PngDecodeErrors::GenericStatic(
+                    "[strict-mode]: Invalid cICP matrix coefficients or range"
+                ));
+            }
+            warn!("Invalid cICP matrix coefficients or range");
+        }
+        let cicp = CodingIndependentCodePoints {
+            color_primaries,
+            transfer_characteristics,
+            matrix_coefficients,
+            full_range: range != 0
+        };
+        self.png_info.cicp = Some(cicp);
+        // skip past crc
+        self.stream.skip(4)?;
+
+        Ok(())
+    }
+
+    /// Parse the mDCV chunk
+    pub(crate) fn parse_mdcv(&mut self, chunk: PngChunk) -> Result<(), PngDecodeErrors> {
+        if chunk.length != 24 {
+            if self.options.strict_mode() {
+                return Err(PngDecodeErrors::GenericStatic("Invalid mDCV chunk length"));
+            }
+            warn!("Invalid mDCV chunk length {:?}", chunk.length);
+            // skip chunk + crc
+            self.stream.skip(chunk.length + 4)?;
+            return Ok(());
+        }
+        let mut point = || (self.stream.get_u16_be(), self.stream.get_u16_be());
+
+        let (red, green, blue, white_point) = (point(), point(), point(), point());
+
+        let mdcv = MasteringDisplayColorVolume {
+            red,
+            green,
+            blue,
+            white_point,
+            max_luminance: self.stream.get_u32_be(),
+            min_luminance: self.stream.get_u32_be()
+        };
+        self.png_info.mastering_display = Some(mdcv);
+        // skip past crc
+        self.stream.skip(4)?;
+
+        Ok(())
+    }
+
+    /// Parse the cLLI chunk
+    pub(crate) fn parse_clli(&mut self, chunk: PngChunk) -> Result<(), PngDecodeErrors> {
+        if chunk.length != 8 {
+            if self.options.strict_mode() {
+                return Err(PngDecodeErrors::GenericStatic("Invalid cLLI chunk length"));
+            }
+            warn!("Invalid cLLI chunk length {:?}", chunk.length);
+            // skip chunk + crc
+            self.stream.skip(chunk.length + 4)?;
+            return Ok(());
+        }
+        let clli = ContentLightLevel {
+            max_content_light_level:       self.stream.get_u32_be(),
+            max_frame_average_light_level: self.stream.get_u32_be()
+        };
+        self.png_info.content_light_level = Some(clli);
+        // skip past crc
+        self.stream.skip(4)?;
+
+        Ok(())
+    }
+
+    /// Parse the pHYs chunk
+    pub(crate) fn parse_phys(&mut self, chunk: PngChunk) -> Result<(), PngDecodeErrors> {
+        if chunk.length != 9 {
+            if self.options.strict_mode() {
+                return Err(PngDecodeErrors::GenericStatic("Invalid pHYs chunk length"));
+            }
+            warn!("Invalid pHYs chunk length {:?}", chunk.length);
+            // skip chunk + crc
+            self.stream.skip(chunk.length + 4)?;
+            return Ok(());
+        }
+        let phys = PhysChunk {
+            x_pixels_per_unit: self.stream.get_u32_be(),
+            y_pixels_per_unit: self.stream.get_u32_be(),
+            unit_is_meter:     self.stream.read_u8() == 1
+        };
+        self.png_info.physical_dimensions = Some(phys);
+        // skip past crc
+        self.stream.skip(4)?;
+
+        Ok(())
+    }
+
     pub(crate) fn parse_exif(&mut self, chunk: PngChunk) -> Result<(), PngDecodeErrors> {
         let data = self.stream.peek_at(0, chunk.length).unwrap();
 
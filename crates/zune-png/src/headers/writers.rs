@@ -7,35 +7,32 @@
 use alloc::vec::Vec;
 
 use zune_core::bytestream::{ZByteIoError, ZWriter, ZByteWriterTrait};
-use zune_core::colorspace::ColorSpace;
+use zune_core::colorspace::RenderingIntent;
+use zune_inflate::DeflateEncoder;
 
+use crate::apng::FrameInfo;
 use crate::crc::{calc_crc, calc_crc_with_bytes};
-use crate::decoder::PngChunk;
+use crate::decoder::{ItxtChunk, PngChunk, TextChunk, ZtxtChunk};
 use crate::encoder::PngEncoder;
+use crate::enums::InterlaceMethod;
 
 pub(crate) fn write_ihdr(ctx: &PngEncoder, output: &mut ZWriter<&mut Vec<u8>>) {
     // write width and height
     output.write_u32_be(ctx.options.width() as u32);
     output.write_u32_be(ctx.options.height() as u32);
-    // write depth
-    output.write_u8(ctx.options.depth().bit_size() as u8);
-    // write color
-    let color = ctx.options.colorspace();
-
-    let color_int = match color {
-        ColorSpace::Luma => 0,
-        ColorSpace::RGB => 2,
-        ColorSpace::LumaA => 4,
-        ColorSpace::RGBA => 6,
-        _ => unreachable!()
-    };
-    output.write_u8(color_int);
+    // write depth and color
+    output.write_u8(ctx.layout.depth);
+    output.write_u8(ctx.layout.color.to_int());
     //compression method
     output.write_u8(0);
     // filter method for first row
     output.write_u8(ctx.row_filter.to_int());
-    // interlace method, always Standard
-    output.write_u8(0);
+    // interlace method
+    let interlace = match ctx.interlace_method {
+        InterlaceMethod::Adam7 => 1,
+        _ => 0
+    };
+    output.write_u8(interlace);
 }
 
 pub fn write_exif(ctx: &PngEncoder, writer: &mut ZWriter<&mut alloc::vec::Vec<u8>>) {
@@ -46,12 +43,138 @@ pub fn write_exif(ctx: &PngEncoder, writer: &mut ZWriter<&mut alloc::vec::Vec<u8
 
 pub fn write_gamma(ctx: &PngEncoder, writer: &mut ZWriter<&mut Vec<u8>>) {
     if let Some(gamma) = ctx.gamma {
-        // scale by 100000.0
-        let gamma_value = (gamma * 100000.0) as u32;
+        // scale by 100000.0, rounding to the nearest value
+        let gamma_value = (gamma * 100000.0 + 0.5) as u32;
         writer.write_u32_be(gamma_value);
     }
 }
 
+pub(crate) fn write_iccp(ctx: &PngEncoder, writer: &mut ZWriter<&mut Vec<u8>>) {
+    if let Some(profile) = ctx.icc_profile {
+        // profile name, null separator and compression method
+        writer.write_all(b"ICC profile\0\0").unwrap();
+        writer
+            .write_all(&DeflateEncoder::new(profile).encode_zlib())
+            .unwrap();
+    }
+}
+
+pub(crate) fn write_srgb(ctx: &PngEncoder, writer: &mut ZWriter<&mut Vec<u8>>) {
+    if let Some(intent) = ctx.srgb_intent {
+        let value = match intent {
+            RenderingIntent::Perceptual => 0,
+            RenderingIntent::RelativeColorimetric => 1,
+            RenderingIntent::Saturation => 2,
+            RenderingIntent::AbsoluteColorimetric => 3
+        };
+        writer.write_u8(value);
+    }
+}
+
+pub(crate) fn write_chrm(ctx: &PngEncoder, writer: &mut ZWriter<&mut Vec<u8>>) {
+    if let Some(chrm) = ctx.chromaticities {
+        for (x, y) in [chrm.white_point, chrm.red, chrm.green, chrm.blue] {
+            // scale by 100000.0, rounding to the nearest value
+            writer.write_u32_be((x * 100000.0 + 0.5) as u32);
+            writer.write_u32_be((y * 100000.0 + 0.5) as u32);
+        }
+    }
+}
+
+pub(crate) fn write_cicp(ctx: &PngEncoder, writer: &mut ZWriter<&mut Vec<u8>>) {
+    if let Some(cicp) = ctx.cicp {
+        writer.write_u8(cicp.color_primaries);
+        writer.write_u8(cicp.transfer_characteristics);
+        writer.write_u8(cicp.matrix_coefficients);
+        writer.write_u8(u8::from(cicp.full_range));
+    }
+}
+
+pub(crate) fn write_mdcv(ctx: &PngEncoder, writer: &mut ZWriter<&mut Vec<u8>>) {
+    if let Some(mdcv) = ctx.display_volume {
+        for (x, y) in [mdcv.red, mdcv.green, mdcv.blue, mdcv.white_point] {
+            writer.write_u16_be(x);
+            writer.write_u16_be(y);
+        }
+        writer.write_u32_be(mdcv.max_luminance);
+        writer.write_u32_be(mdcv.min_luminance);
+    }
+}
+
+pub(crate) fn write_clli(ctx: &PngEncoder, writer: &mut ZWriter<&mut Vec<u8>>) {
+    if let Some(clli) = ctx.content_light {
+        writer.write_u32_be(clli.max_content_light_level);
+        writer.write_u32_be(clli.max_frame_average_light_level);
+    }
+}
+
+pub(crate) fn write_phys(ctx: &PngEncoder, writer: &mut ZWriter<&mut Vec<u8>>) {
+    if let Some(phys) = ctx.physical_dims {
+        writer.write_u32_be(phys.x_pixels_per_unit);
+        writer.write_u32_be(phys.y_pixels_per_unit);
+        writer.write_u8(u8::from(phys.unit_is_meter));
+    }
+}
+
+pub(crate) fn write_time(ctx: &PngEncoder, writer: &mut ZWriter<&mut Vec<u8>>) {
+    if let Some(time) = ctx.time {
+        writer.write_u16_be(time.year);
+        writer
+            .write_all(&[time.month, time.day, time.hour, time.minute, time.second])
+            .unwrap();
+    }
+}
+
+pub(crate) fn write_text(chunk: &TextChunk, writer: &mut ZWriter<&mut Vec<u8>>) {
+    writer.write_all(&chunk.keyword).unwrap();
+    writer.write_u8(0);
+    writer.write_all(&chunk.text).unwrap();
+}
+
+pub(crate) fn write_ztxt(chunk: &ZtxtChunk, writer: &mut ZWriter<&mut Vec<u8>>) {
+    writer.write_all(&chunk.keyword).unwrap();
+    // null separator and compression method
+    writer.write_all(&[0, 0]).unwrap();
+    writer
+        .write_all(&DeflateEncoder::new(&chunk.text).encode_zlib())
+        .unwrap();
+}
+
+pub(crate) fn write_itxt(chunk: &ItxtChunk, writer: &mut ZWriter<&mut Vec<u8>>) {
+    writer.write_all(&chunk.keyword).unwrap();
+    // null separator, compression flag and method, then an empty language
+    // tag and translated keyword, each terminated by a null byte
+    writer.write_all(&[0, 0, 0, 0, 0]).unwrap();
+    writer.write_all(&chunk.text).unwrap();
+}
+
+pub fn write_plte(ctx: &PngEncoder, writer: &mut ZWriter<&mut Vec<u8>>) {
+    for entry in &ctx.layout.palette {
+        writer.write_all(entry).unwrap();
+    }
+}
+
+pub fn write_trns(ctx: &PngEncoder, writer: &mut ZWriter<&mut Vec<u8>>) {
+    writer.write_all(&ctx.layout.transparency).unwrap();
+}
+
+pub(crate) fn write_actl(ctx: &PngEncoder, writer: &mut ZWriter<&mut Vec<u8>>) {
+    writer.write_u32_be(ctx.num_animation_frames() as u32);
+    writer.write_u32_be(ctx.num_plays);
+}
+
+pub(crate) fn write_fctl(sequence: u32, info: &FrameInfo, writer: &mut ZWriter<&mut Vec<u8>>) {
+    writer.write_u32_be(sequence);
+    writer.write_u32_be(info.width as u32);
+    writer.write_u32_be(info.height as u32);
+    writer.write_u32_be(info.x_offset as u32);
+    writer.write_u32_be(info.y_offset as u32);
+    writer.write_u16_be(info.delay_num);
+    writer.write_u16_be(info.delay_denom);
+    writer.write_u8(info.dispose_op.to_int());
+    writer.write_u8(info.blend_op.to_int());
+}
+
 // iend is a no-op
 pub fn write_iend(_: &PngEncoder, _: &mut ZWriter<&mut Vec<u8>>) {}
 
@@ -167,10 +167,15 @@ extern crate core;
 
 #[cfg(feature = "std")]
 pub use apng::post_process_image;
-pub use apng::{BlendOp, DisposeOp};
-pub use decoder::{ItxtChunk, PngDecoder, PngInfo, TextChunk, TimeInfo, ZtxtChunk};
+pub use apng::{BlendOp, DisposeOp, FrameInfo};
+pub use decoder::{
+    ChrmChunk, ItxtChunk, PLTEEntry, PhysChunk, PngDecoder, PngInfo, TextChunk, TimeInfo,
+    ZtxtChunk
+};
 pub use encoder::PngEncoder;
-pub use enums::InterlaceMethod;
+pub use enums::{FilterStrategy, InterlaceMethod};
+pub use optimizer::{PngOptimizer, StripChunks};
+pub use rows::{RowDecoder, RowInfo};
 pub use zune_core;
 
 mod apng;
@@ -182,5 +187,7 @@ mod enums;
 pub mod error;
 mod filters;
 mod headers;
+mod optimizer;
 mod options;
+mod rows;
 mod utils;
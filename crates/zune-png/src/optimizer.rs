@@ -0,0 +1,382 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software; You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Lossless PNG optimization
+//!
+//! The optimizer decodes a PNG and encodes it again, trying out
+//! the ways the encoder can store the same pixels and keeping the
+//! smallest result, see [`PngOptimizer`]
+use alloc::vec::Vec;
+
+use zune_core::bytestream::ZCursor;
+use zune_core::options::{DecoderOptions, EncoderOptions};
+
+use crate::apng::{BlendOp, DisposeOp, FrameInfo};
+use crate::decoder::PngInfo;
+use crate::enums::{FilterStrategy, InterlaceMethod};
+use crate::error::PngDecodeErrors;
+use crate::{PngDecoder, PngEncoder};
+
+/// Chunks needed to show the image, which are never removed
+const IMAGE_CHUNKS: [&[u8; 4]; 8] = [
+    b"IHDR", b"PLTE", b"IDAT", b"IEND", b"tRNS", b"acTL", b"fcTL", b"fdAT"
+];
+/// Chunks kept by [`StripChunks::Safe`] in addition to the image chunks
+const SAFE_CHUNKS: [&[u8; 4]; 8] = [
+    b"iCCP", b"sRGB", b"gAMA", b"cHRM", b"cICP", b"mDCV", b"cLLI", b"pHYs"
+];
+
+/// Which ancillary chunks the optimizer removes
+///
+/// Chunks the encoder can't write (e.g. bKGD, sBIT, hIST, sPLT and
+/// private chunks) are always removed. Transparency and animation
+/// chunks are part of the image and always kept.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StripChunks {
+    /// Keep every chunk the encoder can write
+    None,
+    /// Remove chunks which don't change how the image is displayed,
+    /// i.e. text, exif and time chunks, keeping color information
+    /// (iCCP, sRGB, gAMA, cHRM, cICP, mDCV and cLLI) and pHYs
+    Safe,
+    /// Remove every ancillary chunk
+    All
+}
+
+/// A lossless PNG optimizer
+///
+/// Takes an existing PNG and produces the smallest equivalent file
+/// it can find, with exactly the same pixels. It
+///
+/// - Tries reducing the bit depth, color type and converting to a palette where
+///   this is lossless, see [`PngEncoder::set_reduce_colors`]
+/// - Tries [filter strategies](FilterStrategy), more of them at higher levels
+/// - Compresses the winner with optimal parsing at the highest level
+/// - Removes ancillary chunks according to a [`StripChunks`] policy
+///
+/// If the result isn't smaller than the input with just the chunks removed,
+/// that is returned instead, so unless the interlace method changes, the
+/// output is never larger than the input.
+///
+/// # Example
+/// ```
+/// use zune_core::bit_depth::BitDepth;
+/// use zune_core::colorspace::ColorSpace;
+/// use zune_core::options::EncoderOptions;
+/// use zune_png::{PngEncoder, PngOptimizer, StripChunks};
+///
+/// let pixels = vec![20_u8; 32 * 32 * 3];
+/// let options = EncoderOptions::new(32, 32, ColorSpace::RGB, BitDepth::Eight);
+///
+/// let mut png = vec![];
+/// PngEncoder::new(&pixels, options).encode(&mut png).unwrap();
+///
+/// let mut optimizer = PngOptimizer::new();
+/// optimizer.set_strip_chunks(StripChunks::Safe);
+///
+/// let optimized = optimizer.optimize(&png).unwrap();
+/// assert!(optimized.len() <= png.len());
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct PngOptimizer {
+    level:        u8,
+    strip_chunks: StripChunks,
+    interlace:    Option<InterlaceMethod>,
+    num_threads:  u8
+}
+
+impl Default for PngOptimizer {
+    fn default() -> Self {
+        PngOptimizer {
+            level:        4,
+            strip_chunks: StripChunks::None,
+            interlace:    None,
+            num_threads:  1
+        }
+    }
+}
+
+/// A decoded PNG, with everything needed to write it again
+struct DecodedPng {
+    info:       PngInfo,
+    options:    EncoderOptions,
+    pixels:     Vec<u8>,
+    /// Frame control of the image, for animations
+    frame_info: Option<FrameInfo>,
+    /// Animation frames following the image
+    frames:     Vec<(Vec<u8>, FrameInfo)>,
+    num_plays:  u32
+}
+
+impl PngOptimizer {
+    /// Create an optimizer with the default level of 4,
+    /// which doesn't strip any chunk
+    pub fn new() -> PngOptimizer {
+        PngOptimizer::default()
+    }
+
+    /// Set how hard the optimizer tries
+    ///
+    /// | Level | Filter strategies tried                          |
+    /// |-------|--------------------------------------------------|
+    /// | 0-2   | `MinSum`                                         |
+    /// | 3-6   | `None`, `MinSum` and `Entropy`                   |
+    /// | 7+    | Every strategy, including `BruteForce`           |
+    ///
+    /// At level 9 and above the smallest result is compressed again with
+    /// optimal parsing, which is slow.
+    ///
+    /// The default level is 4
+    pub fn set_level(&mut self, level: u8) {
+        self.level = level;
+    }
+
+    /// Set which ancillary chunks are removed
+    ///
+    /// The default is [`StripChunks::None`]
+    pub fn set_strip_chunks(&mut self, strip: StripChunks) {
+        self.strip_chunks = strip;
+    }
+
+    /// Set the interlace method of the output, `None` keeps
+    /// that of the input
+    ///
+    /// Interlaced images are usually bigger, so converting them to
+    /// [`InterlaceMethod::Standard`] tends to save space
+    pub fn set_interlace_method(&mut self, interlace: Option<InterlaceMethod>) {
+        self.interlace = interlace;
+    }
+
+    /// Set the number of threads used for compressing large images
+    ///
+    /// The default is 1
+    pub fn set_num_threads(&mut self, num_threads: u8) {
+        self.num_threads = num_threads;
+    }
+
+    /// Optimize a PNG file, returning the smallest equivalent file found
+    ///
+    /// # Errors
+    /// If `png` can't be decoded, the optimizer runs the decoder in strict
+    /// mode so that corrupt files aren't silently rewritten
+    pub fn optimize(&self, png: &[u8]) -> Result<Vec<u8>, PngDecodeErrors> {
+        let image = self.decode(png)?;
+
+        let strategies: &[FilterStrategy] = match self.level {
+            0..=2 => &[FilterStrategy::MinSum],
+            3..=6 => &[
+                FilterStrategy::None,
+                FilterStrategy::MinSum,
+                FilterStrategy::Entropy
+            ],
+            _ => &[
+                FilterStrategy::None,
+                FilterStrategy::Sub,
+                FilterStrategy::Up,
+                FilterStrategy::Average,
+                FilterStrategy::Paeth,
+                FilterStrategy::MinSum,
+                FilterStrategy::Entropy,
+                FilterStrategy::BruteForce
+            ]
+        };
+        // trials use the regular deflater, optimal parsing
+        // is kept for the winner as it's much slower
+        let mut best: Option<(Vec<u8>, FilterStrategy, bool)> = None;
+
+        // like oxipng, try without reducing colors too, e.g. a palette
+        // may take more space than it saves on small images
+        for reduce_colors in [true, false] {
+            for strategy in strategies {
+                let output = self.encode(&image, *strategy, reduce_colors, 8)?;
+
+                let is_smaller = match &best {
+                    Some((smallest, ..)) => output.len() < smallest.len(),
+                    None => true
+                };
+                if is_smaller {
+                    best = Some((output, *strategy, reduce_colors));
+                }
+            }
+        }
+        let (mut output, strategy, reduce_colors) = best.unwrap();
+
+        if self.level >= 9 {
+            let squeezed = self.encode(&image, strategy, reduce_colors, 9)?;
+
+            if squeezed.len() < output.len() {
+                output = squeezed;
+            }
+        }
+        let interlace = self.interlace.unwrap_or(image.info.interlace_method);
+
+        if interlace == image.info.interlace_method {
+            // the input with the chunks removed is as good if the result isn't smaller
+            let stripped = self.strip_input(png);
+
+            if stripped.len() <= output.len() {
+                return Ok(stripped);
+            }
+        }
+        Ok(output)
+    }
+
+    /// Remove the chunks stripped by the policy from `png`,
+    /// keeping the image data as is
+    fn strip_input(&self, png: &[u8]) -> Vec<u8> {
+        if self.strip_chunks == StripChunks::None {
+            return png.to_vec();
+        }
+        // the signature
+        let mut output = png[..8].to_vec();
+        let mut position = 8;
+
+        // the decoder checked the chunks are complete
+        while let Some(header) = png.get(position..position + 8) {
+            let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+            let end = (position + length + 12).min(png.len());
+            let chunk_type = &header[4..];
+
+            let keep = IMAGE_CHUNKS.iter().any(|x| x == &chunk_type)
+                || (self.strip_chunks == StripChunks::Safe
+                    && SAFE_CHUNKS.iter().any(|x| x == &chunk_type));
+
+            if keep {
+                output.extend_from_slice(&png[position..end]);
+            }
+            if chunk_type == b"IEND" {
+                break;
+            }
+            position = end;
+        }
+        output
+    }
+
+    /// Decode pixels, frames and chunks of a PNG
+    fn decode(&self, png: &[u8]) -> Result<DecodedPng, PngDecodeErrors> {
+        let options = DecoderOptions::default().set_strict_mode(true);
+        let mut decoder = PngDecoder::new_with_options(ZCursor::new(png), options);
+
+        decoder.decode_headers()?;
+
+        let (width, height) = decoder.dimensions().unwrap();
+        let options = EncoderOptions::new(
+            width,
+            height,
+            decoder.colorspace().unwrap(),
+            decoder.depth().unwrap()
+        )
+        .set_num_threads(self.num_threads);
+
+        let mut frame_info = decoder.frame_info();
+
+        if decoder.actl_info.is_some() && frame_info.is_none() {
+            // the image is shown by decoders without APNG support only
+            frame_info = Some(FrameInfo {
+                seq_number: 0,
+                width,
+                height,
+                x_offset: 0,
+                y_offset: 0,
+                delay_num: 0,
+                delay_denom: 0,
+                dispose_op: DisposeOp::None,
+                blend_op: BlendOp::Source,
+                is_part_of_seq: false
+            });
+        }
+        let pixels = decoder.decode_raw()?;
+        let mut frames = Vec::new();
+
+        while decoder.more_frames() {
+            decoder.decode_headers()?;
+
+            let info = decoder.frame_info().ok_or(PngDecodeErrors::GenericStatic(
+                "Animation frame without fcTL"
+            ))?;
+
+            frames.push((decoder.decode_raw()?, info));
+        }
+        let num_plays = decoder.actl_info.map_or(0, |x| x.num_plays);
+
+        Ok(DecodedPng {
+            info: decoder.info().unwrap().clone(),
+            options,
+            pixels,
+            frame_info: frame_info.filter(|_| decoder.actl_info.is_some()),
+            frames,
+            num_plays
+        })
+    }
+
+    /// Encode a decoded PNG with a filter strategy, at an encoder effort
+    fn encode(
+        &self, image: &DecodedPng, strategy: FilterStrategy, reduce_colors: bool, effort: u8
+    ) -> Result<Vec<u8>, PngDecodeErrors> {
+        let info = &image.info;
+        let mut encoder = PngEncoder::new(&image.pixels, image.options.set_effort(effort));
+
+        encoder.set_reduce_colors(reduce_colors);
+        encoder.set_filter_strategy(strategy);
+        encoder.set_interlace_method(self.interlace.unwrap_or(info.interlace_method));
+
+        if let Some(frame_info) = image.frame_info {
+            encoder.set_frame_info(frame_info);
+        }
+        for (pixels, frame_info) in &image.frames {
+            encoder.add_frame(pixels, *frame_info);
+        }
+        encoder.set_num_plays(image.num_plays);
+
+        if self.strip_chunks != StripChunks::All {
+            if let Some(profile) = &info.icc_profile {
+                encoder.add_icc_profile(profile);
+            }
+            if let Some(intent) = info.srgb_intent {
+                encoder.set_srgb_intent(intent);
+            }
+            if let Some(gamma) = info.gamma {
+                encoder.set_gamma(gamma);
+            }
+            if let Some(chromaticities) = info.chromaticities {
+                encoder.set_chromaticities(chromaticities);
+            }
+            if let Some(cicp) = info.cicp {
+                encoder.set_cicp(cicp);
+            }
+            if let Some(mastering_display) = info.mastering_display {
+                encoder.set_mastering_display(mastering_display);
+            }
+            if let Some(content_light) = info.content_light_level {
+                encoder.set_content_light_level(content_light);
+            }
+            if let Some(dimensions) = info.physical_dimensions {
+                encoder.set_physical_dimensions(dimensions);
+            }
+        }
+        if self.strip_chunks == StripChunks::None {
+            if let Some(exif) = &info.exif {
+                encoder.add_exif_segment(exif);
+            }
+            if let Some(time) = info.time_info {
+                encoder.set_time(time);
+            }
+            info.text_chunk
+                .iter()
+                .for_each(|x| encoder.add_text_chunk(x.clone()));
+            info.ztxt_chunk
+                .iter()
+                .for_each(|x| encoder.add_ztxt_chunk(x.clone()));
+            info.itxt_chunk
+                .iter()
+                .for_each(|x| encoder.add_itxt_chunk(x.clone()));
+        }
+        let mut output = Vec::new();
+        encoder.encode(&mut output)?;
+
+        Ok(output)
+    }
+}
@@ -0,0 +1,512 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software; You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+//! Row by row decoding
+//!
+//! Instead of inflating and de-filtering the whole image at once, [`RowDecoder`]
+//! reads IDAT chunks as it needs them, inflates them into a buffer holding a single
+//! scanline and writes the de-filtered row into a buffer supplied by the caller.
+//!
+//! Memory usage is a few rows plus the inflate window, regardless of the image height.
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::mem::swap;
+
+use zune_core::bit_depth::BitDepth;
+use zune_core::bytestream::ZByteReaderTrait;
+use zune_core::colorspace::ColorSpace;
+use zune_inflate::{DeflateFormat, DeflateOptions, DeflateStreamDecoder, StreamStatus};
+
+use crate::decoder::{unfilter_row, PngDecoder, PngInfo};
+use crate::enums::{InterlaceMethod, PngChunkType};
+use crate::error::PngDecodeErrors;
+use crate::utils::convert_be_to_target_endian_u16;
+
+/// Maximum number of compressed bytes read from the stream at once
+const INPUT_SIZE: usize = 1 << 14;
+
+/// Where a row returned by [`RowDecoder::next_row`] belongs in the image
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RowInfo {
+    /// The image row the pixels belong to
+    pub y:       usize,
+    /// The Adam7 pass the row belongs to, from 1 to 7,
+    /// or 0 if the image isn't interlaced
+    pub pass:    u8,
+    /// Column of the first pixel written
+    pub x_start: usize,
+    /// Distance in pixels between written pixels, 1 unless the image is interlaced
+    pub x_step:  usize,
+    /// Number of pixels written
+    pub width:   usize
+}
+
+/// A sub-image stored in the zlib stream, either the whole image
+/// or an Adam7 pass
+#[derive(Copy, Clone)]
+pub(crate) struct Pass {
+    pub(crate) number:  u8,
+    pub(crate) x_start: usize,
+    pub(crate) y_start: usize,
+    pub(crate) x_step:  usize,
+    pub(crate) y_step:  usize,
+    pub(crate) width:   usize,
+    pub(crate) height:  usize
+}
+
+impl Pass {
+    /// Number of bytes a scanline of the pass takes, including the filter byte
+    pub(crate) fn scanline_size(&self, info: &PngInfo) -> usize {
+        let bits_per_pixel = usize::from(info.color.num_components()) * usize::from(info.depth);
+
+        (self.width * bits_per_pixel).div_ceil(8) + 1
+    }
+}
+
+/// Return the sub-images stored in the image data in the order they are stored,
+/// that is the whole image for images which aren't interlaced or the non-empty
+/// Adam7 passes
+pub(crate) fn image_passes(width: usize, height: usize, info: &PngInfo) -> Vec<Pass> {
+    if info.interlace_method == InterlaceMethod::Adam7 {
+        const XORIG: [usize; 7] = [0, 4, 0, 2, 0, 1, 0];
+        const YORIG: [usize; 7] = [0, 0, 4, 0, 2, 0, 1];
+
+        const XSPC: [usize; 7] = [8, 8, 4, 4, 2, 2, 1];
+        const YSPC: [usize; 7] = [8, 8, 8, 4, 4, 2, 2];
+
+        (0..7)
+            .map(|p| Pass {
+                number:  p as u8 + 1,
+                x_start: XORIG[p],
+                y_start: YORIG[p],
+                x_step:  XSPC[p],
+                y_step:  YSPC[p],
+                width:   width.saturating_sub(XORIG[p]).div_ceil(XSPC[p]),
+                height:  height.saturating_sub(YORIG[p]).div_ceil(YSPC[p])
+            })
+            // empty passes aren't stored
+            .filter(|p| p.width != 0 && p.height != 0)
+            .collect()
+    } else {
+        vec![Pass {
+            number: 0,
+            x_start: 0,
+            y_start: 0,
+            x_step: 1,
+            y_step: 1,
+            width,
+            height
+        }]
+    }
+}
+
+/// A decoder returning an image one row at a time
+///
+/// Created by [`PngDecoder::decode_rows`], see that method for details
+pub struct RowDecoder<'a, T: ZByteReaderTrait> {
+    decoder:     &'a mut PngDecoder<T>,
+    info:        PngInfo,
+    inflater:    DeflateStreamDecoder,
+    /// Compressed bytes, inflated from `input_start`
+    input:       Vec<u8>,
+    input_start: usize,
+    /// Bytes of the current IDAT chunk not yet read into `input`
+    chunk_left:  usize,
+    /// True once all IDAT chunks have been read
+    idat_done:   bool,
+    /// True if the image data is read from the stream rather than
+    /// from the frame data stored by `decode_headers`
+    streaming:   bool,
+    passes:      Vec<Pass>,
+    pass:        usize,
+    pass_row:    usize,
+    /// Filter byte followed by the filtered row
+    filtered:    Vec<u8>,
+    /// De-filtered current and previous rows
+    current:     Vec<u8>,
+    previous:    Vec<u8>,
+    /// The current row expanded into the output colorspace
+    expanded:    Vec<u8>,
+    finished:    bool
+}
+
+impl<T: ZByteReaderTrait> PngDecoder<T> {
+    /// Decode the image one row at a time
+    ///
+    /// This returns a [`RowDecoder`] whose [`next_row`](RowDecoder::next_row)
+    /// writes a single row of pixels into a caller supplied buffer, compressed
+    /// data is read from the stream as it is needed, so the full image is never
+    /// held in memory.
+    ///
+    /// Rows are in the same format [`decode_raw`](Self::decode_raw) returns,
+    /// i.e the same colorspace, depth and byte endian.
+    ///
+    /// If [`decode_headers`](Self::decode_headers) was called before, the compressed
+    /// data of the current frame was already read into memory and rows are decoded
+    /// from there, this also allows decoding animation frames row by row. Otherwise
+    /// only chunks up to the image data are read, chunks after it become available
+    /// in [`info`](Self::info) once all rows are read.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use zune_core::bytestream::ZCursor;
+    /// use zune_png::PngDecoder;
+    /// let mut decoder = PngDecoder::new(ZCursor::new(&[]));
+    ///
+    /// let mut rows = decoder.decode_rows().unwrap();
+    /// let mut row = vec![0; rows.row_size()];
+    ///
+    /// while let Some(info) = rows.next_row(&mut row).unwrap() {
+    ///     // pixels of row `info.y` are in `row`
+    /// }
+    /// ```
+    pub fn decode_rows(&mut self) -> Result<RowDecoder<'_, T>, PngDecodeErrors> {
+        let streaming = !self.seen_headers;
+
+        let input = if streaming {
+            self.decode_headers_until_idat()?;
+            vec![]
+        } else {
+            match self.frames.get_mut(self.current_frame) {
+                Some(frame) => core::mem::take(&mut frame.fdat),
+                None => return Err(PngDecodeErrors::GenericStatic("No more frames"))
+            }
+        };
+        let frame_info = self
+            .frame_info()
+            .ok_or(PngDecodeErrors::GenericStatic("Unimplemented frame info"))?;
+
+        let info = self.png_info.clone();
+
+        let passes = image_passes(frame_info.width, frame_info.height, &info);
+
+        // the size of the inflated data, anything past it is an error
+        let limit = passes
+            .iter()
+            .map(|p| p.scanline_size(&info) * p.height)
+            .sum();
+        let stride = passes
+            .iter()
+            .map(|p| p.scanline_size(&info))
+            .max()
+            .unwrap_or(1)
+            - 1;
+
+        let options = DeflateOptions::default()
+            .set_limit(limit)
+            .set_confirm_checksum(self.options.inflate_get_confirm_adler());
+
+        let out_n = self.colorspace().unwrap().num_components();
+        let expanded_size = frame_info.width * out_n * if info.depth == 16 { 2 } else { 1 };
+
+        Ok(RowDecoder {
+            decoder: self,
+            info,
+            inflater: DeflateStreamDecoder::new_with_options(DeflateFormat::Zlib, options),
+            input,
+            input_start: 0,
+            chunk_left: 0,
+            idat_done: !streaming,
+            streaming,
+            passes,
+            pass: 0,
+            pass_row: 0,
+            filtered: vec![0; stride + 1],
+            current: vec![0; stride],
+            previous: vec![0; stride],
+            expanded: vec![0; expanded_size],
+            finished: false
+        })
+    }
+
+    /// Read the signature and chunks up to the first IDAT chunk,
+    /// leaving the stream at the start of that chunk
+    fn decode_headers_until_idat(&mut self) -> Result<(), PngDecodeErrors> {
+        self.decode_signature()?;
+
+        loop {
+            let header = self.read_chunk_header()?;
+
+            match header.chunk_type {
+                PngChunkType::IDAT => {
+                    // go back to the chunk length, the row decoder reads
+                    // image data chunks itself
+                    self.stream.rewind(8)?;
+                    break;
+                }
+                PngChunkType::fcTL => {
+                    // the frame control of the default image, it has the
+                    // image dimensions which we already know
+                    self.parse_fctl_external(header)?;
+                }
+                PngChunkType::IEND => {
+                    return Err(PngDecodeErrors::GenericStatic("No IDAT chunks found"));
+                }
+                _ => self.parse_header(header)?
+            }
+        }
+        self.seen_headers = true;
+        Ok(())
+    }
+}
+
+impl<'a, T: ZByteReaderTrait> RowDecoder<'a, T> {
+    /// Return the image width and height
+    pub fn dimensions(&self) -> (usize, usize) {
+        self.decoder.dimensions().unwrap()
+    }
+
+    /// Return the colorspace of rows written by [`next_row`](Self::next_row)
+    pub fn colorspace(&self) -> ColorSpace {
+        self.decoder.colorspace().unwrap()
+    }
+
+    /// Return the depth of rows written by [`next_row`](Self::next_row)
+    pub fn depth(&self) -> BitDepth {
+        if self.decoder.options.png_get_strip_to_8bit() {
+            BitDepth::Eight
+        } else {
+            self.decoder.depth().unwrap()
+        }
+    }
+
+    /// Return the number of bytes [`next_row`](Self::next_row) writes,
+    /// this is the size of a full image row
+    pub fn row_size(&self) -> usize {
+        self.dimensions().0 * self.colorspace().num_components() * self.depth().size_of()
+    }
+
+    /// Decode the next row into `row`
+    ///
+    /// For images which aren't interlaced, rows are returned from top to bottom
+    /// and fill `row` completely.
+    ///
+    /// For Adam7 interlaced images, rows are returned pass by pass and only the
+    /// pixels belonging to the pass are written, i.e pixels `x_start`, `x_start + x_step`,
+    /// `x_start + 2*x_step` and so on, the remaining pixels in `row` are left untouched.
+    /// An image row is thus returned up to four times, once in every pass it has
+    /// pixels in, the returned [`RowInfo`] tells where the pixels belong.
+    ///
+    /// # Returns
+    /// - `Ok(Some(info))`: A row was written into `row`
+    /// - `Ok(None)`: All rows were returned
+    /// - `Err(e)`: The image is corrupt, or `row` is smaller than [`row_size`](Self::row_size)
+    pub fn next_row(&mut self, row: &mut [u8]) -> Result<Option<RowInfo>, PngDecodeErrors> {
+        let row_size = self.row_size();
+
+        if row.len() < row_size {
+            return Err(PngDecodeErrors::TooSmallOutput(row_size, row.len()));
+        }
+        while self.pass < self.passes.len() && self.pass_row == self.passes[self.pass].height {
+            self.pass += 1;
+            self.pass_row = 0;
+        }
+        if self.pass == self.passes.len() {
+            if !self.finished {
+                self.finish()?;
+                self.finished = true;
+            }
+            return Ok(None);
+        }
+        let pass = self.passes[self.pass];
+
+        let stride = pass.scanline_size(&self.info) - 1;
+
+        self.inflate_exact(stride + 1)?;
+
+        let info = &self.info;
+
+        // filters work on bytes before the first pixel, for depths less
+        // than 8 this is the previous byte
+        let components = if info.depth < 8 {
+            1
+        } else {
+            usize::from(info.color.num_components()) * if info.depth == 16 { 2 } else { 1 }
+        };
+        let prev_row: &[u8] = if self.pass_row == 0 { &[] } else { &self.previous[..stride] };
+
+        unfilter_row(
+            &self.filtered[..=stride],
+            prev_row,
+            &mut self.current[..stride],
+            components,
+            self.decoder.options.use_sse2(),
+            self.decoder.options.use_sse41()
+        )?;
+        // the row we just de-filtered is the previous row of the next one
+        swap(&mut self.current, &mut self.previous);
+
+        let out_n = self.decoder.colorspace().unwrap().num_components();
+        let pixel_size = out_n * if info.depth == 16 { 2 } else { 1 };
+
+        let expanded = &mut self.expanded[..pass.width * pixel_size];
+        expanded[..stride].copy_from_slice(&self.previous[..stride]);
+
+        if self.decoder.needs_post_processing() {
+            self.decoder.post_process_row(expanded, pass.width, info)?;
+        }
+
+        let mut out_pixel_size = pixel_size;
+
+        if info.depth == 16 {
+            if self.decoder.options.png_get_strip_to_8bit() {
+                // take the top 8 bits of every sample, the write never catches on the read
+                for i in 0..expanded.len() / 2 {
+                    expanded[i] = expanded[2 * i];
+                }
+                out_pixel_size /= 2;
+            } else {
+                convert_be_to_target_endian_u16(
+                    expanded,
+                    self.decoder.byte_endian(),
+                    self.decoder.options.use_sse41()
+                );
+            }
+        }
+        if pass.x_step == 1 {
+            row[..pass.width * out_pixel_size]
+                .copy_from_slice(&expanded[..pass.width * out_pixel_size]);
+        } else {
+            for (i, pixel) in expanded
+                .chunks_exact(out_pixel_size)
+                .take(pass.width)
+                .enumerate()
+            {
+                let start = (pass.x_start + i * pass.x_step) * out_pixel_size;
+                row[start..start + out_pixel_size].copy_from_slice(pixel);
+            }
+        }
+        let row_info = RowInfo {
+            y:       pass.y_start + self.pass_row * pass.y_step,
+            pass:    pass.number,
+            x_start: pass.x_start,
+            x_step:  pass.x_step,
+            width:   pass.width
+        };
+        self.pass_row += 1;
+
+        Ok(Some(row_info))
+    }
+
+    /// Inflate exactly `length` bytes into `self.filtered`
+    fn inflate_exact(&mut self, length: usize) -> Result<(), PngDecodeErrors> {
+        let mut written = 0;
+
+        while written < length {
+            if self.input_start == self.input.len() && !self.idat_done {
+                self.read_input()?;
+                continue;
+            }
+            let result = self
+                .inflater
+                .decode(
+                    &self.input[self.input_start..],
+                    &mut self.filtered[written..length]
+                )
+                .map_err(PngDecodeErrors::ZlibDecodeErrors)?;
+
+            self.input_start += result.consumed;
+            written += result.written;
+
+            let truncated = match result.status {
+                StreamStatus::NeedsInput => self.idat_done,
+                StreamStatus::Done => written < length,
+                StreamStatus::OutputFull => false
+            };
+            if truncated {
+                return Err(PngDecodeErrors::GenericStatic(
+                    "Not enough pixels, image data ends early"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read compressed data from the next IDAT chunk into `self.input`
+    ///
+    /// If the next chunk isn't an IDAT chunk, the stream is left at its start
+    /// and `self.idat_done` is set
+    fn read_input(&mut self) -> Result<(), PngDecodeErrors> {
+        while self.chunk_left == 0 {
+            let header = self.decoder.read_chunk_header()?;
+            let stream = &mut self.decoder.stream;
+
+            if header.chunk_type != PngChunkType::IDAT {
+                // go back to the chunk length, the chunk is parsed
+                // once all rows are read
+                stream.rewind(8)?;
+                self.idat_done = true;
+                return Ok(());
+            }
+            self.chunk_left = header.length;
+
+            if header.length == 0 {
+                // skip crc
+                stream.skip(4)?;
+            }
+        }
+        let stream = &mut self.decoder.stream;
+        let length = min(self.chunk_left, INPUT_SIZE);
+
+        self.input.resize(length, 0);
+        stream.read_exact_bytes(&mut self.input)?;
+
+        self.input_start = 0;
+        self.chunk_left -= length;
+
+        if self.chunk_left == 0 {
+            // skip crc
+            stream.skip(4)?;
+        }
+        Ok(())
+    }
+
+    /// Called after the last row, confirms the checksum and
+    /// reads the chunks following the image data
+    fn finish(&mut self) -> Result<(), PngDecodeErrors> {
+        // let the inflater read the adler32 checksum
+        loop {
+            let result = self
+                .inflater
+                .decode(&self.input[self.input_start..], &mut [])
+                .map_err(PngDecodeErrors::ZlibDecodeErrors)?;
+
+            self.input_start += result.consumed;
+
+            match result.status {
+                StreamStatus::NeedsInput if !self.idat_done => self.read_input()?,
+                // all pixels are present, so ignore a missing
+                // checksum or trailing data
+                _ => break
+            }
+        }
+        // skip whatever image data is left
+        while !self.idat_done {
+            self.input_start = self.input.len();
+            self.read_input()?;
+        }
+        if self.streaming {
+            loop {
+                let header = self.decoder.read_chunk_header()?;
+
+                if header.chunk_type == PngChunkType::fcTL {
+                    // animation frames follow, leave them to decode_headers
+                    self.decoder.stream.rewind(8)?;
+                    break;
+                }
+                self.decoder.parse_header(header)?;
+
+                if header.chunk_type == PngChunkType::IEND {
+                    break;
+                }
+            }
+        }
+        // one more frame decoded
+        self.decoder.current_frame += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+use std::fs::{read, read_dir};
+
+use zune_core::bit_depth::BitDepth;
+use zune_core::bytestream::ZCursor;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::EncoderOptions;
+use zune_png::{
+    BlendOp, DisposeOp, FrameInfo, InterlaceMethod, PngDecoder, PngEncoder, PngOptimizer,
+    StripChunks, TextChunk
+};
+
+/// Decode an image as 16 bit RGBA, so that images written
+/// with a different color type or depth can be compared
+fn decode_rgba16(data: &[u8]) -> Vec<u16> {
+    let mut decoder = PngDecoder::new(ZCursor::new(data));
+    let pixels = decoder.decode_raw().unwrap();
+
+    let samples: Vec<u16> = if decoder.depth() == Some(BitDepth::Sixteen) {
+        pixels
+            .chunks_exact(2)
+            .map(|x| u16::from_be_bytes([x[0], x[1]]))
+            .collect()
+    } else {
+        pixels.iter().map(|x| u16::from(*x) * 257).collect()
+    };
+    match decoder.colorspace().unwrap() {
+        ColorSpace::Luma => samples
+            .iter()
+            .flat_map(|x| [*x, *x, *x, u16::MAX])
+            .collect(),
+        ColorSpace::LumaA => samples
+            .chunks_exact(2)
+            .flat_map(|x| [x[0], x[0], x[0], x[1]])
+            .collect(),
+        ColorSpace::RGB => samples
+            .chunks_exact(3)
+            .flat_map(|x| [x[0], x[1], x[2], u16::MAX])
+            .collect(),
+        _ => samples
+    }
+}
+
+#[test]
+fn test_optimizer_png_suite() {
+    let path = env!("CARGO_MANIFEST_DIR").to_string() + "/tests/png_suite";
+
+    for entry in read_dir(path).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+
+        if !name.ends_with(".png") {
+            continue;
+        }
+        let data = read(&path).unwrap();
+
+        for interlace in [None, Some(InterlaceMethod::Standard)] {
+            let mut optimizer = PngOptimizer::new();
+            optimizer.set_strip_chunks(StripChunks::Safe);
+            optimizer.set_interlace_method(interlace);
+
+            // files starting with x are corrupt
+            if name.starts_with('x') {
+                let _ = optimizer.optimize(&data);
+                continue;
+            }
+            let optimized = optimizer.optimize(&data).unwrap();
+
+            assert!(
+                decode_rgba16(&optimized) == decode_rgba16(&data),
+                "{name} differs"
+            );
+            let mut decoder = PngDecoder::new(ZCursor::new(&data));
+            decoder.decode_headers().unwrap();
+
+            // removing interlacing may cost a few bytes, anything else only strips
+            if decoder.info().unwrap().interlace_method == InterlaceMethod::Standard
+                || interlace.is_none()
+            {
+                assert!(
+                    optimized.len() <= data.len(),
+                    "{name} grew from {} to {} bytes",
+                    data.len(),
+                    optimized.len()
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_optimizer_small_truecolor() {
+    // a palette would be larger than the image data of these
+    for name in ["cs8n2c08.png", "g10n2c08.png"] {
+        let data =
+            read(env!("CARGO_MANIFEST_DIR").to_string() + "/tests/png_suite/" + name).unwrap();
+
+        let mut optimizer = PngOptimizer::new();
+        optimizer.set_level(9);
+        optimizer.set_strip_chunks(StripChunks::All);
+
+        let optimized = optimizer.optimize(&data).unwrap();
+        assert!(optimized.len() <= data.len(), "{name} grew");
+        assert!(decode_rgba16(&optimized) == decode_rgba16(&data));
+    }
+}
+
+#[test]
+fn test_optimizer_smaller() {
+    let (width, height) = (40, 30);
+    // few colors, stored as 16 bit RGBA
+    let pixels: Vec<u8> = (0..width * height)
+        .flat_map(|i| {
+            let value = ((i / 7) % 4 * 60) as u8;
+            [value, value, 0, 0, 90, 90, 255, 255]
+        })
+        .collect();
+
+    let options =
+        EncoderOptions::new(width, height, ColorSpace::RGBA, BitDepth::Sixteen).set_effort(0);
+    let mut encoder = PngEncoder::new(&pixels, options);
+    encoder.set_interlace_method(InterlaceMethod::Adam7);
+    encoder.add_text_chunk(TextChunk {
+        keyword: b"Comment".to_vec(),
+        text:    b"Some text".to_vec()
+    });
+    encoder.set_gamma(0.45455);
+
+    let mut png = vec![];
+    encoder.encode(&mut png).unwrap();
+
+    for strip in [StripChunks::None, StripChunks::Safe, StripChunks::All] {
+        let mut optimizer = PngOptimizer::new();
+        optimizer.set_strip_chunks(strip);
+
+        let optimized = optimizer.optimize(&png).unwrap();
+        assert!(optimized.len() < png.len());
+        assert!(decode_rgba16(&optimized) == decode_rgba16(&png));
+
+        let mut decoder = PngDecoder::new(ZCursor::new(&optimized));
+        decoder.decode_headers().unwrap();
+
+        let info = decoder.info().unwrap();
+        // the interlacing of the input is kept
+        assert_eq!(info.interlace_method, InterlaceMethod::Adam7);
+        assert_eq!(
+            info.text_chunk.len(),
+            usize::from(strip == StripChunks::None)
+        );
+        assert_eq!(info.gamma.is_some(), strip != StripChunks::All);
+    }
+}
+
+#[test]
+fn test_optimizer_keeps_unoptimizable_input() {
+    let data =
+        read(env!("CARGO_MANIFEST_DIR").to_string() + "/tests/png_suite/basn0g01.png").unwrap();
+    let mut optimizer = PngOptimizer::new();
+    optimizer.set_level(0);
+
+    let optimized = optimizer.optimize(&data).unwrap();
+    assert!(optimized.len() <= data.len());
+}
+
+#[test]
+fn test_optimizer_animated() {
+    let (width, height) = (16, 12);
+    let info = |delay_num| FrameInfo {
+        seq_number: 0,
+        width,
+        height,
+        x_offset: 0,
+        y_offset: 0,
+        delay_num,
+        delay_denom: 10,
+        dispose_op: DisposeOp::None,
+        blend_op: BlendOp::Source,
+        is_part_of_seq: true
+    };
+    let frames: Vec<Vec<u8>> = (0..3_u8)
+        .map(|f| (0..width * height * 3).map(|x| x as u8 ^ f).collect())
+        .collect();
+
+    let options = EncoderOptions::new(width, height, ColorSpace::RGB, BitDepth::Eight);
+    let mut encoder = PngEncoder::new(&frames[0], options.set_effort(0));
+    encoder.set_frame_info(info(1));
+    encoder.add_frame(&frames[1], info(2));
+    encoder.add_frame(&frames[2], info(3));
+    encoder.set_num_plays(2);
+
+    let mut png = vec![];
+    encoder.encode(&mut png).unwrap();
+
+    let mut optimizer = PngOptimizer::new();
+    optimizer.set_strip_chunks(StripChunks::All);
+    let optimized = optimizer.optimize(&png).unwrap();
+
+    let mut decoder = PngDecoder::new(ZCursor::new(&optimized));
+    decoder.decode_headers().unwrap();
+    assert!(decoder.is_animated());
+
+    let mut num_frames = 0;
+
+    while decoder.more_frames() {
+        decoder.decode_headers().unwrap();
+        let frame_info = decoder.frame_info().unwrap();
+
+        assert_eq!(frame_info.delay_num, num_frames as u16 + 1);
+        assert!(decoder.decode_raw().unwrap() == frames[num_frames]);
+        num_frames += 1;
+    }
+    assert_eq!(num_frames, 3);
+}
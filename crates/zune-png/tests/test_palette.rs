@@ -105,3 +105,122 @@ fn test_palette_8bpp() {
         test_decoding(path);
     }
 }
+
+#[test]
+fn test_keep_palette() {
+    use zune_core::colorspace::ColorSpace;
+    use zune_core::options::DecoderOptions;
+
+    for name in [
+        "basi3p01", "basn3p02", "basi3p04", "basn3p08", "tbbn3p08", "tm3n3p02"
+    ] {
+        let path = env!("CARGO_MANIFEST_DIR").to_string() + "/tests/png_suite/" + name + ".png";
+        let contents = open_and_read(path);
+
+        let mut decoder = zune_png::PngDecoder::new(ZCursor::new(&contents));
+        let expected = decoder.decode_raw().unwrap();
+        let components = decoder.colorspace().unwrap().num_components();
+
+        let options = DecoderOptions::default().png_set_keep_palette(true);
+        let mut decoder = zune_png::PngDecoder::new_with_options(ZCursor::new(&contents), options);
+        let indexes = decoder.decode_raw().unwrap();
+
+        assert_eq!(decoder.colorspace(), Some(ColorSpace::Luma));
+
+        let (width, height) = decoder.dimensions().unwrap();
+        assert_eq!(indexes.len(), width * height);
+
+        // the png crate returns the palette as is
+        let reader = png::Decoder::new(&contents[..]).read_info().unwrap();
+        let palette = decoder.palette().unwrap();
+
+        assert_eq!(
+            palette.len() * 3,
+            reader.info().palette.as_ref().unwrap().len()
+        );
+
+        // looking indexes up gives the colors returned without the option
+        let colors: Vec<u8> = indexes
+            .iter()
+            .flat_map(|x| {
+                let entry = palette[usize::from(*x)];
+                [entry.red, entry.green, entry.blue, entry.alpha]
+            })
+            .collect::<Vec<u8>>()
+            .chunks_exact(4)
+            .flat_map(|x| x[..components].to_vec())
+            .collect();
+
+        assert!(colors == expected, "{name} differs");
+    }
+}
+
+#[test]
+fn test_encode_palette() {
+    use zune_core::bit_depth::BitDepth;
+    use zune_core::colorspace::ColorSpace;
+    use zune_core::options::{DecoderOptions, EncoderOptions};
+    use zune_png::PngEncoder;
+
+    for name in [
+        "basi3p01", "basn3p02", "basi3p04", "basn3p08", "tbbn3p08", "tm3n3p02"
+    ] {
+        let path = env!("CARGO_MANIFEST_DIR").to_string() + "/tests/png_suite/" + name + ".png";
+        let contents = open_and_read(path);
+
+        let keep_palette = DecoderOptions::default().png_set_keep_palette(true);
+        let mut decoder =
+            zune_png::PngDecoder::new_with_options(ZCursor::new(&contents), keep_palette);
+        let indexes = decoder.decode_raw().unwrap();
+        let (width, height) = decoder.dimensions().unwrap();
+
+        // edit the palette, keeping its order
+        let mut palette = decoder.palette().unwrap().to_vec();
+        palette.iter_mut().for_each(|x| x.red = 255 - x.red);
+
+        let options = EncoderOptions::new(width, height, ColorSpace::Luma, BitDepth::Eight);
+        let mut encoder = PngEncoder::new(&indexes, options);
+        encoder.set_palette(&palette);
+        // ignored as the palette is given
+        encoder.set_reduce_colors(true);
+
+        let mut encoded = vec![];
+        encoder.encode(&mut encoded).unwrap();
+
+        let mut decoder =
+            zune_png::PngDecoder::new_with_options(ZCursor::new(&encoded), keep_palette);
+        assert!(decoder.decode_raw().unwrap() == indexes, "{name} differs");
+        assert!(
+            decoder.palette().unwrap() == palette,
+            "{name} palette differs"
+        );
+        assert_eq!(decoder.depth(), Some(BitDepth::Eight));
+    }
+}
+
+#[test]
+fn test_encode_palette_invalid() {
+    use zune_core::bit_depth::BitDepth;
+    use zune_core::colorspace::ColorSpace;
+    use zune_core::options::EncoderOptions;
+    use zune_png::{PLTEEntry, PngEncoder};
+
+    let palette = [PLTEEntry::default(); 4];
+    let options = EncoderOptions::new(2, 2, ColorSpace::Luma, BitDepth::Eight);
+
+    // an index past the end of the palette
+    let mut encoder = PngEncoder::new(&[0, 1, 2, 4], options);
+    encoder.set_palette(&palette);
+    assert!(encoder.encode(&mut vec![]).is_err());
+
+    // an empty palette
+    let mut encoder = PngEncoder::new(&[0, 0, 0, 0], options);
+    encoder.set_palette(&[]);
+    assert!(encoder.encode(&mut vec![]).is_err());
+
+    // indexes must be 8 bit Luma
+    let options = EncoderOptions::new(2, 1, ColorSpace::RGB, BitDepth::Eight);
+    let mut encoder = PngEncoder::new(&[0, 1, 2, 3, 0, 1], options);
+    encoder.set_palette(&palette);
+    assert!(encoder.encode(&mut vec![]).is_err());
+}
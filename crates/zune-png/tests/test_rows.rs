@@ -0,0 +1,98 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+use std::fs::{read, read_dir};
+
+use zune_core::bytestream::ZCursor;
+use zune_core::options::DecoderOptions;
+use zune_png::PngDecoder;
+
+/// Decode an image row by row, placing pixels where they belong
+fn decode_rows(decoder: &mut PngDecoder<ZCursor<&[u8]>>) -> Vec<u8> {
+    let mut rows = decoder.decode_rows().unwrap();
+
+    let row_size = rows.row_size();
+    let (width, height) = rows.dimensions();
+    let pixel_size = row_size / width;
+
+    let mut out = vec![0; row_size * height];
+    let mut row = vec![0; row_size];
+
+    while let Some(info) = rows.next_row(&mut row).unwrap() {
+        // interlaced rows only write some pixels
+        for x in (info.x_start..).step_by(info.x_step).take(info.width) {
+            let start = info.y * row_size + x * pixel_size;
+
+            out[start..start + pixel_size]
+                .copy_from_slice(&row[x * pixel_size..(x + 1) * pixel_size]);
+        }
+    }
+    out
+}
+
+#[test]
+fn test_rows_match_decode_raw() {
+    let path = env!("CARGO_MANIFEST_DIR").to_string() + "/tests/png_suite";
+
+    let all_options = [
+        DecoderOptions::default(),
+        DecoderOptions::default().png_set_add_alpha_channel(true),
+        DecoderOptions::default().png_set_strip_to_8bit(true)
+    ];
+    for entry in read_dir(path).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+
+        // files starting with x are corrupt
+        if !name.ends_with(".png") || name.starts_with('x') {
+            continue;
+        }
+        let data = read(&path).unwrap();
+
+        for options in all_options {
+            let Ok(expected) =
+                PngDecoder::new_with_options(ZCursor::new(&data), options).decode_raw()
+            else {
+                continue;
+            };
+            let mut decoder = PngDecoder::new_with_options(ZCursor::new(&data[..]), options);
+            let mut rows = decoder.decode_rows().unwrap();
+            let mut row = vec![0; rows.row_size()];
+
+            let height = rows.dimensions().1;
+            let mut expected_y = 0;
+
+            // rows of images which aren't interlaced come in order
+            while let Some(info) = rows.next_row(&mut row).unwrap() {
+                if info.pass == 0 {
+                    assert_eq!(info.y, expected_y);
+                    expected_y += 1;
+                }
+            }
+            assert!(expected_y == 0 || expected_y == height);
+
+            let mut decoder = PngDecoder::new_with_options(ZCursor::new(&data[..]), options);
+            assert!(expected == decode_rows(&mut decoder), "{name} differs");
+        }
+    }
+}
+
+#[test]
+fn test_rows_after_decode_headers() {
+    let path = env!("CARGO_MANIFEST_DIR").to_string() + "/tests/png_suite/basi2c16.png";
+    let data = read(path).unwrap();
+
+    let expected = PngDecoder::new(ZCursor::new(&data)).decode_raw().unwrap();
+
+    let mut decoder = PngDecoder::new(ZCursor::new(&data[..]));
+    decoder.decode_headers().unwrap();
+
+    assert_eq!(decode_rows(&mut decoder), expected);
+    // the frame is consumed
+    assert!(decoder.decode_rows().is_err());
+}
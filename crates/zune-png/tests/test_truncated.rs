@@ -0,0 +1,201 @@
+/*
+ * Copyright (c) 2023.
+ *
+ * This software is free software;
+ *
+ * You can redistribute it or modify it under terms of the MIT, Apache License or Zlib license
+ */
+
+use std::fs::read;
+
+use zune_core::bit_depth::BitDepth;
+use zune_core::bytestream::ZCursor;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::{DecoderOptions, EncoderOptions};
+use zune_png::error::PngDecodeErrors;
+use zune_png::{InterlaceMethod, PngDecoder, PngEncoder};
+
+fn open_suite(name: &str) -> Vec<u8> {
+    read(env!("CARGO_MANIFEST_DIR").to_string() + "/tests/png_suite/" + name).unwrap()
+}
+
+/// Encode a noisy image which doesn't compress well, so that
+/// cutting the file anywhere in the image data loses some rows
+fn noisy_image(interlace: InterlaceMethod) -> Vec<u8> {
+    let (width, height) = (64, 48);
+    let pixels: Vec<u8> = (0..width * height * 3)
+        .map(|x: usize| {
+            // xorshift the index
+            let mut x = x as u32 + 1;
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect();
+
+    let options = EncoderOptions::new(width, height, ColorSpace::RGB, BitDepth::Eight);
+    let mut encoder = PngEncoder::new(&pixels, options);
+    encoder.set_interlace_method(interlace);
+
+    let mut out = vec![];
+    encoder.encode(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn test_truncated_keeps_decoded_rows() {
+    let data = noisy_image(InterlaceMethod::Standard);
+
+    let mut decoder = PngDecoder::new(ZCursor::new(&data));
+    let expected = decoder.decode_raw().unwrap();
+    assert!(decoder.error_report().is_none());
+
+    let row_size = expected.len() / decoder.dimensions().unwrap().1;
+
+    for length in [data.len() / 2, data.len() * 3 / 4, data.len() - 40] {
+        let truncated = &data[..length];
+
+        let mut decoder = PngDecoder::new(ZCursor::new(truncated));
+        let pixels = decoder.decode_raw().unwrap();
+        let report = decoder.error_report().unwrap();
+
+        assert_eq!(report.total_rows, 48);
+        assert!(report.rows_decoded > 0 && report.rows_decoded < report.total_rows);
+
+        let decoded = report.rows_decoded * row_size;
+
+        assert_eq!(pixels[..decoded], expected[..decoded]);
+        assert!(pixels[decoded..].iter().all(|x| *x == 0));
+
+        // strict mode keeps failing
+        let options = DecoderOptions::default().set_strict_mode(true);
+        let mut decoder = PngDecoder::new_with_options(ZCursor::new(truncated), options);
+        assert!(decoder.decode_raw().is_err());
+    }
+}
+
+#[test]
+fn test_truncated_interlaced() {
+    let data = noisy_image(InterlaceMethod::Adam7);
+    let expected = PngDecoder::new(ZCursor::new(&data)).decode_raw().unwrap();
+
+    let mut decoder = PngDecoder::new(ZCursor::new(&data[..data.len() * 2 / 3]));
+    let pixels = decoder.decode_raw().unwrap();
+    let report = decoder.error_report().unwrap();
+
+    assert!(report.rows_decoded > 0 && report.rows_decoded < report.total_rows);
+
+    let mut num_decoded = 0;
+    // every pixel is either decoded or zero
+    for (a, b) in pixels.chunks_exact(3).zip(expected.chunks_exact(3)) {
+        if a == b {
+            num_decoded += 1;
+        } else {
+            assert_eq!(a, [0, 0, 0]);
+        }
+    }
+    // the first passes are complete
+    assert!(num_decoded >= 64 * 48 / 4);
+}
+
+#[test]
+fn test_missing_iend() {
+    let data = open_suite("basn0g08.png");
+    let expected = PngDecoder::new(ZCursor::new(&data)).decode_raw().unwrap();
+
+    let mut decoder = PngDecoder::new(ZCursor::new(&data[..data.len() - 12]));
+    let pixels = decoder.decode_raw().unwrap();
+    let report = decoder.error_report().unwrap();
+
+    assert_eq!(report.rows_decoded, report.total_rows);
+    assert_eq!(pixels, expected);
+}
+
+#[test]
+fn test_no_image_data() {
+    let data = open_suite("basn0g08.png");
+
+    // cut inside the headers, there is nothing to show
+    let mut decoder = PngDecoder::new(ZCursor::new(&data[..40]));
+    assert!(decoder.decode_raw().is_err());
+}
+
+/// Offset of the data of the first IDAT chunk and its length
+fn idat_range(data: &[u8]) -> (usize, usize) {
+    let start = data.windows(4).position(|x| x == b"IDAT").unwrap() + 4;
+    let length = u32::from_be_bytes(data[start - 8..start - 4].try_into().unwrap());
+
+    (start, length as usize)
+}
+
+#[test]
+fn test_corrupt_image_data() {
+    let data = noisy_image(InterlaceMethod::Standard);
+
+    let mut decoder = PngDecoder::new(ZCursor::new(&data));
+    let expected = decoder.decode_raw().unwrap();
+    let row_size = expected.len() / decoder.dimensions().unwrap().1;
+
+    let (start, length) = idat_range(&data);
+    let mut corrupt = data.clone();
+    corrupt[start + length * 2 / 3..][..8].fill(0xFF);
+
+    let mut decoder = PngDecoder::new(ZCursor::new(&corrupt));
+    let pixels = decoder.decode_raw().unwrap();
+    let report = decoder.error_report().unwrap();
+
+    assert!(matches!(report.error, PngDecodeErrors::ZlibDecodeErrors(_)));
+    assert!(report.rows_decoded > 0 && report.rows_decoded < report.total_rows);
+
+    let decoded = report.rows_decoded * row_size;
+
+    assert_eq!(pixels[..decoded], expected[..decoded]);
+    assert!(pixels[decoded..].iter().all(|x| *x == 0));
+}
+
+#[test]
+fn test_idat_crc_error() {
+    let data = noisy_image(InterlaceMethod::Standard);
+    let options = DecoderOptions::default().png_set_confirm_crc(true);
+
+    let mut decoder = PngDecoder::new(ZCursor::new(&data));
+    let expected = decoder.decode_raw().unwrap();
+    let row_size = expected.len() / decoder.dimensions().unwrap().1;
+
+    let (start, length) = idat_range(&data);
+    let chunk_start = start - 8;
+
+    // only the crc is wrong, all rows are decoded
+    let mut corrupt = data.clone();
+    corrupt[start + length] ^= 1;
+
+    let mut decoder = PngDecoder::new_with_options(ZCursor::new(&corrupt), options);
+    let pixels = decoder.decode_raw().unwrap();
+    let report = decoder.error_report().unwrap();
+
+    assert!(matches!(report.error, PngDecodeErrors::BadCrc(..)));
+    assert_eq!(report.position, Some(chunk_start));
+    assert_eq!(report.rows_decoded, report.total_rows);
+    assert_eq!(pixels, expected);
+
+    // corrupt image data is decoded up to the zlib error
+    let mut corrupt = data.clone();
+    corrupt[start + length * 2 / 3..][..8].fill(0xFF);
+
+    let mut decoder = PngDecoder::new_with_options(ZCursor::new(&corrupt), options);
+    let pixels = decoder.decode_raw().unwrap();
+    let report = decoder.error_report().unwrap();
+
+    assert!(matches!(report.error, PngDecodeErrors::BadCrc(..)));
+    assert!(report.rows_decoded > 0 && report.rows_decoded < report.total_rows);
+
+    let decoded = report.rows_decoded * row_size;
+
+    assert_eq!(pixels[..decoded], expected[..decoded]);
+    assert!(pixels[decoded..].iter().all(|x| *x == 0));
+
+    let options = options.set_strict_mode(true);
+    let mut decoder = PngDecoder::new_with_options(ZCursor::new(&corrupt), options);
+    assert!(decoder.decode_raw().is_err());
+}